@@ -1,6 +1,5 @@
 #![no_std]
 #![no_main]
-#![feature(type_alias_impl_trait)]
 
 use embassy_stm32::{
     exti::ExtiInput,
@@ -11,7 +10,7 @@ use embassy_stm32::{
 };
 use embassy_time::{Duration, Timer};
 use embedded_nal_async::{TcpConnect};
-use embedded_io::asynch::{Write, Read};
+use embedded_io_async::{Write, Read};
 use es_wifi_driver::*;
 use static_cell::StaticCell;
 
@@ -24,11 +23,13 @@ pub type WifiWake = Output<'static, PB13>;
 pub type WifiReset = Output<'static, PE8>;
 pub type WifiCs = Output<'static, PE0>;
 pub type WifiReady = ExtiInput<'static, PE1>;
-pub type WifiAdapter = EsWifi<SPI, WifiCs, WifiReset, WifiWake, WifiReady>;
+pub type WifiAdapter = EsWifi<SpiTransport<SPI, WifiCs, WifiReady>, WifiReset, WifiWake>;
 
 #[embassy_executor::main]
 async fn main(spawner: embassy_executor::Spawner) {
     let p = embassy_stm32::init(Default::default());
+    let mut config = spi::Config::default();
+    config.frequency = hz(4_000_000);
     let spi = spi::Spi::new(
         p.SPI3,
         p.PC10,
@@ -36,8 +37,7 @@ async fn main(spawner: embassy_executor::Spawner) {
         p.PC11,
         p.DMA2_CH2,
         p.DMA2_CH1,
-        hz(4_000_000),
-        spi::Config::default(),
+        config,
     );
 
     let _boot = Output::new(p.PB12, Level::Low, Speed::VeryHigh);
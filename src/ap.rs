@@ -0,0 +1,372 @@
+//! Soft access point mode, where the module hosts its own network instead of joining
+//! one. Mostly useful for provisioning.
+
+use crate::{
+    parser, CommandId, Credentials, DriverState, Error, EsWifi, Secret, Transport, WifiMode,
+};
+use core::net::Ipv4Addr;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embedded_hal::digital::OutputPin;
+use heapless::{String, Vec};
+
+/// Address of the module on the hosted network when no [`DhcpServerConfig`] is given.
+const DEFAULT_ADDRESS: Ipv4Addr = Ipv4Addr::new(192, 168, 10, 1);
+
+/// Settings of the network hosted in soft access point mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ApConfig<'a> {
+    /// Network name.
+    pub ssid: &'a str,
+    /// WPA2 passphrase, or an empty string for an open network.
+    pub password: &'a str,
+    /// WiFi channel to host the network on.
+    pub channel: u8,
+    /// Maximum number of stations allowed to associate at the same time.
+    pub max_stations: u8,
+    /// Addressing of the hosted network, or `None` for the firmware defaults.
+    pub dhcp: Option<DhcpServerConfig>,
+}
+
+/// Addressing of the network hosted in soft access point mode.
+///
+/// Picking a subnet other than the firmware default avoids clashing with the network
+/// the device is being provisioned for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DhcpServerConfig {
+    /// Address of the module on the hosted network.
+    pub address: Ipv4Addr,
+    /// Netmask of the hosted network.
+    pub netmask: Ipv4Addr,
+    /// First address leased to stations.
+    pub lease_start: Ipv4Addr,
+    /// Last address leased to stations.
+    pub lease_end: Ipv4Addr,
+}
+
+impl DhcpServerConfig {
+    /// Whether the lease range lies within the subnet and excludes the module's address.
+    fn is_valid(&self) -> bool {
+        let mask = u32::from(self.netmask);
+        let network = u32::from(self.address) & mask;
+        let start = u32::from(self.lease_start);
+        let end = u32::from(self.lease_end);
+        let address = u32::from(self.address);
+        start & mask == network
+            && end & mask == network
+            && start <= end
+            && !(start..=end).contains(&address)
+    }
+}
+
+// The passphrase is left out, so it does not end up in logs.
+#[cfg(feature = "defmt")]
+impl<'a> defmt::Format for ApConfig<'a> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "ApConfig {{ ssid: {}, channel: {}, max_stations: {}, dhcp: {} }}",
+            self.ssid,
+            self.channel,
+            self.max_stations,
+            self.dhcp
+        )
+    }
+}
+
+impl<'a> ApConfig<'a> {
+    /// Network named `ssid` protected by `password` on channel 1, accepting a single
+    /// station.
+    pub fn new(ssid: &'a str, password: &'a str) -> Self {
+        Self {
+            ssid,
+            password,
+            channel: 1,
+            max_stations: 1,
+            dhcp: None,
+        }
+    }
+
+    /// Check that the network name and passphrase fit what the firmware accepts and that
+    /// the addressing is consistent, before any of them is sent to the module.
+    pub(crate) fn validate(&self) -> Result<(), ApError> {
+        let password = self.password.len();
+        if self.ssid.is_empty() || self.ssid.len() > 32 {
+            warn!("Soft access point SSID must be 1 to 32 bytes long");
+            return Err(ApError::InvalidConfig);
+        }
+        if password != 0 && !(8..=63).contains(&password) {
+            warn!("Soft access point passphrase must be 8 to 63 bytes long");
+            return Err(ApError::InvalidConfig);
+        }
+        if self.dhcp.is_some_and(|dhcp| !dhcp.is_valid()) {
+            warn!("Soft access point lease range is outside its subnet");
+            return Err(ApError::InvalidConfig);
+        }
+        Ok(())
+    }
+}
+
+/// Station associated with the soft access point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Station {
+    /// MAC address of the station.
+    pub mac: [u8; 6],
+    /// Signal strength of the station, in dBm.
+    pub rssi: i8,
+}
+
+/// Soft access point errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ApError {
+    /// The module is not running in soft access point mode
+    NotActive,
+    /// The module rejected the settings or request
+    Rejected,
+    /// The settings are inconsistent or out of range, such as a lease range outside the
+    /// subnet or an SSID longer than 32 bytes
+    InvalidConfig,
+    /// Communication with the module failed
+    Transport,
+    /// Module firmware does not know the command
+    Unsupported(CommandId),
+}
+
+impl<TRANSPORT> From<Error<TRANSPORT>> for ApError {
+    fn from(e: Error<TRANSPORT>) -> Self {
+        match e {
+            Error::Unsupported(command) => ApError::Unsupported(command),
+            _ => ApError::Transport,
+        }
+    }
+}
+
+impl<TRANSPORT, const SOCKETS: usize> DriverState<TRANSPORT, SOCKETS>
+where
+    TRANSPORT: Transport,
+{
+    pub(crate) async fn start_ap(&mut self, config: &ApConfig<'_>) -> Result<(), ApError> {
+        config.validate()?;
+        // Security is numbered 0 for open, 3 for WPA2.
+        let security: u8 = if config.password.is_empty() { 0 } else { 3 };
+
+        self.ap_command(command!(40, "AS=0,", config.ssid)).await?;
+        self.ap_command(command!(8, "A1=", security)).await?;
+        if !config.password.is_empty() {
+            let accepted = self
+                .send_secret(command!(72, "A2=", config.password))
+                .await
+                .map_err(ApError::from)?;
+            if !accepted {
+                return Err(ApError::Rejected);
+            }
+        }
+        self.ap_command(command!(8, "AC=", config.channel)).await?;
+        self.ap_command(command!(8, "AT=", config.max_stations))
+            .await?;
+        if let Some(dhcp) = config.dhcp {
+            self.ap_command(command!(40, "AI=", dhcp.address, ",", dhcp.netmask))
+                .await?;
+            self.ap_command(command!(40, "AL=", dhcp.lease_start, ",", dhcp.lease_end))
+                .await?;
+        }
+        self.ap_command(command!(4, "A0")).await?;
+        self.web_config = None;
+        self.ap_address = Some(config.dhcp.map_or(DEFAULT_ADDRESS, |dhcp| dhcp.address));
+        self.mode = Some(WifiMode::AccessPoint);
+        Ok(())
+    }
+
+    async fn ap_command<const N: usize>(&mut self, command: Vec<u8, N>) -> Result<(), ApError> {
+        let response = self.send_command(command).await.map_err(ApError::from)?;
+        if parser::is_ok(response) {
+            Ok(())
+        } else {
+            Err(ApError::Rejected)
+        }
+    }
+
+    async fn ap_stations<const N: usize>(&mut self) -> Result<Vec<Station, N>, ApError> {
+        if self.ap_address.is_none() {
+            return Err(ApError::NotActive);
+        }
+        let response = self
+            .send_command(command!(4, "AR"))
+            .await
+            .map_err(ApError::from)?;
+        let stations = parser::ap_stations(response).ok_or(ApError::Rejected)?;
+        Ok(stations
+            .map(|(mac, rssi)| Station { mac, rssi })
+            .take(N)
+            .collect())
+    }
+
+    async fn set_web_config(&mut self, enabled: bool) -> Result<(), ApError> {
+        if self.ap_address.is_none() {
+            return Err(ApError::NotActive);
+        }
+        // The page stores what it is given with the network the module already had, so
+        // that one is kept to tell them apart.
+        let existing = if enabled {
+            Some(self.stored_credentials().await?)
+        } else {
+            None
+        };
+        self.ap_command(command!(8, "AW=", enabled as usize))
+            .await?;
+        self.web_config = existing;
+        Ok(())
+    }
+
+    async fn web_config_credentials(&mut self) -> Result<Option<Credentials>, ApError> {
+        if self.ap_address.is_none() {
+            return Err(ApError::NotActive);
+        }
+        let Some(existing) = self.web_config.take() else {
+            return Ok(None);
+        };
+        let stored = self.stored_credentials().await;
+        let submitted =
+            stored.map(|stored| stored.filter(|stored| Some(stored) != existing.as_ref()));
+        self.web_config = Some(existing);
+        submitted
+    }
+
+    /// Credentials of the network kept in the module's settings, if any.
+    async fn stored_credentials(&mut self) -> Result<Option<Credentials>, ApError> {
+        self.query_secret(command!(4, "C?"), |response| {
+            let (ssid, password) =
+                parser::network_credentials(response).ok_or(ApError::Rejected)?;
+            if ssid.is_empty() {
+                return Ok(None);
+            }
+            let mut credentials = Credentials {
+                ssid: String::new(),
+                password: Secret::default(),
+            };
+            let ssid = core::str::from_utf8(ssid).map_err(|_| ApError::Rejected)?;
+            let password = core::str::from_utf8(password).map_err(|_| ApError::Rejected)?;
+            credentials
+                .ssid
+                .push_str(ssid)
+                .map_err(|_| ApError::Rejected)?;
+            credentials.password = Secret::new(password).ok_or(ApError::Rejected)?;
+            Ok(Some(credentials))
+        })
+        .await
+        .map_err(ApError::from)?
+    }
+
+    async fn deauthenticate(&mut self, mac: [u8; 6]) -> Result<(), ApError> {
+        if self.ap_address.is_none() {
+            return Err(ApError::NotActive);
+        }
+        self.ap_command(command!(32, "AK=", mac)).await
+    }
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Address of the module on the network it hosts, or `None` unless running as a soft
+    /// access point.
+    pub async fn ap_address(&self) -> Option<Ipv4Addr> {
+        self.adapter.lock().await.ap_address
+    }
+
+    /// Stations currently associated with the soft access point, up to `N` of them.
+    pub async fn ap_stations<const N: usize>(&self) -> Result<Vec<Station, N>, ApError> {
+        self.adapter.lock().await.ap_stations().await
+    }
+
+    /// Enable or disable the configuration web page served by the module firmware on the
+    /// soft access point, through which users can enter the network to join.
+    pub async fn set_web_config(&self, enabled: bool) -> Result<(), ApError> {
+        self.adapter.lock().await.set_web_config(enabled).await
+    }
+
+    /// Credentials entered on the configuration web page, or `None` until a network has
+    /// been submitted since the page was enabled with [`set_web_config`](Self::set_web_config).
+    ///
+    /// The network the module already had when the page was enabled is not reported, so
+    /// submitting that same network again goes unnoticed.
+    pub async fn web_config_credentials(&self) -> Result<Option<Credentials>, ApError> {
+        self.adapter.lock().await.web_config_credentials().await
+    }
+
+    /// Disconnect the station with the given MAC address from the soft access point.
+    pub async fn deauthenticate(&self, mac: [u8; 6]) -> Result<(), ApError> {
+        self.adapter.lock().await.deauthenticate(mac).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApConfig, ApError, DhcpServerConfig};
+    use core::net::Ipv4Addr;
+
+    #[test]
+    fn test_config_validation() {
+        let long = core::str::from_utf8(&[b'x'; 80]).unwrap();
+        assert_eq!(ApConfig::new("provision", "").validate(), Ok(()));
+        assert_eq!(ApConfig::new("provision", "12345678").validate(), Ok(()));
+        assert_eq!(ApConfig::new(&long[..32], &long[..63]).validate(), Ok(()));
+        for (ssid, password) in [
+            ("", ""),
+            (&long[..33], ""),
+            ("provision", "1234567"),
+            ("provision", &long[..64]),
+            // Too long for the commands, which used to panic.
+            (&long[..40], long),
+        ] {
+            assert_eq!(
+                ApConfig::new(ssid, password).validate(),
+                Err(ApError::InvalidConfig)
+            );
+        }
+    }
+
+    #[test]
+    fn test_dhcp_range_validation() {
+        let config = DhcpServerConfig {
+            address: Ipv4Addr::new(10, 42, 0, 1),
+            netmask: Ipv4Addr::new(255, 255, 255, 0),
+            lease_start: Ipv4Addr::new(10, 42, 0, 10),
+            lease_end: Ipv4Addr::new(10, 42, 0, 20),
+        };
+        assert!(config.is_valid());
+        assert!(!DhcpServerConfig {
+            lease_end: Ipv4Addr::new(10, 42, 1, 20),
+            ..config
+        }
+        .is_valid());
+        assert!(!DhcpServerConfig {
+            lease_start: Ipv4Addr::new(10, 42, 0, 1),
+            ..config
+        }
+        .is_valid());
+        assert!(!DhcpServerConfig {
+            lease_start: Ipv4Addr::new(10, 42, 0, 30),
+            ..config
+        }
+        .is_valid());
+
+        // Checked along with the rest of the settings, before anything is sent.
+        let mut ap = ApConfig::new("provision", "");
+        ap.dhcp = Some(config);
+        assert_eq!(ap.validate(), Ok(()));
+        ap.dhcp = Some(DhcpServerConfig {
+            lease_start: Ipv4Addr::new(10, 42, 0, 1),
+            ..config
+        });
+        assert_eq!(ap.validate(), Err(ApError::InvalidConfig));
+    }
+}
@@ -0,0 +1,215 @@
+//! Interactive console driving an eS-WiFi module from a Linux host, for module bring-up
+//! and firmware regression checks.
+//!
+//! The module's UART is reached through a USB serial adapter, such as an FTDI cable.
+//! Configure the port before starting the console:
+//!
+//! ```text
+//! stty -F /dev/ttyUSB0 115200 raw -echo
+//! cargo run --features cli --bin es-wifi-cli -- /dev/ttyUSB0 <ssid> <psk>
+//! ```
+
+use es_wifi_driver::{EsWifi, NoPin, SocketAddr, UartTransport};
+use std::fs::{File, OpenOptions};
+use std::future::{poll_fn, Future};
+use std::io::{self, BufRead, Read as _, Write as _};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake};
+use std::thread::{self, Thread};
+
+use embassy_time::{with_timeout, Duration};
+use embedded_io::ErrorKind;
+use embedded_io_async::{Read, Write};
+use embedded_nal_async::TcpConnect;
+
+type Driver = EsWifi<UartTransport<Serial>, NoPin, NoPin>;
+
+const HELP: &str = "\
+commands:
+  scan                  list access points in range
+  connect <ip>:<port>   open a TCP connection
+  send <text>           send a line of text on the connection
+  recv                  print data received on the connection
+  close                 close the connection
+  info                  dump module settings
+  health                check the module and the network
+  stats                 show link and recovery statistics
+  reset                 restart the module with its software reset
+  quit                  leave the console";
+
+/// Serial port opened as a plain file, with blocking reads and writes.
+struct Serial(File);
+
+impl embedded_io::ErrorType for Serial {
+    type Error = ErrorKind;
+}
+
+impl Read for Serial {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, ErrorKind> {
+        self.0.read(buf).map_err(|_| ErrorKind::Other)
+    }
+}
+
+impl Write for Serial {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, ErrorKind> {
+        self.0.write(buf).map_err(|_| ErrorKind::Other)
+    }
+
+    async fn flush(&mut self) -> Result<(), ErrorKind> {
+        self.0.flush().map_err(|_| ErrorKind::Other)
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 4 {
+        eprintln!("usage: {} <serial device> <ssid> <psk>", args[0]);
+        std::process::exit(2);
+    }
+    let port = match OpenOptions::new().read(true).write(true).open(&args[1]) {
+        Ok(port) => port,
+        Err(e) => {
+            eprintln!("unable to open {}: {}", args[1], e);
+            std::process::exit(1);
+        }
+    };
+
+    let driver: Driver = EsWifi::with_transport(UartTransport::new(Serial(port)), None, None);
+    let run = async {
+        if let Err(e) = driver.run(&args[2], &args[3]).await {
+            println!("driver stopped: {:?}", e);
+        }
+    };
+    block_on(first(run, console(&driver)));
+}
+
+async fn console(driver: &Driver) {
+    println!("{}", HELP);
+    let mut connection = None;
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => return,
+        };
+        let (command, argument) = match line.trim().split_once(' ') {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (line.trim(), ""),
+        };
+        match command {
+            "" => {}
+            "scan" => {
+                let result = driver
+                    .scan(|ap| {
+                        println!(
+                            "{:32} {:02x?} {:4} dBm  channel {:2}  {:?}",
+                            ap.ssid.as_str(),
+                            ap.bssid,
+                            ap.rssi,
+                            ap.channel,
+                            ap.security
+                        )
+                    })
+                    .await;
+                if let Err(e) = result {
+                    println!("scan failed: {:?}", e);
+                }
+            }
+            "connect" => match parse_addr(argument) {
+                Some(remote) => match driver.connect(remote).await {
+                    Ok(socket) => {
+                        println!("connected");
+                        connection = Some(socket);
+                    }
+                    Err(e) => println!("connect failed: {:?}", e),
+                },
+                None => println!("expected <ip>:<port>"),
+            },
+            "send" => match connection.as_mut() {
+                Some(socket) => {
+                    let mut data = argument.as_bytes().to_vec();
+                    data.extend_from_slice(b"\r\n");
+                    match socket.write_all(&data).await {
+                        Ok(()) => println!("sent {} bytes", data.len()),
+                        Err(e) => println!("send failed: {:?}", e),
+                    }
+                }
+                None => println!("not connected"),
+            },
+            "recv" => match connection.as_mut() {
+                Some(socket) => {
+                    let mut buf = [0; 512];
+                    match with_timeout(Duration::from_secs(2), socket.read(&mut buf)).await {
+                        Ok(Ok(len)) => println!("{}", String::from_utf8_lossy(&buf[..len])),
+                        Ok(Err(e)) => println!("receive failed: {:?}", e),
+                        Err(_) => println!("nothing received"),
+                    }
+                }
+                None => println!("not connected"),
+            },
+            "close" => {
+                if connection.take().is_none() {
+                    println!("not connected");
+                }
+            }
+            "info" => match driver.dump_settings().await {
+                Ok(settings) => {
+                    for (name, value) in settings.iter() {
+                        println!("{:24} {}", name, value);
+                    }
+                }
+                Err(e) => println!("unable to read settings: {:?}", e),
+            },
+            "health" => println!("{:?}", driver.health_check(true).await),
+            "stats" => {
+                println!("network:     {:?}", driver.join_info().await);
+                println!("association: {:?}", driver.association().await);
+                println!("recoveries:  {:?}", driver.recovery_stats().await);
+            }
+            "reset" => driver.soft_reset().await,
+            "quit" => return,
+            _ => println!("{}", HELP),
+        }
+    }
+}
+
+fn parse_addr(addr: &str) -> Option<SocketAddr> {
+    let addr: std::net::SocketAddrV4 = addr.parse().ok()?;
+    Some(SocketAddr::V4(addr))
+}
+
+/// Run both futures until either completes.
+async fn first(a: impl Future<Output = ()>, b: impl Future<Output = ()>) {
+    let mut a = Box::pin(a);
+    let mut b = Box::pin(b);
+    poll_fn(|cx| {
+        if a.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(());
+        }
+        b.as_mut().poll(cx)
+    })
+    .await
+}
+
+/// Run `future` on the current thread, parking it while the future waits.
+fn block_on<F: Future>(future: F) -> F::Output {
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Arc::new(Unpark(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        thread::park();
+    }
+}
@@ -0,0 +1,162 @@
+//! Blocking `embedded-nal` stack for applications that have not moved to async yet.
+//!
+//! Each call runs the async driver to completion on the spot, calling an idle hook
+//! whenever it has to wait. The hook can run another executor, sleep until the next
+//! interrupt, or do nothing at all to busy-poll.
+
+use crate::{Credentials, Error, EsWifi, Mode, Protocol, SocketError, SocketHandle, Transport};
+use core::future::Future;
+use core::net::SocketAddr;
+use core::pin::Pin;
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use embassy_sync::blocking_mutex::raw::{NoopRawMutex, RawMutex};
+use embedded_hal::digital::OutputPin;
+use embedded_nal::{nb, TcpClientStack, TcpError, TcpErrorKind};
+
+/// Blocking TCP client stack on top of [`EsWifi`].
+///
+/// Unlike [`EsWifi::run`], this does not keep a run loop going: join the network with
+/// [`BlockingStack::join`] and close sockets explicitly, as `embedded-nal` expects.
+pub struct BlockingStack<
+    'a,
+    TRANSPORT,
+    RESET,
+    WAKEUP,
+    IDLE,
+    const SOCKETS: usize = 4,
+    M = NoopRawMutex,
+> where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    IDLE: FnMut(),
+    M: RawMutex,
+{
+    driver: &'a EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>,
+    idle: IDLE,
+}
+
+impl<'a, TRANSPORT, RESET, WAKEUP, IDLE, const SOCKETS: usize, M>
+    BlockingStack<'a, TRANSPORT, RESET, WAKEUP, IDLE, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    IDLE: FnMut(),
+    M: RawMutex,
+{
+    /// Wrap `driver`, calling `idle` whenever the driver has to wait.
+    pub fn new(driver: &'a EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>, idle: IDLE) -> Self {
+        Self { driver, idle }
+    }
+
+    /// Start the module and join the network.
+    pub fn join(&mut self, ssid: &str, psk: &str) -> Result<(), Error<TRANSPORT::Error>> {
+        let credentials = Credentials::new(ssid, psk).map_err(Error::Join)?;
+        let driver = self.driver;
+        self.block_on(driver.reset(&mut Mode::Station(credentials)))
+    }
+
+    fn block_on<F: Future>(&mut self, future: F) -> F::Output {
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut future = future;
+        // The future is never moved again after being pinned here.
+        let mut future = unsafe { Pin::new_unchecked(&mut future) };
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            (self.idle)();
+        }
+    }
+}
+
+impl<'a, TRANSPORT, RESET, WAKEUP, IDLE, const SOCKETS: usize, M> TcpClientStack
+    for BlockingStack<'a, TRANSPORT, RESET, WAKEUP, IDLE, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    IDLE: FnMut(),
+    M: RawMutex,
+{
+    type TcpSocket = SocketHandle;
+    type Error = SocketError;
+
+    fn socket(&mut self) -> Result<SocketHandle, SocketError> {
+        let driver = self.driver;
+        self.block_on(driver.new_socket())
+    }
+
+    fn connect(
+        &mut self,
+        socket: &mut SocketHandle,
+        remote: SocketAddr,
+    ) -> nb::Result<(), SocketError> {
+        let (driver, handle) = (self.driver, *socket);
+        self.block_on(async move {
+            let mut adapter = driver.adapter.lock().await;
+            adapter
+                .connect(handle, remote, Protocol::Tcp, None, None)
+                .await
+        })?;
+        Ok(())
+    }
+
+    fn send(&mut self, socket: &mut SocketHandle, buffer: &[u8]) -> nb::Result<usize, SocketError> {
+        let (driver, handle) = (self.driver, *socket);
+        let written = self.block_on(async move {
+            let mut adapter = driver.adapter.lock().await;
+            adapter.write(handle, buffer, &mut |_| {}).await
+        })?;
+        // Nothing written means the module's buffer is full.
+        match written {
+            0 if !buffer.is_empty() => Err(nb::Error::WouldBlock),
+            written => Ok(written),
+        }
+    }
+
+    fn receive(
+        &mut self,
+        socket: &mut SocketHandle,
+        buffer: &mut [u8],
+    ) -> nb::Result<usize, SocketError> {
+        let (driver, handle) = (self.driver, *socket);
+        let empty = buffer.is_empty();
+        let received = self.block_on(async move {
+            let mut adapter = driver.adapter.lock().await;
+            adapter.read(handle, buffer).await
+        })?;
+        match received {
+            0 if !empty => Err(nb::Error::WouldBlock),
+            received => Ok(received),
+        }
+    }
+
+    fn close(&mut self, socket: SocketHandle) -> Result<(), SocketError> {
+        let driver = self.driver;
+        self.block_on(async move { driver.adapter.lock().await.close(socket).await })
+    }
+}
+
+impl TcpError for SocketError {
+    fn kind(&self) -> TcpErrorKind {
+        match self {
+            SocketError::SocketClosed => TcpErrorKind::PipeClosed,
+            _ => TcpErrorKind::Other,
+        }
+    }
+}
+
+/// Waker for futures that are polled in a loop rather than when woken.
+fn noop_waker() -> Waker {
+    const VTABLE: RawWakerVTable = RawWakerVTable::new(
+        |_| RawWaker::new(core::ptr::null(), &VTABLE),
+        |_| {},
+        |_| {},
+        |_| {},
+    );
+    // The vtable functions ignore the data pointer, so any value is sound.
+    unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) }
+}
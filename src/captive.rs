@@ -0,0 +1,163 @@
+//! DNS responder for provisioning in soft access point mode, answering every query with
+//! the module's own address.
+//!
+//! Phones check for a captive portal by looking up a well-known name right after joining
+//! a network. Pointing every name at the module makes them open the provisioning page on
+//! their own, instead of users having to type its address.
+
+use crate::{EsWifi, SocketError, Transport};
+use core::net::Ipv4Addr;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embedded_hal::digital::OutputPin;
+
+/// DNS port.
+const PORT: u16 = 53;
+
+/// Time-to-live of the answers, in seconds. Kept short so phones do not hold on to the
+/// module's address once provisioned.
+const TTL: u32 = 10;
+
+const TYPE_A: u16 = 1;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+
+/// Largest DNS message exchanged over UDP.
+const MAX_MESSAGE: usize = 512;
+
+/// Write the response to the DNS `query` into `out`, pointing the name asked for at `ip`,
+/// and return its length, or `None` if `query` is not a standard query for a single
+/// name.
+///
+/// Queries for records other than addresses get an empty answer, so clients fall back to
+/// asking for an `A` record.
+pub(crate) fn answer(query: &[u8], ip: Ipv4Addr, out: &mut [u8]) -> Option<usize> {
+    let header = query.get(..12)?;
+    let flags = u16::from_be_bytes([header[2], header[3]]);
+    // A response, or an opcode other than a standard query
+    if flags & 0xF800 != 0 {
+        return None;
+    }
+    if header[4..6] != [0, 1] {
+        return None;
+    }
+
+    let mut pos = 12;
+    loop {
+        let len = *query.get(pos)? as usize;
+        // The name of the first question cannot be compressed.
+        if len & 0xC0 != 0 {
+            return None;
+        }
+        pos += 1 + len;
+        if len == 0 {
+            break;
+        }
+    }
+    let question = query.get(12..pos + 4)?;
+    let qtype = u16::from_be_bytes([question[question.len() - 4], question[question.len() - 3]]);
+    let qclass = u16::from_be_bytes([question[question.len() - 2], question[question.len() - 1]]);
+    let answers = (qclass == CLASS_IN && (qtype == TYPE_A || qtype == TYPE_ANY)) as u8;
+
+    let len = 12 + question.len() + answers as usize * 16;
+    let out = out.get_mut(..len)?;
+    // id, flags (authoritative response, recursion desired copied), 1 question, the
+    // answers, 0 authority, 0 additional
+    out[..2].copy_from_slice(&header[..2]);
+    out[2] = 0x84 | (header[2] & 0x01);
+    out[3] = 0;
+    out[4..12].copy_from_slice(&[0, 1, 0, answers, 0, 0, 0, 0]);
+    out[12..12 + question.len()].copy_from_slice(question);
+    if answers > 0 {
+        let record = &mut out[12 + question.len()..];
+        // Name pointing back at the question
+        record[..2].copy_from_slice(&0xC00Cu16.to_be_bytes());
+        record[2..4].copy_from_slice(&TYPE_A.to_be_bytes());
+        record[4..6].copy_from_slice(&CLASS_IN.to_be_bytes());
+        record[6..10].copy_from_slice(&TTL.to_be_bytes());
+        record[10..12].copy_from_slice(&4u16.to_be_bytes());
+        record[12..16].copy_from_slice(&ip.octets());
+    }
+    Some(len)
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Answer every DNS query on the hosted network with the module's address, so phones
+    /// joining it open the provisioning page served by the host on their own.
+    ///
+    /// Runs until an error occurs and should be spawned alongside [`EsWifi::run_ap`] once
+    /// the access point is up. Uses one of the module's sockets while running, replying
+    /// through the module to the host the last query came from.
+    pub async fn run_captive_dns(&self) -> Result<(), SocketError> {
+        use embedded_io_async::Write;
+
+        let ip = self.ap_address().await.ok_or(SocketError::SocketClosed)?;
+        let mut socket = self.udp_bind(PORT).await?;
+
+        let mut query = [0u8; MAX_MESSAGE];
+        let mut response = [0u8; MAX_MESSAGE];
+        loop {
+            let len = match socket.recv_from(&mut query).await {
+                Ok((len, _)) => len,
+                Err(SocketError::DatagramTooLarge) => continue,
+                Err(e) => return Err(e),
+            };
+            if let Some(len) = answer(&query[..len], ip, &mut response) {
+                trace!("Answering DNS query with {}", ip);
+                socket.write(&response[..len]).await?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(name: &[&str], qtype: u16) -> ([u8; 64], usize) {
+        let mut packet = [0u8; 64];
+        packet[..4].copy_from_slice(&[0x12, 0x34, 0x01, 0x00]);
+        packet[5] = 1;
+        let mut pos = 12;
+        for label in name {
+            packet[pos] = label.len() as u8;
+            packet[pos + 1..pos + 1 + label.len()].copy_from_slice(label.as_bytes());
+            pos += 1 + label.len();
+        }
+        packet[pos] = 0;
+        pos += 1;
+        packet[pos..pos + 2].copy_from_slice(&qtype.to_be_bytes());
+        packet[pos + 2..pos + 4].copy_from_slice(&CLASS_IN.to_be_bytes());
+        (packet, pos + 4)
+    }
+
+    #[test]
+    fn test_answer() {
+        let ip = Ipv4Addr::new(192, 168, 10, 1);
+        let mut out = [0u8; MAX_MESSAGE];
+
+        let (packet, len) = query(&["connectivitycheck", "gstatic", "com"], TYPE_A);
+        let answered = answer(&packet[..len], ip, &mut out).unwrap();
+        assert_eq!(answered, len + 16);
+        assert_eq!(&out[..4], &[0x12, 0x34, 0x85, 0x00]);
+        assert_eq!(&out[4..12], &[0, 1, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(&out[12..len], &packet[12..len]);
+        assert_eq!(&out[answered - 4..answered], &[192, 168, 10, 1]);
+
+        // No address to give for an AAAA record.
+        let (packet, len) = query(&["captive", "apple", "com"], 28);
+        assert_eq!(answer(&packet[..len], ip, &mut out), Some(len));
+        assert_eq!(out[7], 0);
+
+        // Responses and truncated queries are ignored.
+        let (mut packet, len) = query(&["example", "com"], TYPE_A);
+        assert_eq!(answer(&packet[..len - 1], ip, &mut out), None);
+        packet[2] |= 0x80;
+        assert_eq!(answer(&packet[..len], ip, &mut out), None);
+    }
+}
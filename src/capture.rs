@@ -0,0 +1,533 @@
+//! Capturing the exchanges with the module as text, and replaying captures, so problems
+//! seen in the field can be turned into regression tests.
+//!
+//! A capture has one record per line: `> ` followed by a command and any payload sent
+//! with it, or `< ` followed by a response. Printable ASCII appears as is, other bytes
+//! as `\r`, `\n`, `\\` or `\xNN`, and `\*` stands for bytes redacted from the capture.
+//! Blank lines and lines starting with `#` are ignored, so captures can be annotated.
+//!
+//! Passphrases and keys are redacted when captured: the values of `C2=` and `A2=`, their
+//! echo in the module's response, and the passphrase field of the settings shown by `C?`
+//! and `Z?`.
+
+use crate::secret::Wipe;
+use crate::Transport;
+use core::fmt::Write;
+use heapless::Vec;
+
+/// Commands carrying a passphrase or key, which follows the `=`.
+const SECRET_COMMANDS: [&[u8]; 2] = [b"C2=", b"A2="];
+
+/// Commands showing the network settings, whose second field is the passphrase.
+const SETTINGS_QUERIES: [&[u8]; 2] = [b"C?\r", b"Z?\r"];
+
+/// What to redact from the response being captured.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Redact {
+    Nothing,
+    /// The secret sent with the command, echoed by the module
+    Echo,
+    /// The passphrase field of the network settings
+    Passphrase,
+}
+
+/// [`Transport`] wrapper writing every exchange over another transport to `sink`, in
+/// the format described in the [module documentation](self).
+///
+/// Responses are captured in full even when the driver has no room for all of them.
+/// Failing to write to `sink` does not affect the exchange.
+pub struct Recorder<T, W> {
+    transport: T,
+    sink: W,
+    /// Secret sent with the last command, redacted from its echo.
+    secret: Wipe<Vec<u8, 64>>,
+    redact: Redact,
+}
+
+impl<T: Transport, W: Write> Recorder<T, W> {
+    /// Capture the exchanges over `transport` to `sink`.
+    pub fn new(transport: T, sink: W) -> Self {
+        Self {
+            transport,
+            sink,
+            secret: Wipe(Vec::new()),
+            redact: Redact::Nothing,
+        }
+    }
+
+    /// Where exchanges are captured to.
+    pub fn sink(&mut self) -> &mut W {
+        &mut self.sink
+    }
+
+    /// The wrapped transport.
+    pub fn inner(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    fn forget_secret(&mut self) {
+        #[cfg(feature = "zeroize")]
+        zeroize::Zeroize::zeroize(&mut self.secret.0[..]);
+        self.secret.0.clear();
+    }
+
+    fn record_write(&mut self, command: &[u8], data: &[u8]) -> core::fmt::Result {
+        self.sink.write_str("> ")?;
+        let secret = SECRET_COMMANDS
+            .iter()
+            .find(|prefix| command.starts_with(prefix));
+        match secret {
+            Some(prefix) => {
+                let end = command
+                    .iter()
+                    .rposition(|&byte| byte == b'\r')
+                    .unwrap_or(command.len())
+                    .max(prefix.len());
+                escape(&mut self.sink, &command[..prefix.len()])?;
+                self.sink.write_str("\\*")?;
+                escape(&mut self.sink, &command[end..])?;
+            }
+            None => escape(&mut self.sink, command)?,
+        }
+        escape(&mut self.sink, data)?;
+        self.sink.write_char('\n')
+    }
+}
+
+impl<T: Transport, W: Write> Transport for Recorder<T, W> {
+    type Error = T::Error;
+
+    async fn write(&mut self, command: &[u8], data: &[u8]) -> Result<(), Self::Error> {
+        self.forget_secret();
+        self.redact = Redact::Nothing;
+        if let Some(prefix) = SECRET_COMMANDS
+            .iter()
+            .find(|prefix| command.starts_with(prefix))
+        {
+            let value = &command[prefix.len()..];
+            let value = value.strip_suffix(b"\r").unwrap_or(value);
+            if !value.is_empty() {
+                // A secret too long to remember is not looked for in the echo, so the
+                // whole echo is redacted instead.
+                self.secret.0.extend_from_slice(value).ok();
+                self.redact = Redact::Echo;
+            }
+        } else if SETTINGS_QUERIES.contains(&command) {
+            self.redact = Redact::Passphrase;
+        }
+        self.record_write(command, data).ok();
+        self.transport.write(command, data).await
+    }
+
+    async fn read(
+        &mut self,
+        mut room: bool,
+        mut push: impl FnMut(u8) -> bool,
+    ) -> Result<(), Self::Error> {
+        let mut record = ResponseRecord {
+            sink: &mut self.sink,
+            redact: self.redact,
+            secret: &self.secret.0,
+            pending: Wipe(Vec::new()),
+            fields: 0,
+            redacting: false,
+        };
+        record.sink.write_str("< ").ok();
+        let result = self
+            .transport
+            .read(true, |byte| {
+                record.push(byte);
+                if room {
+                    room = push(byte);
+                }
+                true
+            })
+            .await;
+        record.finish();
+        self.sink.write_char('\n').ok();
+        self.forget_secret();
+        self.redact = Redact::Nothing;
+        result
+    }
+}
+
+/// A response being written to the sink, byte by byte.
+struct ResponseRecord<'a, W> {
+    sink: &'a mut W,
+    redact: Redact,
+    secret: &'a [u8],
+    /// Bytes that may be the start of the secret, held back until that is known.
+    pending: Wipe<Vec<u8, 64>>,
+    /// Commas seen, telling which settings field the bytes belong to.
+    fields: usize,
+    /// Whether redacted bytes were just marked, so the next ones are covered.
+    redacting: bool,
+}
+
+impl<W: Write> ResponseRecord<'_, W> {
+    fn push(&mut self, byte: u8) {
+        match self.redact {
+            Redact::Nothing => self.emit(byte),
+            Redact::Passphrase => {
+                if byte == b',' {
+                    self.fields += 1;
+                }
+                if self.fields == 1 && byte != b',' {
+                    self.redacted();
+                } else {
+                    self.emit(byte);
+                }
+            }
+            Redact::Echo if self.secret.is_empty() => self.redacted_unless_framing(byte),
+            Redact::Echo => {
+                self.pending.0.push(byte).ok();
+                while !self.secret.starts_with(&self.pending.0) {
+                    let first = self.pending.0.remove(0);
+                    self.emit(first);
+                }
+                if self.pending.0.len() == self.secret.len() {
+                    self.pending.0.clear();
+                    self.redacted();
+                }
+            }
+        }
+    }
+
+    /// Redact everything but line endings and the prompt, for an echo of a secret that
+    /// could not be remembered.
+    fn redacted_unless_framing(&mut self, byte: u8) {
+        if matches!(byte, b'\r' | b'\n' | b'>' | b' ') {
+            self.emit(byte);
+        } else {
+            self.redacted();
+        }
+    }
+
+    fn emit(&mut self, byte: u8) {
+        self.redacting = false;
+        escape(self.sink, &[byte]).ok();
+    }
+
+    fn redacted(&mut self) {
+        if !core::mem::replace(&mut self.redacting, true) {
+            self.sink.write_str("\\*").ok();
+        }
+    }
+
+    /// Write out the bytes still held back, once the response is complete.
+    fn finish(mut self) {
+        let pending = core::mem::take(&mut self.pending.0);
+        for byte in pending {
+            self.emit(byte);
+        }
+    }
+}
+
+/// Write `bytes` in the capture format.
+fn escape(sink: &mut impl Write, bytes: &[u8]) -> core::fmt::Result {
+    for &byte in bytes {
+        match byte {
+            b'\r' => sink.write_str("\\r")?,
+            b'\n' => sink.write_str("\\n")?,
+            b'\\' => sink.write_str("\\\\")?,
+            0x20..=0x7e => sink.write_char(byte as char)?,
+            _ => write!(sink, "\\x{:02x}", byte)?,
+        }
+    }
+    Ok(())
+}
+
+/// Piece of a captured record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Byte(u8),
+    Redacted,
+}
+
+/// Read a record in the capture format, yielding `Err` for a malformed escape.
+#[derive(Clone)]
+struct Unescape<'a> {
+    bytes: core::slice::Iter<'a, u8>,
+}
+
+impl Iterator for Unescape<'_> {
+    type Item = Result<Token, ()>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let byte = *self.bytes.next()?;
+        if byte != b'\\' {
+            return Some(Ok(Token::Byte(byte)));
+        }
+        let token = match self.bytes.next() {
+            Some(b'r') => Token::Byte(b'\r'),
+            Some(b'n') => Token::Byte(b'\n'),
+            Some(b'\\') => Token::Byte(b'\\'),
+            Some(b'*') => Token::Redacted,
+            Some(b'x') => {
+                let high = self.bytes.next().and_then(|c| (*c as char).to_digit(16));
+                let low = self.bytes.next().and_then(|c| (*c as char).to_digit(16));
+                match (high, low) {
+                    (Some(high), Some(low)) => Token::Byte((high * 16 + low) as u8),
+                    _ => return Some(Err(())),
+                }
+            }
+            _ => return Some(Err(())),
+        };
+        Some(Ok(token))
+    }
+}
+
+/// Errors of a [`Replay`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ReplayError {
+    /// The driver sent something else than captured on the given line, or read a
+    /// response where a command was captured
+    Mismatch {
+        /// Line of the capture, counting from 1
+        line: usize,
+    },
+    /// The line is not a record in the capture format
+    Malformed {
+        /// Line of the capture, counting from 1
+        line: usize,
+    },
+    /// The driver went on past the end of the capture
+    Exhausted,
+}
+
+/// [`Transport`] playing back a capture made with [`Recorder`], checking that the
+/// driver sends the captured commands and answering them with the captured responses.
+///
+/// Redacted bytes in a captured command match anything up to the byte following them,
+/// and are played back as a single `*` in responses.
+pub struct Replay<'a> {
+    lines: core::iter::Enumerate<core::str::Lines<'a>>,
+}
+
+impl<'a> Replay<'a> {
+    /// Play back `capture`.
+    pub fn new(capture: &'a str) -> Self {
+        Self {
+            lines: capture.lines().enumerate(),
+        }
+    }
+
+    /// Whether every record of the capture has been played back.
+    pub fn finished(&self) -> bool {
+        self.lines.clone().all(|(_, line)| is_comment(line))
+    }
+
+    /// Next record, as its line number, direction and contents.
+    fn next_record(&mut self) -> Result<(usize, u8, &'a str), ReplayError> {
+        let (index, line) = self
+            .lines
+            .find(|(_, line)| !is_comment(line))
+            .ok_or(ReplayError::Exhausted)?;
+        let line_number = index + 1;
+        let record = line.trim_end_matches('\r');
+        match record.as_bytes() {
+            [direction @ (b'>' | b'<'), b' ', ..] => Ok((line_number, *direction, &record[2..])),
+            _ => Err(ReplayError::Malformed { line: line_number }),
+        }
+    }
+}
+
+fn is_comment(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('#')
+}
+
+/// Whether `sent` matches the captured `record`.
+fn matches_record(record: &str, sent: impl Iterator<Item = u8>) -> Result<bool, ()> {
+    let mut expected = Unescape {
+        bytes: record.as_bytes().iter(),
+    }
+    .peekable();
+    for byte in sent {
+        loop {
+            match expected.peek().copied().transpose()? {
+                Some(Token::Byte(b)) if b == byte => {
+                    expected.next();
+                    break;
+                }
+                Some(Token::Redacted) => {
+                    let mut after = expected.clone();
+                    after.next();
+                    if after.next().transpose()? == Some(Token::Byte(byte)) {
+                        expected.next();
+                        continue;
+                    }
+                    break;
+                }
+                _ => return Ok(false),
+            }
+        }
+    }
+    for token in expected {
+        if token? != Token::Redacted {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+impl Transport for Replay<'_> {
+    type Error = ReplayError;
+
+    async fn write(&mut self, command: &[u8], data: &[u8]) -> Result<(), ReplayError> {
+        let (line, direction, record) = self.next_record()?;
+        let sent = command.iter().chain(data).copied();
+        match matches_record(record, sent) {
+            Ok(true) if direction == b'>' => Ok(()),
+            Ok(_) => Err(ReplayError::Mismatch { line }),
+            Err(()) => Err(ReplayError::Malformed { line }),
+        }
+    }
+
+    async fn read(
+        &mut self,
+        mut room: bool,
+        mut push: impl FnMut(u8) -> bool,
+    ) -> Result<(), ReplayError> {
+        let (line, direction, record) = self.next_record()?;
+        if direction != b'<' {
+            return Err(ReplayError::Mismatch { line });
+        }
+        let tokens = Unescape {
+            bytes: record.as_bytes().iter(),
+        };
+        for token in tokens {
+            let byte = match token.map_err(|_| ReplayError::Malformed { line })? {
+                Token::Byte(byte) => byte,
+                Token::Redacted => b'*',
+            };
+            if room {
+                room = push(byte);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+    use embassy_futures::block_on;
+    use heapless::String;
+
+    /// Transport answering every command with the same canned response.
+    struct Script(&'static [u8]);
+
+    impl Transport for Script {
+        type Error = Infallible;
+
+        async fn write(&mut self, _command: &[u8], _data: &[u8]) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        async fn read(
+            &mut self,
+            _room: bool,
+            mut push: impl FnMut(u8) -> bool,
+        ) -> Result<(), Infallible> {
+            for &byte in self.0 {
+                push(byte);
+            }
+            Ok(())
+        }
+    }
+
+    fn capture(command: &[u8], data: &[u8], response: &'static [u8]) -> String<256> {
+        let mut recorder = Recorder::new(Script(response), String::new());
+        let mut received = Vec::<u8, 64>::new();
+        block_on(async {
+            recorder.write(command, data).await.unwrap();
+            recorder
+                .read(true, |byte| received.push(byte).is_ok())
+                .await
+                .unwrap();
+        });
+        assert_eq!(&received[..], response);
+        recorder.sink().clone()
+    }
+
+    #[test]
+    fn test_capture_format() {
+        assert_eq!(
+            capture(b"S3=3\r", b"a\\\x00", b"\r\n3\r\nOK\r\n> "),
+            "> S3=3\\ra\\\\\\x00\n< \\r\\n3\\r\\nOK\\r\\n> \n"
+        );
+    }
+
+    #[test]
+    fn test_secrets_redacted() {
+        assert_eq!(
+            capture(b"C2=hunter2\r", b"", b"\r\nC2=hunter2\r\nOK\r\n> "),
+            "> C2=\\*\\r\n< \\r\\nC2=\\*\\r\\nOK\\r\\n> \n"
+        );
+        assert_eq!(
+            capture(
+                b"C?\r",
+                b"",
+                b"\r\ndrogue,hunter2,3,1,0,192.168.1.174\r\nOK\r\n> "
+            ),
+            "> C?\\r\n< \\r\\ndrogue,\\*,3,1,0,192.168.1.174\\r\\nOK\\r\\n> \n"
+        );
+    }
+
+    #[test]
+    fn test_replay() {
+        let capture = "# Reading the signal strength\n\
+                       > CR\\r\n\
+                       < \\r\\n-52\\r\\nOK\\r\\n> \n\
+                       > C2=\\*\\r\n\
+                       < \\r\\nOK\\r\\n> \n";
+        let mut replay = Replay::new(capture);
+        let mut response = Vec::<u8, 64>::new();
+        block_on(async {
+            replay.write(b"CR\r", b"").await.unwrap();
+            replay
+                .read(true, |byte| response.push(byte).is_ok())
+                .await
+                .unwrap();
+            assert_eq!(&response[..], b"\r\n-52\r\nOK\r\n> ");
+            replay.write(b"C2=secret\r", b"").await.unwrap();
+            assert!(!replay.finished());
+            replay.read(true, |_| true).await.unwrap();
+            assert!(replay.finished());
+            assert_eq!(
+                replay.write(b"CR\r", b"").await,
+                Err(ReplayError::Exhausted)
+            );
+        });
+
+        let mut replay = Replay::new(capture);
+        assert_eq!(
+            block_on(replay.write(b"CI\r", b"")),
+            Err(ReplayError::Mismatch { line: 2 })
+        );
+        let mut replay = Replay::new("> CR\\q\n");
+        assert_eq!(
+            block_on(replay.write(b"CR\r", b"")),
+            Err(ReplayError::Malformed { line: 1 })
+        );
+    }
+
+    #[test]
+    fn test_capture_replays() {
+        let captured = capture(b"S3=3\r", b"a\\\xff", b"\r\n3\r\nOK\r\n> ");
+        let mut replay = Replay::new(&captured);
+        let mut response = Vec::<u8, 64>::new();
+        block_on(async {
+            replay.write(b"S3=3\r", b"a\\\xff").await.unwrap();
+            replay
+                .read(true, |byte| response.push(byte).is_ok())
+                .await
+                .unwrap();
+        });
+        assert_eq!(&response[..], b"\r\n3\r\nOK\r\n> ");
+        assert!(replay.finished());
+    }
+}
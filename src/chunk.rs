@@ -0,0 +1,120 @@
+//! Size of the payload carried by each `S3` command, fixed or found by probing.
+//!
+//! Firmware revisions differ in the largest payload they take in a single `S3`: some take
+//! more than the 1200 bytes the driver sends by default, others reject that much. With
+//! probing on, writes start out with the largest size any revision is known to take and
+//! step down each time the module rejects a chunk, until one goes through. The size found
+//! is kept until probing is switched off.
+//!
+//! The probe rides on the next write, typically the first one after connecting, since
+//! probing with data of its own would send that data to the peer. A rejected chunk is not
+//! sent, so the write goes on with a smaller chunk of the same data.
+
+use crate::{EsWifi, Transport};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embedded_hal::digital::OutputPin;
+
+/// Payload sent in a single `S3` command unless probing finds another size.
+const DEFAULT_CHUNK_SIZE: usize = 1200;
+
+/// Payload sizes tried while probing, largest first.
+const PROBE_SIZES: [usize; 5] = [1460, 1200, 1024, 768, 512];
+
+/// Payload size for `S3` commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) enum ChunkSize {
+    /// The default size, without probing.
+    Fixed,
+    /// Probing, with the size at this index of [`PROBE_SIZES`] up next.
+    Probing(usize),
+    /// The largest size the module was found to take.
+    Probed(usize),
+}
+
+impl ChunkSize {
+    /// Largest payload to send in the next `S3`.
+    pub(crate) fn get(&self) -> usize {
+        match *self {
+            ChunkSize::Fixed => DEFAULT_CHUNK_SIZE,
+            ChunkSize::Probing(index) => PROBE_SIZES[index],
+            ChunkSize::Probed(size) => size,
+        }
+    }
+
+    /// Note that the module took a chunk of `len` bytes.
+    pub(crate) fn accepted(&mut self, len: usize) {
+        if let ChunkSize::Probing(index) = *self {
+            if len == PROBE_SIZES[index] {
+                debug!("Module takes {} bytes per write", len);
+                *self = ChunkSize::Probed(len);
+            }
+        }
+    }
+
+    /// Note that the module rejected a chunk of `len` bytes, returning whether to try again
+    /// with a smaller one.
+    pub(crate) fn rejected(&mut self, len: usize) -> bool {
+        match *self {
+            ChunkSize::Probing(index)
+                if len == PROBE_SIZES[index] && index + 1 < PROBE_SIZES.len() =>
+            {
+                *self = ChunkSize::Probing(index + 1);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Probe for the largest payload the firmware takes per write command, rather than
+    /// sending 1200 bytes at most. Off by default.
+    ///
+    /// The probe takes place during the next write. The size found is kept until probing is
+    /// switched off.
+    pub async fn set_write_probe(&self, enabled: bool) {
+        let mut adapter = self.adapter.lock().await;
+        adapter.chunk_size = match (enabled, adapter.chunk_size) {
+            (false, _) => ChunkSize::Fixed,
+            (true, ChunkSize::Fixed) => ChunkSize::Probing(0),
+            (true, size) => size,
+        };
+    }
+
+    /// Largest payload sent to the module in a single write command, as found by probing
+    /// if it is on.
+    pub async fn write_chunk_size(&self) -> usize {
+        self.adapter.lock().await.chunk_size.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe() {
+        let mut size = ChunkSize::Probing(0);
+        assert_eq!(size.get(), 1460);
+        assert!(size.rejected(1460));
+        // A short chunk going through says nothing about the size.
+        size.accepted(300);
+        assert_eq!(size, ChunkSize::Probing(1));
+        assert!(size.rejected(1200));
+        size.accepted(1024);
+        assert_eq!(size, ChunkSize::Probed(1024));
+        assert!(!size.rejected(1024));
+        assert_eq!(size.get(), 1024);
+
+        let mut size = ChunkSize::Probing(PROBE_SIZES.len() - 1);
+        assert!(!size.rejected(512));
+        assert!(!ChunkSize::Fixed.rejected(1200));
+    }
+}
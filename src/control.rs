@@ -0,0 +1,144 @@
+//! Handle for joining and leaving networks at runtime, shaped like the control objects of
+//! other WiFi drivers such as `cyw43`.
+
+use crate::{
+    parser, CloseReason, Credentials, EsWifi, JoinError, JoinInfo, ModuleError, RecoveryStats,
+    ResetStats, ScanError, ScanResult, Transport,
+};
+use embassy_sync::blocking_mutex::raw::{NoopRawMutex, RawMutex};
+use embassy_time::Duration;
+use embedded_hal::digital::OutputPin;
+
+/// Snapshot of the driver's view of the network, see [`Control::stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NetworkStats {
+    /// Details of the network joined, or `None` while not joined
+    pub join_info: Option<JoinInfo>,
+    /// Number of sockets currently connected
+    pub connected_sockets: usize,
+    /// Recoveries the run loop performed so far
+    pub recoveries: RecoveryStats,
+    /// Restarts of the module by the driver
+    pub resets: ResetStats,
+}
+
+/// Handle for managing the network connection while [`EsWifi::run`] serves sockets, or
+/// without running it at all. Obtained with [`EsWifi::control`].
+///
+/// Handles are cheap to copy and can be given to any task on the executor running the
+/// driver. With the default `M` of [`NoopRawMutex`] they are not `Send` and must stay on
+/// that executor; a driver built with `CriticalSectionRawMutex` hands out handles that can
+/// be given to tasks on other executors or interrupt priorities.
+///
+/// The run loop rejoins with the credentials it was started with whenever it has to
+/// recover the module, replacing a network joined through this handle.
+pub struct Control<'a, TRANSPORT, RESET, WAKEUP, const SOCKETS: usize = 4, M = NoopRawMutex>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    pub(crate) driver: &'a EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>,
+}
+
+impl<'a, TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> Clone
+    for Control<'a, TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> Copy
+    for Control<'a, TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+}
+
+impl<'a, TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M>
+    Control<'a, TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Join the network `ssid` with passphrase `psk`, returning the details of the
+    /// network once an address has been obtained.
+    pub async fn join(&self, ssid: &str, psk: &str) -> Result<JoinInfo, JoinError> {
+        let credentials = Credentials::new(ssid, psk)?;
+        self.driver.join_network(&credentials).await
+    }
+
+    /// Disconnect from the network joined, or stop the access point hosted, closing all
+    /// connections.
+    pub async fn leave(&self) {
+        self.driver.leave_mode(CloseReason::Left).await;
+    }
+
+    /// Forget the addresses kept by the lookup cache, see
+    /// [`EsWifi::set_dns_cache`](crate::EsWifi::set_dns_cache), such as after a service
+    /// moved to another address.
+    pub async fn flush_dns_cache(&self) {
+        self.driver.adapter.lock().await.dns_cache.flush();
+    }
+
+    /// Scan for access points in range, calling `on_result` for each one found.
+    pub async fn scan(&self, on_result: impl FnMut(ScanResult)) -> Result<(), ScanError> {
+        self.driver.scan(on_result).await
+    }
+
+    /// Signal strength of the current association in dBm, or `None` when not associated.
+    pub async fn rssi(&self) -> Option<i8> {
+        self.driver.adapter.lock().await.rssi().await
+    }
+
+    /// Current state of the network connection.
+    pub async fn stats(&self) -> NetworkStats {
+        let adapter = self.driver.adapter.lock().await;
+        NetworkStats {
+            join_info: adapter.join_info,
+            connected_sockets: adapter.socket_pool.connected().len(),
+            recoveries: adapter.recoveries,
+            resets: adapter.resets,
+        }
+    }
+
+    /// Time since the module booted, as kept by the module itself. Firmware that does not
+    /// keep it reports [`ModuleError::Unsupported`].
+    pub async fn module_uptime(&self) -> Result<Duration, ModuleError> {
+        let mut adapter = self.driver.adapter.lock().await;
+        let response = adapter
+            .send_command(command!(4, "ZU?"))
+            .await
+            .map_err(ModuleError::from)?;
+        parser::value(response)
+            .and_then(parser::atoi_usize)
+            .map(|seconds| Duration::from_secs(seconds as u64))
+            .ok_or(ModuleError::Rejected)
+    }
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Handle for joining and leaving networks, scanning and reading the link's state.
+    pub fn control(&self) -> Control<'_, TRANSPORT, RESET, WAKEUP, SOCKETS, M> {
+        Control { driver: self }
+    }
+}
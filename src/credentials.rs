@@ -0,0 +1,83 @@
+//! Network credentials, and where the run loop gets them from.
+
+use crate::{JoinError, Secret};
+use heapless::String;
+
+/// Credentials of a network to join.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Credentials {
+    /// SSID of the network to join.
+    pub ssid: String<32>,
+    /// Passphrase of the network to join.
+    pub password: Secret<64>,
+}
+
+impl Credentials {
+    /// Copy `ssid` and `password`, failing when either is too long for the module.
+    pub fn new(ssid: &str, password: &str) -> Result<Self, JoinError> {
+        let mut credentials = Self {
+            ssid: String::new(),
+            password: Secret::new(password).ok_or(JoinError::InvalidPassword)?,
+        };
+        credentials
+            .ssid
+            .push_str(ssid)
+            .map_err(|_| JoinError::InvalidSsid)?;
+        Ok(credentials)
+    }
+}
+
+// The passphrase is left out, so it does not end up in logs.
+#[cfg(feature = "defmt")]
+impl defmt::Format for Credentials {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "Credentials {{ ssid: {} }}", self.ssid.as_str())
+    }
+}
+
+/// Source of the credentials used by [`EsWifi::run_with`](crate::EsWifi::run_with).
+///
+/// Credentials are requested each time the module (re)joins the network and dropped
+/// right after, so they can be kept in a secure element or encrypted flash in between.
+/// Synchronous sources can be plain closures returning `Option<Credentials>`.
+#[allow(async_fn_in_trait)]
+pub trait CredentialsProvider {
+    /// Credentials of the network to join, or `None` if they are not available.
+    async fn credentials(&mut self) -> Option<Credentials>;
+}
+
+impl CredentialsProvider for Credentials {
+    async fn credentials(&mut self) -> Option<Credentials> {
+        Some(self.clone())
+    }
+}
+
+impl<F> CredentialsProvider for F
+where
+    F: FnMut() -> Option<Credentials>,
+{
+    async fn credentials(&mut self) -> Option<Credentials> {
+        self()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credentials_too_long() {
+        let ssid = "a".repeat(33);
+        let password = "p".repeat(65);
+        assert!(matches!(
+            Credentials::new(&ssid, "secret"),
+            Err(JoinError::InvalidSsid)
+        ));
+        assert!(matches!(
+            Credentials::new("home", &password),
+            Err(JoinError::InvalidPassword)
+        ));
+        assert!(Credentials::new("home", "secret").is_ok());
+    }
+}
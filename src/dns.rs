@@ -0,0 +1,270 @@
+//! Host name lookups through the module's resolver.
+
+use crate::{parser, DriverState, EsWifi, Transport};
+use core::net::IpAddr;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_time::{Duration, Instant};
+use embedded_hal::digital::OutputPin;
+use embedded_nal_async::{AddrType, Dns};
+use heapless::{String, Vec};
+
+/// Longest host name accepted for lookups, as limited by DNS.
+const MAX_HOSTNAME: usize = 253;
+
+/// Number of names kept in the lookup cache.
+const CACHE_SIZE: usize = 4;
+
+/// Longest host name kept in the lookup cache. Longer names are looked up every time.
+const MAX_CACHED_HOSTNAME: usize = 64;
+
+/// Host name lookup errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DnsError {
+    /// Host name is empty or too long
+    InvalidHostname,
+    /// The name could not be resolved
+    NotFound,
+    /// The module only resolves names to IPv4 addresses
+    Unsupported,
+    /// Communication with the module failed
+    Transport,
+}
+
+/// Cache of recent host name lookups, see [`EsWifi::set_dns_cache`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DnsCacheConfig {
+    /// How long an address is used without looking the name up again. The module does
+    /// not report the TTL of its answers, so this applies to every name.
+    #[cfg_attr(feature = "serde", serde(with = "crate::millis"))]
+    pub ttl: Duration,
+    /// Time a connection to an address past its TTL is given by
+    /// [`EsWifi::connect_host`] before the name is looked up again.
+    #[cfg_attr(feature = "serde", serde(with = "crate::millis"))]
+    pub head_start: Duration,
+}
+
+impl Default for DnsCacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(60),
+            head_start: Duration::from_millis(300),
+        }
+    }
+}
+
+/// Addresses of names looked up recently. Entries past their TTL are kept until room is
+/// needed, as a first guess for [`EsWifi::connect_host`].
+pub(crate) struct DnsCache {
+    config: Option<DnsCacheConfig>,
+    entries: Vec<CacheEntry, CACHE_SIZE>,
+}
+
+/// Address found in the lookup cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) struct Cached {
+    pub(crate) ip: IpAddr,
+    /// Whether the address is still within its TTL.
+    pub(crate) fresh: bool,
+}
+
+struct CacheEntry {
+    host: String<MAX_CACHED_HOSTNAME>,
+    ip: IpAddr,
+    expires: Instant,
+}
+
+impl DnsCache {
+    pub(crate) const fn new() -> Self {
+        Self {
+            config: None,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Head start for connections to addresses past their TTL, while the cache is on.
+    pub(crate) fn head_start(&self) -> Option<Duration> {
+        self.config.map(|config| config.head_start)
+    }
+
+    /// Address `host` last resolved to.
+    pub(crate) fn get(&self, host: &str, now: Instant) -> Option<Cached> {
+        self.entries
+            .iter()
+            .find(|entry| entry.host.eq_ignore_ascii_case(host))
+            .map(|entry| Cached {
+                ip: entry.ip,
+                fresh: entry.expires > now,
+            })
+    }
+
+    /// Remember that `host` resolved to `ip`, making room by dropping the entry that
+    /// expires first.
+    fn insert(&mut self, host: &str, ip: IpAddr, now: Instant) {
+        let Some(config) = self.config else {
+            return;
+        };
+        let mut name = String::new();
+        if name.push_str(host).is_err() {
+            return;
+        }
+        self.entries
+            .retain(|entry| !entry.host.eq_ignore_ascii_case(host));
+        if self.entries.is_full() {
+            if let Some(oldest) = (0..self.entries.len()).min_by_key(|&i| self.entries[i].expires) {
+                self.entries.swap_remove(oldest);
+            }
+        }
+        self.entries
+            .push(CacheEntry {
+                host: name,
+                ip,
+                expires: now + config.ttl,
+            })
+            .ok();
+    }
+
+    /// Forget every name looked up.
+    pub(crate) fn flush(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl<TRANSPORT, const SOCKETS: usize> DriverState<TRANSPORT, SOCKETS>
+where
+    TRANSPORT: Transport,
+{
+    async fn resolve(&mut self, host: &str) -> Result<IpAddr, DnsError> {
+        if host.is_empty() || host.len() > MAX_HOSTNAME {
+            return Err(DnsError::InvalidHostname);
+        }
+        if let Some(Cached { ip, fresh: true }) = self.dns_cache.get(host, Instant::now()) {
+            trace!("Resolved {} from cache", host);
+            return Ok(ip);
+        }
+        let response = self
+            .send_command(command!(264, "D0=", host))
+            .await
+            .map_err(|_| DnsError::Transport)?;
+        let ip = IpAddr::V4(parser::host_address(response).ok_or(DnsError::NotFound)?);
+        trace!("Resolved {}", host);
+        self.dns_cache.insert(host, ip, Instant::now());
+        Ok(ip)
+    }
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Keep the addresses of up to four recently looked up names, so frequent reconnects
+    /// to the same host do not each wait for the module's resolver. Names longer than 64
+    /// bytes are not kept. Disabled by default.
+    ///
+    /// Changing the setting forgets the names kept so far.
+    pub async fn set_dns_cache(&self, config: Option<DnsCacheConfig>) {
+        let mut adapter = self.adapter.lock().await;
+        adapter.dns_cache.config = config;
+        adapter.dns_cache.flush();
+    }
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> Dns
+    for EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    type Error = DnsError;
+
+    async fn get_host_by_name(&self, host: &str, addr_type: AddrType) -> Result<IpAddr, DnsError> {
+        if matches!(addr_type, AddrType::IPv6) {
+            return Err(DnsError::Unsupported);
+        }
+        self.adapter.lock().await.resolve(host).await
+    }
+
+    async fn get_host_by_address(
+        &self,
+        _addr: IpAddr,
+        _result: &mut [u8],
+    ) -> Result<usize, DnsError> {
+        Err(DnsError::Unsupported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(ttl: u64) -> DnsCache {
+        DnsCache {
+            config: Some(DnsCacheConfig {
+                ttl: Duration::from_secs(ttl),
+                ..DnsCacheConfig::default()
+            }),
+            entries: Vec::new(),
+        }
+    }
+
+    fn ip(cache: &DnsCache, host: &str, now: Instant) -> Option<IpAddr> {
+        cache.get(host, now).map(|cached| cached.ip)
+    }
+
+    #[test]
+    fn test_cache_expiry() {
+        let now = Instant::from_secs(100);
+        let ip = IpAddr::from([192, 0, 2, 1]);
+        let mut cache = cache(60);
+        cache.insert("broker.example.com", ip, now);
+        let fresh = Some(Cached { ip, fresh: true });
+        assert_eq!(cache.get("Broker.Example.com", now), fresh);
+        let later = now + Duration::from_secs(59);
+        assert_eq!(cache.get("broker.example.com", later), fresh);
+        // Kept past the TTL, as a first guess.
+        let later = now + Duration::from_secs(60);
+        let stale = Some(Cached { ip, fresh: false });
+        assert_eq!(cache.get("broker.example.com", later), stale);
+
+        let mut disabled = DnsCache::new();
+        disabled.insert("broker.example.com", ip, now);
+        assert_eq!(disabled.get("broker.example.com", now), None);
+    }
+
+    #[test]
+    fn test_cache_eviction() {
+        let now = Instant::from_secs(100);
+        let mut cache = cache(60);
+        let hosts = [
+            "a.example",
+            "b.example",
+            "c.example",
+            "d.example",
+            "e.example",
+        ];
+        for (i, host) in hosts.iter().enumerate() {
+            let ip = IpAddr::from([192, 0, 2, i as u8]);
+            cache.insert(host, ip, now + Duration::from_secs(i as u64));
+        }
+        // The entry expiring first made room for the last one.
+        assert_eq!(ip(&cache, "a.example", now), None);
+        assert_eq!(
+            ip(&cache, "e.example", now),
+            Some(IpAddr::from([192, 0, 2, 4]))
+        );
+        // Names too long to keep are left out.
+        cache.flush();
+        let long = [b'a'; MAX_CACHED_HOSTNAME + 1];
+        let long = core::str::from_utf8(&long).unwrap();
+        cache.insert(long, IpAddr::from([192, 0, 2, 9]), now);
+        assert_eq!(ip(&cache, long, now), None);
+    }
+}
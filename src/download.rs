@@ -0,0 +1,100 @@
+//! Streaming known-length payloads, such as firmware images, from a socket into a sink.
+
+use crate::{
+    ConnectionState, EsWifiSocket, SocketError, Transport, READ_CHUNK_SIZE, RECV_POLL_INTERVAL,
+};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_time::Timer;
+use embedded_hal::digital::OutputPin;
+use embedded_io_async::Read;
+
+/// Consecutive read errors tolerated before a download gives up.
+const READ_RETRIES: u8 = 3;
+
+/// Errors streaming a payload with [`EsWifiSocket::read_to_writer`].
+///
+/// Each variant carries the number of bytes already written to the sink, so the
+/// transfer can be resumed from that offset, for example with an HTTP range request.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DownloadError<E> {
+    /// Reading from the socket kept failing
+    Socket {
+        /// Bytes written to the sink before the failure
+        written: usize,
+        /// Last error reported by the socket
+        error: SocketError,
+    },
+    /// Writing to the sink failed
+    Sink {
+        /// Bytes written to the sink before the failure
+        written: usize,
+        /// Error reported by the sink
+        error: E,
+    },
+    /// The connection was closed before the whole payload arrived
+    Closed {
+        /// Bytes written to the sink before the connection was closed
+        written: usize,
+    },
+}
+
+impl<E> DownloadError<E> {
+    /// Bytes written to the sink before the transfer failed.
+    pub fn written(&self) -> usize {
+        match self {
+            Self::Socket { written, .. }
+            | Self::Sink { written, .. }
+            | Self::Closed { written } => *written,
+        }
+    }
+}
+
+impl<'a, TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M>
+    EsWifiSocket<'a, TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport + 'a,
+    RESET: OutputPin + 'a,
+    WAKEUP: OutputPin + 'a,
+    M: RawMutex,
+{
+    /// Stream the next `len` bytes received into `sink`, one module read at a time.
+    ///
+    /// Read errors are retried a few times before giving up, as a single failed read
+    /// does not mean the connection is lost.
+    pub async fn read_to_writer<W: embedded_io::Write>(
+        &mut self,
+        mut sink: W,
+        len: usize,
+    ) -> Result<(), DownloadError<W::Error>> {
+        let mut buf = [0; READ_CHUNK_SIZE];
+        let mut written = 0;
+        let mut failures = 0;
+        while written < len {
+            let chunk = core::cmp::min(buf.len(), len - written);
+            match self.read(&mut buf[..chunk]).await {
+                Ok(0) => match self.state().await {
+                    Ok(ConnectionState::Connected) => Timer::after(RECV_POLL_INTERVAL).await,
+                    Ok(_) | Err(SocketError::SocketClosed) => {
+                        return Err(DownloadError::Closed { written })
+                    }
+                    Err(error) => return Err(DownloadError::Socket { written, error }),
+                },
+                Ok(received) => {
+                    failures = 0;
+                    sink.write_all(&buf[..received])
+                        .map_err(|error| DownloadError::Sink { written, error })?;
+                    written += received;
+                }
+                Err(SocketError::ReadError) if failures < READ_RETRIES => {
+                    failures += 1;
+                    debug!("[{}] Read failed, retrying download", self.handle);
+                    Timer::after(RECV_POLL_INTERVAL).await;
+                }
+                Err(SocketError::SocketClosed) => return Err(DownloadError::Closed { written }),
+                Err(error) => return Err(DownloadError::Socket { written, error }),
+            }
+        }
+        Ok(())
+    }
+}
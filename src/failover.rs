@@ -0,0 +1,129 @@
+//! Connecting to whichever of several addresses of a service is up.
+
+use crate::dns::Cached;
+use crate::{ConnectConfig, EsWifi, EsWifiSocket, Protocol, SocketError, Transport};
+use core::net::SocketAddr;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_time::{Duration, Instant};
+use embedded_hal::digital::OutputPin;
+use embedded_nal_async::{AddrType, Dns};
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Connect to the first of `remotes` that accepts the connection, trying them in
+    /// order and giving each `attempt_timeout`.
+    ///
+    /// Any failure moves on to the next address, so a refused connection does not stop
+    /// the search. If all of them fail, the last failure is returned. An address the
+    /// module has not connected to in time fails with [`SocketError::ConnectTimeout`];
+    /// the module cannot be interrupted though, so the next address is only tried once
+    /// it gives up on its own.
+    pub async fn connect_any(
+        &self,
+        remotes: &[SocketAddr],
+        attempt_timeout: Duration,
+    ) -> Result<EsWifiSocket<'_, TRANSPORT, RESET, WAKEUP, SOCKETS, M>, SocketError> {
+        let config = ConnectConfig {
+            timeout: attempt_timeout,
+            max_attempts: Some(1),
+            ..self.connect_config
+        };
+        let mut last_error = SocketError::InvalidAddress;
+        for &remote in remotes {
+            let handle = self.new_socket().await?;
+            let mut socket = self.socket(handle, Protocol::Tcp);
+            match socket
+                .connect(remote, config, None, Some(attempt_timeout))
+                .await
+            {
+                Ok(()) => return Ok(socket),
+                Err(e) => {
+                    debug!("Connecting to candidate address failed: {:?}", e);
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Connect to `host` on `port`, falling back to the `fallback` addresses in order
+    /// when the name does not resolve or the address it resolves to does not accept the
+    /// connection. Each address gets `attempt_timeout`.
+    ///
+    /// With the lookup cache on, see [`set_dns_cache`](Self::set_dns_cache), an address
+    /// of `host` past its TTL is tried first, with the configured head start, before the
+    /// name is looked up again. The module handles one command at a time, so rather than
+    /// racing the lookup, the cached address gets its head start on its own, and is given
+    /// up on once it runs out. When the service has moved, this costs the head start, or
+    /// as long as the module takes to give up on an address that does not answer;
+    /// otherwise it saves the lookup.
+    pub async fn connect_host(
+        &self,
+        host: &str,
+        port: u16,
+        fallback: &[SocketAddr],
+        attempt_timeout: Duration,
+    ) -> Result<EsWifiSocket<'_, TRANSPORT, RESET, WAKEUP, SOCKETS, M>, SocketError> {
+        let mut last_error = SocketError::HostUnreachable;
+        let stale = {
+            let adapter = self.adapter.lock().await;
+            match adapter.dns_cache.get(host, Instant::now()) {
+                Some(Cached { ip, fresh: false }) => adapter
+                    .dns_cache
+                    .head_start()
+                    .map(|head_start| (head_start, ip)),
+                _ => None,
+            }
+        };
+        if let Some((head_start, ip)) = stale {
+            let remote = SocketAddr::new(ip, port);
+            match self
+                .connect_any(&[remote], head_start.min(attempt_timeout))
+                .await
+            {
+                Ok(mut socket) => {
+                    socket.timings.dns = Some(Duration::from_ticks(0));
+                    return Ok(socket);
+                }
+                Err(e) => {
+                    debug!("Cached address of {} failed: {:?}", host, e);
+                    last_error = e;
+                }
+            }
+        }
+        let mut resolved = None;
+        let start = Instant::now();
+        let lookup = self.get_host_by_name(host, AddrType::IPv4).await;
+        let dns = Some(start.elapsed());
+        match lookup {
+            Ok(ip) => {
+                let remote = SocketAddr::new(ip, port);
+                match self.connect_any(&[remote], attempt_timeout).await {
+                    Ok(mut socket) => {
+                        socket.timings.dns = dns;
+                        return Ok(socket);
+                    }
+                    Err(e) => last_error = e,
+                }
+                resolved = Some(remote);
+            }
+            Err(e) => warn!("Unable to resolve {}: {:?}", host, e),
+        }
+        // The resolved address already failed, so it is not tried again.
+        for &remote in fallback.iter().filter(|&&remote| Some(remote) != resolved) {
+            match self.connect_any(&[remote], attempt_timeout).await {
+                Ok(mut socket) => {
+                    socket.timings.dns = dns;
+                    return Ok(socket);
+                }
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+}
@@ -0,0 +1,257 @@
+//! Faults injected into the link to the module, for testing how applications and the
+//! driver itself recover from them.
+
+use crate::transport::Transport;
+use core::future::pending;
+
+/// Stray byte the module sends while it has no data ready.
+const NAK: u8 = 0x15;
+
+/// How often each kind of fault is injected, in faults per thousand transfers.
+///
+/// Every write and every read of a response is a transfer. Rates of 1000 or more inject
+/// the fault every time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FaultRates {
+    /// Transfers failing with [`FaultError::Injected`], as if the bus reported an error
+    pub error: u16,
+    /// Transfers that never complete, for exercising the driver's timeouts
+    pub timeout: u16,
+    /// Responses with a stray NAK in front, as sent by a module that is not ready yet
+    pub nak: u16,
+    /// Responses with one of their first bytes garbled
+    pub malformed: u16,
+}
+
+/// Number of faults of each kind injected so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FaultStats {
+    /// Transfers failed
+    pub errors: u32,
+    /// Transfers left hanging
+    pub timeouts: u32,
+    /// NAKs inserted into responses
+    pub naks: u32,
+    /// Responses garbled
+    pub malformed: u32,
+}
+
+/// Errors of a [`FaultInjector`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FaultError<E> {
+    /// Error reported by the wrapped transport
+    Transport(E),
+    /// Error injected in place of a transfer
+    Injected,
+}
+
+/// [`Transport`] wrapper injecting faults into the transfers of another at random, at
+/// the configured [`FaultRates`].
+///
+/// Faults are drawn from a pseudo-random sequence fixed by the seed, so a failing run
+/// can be repeated exactly.
+pub struct FaultInjector<T> {
+    transport: T,
+    rates: FaultRates,
+    state: u32,
+    stats: FaultStats,
+}
+
+impl<T: Transport> FaultInjector<T> {
+    /// Wrap `transport`, drawing faults from the sequence started by `seed`.
+    pub fn new(transport: T, rates: FaultRates, seed: u32) -> Self {
+        Self {
+            transport,
+            rates,
+            // Xorshift never leaves zero.
+            state: if seed == 0 { 0x9e37_79b9 } else { seed },
+            stats: FaultStats::default(),
+        }
+    }
+
+    /// Change how often faults are injected from the next transfer on.
+    pub fn set_rates(&mut self, rates: FaultRates) {
+        self.rates = rates;
+    }
+
+    /// Faults injected so far.
+    pub fn stats(&self) -> FaultStats {
+        self.stats
+    }
+
+    /// The wrapped transport.
+    pub fn inner(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    fn next(&mut self) -> u32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state
+    }
+
+    /// Whether a fault occurring `rate` times per thousand happens this time.
+    fn roll(&mut self, rate: u16) -> bool {
+        rate > 0 && self.next() % 1000 < rate as u32
+    }
+
+    /// Fail or hang the transfer about to start, if one of those faults is due.
+    async fn disrupt(&mut self) -> Result<(), FaultError<T::Error>> {
+        if self.roll(self.rates.error) {
+            self.stats.errors += 1;
+            return Err(FaultError::Injected);
+        }
+        if self.roll(self.rates.timeout) {
+            self.stats.timeouts += 1;
+            pending::<()>().await;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Transport> Transport for FaultInjector<T> {
+    type Error = FaultError<T::Error>;
+
+    async fn write(&mut self, command: &[u8], data: &[u8]) -> Result<(), Self::Error> {
+        self.disrupt().await?;
+        self.transport
+            .write(command, data)
+            .await
+            .map_err(FaultError::Transport)
+    }
+
+    async fn read(
+        &mut self,
+        room: bool,
+        mut push: impl FnMut(u8) -> bool,
+    ) -> Result<(), Self::Error> {
+        self.disrupt().await?;
+        let mut nak = self.roll(self.rates.nak);
+        if nak {
+            self.stats.naks += 1;
+        }
+        // Responses start with a line ending, so garbling one of the first bytes breaks
+        // them for sure.
+        let garble = if self.roll(self.rates.malformed) {
+            self.stats.malformed += 1;
+            Some(self.next() as usize % 4)
+        } else {
+            None
+        };
+        let mut pos = 0;
+        self.transport
+            .read(room, |byte| {
+                if core::mem::take(&mut nak) {
+                    push(NAK);
+                }
+                let byte = if garble == Some(pos) { b'#' } else { byte };
+                pos += 1;
+                push(byte)
+            })
+            .await
+            .map_err(FaultError::Transport)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+    use embassy_futures::block_on;
+    use heapless::Vec;
+
+    /// Transport answering every read with `OK`.
+    struct OkModule;
+
+    impl Transport for OkModule {
+        type Error = Infallible;
+
+        async fn write(&mut self, _command: &[u8], _data: &[u8]) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        async fn read(
+            &mut self,
+            _room: bool,
+            mut push: impl FnMut(u8) -> bool,
+        ) -> Result<(), Infallible> {
+            for &byte in b"\r\nOK\r\n> " {
+                push(byte);
+            }
+            Ok(())
+        }
+    }
+
+    fn read(
+        transport: &mut FaultInjector<OkModule>,
+    ) -> Result<Vec<u8, 16>, FaultError<Infallible>> {
+        let mut response = Vec::new();
+        block_on(transport.read(true, |byte| response.push(byte).is_ok()))?;
+        Ok(response)
+    }
+
+    #[test]
+    fn test_no_faults() {
+        let mut transport = FaultInjector::new(OkModule, FaultRates::default(), 1);
+        for _ in 0..100 {
+            assert_eq!(read(&mut transport).unwrap(), b"\r\nOK\r\n> ");
+        }
+        assert_eq!(transport.stats(), FaultStats::default());
+    }
+
+    #[test]
+    fn test_every_fault() {
+        let rates = FaultRates {
+            error: 1000,
+            ..FaultRates::default()
+        };
+        let mut transport = FaultInjector::new(OkModule, rates, 1);
+        assert_eq!(read(&mut transport), Err(FaultError::Injected));
+        assert_eq!(
+            block_on(transport.write(b"AT\r", &[])),
+            Err(FaultError::Injected)
+        );
+
+        transport.set_rates(FaultRates {
+            nak: 1000,
+            ..FaultRates::default()
+        });
+        assert_eq!(read(&mut transport).unwrap(), b"\x15\r\nOK\r\n> ");
+
+        transport.set_rates(FaultRates {
+            malformed: 1000,
+            ..FaultRates::default()
+        });
+        let response = read(&mut transport).unwrap();
+        assert_eq!(response.len(), 8);
+        assert_eq!(response.iter().filter(|&&byte| byte == b'#').count(), 1);
+
+        assert_eq!(
+            transport.stats(),
+            FaultStats {
+                errors: 2,
+                timeouts: 0,
+                naks: 1,
+                malformed: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_rate() {
+        let rates = FaultRates {
+            error: 100,
+            ..FaultRates::default()
+        };
+        let mut transport = FaultInjector::new(OkModule, rates, 42);
+        let failed = (0..10_000)
+            .filter(|_| read(&mut transport).is_err())
+            .count();
+        assert!((800..1200).contains(&failed), "{} failed", failed);
+    }
+}
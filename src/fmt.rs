@@ -1,5 +1,5 @@
 #![macro_use]
-#![allow(unused_macros)]
+#![allow(unused_macros, dead_code)]
 
 #[cfg(all(feature = "defmt", feature = "log"))]
 compile_error!("You may not enable both `defmt` and `log` features.");
@@ -106,11 +106,11 @@ macro_rules! panic {
 macro_rules! trace {
     ($s:literal $(, $x:expr)* $(,)?) => {
         {
-            #[cfg(feature = "log")]
+            #[cfg(all(feature = "log", not(feature = "tiny")))]
             ::log::trace!($s $(, $x)*);
-            #[cfg(feature = "defmt")]
+            #[cfg(all(feature = "defmt", not(feature = "tiny")))]
             ::defmt::trace!($s $(, $x)*);
-            #[cfg(not(any(feature = "log", feature="defmt")))]
+            #[cfg(any(feature = "tiny", not(any(feature = "log", feature="defmt"))))]
             let _ = ($( & $x ),*);
         }
     };
@@ -119,11 +119,11 @@ macro_rules! trace {
 macro_rules! debug {
     ($s:literal $(, $x:expr)* $(,)?) => {
         {
-            #[cfg(feature = "log")]
+            #[cfg(all(feature = "log", not(feature = "tiny")))]
             ::log::debug!($s $(, $x)*);
-            #[cfg(feature = "defmt")]
+            #[cfg(all(feature = "defmt", not(feature = "tiny")))]
             ::defmt::debug!($s $(, $x)*);
-            #[cfg(not(any(feature = "log", feature="defmt")))]
+            #[cfg(any(feature = "tiny", not(any(feature = "log", feature="defmt"))))]
             let _ = ($( & $x ),*);
         }
     };
@@ -0,0 +1,65 @@
+//! Application-level heartbeats, keeping idle connections alive through NAT routers.
+
+use crate::{EsWifi, EsWifiSocket, Message, Transport};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_time::{Duration, Instant};
+use embedded_hal::digital::OutputPin;
+
+/// Payload written to a connection after it has been idle for `interval`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Heartbeat {
+    /// Time without traffic after which the payload is written.
+    pub interval: Duration,
+    /// Bytes written, which the peer is expected to ignore.
+    pub payload: &'static [u8],
+}
+
+impl<'a, TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M>
+    EsWifiSocket<'a, TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport + 'a,
+    RESET: OutputPin + 'a,
+    WAKEUP: OutputPin + 'a,
+    M: RawMutex,
+{
+    /// Have the run loop write `heartbeat`'s payload whenever the connection has been idle
+    /// for its interval, so routers do not drop it. Disabled by default.
+    pub async fn set_heartbeat(&self, heartbeat: Option<Heartbeat>) {
+        self.adapter
+            .adapter
+            .lock()
+            .await
+            .socket_pool
+            .set_heartbeat(self.handle, heartbeat);
+        // Have the run loop pick up the new schedule.
+        self.control.send(Message::Heartbeat).await;
+    }
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Write the heartbeats that are due, returning when the next one is.
+    pub(crate) async fn send_heartbeats(&self) -> Option<Instant> {
+        let mut adapter = self.adapter.lock().await;
+        for (handle, heartbeat) in adapter.socket_pool.due_heartbeats(Instant::now()) {
+            trace!("[{}] Sending heartbeat", handle);
+            if let Err(e) = adapter.write(handle, heartbeat.payload, &mut |_| {}).await {
+                warn!("Error sending heartbeat on {}: {:?}", handle, e);
+            }
+            // Also postpones the next attempt when the module's buffer was full.
+            adapter.socket_pool.touch(handle);
+        }
+        adapter.socket_pool.next_heartbeat()
+    }
+
+    /// When the next heartbeat is due, if any connection has one.
+    pub(crate) async fn next_heartbeat(&self) -> Option<Instant> {
+        self.adapter.lock().await.socket_pool.next_heartbeat()
+    }
+}
@@ -0,0 +1,160 @@
+//! The last few commands sent to the module and their responses, kept at all times so a
+//! failure in the field can be looked into after the fact, without tracing having been
+//! switched on beforehand.
+
+use crate::{CommandId, Control, DriverState, Transport};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_time::Instant;
+use embedded_hal::digital::OutputPin;
+use heapless::{Deque, Vec};
+
+/// Number of exchanges kept.
+pub const HISTORY_SIZE: usize = 8;
+
+/// Bytes kept of each command.
+const COMMAND_SIZE: usize = 24;
+
+/// Bytes kept of each response.
+const RESPONSE_SIZE: usize = 48;
+
+/// A command sent to the module and its response, see [`Control::command_history`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HistoryEntry {
+    /// When the command was sent
+    pub at: Instant,
+    /// Start of the command. Only the command code is kept of commands holding a secret.
+    pub command: Vec<u8, COMMAND_SIZE>,
+    /// Start of the response, or `None` if none was received or it carried socket data
+    pub response: Option<Vec<u8, RESPONSE_SIZE>>,
+    /// Whether the command or its response was cut short, or left out for holding a
+    /// secret
+    pub truncated: bool,
+}
+
+/// Ring of the last exchanges with the module.
+pub(crate) struct History {
+    entries: Deque<HistoryEntry, HISTORY_SIZE>,
+    /// Whether the exchange under way holds a secret.
+    withholding: bool,
+}
+
+impl History {
+    pub(crate) const fn new() -> Self {
+        Self {
+            entries: Deque::new(),
+            withholding: false,
+        }
+    }
+
+    /// Leave out the exchanges that follow, until called again with `false`.
+    pub(crate) fn withhold(&mut self, withholding: bool) {
+        self.withholding = withholding;
+    }
+
+    /// Note `command` being sent, dropping the oldest exchange when full.
+    pub(crate) fn command(&mut self, command: &[u8]) {
+        if self.entries.is_full() {
+            self.entries.pop_front();
+        }
+        let id = CommandId::of(command);
+        let (kept, truncated) = if self.withholding {
+            (id.as_str().as_bytes(), true)
+        } else {
+            let len = command.len().min(COMMAND_SIZE);
+            (&command[..len], len < command.len())
+        };
+        let entry = HistoryEntry {
+            at: Instant::now(),
+            command: Vec::from_slice(kept).unwrap_or_default(),
+            response: None,
+            truncated,
+        };
+        self.entries.push_back(entry).ok();
+    }
+
+    /// Note `response` to the last command sent.
+    pub(crate) fn response(&mut self, response: &[u8]) {
+        let withholding = self.withholding;
+        if let Some(entry) = self.entries.back_mut() {
+            let len = if withholding {
+                0
+            } else {
+                response.len().min(RESPONSE_SIZE)
+            };
+            entry.response = Vec::from_slice(&response[..len]).ok();
+            entry.truncated |= len < response.len();
+        }
+    }
+
+    pub(crate) fn entries(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries.iter()
+    }
+}
+
+impl<TRANSPORT, const SOCKETS: usize> DriverState<TRANSPORT, SOCKETS>
+where
+    TRANSPORT: Transport,
+{
+    fn command_history(&self) -> Vec<HistoryEntry, HISTORY_SIZE> {
+        self.history.entries().cloned().collect()
+    }
+}
+
+impl<'a, TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M>
+    Control<'a, TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// The last commands sent to the module and their responses, oldest first, for
+    /// diagnosing an error after it happened.
+    pub async fn command_history(&self) -> Vec<HistoryEntry, HISTORY_SIZE> {
+        self.driver.adapter.lock().await.command_history()
+    }
+
+    /// Log the last commands sent to the module and their responses, oldest first.
+    pub async fn dump_command_history(&self) {
+        for entry in self.command_history().await {
+            info!("At {} ms:", entry.at.as_millis());
+            crate::trace::log_exchange(">", &entry.command);
+            match entry.response {
+                Some(response) => crate::trace::log_exchange("<", &response),
+                None => info!("< (no response)"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let mut history = History::new();
+        for i in 0..HISTORY_SIZE + 2 {
+            history.command(if i % 2 == 0 { b"CR\r" } else { b"C?\r" });
+            history.response(b"\r\nOK\r\n> ");
+        }
+        assert_eq!(history.entries().count(), HISTORY_SIZE);
+        assert_eq!(&history.entries().next().unwrap().command[..], b"CR\r");
+
+        history.command(&[b'x'; 40]);
+        let entry = history.entries().last().unwrap();
+        assert_eq!(entry.command.len(), COMMAND_SIZE);
+        assert_eq!(entry.response, None);
+        assert!(entry.truncated);
+
+        history.withhold(true);
+        history.command(b"C2=secret\r");
+        history.response(b"C2=secret\r\r\nOK\r\n> ");
+        history.withhold(false);
+        let entry = history.entries().last().unwrap();
+        assert_eq!(&entry.command[..], b"C2");
+        assert_eq!(entry.response.as_deref(), Some(&b""[..]));
+        assert!(entry.truncated);
+    }
+}
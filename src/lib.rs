@@ -1,33 +1,112 @@
 #![cfg_attr(not(feature = "std"), no_std)]
-#![feature(async_fn_in_trait)]
-#![feature(impl_trait_projections)]
-#![allow(incomplete_features)]
 #![doc = include_str!("../README.md")]
 #![warn(missing_docs)]
+// Macro modules go first, so their macros are in scope for the rest of the crate.
 mod fmt;
-mod parser;
+#[macro_use]
+mod proto;
+
+mod ap;
+#[cfg(feature = "blocking")]
+mod blocking;
+mod captive;
+#[cfg(feature = "capture")]
+mod capture;
+mod chunk;
+mod control;
+mod credentials;
+mod dns;
+mod download;
+mod failover;
+#[cfg(feature = "test-hooks")]
+mod faults;
+mod heartbeat;
+mod history;
+#[cfg(feature = "wifi-manager")]
+mod manager;
+mod mdns;
+#[cfg(feature = "serde")]
+mod millis;
+mod mode;
+mod module;
+mod monitor;
+mod pool;
+mod power;
+#[cfg(feature = "http-provisioning")]
+mod provisioning;
+mod proxy;
+mod rate;
+mod recovery;
+mod reserve;
+mod scan;
+mod secret;
+mod show;
+mod sleep;
 mod socket_pool;
-
-use socket_pool::SocketPool;
-
-use embedded_hal::digital::{InputPin, OutputPin};
+mod span;
+mod trace;
+mod transport;
+mod wake;
+
+pub use ap::{ApConfig, ApError, DhcpServerConfig, Station};
+#[cfg(feature = "blocking")]
+pub use blocking::BlockingStack;
+#[cfg(feature = "capture")]
+pub use capture::{Recorder, Replay, ReplayError};
+use chunk::ChunkSize;
+pub use control::{Control, NetworkStats};
+pub use core::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+pub use credentials::{Credentials, CredentialsProvider};
+use dns::DnsCache;
+pub use dns::{DnsCacheConfig, DnsError};
+pub use download::DownloadError;
+#[cfg(feature = "test-hooks")]
+pub use faults::{FaultError, FaultInjector, FaultRates, FaultStats};
+pub use heartbeat::Heartbeat;
+use history::History;
+pub use history::{HistoryEntry, HISTORY_SIZE};
+#[cfg(feature = "wifi-manager")]
+pub use manager::{LinkEvent, WifiManager, WifiManagerError};
+pub use mode::WifiMode;
+pub use module::{Antenna, HealthReport, ModuleError, Settings};
+pub use monitor::{AssociationInfo, LinkMonitorConfig, ProbeConfig, RoamingConfig};
+pub use pool::{PooledConnection, TcpClientPool};
+pub use power::PowerSaveConfig;
+#[cfg(feature = "parser")]
+#[doc(hidden)]
+pub use proto::parser;
+#[cfg(not(feature = "parser"))]
+use proto::parser;
+pub use proxy::{ProxyConfig, ProxyCredentials};
+pub use rate::RateLimit;
+use rate::TokenBucket;
+pub use recovery::{RecoveryLevel, RecoveryStats, ResetStats};
+pub use reserve::SocketReservation;
+pub use scan::{ScanError, ScanFilter, ScanResult, Security};
+pub use secret::Secret;
+use secret::Wipe;
+pub use show::{ConnectionSettings, SocketSettings};
+pub use sleep::{HostResume, HostSleep};
+pub use socket_pool::SocketHandle;
+use socket_pool::{SocketPool, SocketState};
+use span::Span;
+pub use transport::{SpiError, SpiTransport, Transport, UartTransport};
+pub use wake::{WakePattern, WAKE_PATTERNS};
+
+use embedded_hal::digital::{self, Error as _, InputPin, OutputPin};
 
 use {
-    core::fmt::{Debug, Write as FmtWrite},
+    core::fmt::Debug,
     embassy_sync::{
-        blocking_mutex::raw::NoopRawMutex,
+        blocking_mutex::raw::{NoopRawMutex, RawMutex},
         channel::{Channel, DynamicSender},
+        mutex::Mutex,
     },
-    embassy_time::{block_for, with_timeout, Duration, Instant, Timer},
+    embassy_time::{with_deadline, with_timeout, Duration, Instant, Timer},
     embedded_hal_async::{digital::Wait, spi::*},
-    embedded_nal_async::*,
-    futures_intrusive::sync::LocalMutex,
-    heapless::String,
-    parser::{CloseResponse, ConnectResponse, JoinResponse, ReadResponse, WriteResponse},
+    heapless::{String, Vec},
 };
 
-type DriverMutex = NoopRawMutex;
-
 /// Socket error variants
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -36,6 +115,12 @@ pub enum SocketError {
     OpenError,
     /// Error connecting with socket
     ConnectError,
+    /// Remote host actively refused the connection
+    ConnectionRefused,
+    /// Remote host could not be reached
+    HostUnreachable,
+    /// Connection was not established in time
+    ConnectTimeout,
     /// Error reading from socket
     ReadError,
     /// Error writing to socket
@@ -44,6 +129,174 @@ pub enum SocketError {
     CloseError,
     /// Attempting to use closed socket
     SocketClosed,
+    /// Operation is not supported by this kind of socket
+    Unsupported,
+    /// Address is not valid for this operation
+    InvalidAddress,
+    /// Received datagram does not fit in the provided buffer
+    DatagramTooLarge,
+    /// Module did not accept data within the socket's write timeout
+    TimedOut,
+    /// HTTP proxy refused to open a tunnel to the remote host
+    ProxyRejected,
+    /// HTTP proxy requires credentials, or did not accept the ones given
+    ProxyAuthenticationRequired,
+}
+
+impl SocketError {
+    /// Whether the same operation may succeed when tried again, as opposed to a failure
+    /// that will keep occurring, such as a refused connection.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            SocketError::ConnectError | SocketError::HostUnreachable | SocketError::ConnectTimeout
+        )
+    }
+}
+
+/// Options for connections secured by the module's own TLS stack.
+///
+/// The default verifies the server certificate against the CA certificate in slot 0,
+/// checks it was issued for `server_name` and requires TLS 1.2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TlsConfig<'a> {
+    /// How the server certificate is checked.
+    pub verify: TlsVerify,
+    /// Certificate slot holding the CA certificate used to verify the server.
+    pub ca_slot: u8,
+    /// Protocol version to negotiate.
+    pub version: TlsVersion,
+    /// Client certificate presented for mutual authentication, if the server requires
+    /// one.
+    pub client_identity: Option<ClientIdentity>,
+    /// Name of the server, sent for SNI and checked against its certificate.
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    pub server_name: Option<&'a str>,
+    /// Whether the server certificate must have been issued for `server_name`.
+    ///
+    /// Connecting without a `server_name` is refused while this is set.
+    pub verify_hostname: bool,
+}
+
+/// Certificate slots holding the client certificate and private key used for mutual
+/// TLS, as required by services such as AWS IoT and Azure IoT Hub.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClientIdentity {
+    /// Slot holding the client certificate.
+    pub cert_slot: u8,
+    /// Slot holding the private key matching the client certificate.
+    pub key_slot: u8,
+}
+
+impl Default for TlsConfig<'_> {
+    fn default() -> Self {
+        Self {
+            verify: TlsVerify::Required,
+            ca_slot: 0,
+            version: TlsVersion::Tls12,
+            client_identity: None,
+            server_name: None,
+            verify_hostname: true,
+        }
+    }
+}
+
+/// Server certificate verification, as numbered by the module's `P9` command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TlsVerify {
+    /// Accept any server certificate
+    None = 0,
+    /// Verify the server certificate if one is presented
+    Optional = 1,
+    /// Reject servers whose certificate does not verify
+    Required = 2,
+}
+
+/// TLS protocol version, as numbered by the module's `PV` command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TlsVersion {
+    /// TLS 1.0
+    Tls10 = 1,
+    /// TLS 1.1
+    Tls11 = 2,
+    /// TLS 1.2
+    Tls12 = 3,
+}
+
+/// Transport protocol of a socket, as numbered by the module's `P1` command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Protocol {
+    /// TCP
+    Tcp = 0,
+    /// UDP
+    Udp = 1,
+    /// TCP secured by the module's TLS stack
+    Tls = 3,
+}
+
+/// Traffic and error counters of a single socket, see [`EsWifiSocket::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SocketStats {
+    /// Bytes accepted by the module for sending
+    pub bytes_sent: u64,
+    /// Bytes received from the module
+    pub bytes_received: u64,
+    /// Connect attempts and writes repeated because the module was not ready
+    pub retries: u32,
+    /// Operations that failed with an error
+    pub errors: u32,
+}
+
+/// Time taken to establish a connection, see [`EsWifiSocket::timings`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnectTimings {
+    /// Time looking up the host name, for connections made by name through
+    /// [`EsWifi::connect_host`]
+    pub dns: Option<Duration>,
+    /// Time from the first connect attempt until the connection was established,
+    /// including retries
+    pub connect: Duration,
+    /// Time the module took to open the connection on the last attempt, for TLS
+    /// connections. The module performs the handshake as part of opening the connection
+    /// and does not report when the TCP connection is up, so this includes the TCP
+    /// connect.
+    pub handshake: Option<Duration>,
+}
+
+/// Details of data received, see [`EsWifiSocket::read_with_meta`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct RxMeta {
+    /// When the driver finished receiving the data from the module. The module does not
+    /// report when the data arrived over the network, so this includes the time it was
+    /// held in the module's buffer.
+    pub received_at: Instant,
+}
+
+/// Connection state of a socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConnectionState {
+    /// Socket has been closed, or is in the process of closing
+    Closed,
+    /// Socket is allocated, but not connected
+    Open,
+    /// Socket is connected
+    Connected,
+    /// Socket was connected, but the module reports the connection is gone
+    Disconnected,
 }
 
 /// WiFi join errors
@@ -58,306 +311,874 @@ pub enum JoinError {
     Unknown,
     /// Error associating to AP
     UnableToAssociate,
+    /// The access point rejected the passkey
+    WrongPassword,
+    /// No access point with the given SSID was found
+    NetworkNotFound,
+    /// Associated with the access point, but no address was obtained over DHCP in time
+    DhcpTimeout,
+    /// The access point did not respond in time
+    AssociationTimeout,
+    /// The [`CredentialsProvider`] had no credentials to join with
+    CredentialsUnavailable,
+}
+
+/// Details of the network joined, gathered right after joining.
+///
+/// Fields other than the address are `None` when the module did not report them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct JoinInfo {
+    /// Address obtained over DHCP
+    pub ip: IpAddr,
+    /// Network mask
+    pub netmask: Option<Ipv4Addr>,
+    /// Default gateway
+    pub gateway: Option<Ipv4Addr>,
+    /// Primary DNS server
+    pub dns: Option<Ipv4Addr>,
+    /// MAC address of the access point, only known when it was selected by the driver,
+    /// as when roaming
+    pub bssid: Option<[u8; 6]>,
+    /// Signal strength, in dBm
+    pub rssi: Option<i8>,
+    /// WiFi channel
+    pub channel: Option<u8>,
+}
+
+/// Retry behavior when establishing a connection.
+///
+/// The module rejects connection attempts while a previous connection on the same
+/// socket is still being torn down, so by default the driver keeps retrying for up
+/// to a minute. Only transient failures are retried, see [`SocketError::is_transient`];
+/// a refused connection or an invalid address is reported right away.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConnectConfig {
+    /// Overall time allowed for establishing the connection, including retries. An
+    /// attempt under way is not cut short, so the module's own connect timeout can
+    /// stretch it.
+    #[cfg_attr(feature = "serde", serde(with = "crate::millis"))]
+    pub timeout: Duration,
+    /// Delay between attempts after the module reports a failure.
+    #[cfg_attr(feature = "serde", serde(with = "crate::millis"))]
+    pub retry_interval: Duration,
+    /// Maximum number of attempts, or `None` to retry until `timeout` expires.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ConnectConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(60),
+            retry_interval: Duration::from_millis(100),
+            max_attempts: None,
+        }
+    }
 }
 
 /// Error type for driver
 #[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub enum Error<SPI, CS, RESET, READY> {
-    /// Chip select error
-    CS(CS),
-    /// Reset pin error
-    Reset(RESET),
-    /// SPI error
-    SPI(SPI),
-    /// Ready pin error
-    READY(READY),
+pub enum Error<TRANSPORT> {
+    /// Transport error
+    Transport(TRANSPORT),
+    /// RESET or WAKEUP pin error
+    Pin(digital::ErrorKind),
     /// Socket error
     Socket(SocketError),
     /// Join error
     Join(JoinError),
+    /// Soft access point error
+    AccessPoint(ApError),
+    /// Response did not fit in the buffer provided for it
+    BufferTooSmall,
+    /// Module firmware does not support the configured number of sockets
+    UnsupportedSocketCount(usize),
+    /// Module did not answer with its prompt after being reset or resynchronized
+    InitFailed {
+        /// Bytes received from the module in the last attempt
+        received: [u8; INIT_RESPONSE_SIZE],
+        /// Number of valid bytes in `received`
+        len: usize,
+    },
+    /// Module firmware does not know the command
+    Unsupported(CommandId),
+}
+
+/// Code of a module command, such as `P9` or `ZA`, as named in the AT command set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandId {
+    code: [u8; 2],
+    len: u8,
 }
 
-const NAK: u8 = 0x15;
+impl CommandId {
+    /// Code of `command`: its leading letters and digits, of which there are at most two.
+    pub(crate) fn of(command: &[u8]) -> Self {
+        let mut code = [0; 2];
+        let len = command
+            .iter()
+            .take(code.len())
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .zip(code.iter_mut())
+            .map(|(c, slot)| *slot = *c)
+            .count();
+        Self {
+            code,
+            len: len as u8,
+        }
+    }
 
-macro_rules! command {
-    ($size:tt, $($arg:tt)*) => ({
-        //let mut c = String::new();
-        //c
-        let mut c = String::<$size>::new();
-        write!(c, $($arg)*).unwrap();
-        c.push_str("\r").unwrap();
-        c
-    })
+    /// The code as text.
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.code[..self.len as usize]).unwrap_or_default()
+    }
 }
 
-struct Cs<'a, CS: OutputPin + 'a> {
-    cs: &'a mut CS,
+impl core::fmt::Display for CommandId {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
-impl<'a, CS: OutputPin + 'a> Cs<'a, CS> {
-    fn new(cs: &'a mut CS) -> Result<Self, CS::Error> {
-        cs.set_low()?;
-        block_for(Duration::from_micros(1000));
-        Ok(Self { cs })
+#[cfg(feature = "defmt")]
+impl defmt::Format for CommandId {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "{=str}", self.as_str())
     }
 }
 
-impl<'a, CS: OutputPin + 'a> Drop for Cs<'a, CS> {
-    fn drop(&mut self) {
-        let _ = self.cs.set_high();
-        block_for(Duration::from_micros(15));
+/// Bytes of the boot prompt kept for diagnostics when the module fails to start.
+const INIT_RESPONSE_SIZE: usize = 16;
+
+/// Number of reset and prompt handshake attempts when starting the module.
+const START_ATTEMPTS: u32 = 3;
+
+/// Time allowed for the prompt after a reset, or after asking for it again.
+const PROMPT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Number of times the prompt is asked for again after a reset before giving up on it.
+const PROMPT_REQUESTS: u32 = 3;
+
+/// Malformed responses in a row after which the driver gets back in step with the module,
+/// see [`EsWifi::resync`].
+const RESYNC_AFTER: u8 = 3;
+
+/// Time allowed for the module to accept the software reset command.
+const RESET_COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Delay before the second start attempt, doubled for every further one.
+const START_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Largest payload requested from the module in a single `R1` read.
+const READ_CHUNK_SIZE: usize = 1460;
+
+/// Consecutive words of NAKs tolerated within a response. Larger than a full read, so
+/// a payload that happens to consist of NAK bytes is not mistaken for a stalled module.
+const MAX_NAK_WORDS: usize = READ_CHUNK_SIZE;
+
+/// How often connections are checked against the idle timeout, when one is set.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time after joining before roaming is first considered.
+const ROAMING_SETTLE_TIME: Duration = Duration::from_secs(10);
+
+/// Number of events held for [`EsWifi::next_event`] before new ones are dropped.
+const EVENT_QUEUE_SIZE: usize = 4;
+
+/// Delay between write attempts while the module's transmit buffer is full.
+const WRITE_BACKOFF: Duration = Duration::from_millis(20);
+
+/// Time a socket waits for the module to accept data, unless set otherwise.
+const DEFAULT_WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest server name accepted for SNI, as limited by DNS.
+const MAX_SERVER_NAME: usize = 253;
+
+/// Delay between checks for an address while waiting for DHCP to complete.
+const DHCP_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Time allowed for the module to close a socket.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delay between polls of the module while waiting for a datagram.
+const RECV_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Delay between polls of the module while waiting for a client to connect.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Size of the buffer shared by all command responses, and by scan results line by line.
+///
+/// The default of 1024 bytes fits the longest responses the driver expects. Set the
+/// `ES_WIFI_SCRATCH_SIZE` environment variable when building to lower it on targets short
+/// of RAM; the responses that no longer fit, such as those of joining a network with
+/// verbose messages on, then fail with [`Error::BufferTooSmall`].
+const SCRATCH_SIZE: usize = match option_env!("ES_WIFI_SCRATCH_SIZE") {
+    Some(size) => parse_size(size),
+    None => 1024,
+};
+
+/// Parse a decimal size at compile time.
+const fn parse_size(size: &str) -> usize {
+    let digits = size.as_bytes();
+    core::assert!(!digits.is_empty(), "ES_WIFI_SCRATCH_SIZE must not be empty");
+    let mut value = 0;
+    let mut i = 0;
+    while i < digits.len() {
+        core::assert!(
+            digits[i].is_ascii_digit(),
+            "ES_WIFI_SCRATCH_SIZE must be a decimal number"
+        );
+        value = value * 10 + (digits[i] - b'0') as usize;
+        i += 1;
     }
+    value
 }
 
-/// Es-WiFi driver state
-struct DriverState<SPI, CS, RESET, WAKEUP, READY>
+/// Destination for a module response, filled in order across a header, a body and a trailer.
+///
+/// Lets the payload of a read land directly in the caller's buffer while the framing
+/// around it goes into small scratch buffers.
+struct ResponseBuf<'a> {
+    head: &'a mut [u8],
+    body: &'a mut [u8],
+    tail: &'a mut [u8],
+    /// Number of bytes filled in so far.
+    filled: usize,
+    /// Whether the response was longer than the buffers, and the rest discarded.
+    truncated: bool,
+}
+
+impl<'a> ResponseBuf<'a> {
+    fn new(head: &'a mut [u8], body: &'a mut [u8], tail: &'a mut [u8]) -> Self {
+        Self {
+            head,
+            body,
+            tail,
+            filled: 0,
+            truncated: false,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.head.len() + self.body.len() + self.tail.len()
+    }
+
+    /// Append a received byte. Bytes past the end are still taken, to tell a response
+    /// that exactly fills the buffers from one that was cut short.
+    fn push(&mut self, byte: u8) {
+        let index = self.filled;
+        if index >= self.len() {
+            self.truncated = true;
+            return;
+        }
+        if index < self.head.len() {
+            self.head[index] = byte;
+        } else if index - self.head.len() < self.body.len() {
+            self.body[index - self.head.len()] = byte;
+        } else {
+            self.tail[index - self.head.len() - self.body.len()] = byte;
+        }
+        self.filled += 1;
+    }
+}
+
+/// Receive a response into `response`, returning how many bytes of it were filled in.
+/// With `verbose` on, the echo of the command is left out.
+async fn read_response<T: Transport>(
+    transport: &mut T,
+    response: &mut ResponseBuf<'_>,
+    verbose: bool,
+) -> Result<usize, T::Error> {
+    let mut echo = proto::framing::Echo::new(verbose);
+    transport
+        .read(true, |byte| {
+            if echo.keep(byte) {
+                response.push(byte);
+            }
+            true
+        })
+        .await?;
+    Ok(response.filled)
+}
+
+/// A RESET or WAKEUP line, with the pin type erased.
+trait Line {
+    fn set_low(&mut self) -> Result<(), digital::ErrorKind>;
+    fn set_high(&mut self) -> Result<(), digital::ErrorKind>;
+}
+
+impl<P: OutputPin> Line for P {
+    fn set_low(&mut self) -> Result<(), digital::ErrorKind> {
+        OutputPin::set_low(self).map_err(|e| e.kind())
+    }
+
+    fn set_high(&mut self) -> Result<(), digital::ErrorKind> {
+        OutputPin::set_high(self).map_err(|e| e.kind())
+    }
+}
+
+/// The RESET and WAKEUP lines, borrowed from [`Adapter`] for the few steps that need them.
+struct Pins<'a> {
+    reset: Option<&'a mut dyn Line>,
+    wakeup: Option<&'a mut dyn Line>,
+}
+
+/// Es-WiFi driver state together with the pins it drives.
+///
+/// Everything but the pins lives in [`DriverState`], which is only generic over the
+/// transport, so that its code is compiled once whatever pin types a firmware uses.
+struct Adapter<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize>
 where
-    SPI: SpiBus<u8>,
-    CS: OutputPin,
+    TRANSPORT: Transport,
     RESET: OutputPin,
     WAKEUP: OutputPin,
-    READY: InputPin + Wait,
 {
-    spi: SPI,
-    cs: CS,
-    reset: RESET,
-    wakeup: WAKEUP,
-    ready: READY,
-    socket_pool: SocketPool,
+    state: DriverState<TRANSPORT, SOCKETS>,
+    reset: Option<RESET>,
+    wakeup: Option<WAKEUP>,
 }
 
-impl<SPI, CS, RESET, WAKEUP, READY> DriverState<SPI, CS, RESET, WAKEUP, READY>
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize> Adapter<TRANSPORT, RESET, WAKEUP, SOCKETS>
 where
-    SPI: SpiBus<u8>,
-    CS: OutputPin,
+    TRANSPORT: Transport,
     RESET: OutputPin,
     WAKEUP: OutputPin,
-    READY: InputPin + Wait,
 {
-    /// Create a new instance of the es-wifi driver using the provided peripheral and pins.
-    fn new(spi: SPI, cs: CS, reset: RESET, wakeup: WAKEUP, ready: READY) -> Self {
+    fn new(transport: TRANSPORT, reset: Option<RESET>, wakeup: Option<WAKEUP>) -> Self {
         Self {
-            spi,
-            cs,
+            state: DriverState::new(transport),
             reset,
             wakeup,
-            ready,
+        }
+    }
+
+    /// Split into the driver state and the pins it needs to restart the module.
+    fn split(&mut self) -> (&mut DriverState<TRANSPORT, SOCKETS>, Pins<'_>) {
+        let pins = Pins {
+            reset: self.reset.as_mut().map(|pin| pin as &mut dyn Line),
+            wakeup: self.wakeup.as_mut().map(|pin| pin as &mut dyn Line),
+        };
+        (&mut self.state, pins)
+    }
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize> core::ops::Deref
+    for Adapter<TRANSPORT, RESET, WAKEUP, SOCKETS>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+{
+    type Target = DriverState<TRANSPORT, SOCKETS>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.state
+    }
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize> core::ops::DerefMut
+    for Adapter<TRANSPORT, RESET, WAKEUP, SOCKETS>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.state
+    }
+}
+
+/// Es-WiFi driver state
+struct DriverState<TRANSPORT, const SOCKETS: usize>
+where
+    TRANSPORT: Transport,
+{
+    transport: TRANSPORT,
+    socket_pool: SocketPool<SOCKETS>,
+    join_info: Option<JoinInfo>,
+    /// Address of the module on the network it hosts, while in soft access point mode.
+    ap_address: Option<Ipv4Addr>,
+    /// Role the module took last, `None` until it joined or hosted a network.
+    mode: Option<WifiMode>,
+    /// Credentials the module had when its configuration web page was enabled, `None`
+    /// while the page is off.
+    web_config: Option<Option<Credentials>>,
+    signal_low: bool,
+    probe_failures: u8,
+    recoveries: RecoveryStats,
+    resets: ResetStats,
+    rate_limit: Option<TokenBucket>,
+    last_error: Option<String<64>>,
+    /// Malformed responses received in a row.
+    malformed: u8,
+    /// Whether the response to a command given up on is still to come, see
+    /// [`DriverState::send_until`].
+    unanswered: bool,
+    /// Whether the module's verbose messages are left on, see [`EsWifi::set_verbose`].
+    verbose: bool,
+    dns_cache: DnsCache,
+    chunk_size: ChunkSize,
+    history: History,
+    /// Buffer shared by every command response, since the module handles one at a time.
+    scratch: [u8; SCRATCH_SIZE],
+    trace_until: Option<Instant>,
+}
+
+impl<TRANSPORT, const SOCKETS: usize> DriverState<TRANSPORT, SOCKETS>
+where
+    TRANSPORT: Transport,
+{
+    /// Create a new instance of the es-wifi driver using the provided transport.
+    fn new(transport: TRANSPORT) -> Self {
+        const { core::assert!(SOCKETS > 0, "the driver needs at least one socket") };
+        Self {
+            transport,
             socket_pool: SocketPool::new(),
+            join_info: None,
+            ap_address: None,
+            mode: None,
+            web_config: None,
+            signal_low: false,
+            probe_failures: 0,
+            recoveries: RecoveryStats::default(),
+            resets: ResetStats::default(),
+            rate_limit: None,
+            last_error: None,
+            malformed: 0,
+            unanswered: false,
+            verbose: false,
+            dns_cache: DnsCache::new(),
+            chunk_size: ChunkSize::Fixed,
+            history: History::new(),
+            scratch: [0; SCRATCH_SIZE],
+            trace_until: None,
         }
     }
 
-    async fn wakeup(&mut self) {
-        self.wakeup.set_low().ok().unwrap();
+    async fn wakeup(&mut self, pins: &mut Pins<'_>) -> Result<(), Error<TRANSPORT::Error>> {
+        let wakeup = match &mut pins.wakeup {
+            Some(wakeup) => wakeup,
+            None => return Ok(()),
+        };
+        wakeup.set_low().map_err(Error::Pin)?;
         Timer::after(Duration::from_millis(50)).await;
-        self.wakeup.set_high().ok().unwrap();
+        wakeup.set_high().map_err(Error::Pin)?;
         Timer::after(Duration::from_millis(50)).await;
+        Ok(())
     }
 
-    async fn reset(&mut self) {
-        self.reset.set_low().ok().unwrap();
+    async fn reset(&mut self, pins: &mut Pins<'_>) -> Result<(), Error<TRANSPORT::Error>> {
+        let reset = match &mut pins.reset {
+            Some(reset) => reset,
+            None => {
+                // Boards without a RESET line rely on the software reset command instead.
+                self.reset_command().await;
+                return Ok(());
+            }
+        };
+        reset.set_low().map_err(Error::Pin)?;
         Timer::after(Duration::from_millis(50)).await;
-        self.reset.set_high().ok().unwrap();
+        reset.set_high().map_err(Error::Pin)?;
         Timer::after(Duration::from_millis(50)).await;
+        self.resets.record();
+        Ok(())
     }
 
-    async fn wait_ready(
-        &mut self,
-    ) -> Result<(), Error<SPI::Error, CS::Error, RESET::Error, READY::Error>> {
-        while self.ready.is_low().map_err(Error::READY)? {
-            self.ready.wait_for_any_edge().await.map_err(Error::READY)?;
+    /// Send the software reset command, returning whether the module took it.
+    async fn reset_command(&mut self) -> bool {
+        let sent = matches!(
+            with_timeout(RESET_COMMAND_TIMEOUT, self.transmit(&command!(4, "ZR")[..])).await,
+            Ok(Ok(()))
+        );
+        Timer::after(Duration::from_millis(50)).await;
+        if sent {
+            self.resets.record();
         }
-        Ok(())
+        sent
     }
 
-    async fn start(
-        &mut self,
-    ) -> Result<(), Error<SPI::Error, CS::Error, RESET::Error, READY::Error>> {
+    async fn start(&mut self, pins: &mut Pins<'_>) -> Result<(), Error<TRANSPORT::Error>> {
         info!("Starting eS-WiFi adapter!");
 
-        self.reset().await;
-        self.wakeup().await;
-
-        let mut response = [0; 4];
+        let mut response = [0; INIT_RESPONSE_SIZE];
         let mut pos = 0;
+        for attempt in 0..START_ATTEMPTS {
+            if attempt > 0 {
+                // Give a module that is slow to boot progressively more time.
+                Timer::after(START_RETRY_DELAY * (1 << attempt)).await;
+            }
+            self.reset(pins).await?;
+            self.wakeup(pins).await?;
+            self.clear_state();
+
+            if self.await_prompt(&mut response, &mut pos).await? {
+                self.init().await?;
+                //self.state = State::Ready;
+                info!("eS-WiFi adapter is ready");
+                return Ok(());
+            }
+            warn!(
+                "eS-WiFi adapter failed to initialize (attempt {}): {:?}",
+                attempt + 1,
+                &response[0..pos]
+            );
+        }
+        Err(Error::InitFailed {
+            received: response,
+            len: pos,
+        })
+    }
 
-        self.wait_ready().await?;
-        {
-            let _cs = Cs::new(&mut self.cs).map_err(Error::CS)?;
-            loop {
-                if self.ready.is_low().map_err(Error::READY)? {
-                    break;
-                }
+    /// Forget everything learned from the module before it was restarted.
+    fn clear_state(&mut self) {
+        self.join_info = None;
+        self.ap_address = None;
+        self.mode = None;
+        self.web_config = None;
+        self.signal_low = false;
+        self.probe_failures = 0;
+        self.dns_cache.flush();
+    }
 
-                if pos >= response.len() {
-                    break;
-                }
+    /// Configure a module that has just booted.
+    async fn init(&mut self) -> Result<(), Error<TRANSPORT::Error>> {
+        self.send_command(self.verbosity_command()).await?;
+        self.check_sockets().await
+    }
 
-                let mut chunk = [0x0A, 0x0A];
-                Self::spi_transfer(&mut self.spi, &mut chunk, &[0x0A, 0x0A]).await?;
+    /// Command turning the module's verbose messages off, or leaving them on when asked
+    /// to.
+    fn verbosity_command(&self) -> Vec<u8, 8> {
+        if self.verbose {
+            command!(8, "MT=0")
+        } else {
+            command!(8, "MT=1")
+        }
+    }
 
-                // reverse order going from 16 -> 2*8 bits
-                if chunk[1] != NAK {
-                    response[pos] = chunk[1];
-                    pos += 1;
-                }
-                if chunk[0] != NAK {
-                    response[pos] = chunk[0];
-                    pos += 1;
-                }
+    /// Wait for the prompt the module prints once it has booted, asking for it again with
+    /// a bare carriage return while it does not show up. The end of an earlier response or
+    /// NAK padding may come along with it, so the prompt is looked for anywhere in what is
+    /// received.
+    ///
+    /// Returns whether the prompt was seen, keeping the start of the last bytes received
+    /// in `received` and their number in `len`.
+    async fn await_prompt(
+        &mut self,
+        received: &mut [u8],
+        len: &mut usize,
+    ) -> Result<bool, Error<TRANSPORT::Error>> {
+        for request in 0..=PROMPT_REQUESTS {
+            if request > 0 {
+                trace!("No prompt from eS-WiFi adapter yet, asking again");
+                self.transmit(b"\r").await?;
+            }
+            let response = match with_timeout(PROMPT_TIMEOUT, self.handshake()).await {
+                Ok(response) => response?,
+                Err(_) => &[],
+            };
+            *len = core::cmp::min(response.len(), received.len());
+            received[..*len].copy_from_slice(&response[..*len]);
+            if proto::framing::contains_prompt(response) {
+                return Ok(true);
             }
         }
+        Ok(false)
+    }
 
-        let needle = &[b'\r', b'\n', b'>', b' '];
+    /// Read the prompt the module sends once it has booted, returning what was received.
+    async fn handshake(&mut self) -> Result<&[u8], Error<TRANSPORT::Error>> {
+        let Self {
+            transport,
+            scratch,
+            verbose,
+            ..
+        } = self;
+        let len = read_response(
+            transport,
+            &mut ResponseBuf::new(&mut [], &mut scratch[..], &mut []),
+            *verbose,
+        )
+        .await
+        .map_err(Error::Transport)?;
+        Ok(&scratch[..len])
+    }
 
-        if !response[0..pos].starts_with(needle) {
-            info!(
-                "eS-WiFi adapter failed to initialize {:?}",
-                &response[0..pos]
-            );
+    /// Verify the firmware supports as many sockets as the driver is configured for, by
+    /// selecting the highest one.
+    async fn check_sockets(&mut self) -> Result<(), Error<TRANSPORT::Error>> {
+        let response = self.send_command(command!(8, "P0=", SOCKETS - 1)).await?;
+        if parser::is_ok(response) {
+            Ok(())
         } else {
-            // disable verbosity
-            let mut resp = [0; 16];
-            self.send_string(command!(8, "MT=1"), &mut resp).await?;
-            //self.state = State::Ready;
-            info!("eS-WiFi adapter is ready");
+            warn!("eS-WiFi firmware does not support {} sockets", SOCKETS);
+            Err(Error::UnsupportedSocketCount(SOCKETS))
         }
-
-        Ok(())
     }
 
-    async fn join_wep(&mut self, ssid: &str, password: &str) -> Result<IpAddr, JoinError> {
-        let mut response = [0; 1024];
-
-        self.send_string(command!(36, "CB=2"), &mut response)
+    async fn join_wep(&mut self, ssid: &str, password: &str) -> Result<JoinInfo, JoinError> {
+        self.send_command(command!(36, "CB=2"))
             .await
             .map_err(|_| JoinError::InvalidSsid)?;
 
-        self.send_string(command!(36, "C1={}", ssid), &mut response)
+        self.send_command(command!(36, "C1=", ssid))
             .await
             .map_err(|_| JoinError::InvalidSsid)?;
 
-        self.send_string(command!(72, "C2={}", password), &mut response)
+        self.send_secret(command!(72, "C2=", password))
             .await
             .map_err(|_| JoinError::InvalidPassword)?;
 
-        self.send_string(command!(8, "C3=4"), &mut response)
+        self.send_command(command!(8, "C3=4"))
             .await
             .map_err(|_| JoinError::Unknown)?;
 
         let response = self
-            .send_string(command!(4, "C0"), &mut response)
+            .send_command(command!(4, "C0"))
             .await
             .map_err(|_| JoinError::Unknown)?;
 
-        //info!("[[{}]]", response);
-
-        let parse_result = parser::join_response(&response);
+        let ip = proto::join_result(response)?;
+        Ok(self.joined(ip).await)
+    }
 
-        match parse_result {
-            Ok((_, response)) => match response {
-                JoinResponse::Ok(ip) => Ok(ip),
-                JoinResponse::JoinError => Err(JoinError::UnableToAssociate),
-            },
-            Err(_) => {
-                trace!("{:?}", &response);
-                Err(JoinError::UnableToAssociate)
+    /// Wait up to `timeout` for the module to obtain an address over DHCP, without
+    /// associating again.
+    async fn await_address(&mut self, timeout: Duration) -> Result<JoinInfo, JoinError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let address = self
+                .query_secret(command!(4, "C?"), parser::network_address)
+                .await
+                .map_err(|_| JoinError::Unknown)?;
+            if let Some(ip) = address {
+                if !ip.is_unspecified() {
+                    return Ok(self.joined(IpAddr::V4(ip)).await);
+                }
             }
+            if Instant::now() + DHCP_POLL_INTERVAL >= deadline {
+                return Err(JoinError::DhcpTimeout);
+            }
+            Timer::after(DHCP_POLL_INTERVAL).await;
         }
     }
 
-    async fn send_string<'m, const N: usize>(
-        &'m mut self,
-        mut command: String<N>,
-        response: &'m mut [u8],
-    ) -> Result<&'m [u8], Error<SPI::Error, CS::Error, RESET::Error, READY::Error>> {
-        if command.len() % 2 != 0 {
-            command.push('\n').unwrap();
-        }
-        self.send(command.as_bytes(), response).await
+    /// Gather the details of the network just joined with address `ip`.
+    async fn joined(&mut self, ip: IpAddr) -> JoinInfo {
+        let (netmask, gateway, dns) = self
+            .query_secret(command!(4, "C?"), |settings| {
+                (
+                    parser::network_netmask(settings),
+                    parser::network_gateway(settings),
+                    parser::network_dns(settings),
+                )
+            })
+            .await
+            .unwrap_or((None, None, None));
+        let info = JoinInfo {
+            ip,
+            netmask,
+            gateway,
+            dns,
+            bssid: None,
+            rssi: self.rssi().await,
+            channel: self.association().await.map(|info| info.channel),
+        };
+        self.join_info.replace(info);
+        self.mode = Some(WifiMode::Station);
+        info
     }
 
-    async fn send<'m>(
-        &'m mut self,
-        command: &[u8],
-        response: &'m mut [u8],
-    ) -> Result<&'m [u8], Error<SPI::Error, CS::Error, RESET::Error, READY::Error>> {
-        //trace!("send {:?}", core::str::from_utf8(&command[..]).unwrap());
+    /// Send a command, returning its response from the scratch buffer.
+    async fn send_command<const N: usize>(
+        &mut self,
+        command: Vec<u8, N>,
+    ) -> Result<&[u8], Error<TRANSPORT::Error>> {
+        self.send(&command[..]).await
+    }
 
-        self.wait_ready().await?;
-        {
-            let _cs = Cs::new(&mut self.cs).map_err(Error::CS)?;
-            for chunk in command.chunks(2) {
-                let mut xfer: [u8; 2] = [0; 2];
-                xfer[1] = chunk[0];
-                if chunk.len() == 2 {
-                    xfer[0] = chunk[1]
-                } else {
-                    xfer[0] = 0x0A
-                }
+    /// Send a command carrying a passphrase or key, wiping it once transmitted, and
+    /// return whether the module accepted it. The module echoes the command, so its
+    /// response is wiped as well rather than returned.
+    async fn send_secret<const N: usize>(
+        &mut self,
+        command: Vec<u8, N>,
+    ) -> Result<bool, Error<TRANSPORT::Error>> {
+        let command = Wipe(command);
+        let trace_until = self.withhold_trace();
+        let result = self.send(&command.0[..]).await.map(parser::is_ok);
+        self.restore_trace(trace_until);
+        self.wipe_scratch();
+        result
+    }
 
-                let a = xfer[0];
-                let b = xfer[1];
-                Self::spi_transfer(&mut self.spi, &mut xfer[..], &[a, b]).await?;
-            }
-            /*assert!(command.len() % 2 == 0);
-            self.spi.transfer(&mut command[..]).map_err(SPI)?;*/
-        }
-        //info!("sent! awaiting response");
+    /// Send a command whose response holds the passphrase, handing it to `parse` and
+    /// wiping it from the scratch buffer afterwards.
+    async fn query_secret<R, const N: usize>(
+        &mut self,
+        command: Vec<u8, N>,
+        parse: impl FnOnce(&[u8]) -> R,
+    ) -> Result<R, Error<TRANSPORT::Error>> {
+        let trace_until = self.withhold_trace();
+        let result = self.send_command(command).await.map(parse);
+        self.restore_trace(trace_until);
+        self.wipe_scratch();
+        result
+    }
 
-        self.receive(response).await
+    /// Pause tracing and the command history for an exchange holding a secret,
+    /// returning when to trace until once it is over.
+    fn withhold_trace(&mut self) -> Option<Instant> {
+        if self.tracing() {
+            info!("> (exchange holding a secret withheld)");
+        }
+        self.history.withhold(true);
+        self.trace_until.take()
     }
 
-    async fn spi_transfer(
-        spi: &mut SPI,
-        rx: &mut [u8],
-        _tx: &[u8],
-    ) -> Result<(), Error<SPI::Error, CS::Error, RESET::Error, READY::Error>> {
-        spi.transfer_in_place(rx).await.map_err(Error::SPI)?;
-        Ok(())
+    /// Resume tracing until `until` and the command history after an exchange holding a
+    /// secret.
+    fn restore_trace(&mut self, until: Option<Instant>) {
+        self.trace_until = until;
+        self.history.withhold(false);
     }
 
-    async fn receive<'m>(
-        &'m mut self,
-        response: &'m mut [u8],
-    ) -> Result<&'m [u8], Error<SPI::Error, CS::Error, RESET::Error, READY::Error>> {
-        let mut pos = 0;
+    /// Clear the scratch buffer after it held something sensitive.
+    fn wipe_scratch(&mut self) {
+        #[cfg(feature = "zeroize")]
+        zeroize::Zeroize::zeroize(&mut self.scratch[..]);
+    }
 
-        //trace!("Awaiting response ready");
-        self.wait_ready().await?;
-        //trace!("Response ready... reading");
+    async fn send(&mut self, command: &[u8]) -> Result<&[u8], Error<TRANSPORT::Error>> {
+        if self.malformed >= RESYNC_AFTER {
+            warn!("Responses keep failing to parse, resynchronizing with the module");
+            if self.resync().await.is_err() {
+                warn!("Unable to resynchronize with the module");
+            }
+        }
+        self.transmit(command).await?;
+        let response = self.receive().await?;
+        if parser::unknown_command(response) {
+            let id = CommandId::of(command);
+            debug!("Module does not support the {} command", id);
+            return Err(Error::Unsupported(id));
+        }
+        Ok(response)
+    }
 
-        let _cs = Cs::new(&mut self.cs).map_err(Error::CS)?;
+    /// Send a command, giving up on its response at `deadline` with `None`. The module
+    /// cannot be interrupted, so the rest of the response is then read, and dropped,
+    /// before the next command goes out.
+    async fn send_until(
+        &mut self,
+        command: &[u8],
+        deadline: Instant,
+    ) -> Result<Option<&[u8]>, Error<TRANSPORT::Error>> {
+        self.transmit(command).await?;
+        let received = with_deadline(deadline, async {
+            self.receive().await.map(|response| response.len())
+        })
+        .await;
+        match received {
+            Ok(len) => Ok(Some(&self.scratch[..len?])),
+            Err(_) => {
+                self.unanswered = true;
+                Ok(None)
+            }
+        }
+    }
 
-        while self.ready.is_high().map_err(Error::READY)? && response.len() - pos > 0 {
-            //trace!("Receive pos({}), len({})", pos, response.len());
+    /// Send a command without waiting for its response.
+    async fn transmit(&mut self, command: &[u8]) -> Result<(), Error<TRANSPORT::Error>> {
+        if core::mem::take(&mut self.unanswered) {
+            debug!("Waiting for the module to answer the command given up on");
+            self.handshake().await?;
+        }
+        self.trace(">", command);
+        self.history.command(command);
+        self.transport
+            .write(command, &[])
+            .await
+            .map_err(Error::Transport)
+    }
 
-            let mut xfer: [u8; 2] = [0x0A, 0x0A];
-            Self::spi_transfer(&mut self.spi, &mut xfer, &[0x0A, 0x0A]).await?;
+    /// Receive a response into the scratch buffer.
+    async fn receive(&mut self) -> Result<&[u8], Error<TRANSPORT::Error>> {
+        let tracing = self.tracing();
+        let Self {
+            transport,
+            scratch,
+            last_error,
+            malformed,
+            verbose,
+            history,
+            ..
+        } = self;
+        let mut buf = ResponseBuf::new(&mut [], &mut scratch[..], &mut []);
+        let len = read_response(transport, &mut buf, *verbose)
+            .await
+            .map_err(Error::Transport)?;
+        if buf.truncated {
+            warn!("Response did not fit in {} bytes", SCRATCH_SIZE);
+            return Err(Error::BufferTooSmall);
+        }
+        let response = &scratch[0..len];
+        if tracing {
+            trace::log_exchange("<", response);
+        }
+        history.response(response);
+        if let Some(error) = proto::error_message(response) {
+            *last_error = Some(error);
+        }
+        *malformed = if proto::well_formed(response) {
+            0
+        } else {
+            malformed.saturating_add(1)
+        };
+        Ok(response)
+    }
 
-            if xfer[0] == NAK {
-                block_for(Duration::from_micros(1));
-            }
+    async fn receive_into(
+        &mut self,
+        response: &mut ResponseBuf<'_>,
+    ) -> Result<usize, Error<TRANSPORT::Error>> {
+        read_response(&mut self.transport, response, self.verbose)
+            .await
+            .map_err(Error::Transport)
+    }
 
-            if !self.ready.is_high().map_err(Error::READY)? {
-                if xfer[0] == NAK {
-                    if xfer[1] != NAK {
-                        response[pos] = xfer[1];
-                        pos += 1;
-                    }
-                    break;
+    /// Receive a response line by line, handing each line without its line ending to
+    /// `on_line`. Lines longer than the scratch buffer are skipped.
+    async fn receive_lines(
+        &mut self,
+        mut on_line: impl FnMut(&[u8]),
+    ) -> Result<(), Error<TRANSPORT::Error>> {
+        let Self {
+            transport,
+            scratch,
+            verbose,
+            ..
+        } = self;
+        let mut lines = proto::LineSplitter::new(&mut scratch[..]);
+        let mut echo = proto::framing::Echo::new(*verbose);
+        transport
+            .read(true, |byte| {
+                if !echo.keep(byte) {
+                    return true;
                 }
-            }
-            response[pos] = xfer[1];
-            pos += 1;
-
-            response[pos] = xfer[0];
-            pos += 1;
-        }
-        Ok(&response[0..pos])
+                if let Some(line) = lines.push(byte) {
+                    on_line(line);
+                }
+                true
+            })
+            .await
+            .map_err(Error::Transport)
     }
 
-    async fn socket(&mut self) -> Result<u8, SocketError> {
+    async fn socket(&mut self) -> Result<SocketHandle, SocketError> {
         let h = self
             .socket_pool
             .open()
@@ -367,284 +1188,438 @@ where
         Ok(h)
     }
 
-    fn is_connected(&mut self, handle: u8) -> Result<bool, SocketError> {
+    fn is_connected(&mut self, handle: SocketHandle) -> Result<bool, SocketError> {
         Ok(self.socket_pool.is_connected(handle))
     }
 
-    async fn connect(&mut self, handle: u8, remote: SocketAddr) -> Result<(), SocketError> {
-        let mut response = [0u8; 1024];
+    async fn state(&mut self, handle: SocketHandle) -> Result<ConnectionState, SocketError> {
+        match self.socket_pool.state(handle) {
+            SocketState::Closed | SocketState::HalfClosed => return Ok(ConnectionState::Closed),
+            SocketState::Open => return Ok(ConnectionState::Open),
+            SocketState::Connected => {}
+        }
+        self.send_command(command!(8, "P0=", handle))
+            .await
+            .map_err(|_| SocketError::ReadError)?;
+        let response = self
+            .send_command(command!(4, "P?"))
+            .await
+            .map_err(|_| SocketError::ReadError)?;
+
+        match parser::transport_status(response) {
+            Some(status) if status.client_connected => Ok(ConnectionState::Connected),
+            Some(_) => Ok(ConnectionState::Disconnected),
+            None => {
+                debug!("[{}] Unable to parse transport status", handle);
+                Err(SocketError::ReadError)
+            }
+        }
+    }
+
+    async fn connect(
+        &mut self,
+        handle: SocketHandle,
+        remote: SocketAddr,
+        protocol: Protocol,
+        tls: Option<&TlsConfig<'_>>,
+        timeout: Option<Duration>,
+    ) -> Result<Duration, SocketError> {
+        proto::check_remote(handle, remote, protocol)?;
         let result = async {
-            self.send_string(command!(8, "P0={}", handle), &mut response)
+            self.send_command(command!(8, "P0=", handle))
                 .await
                 .map_err(|_| {
                     trace!("[{}] CONNECT 1", handle);
                     SocketError::ConnectError
                 })?;
 
-            self.send_string(command!(8, "P1=0"), &mut response)
+            self.send_command(command!(8, "P1=", protocol as usize))
                 .await
                 .map_err(|_| {
                     trace!("[{}] CONNECT 2", handle);
 
                     SocketError::ConnectError
                 })?;
-            /*
-            IpProtocol::Udp => {
-                self.send_string(command!(8, "P1=1"), &mut response)
-                    .await
-                    .map_err(|_| SocketError::ConnectError)?;
+
+            if let Some(tls) = tls {
+                self.configure_tls(handle, tls).await?;
             }
-            */
 
-            self.send_string(command!(32, "P3={}", remote.ip()), &mut response)
+            self.send_command(command!(32, "P3=", remote.ip()))
                 .await
                 .map_err(|_| {
                     trace!("[{}] CONNECT 3", handle);
                     SocketError::ConnectError
                 })?;
 
-            self.send_string(command!(32, "P4={}", remote.port()), &mut response)
+            self.send_command(command!(32, "P4=", remote.port()))
                 .await
                 .map_err(|_| {
                     trace!("[{}] CONNECT 4", handle);
                     SocketError::ConnectError
                 })?;
 
-            let response = self
-                .send_string(command!(8, "P6=1"), &mut response)
-                .await
-                .map_err(|_| {
-                    trace!("[{}] CONNECT 5", handle);
-                    SocketError::ConnectError
-                })?;
-
-            match parser::connect_response(&response) {
-                Ok((_, ConnectResponse::Ok)) => {
-                    self.socket_pool.set_connected(handle);
-                    Ok(())
-                }
-                Ok((_, _)) => {
-                    trace!("[{}] CONNECT 6", handle);
-                    Err(SocketError::ConnectError)
-                }
-                Err(_) => {
-                    trace!("[{}] CONNECT 7", handle);
-                    Err(SocketError::ConnectError)
-                }
+            let start = Instant::now();
+            let connect = command!(8, "P6=1");
+            // Only the connection itself counts against the timeout, as the commands
+            // before it may have waited for the module to finish an earlier one.
+            let response = match timeout {
+                Some(timeout) => self.send_until(&connect[..], start + timeout).await,
+                None => self.send_command(connect).await.map(Some),
             }
+            .map_err(|_| {
+                trace!("[{}] CONNECT 5", handle);
+                SocketError::ConnectError
+            })?;
+            let Some(response) = response else {
+                debug!("[{}] Gave up on connecting to {}", handle, remote);
+                return Err(SocketError::ConnectTimeout);
+            };
+            let opened = start.elapsed();
+
+            proto::connect_result(handle, response)?;
+            self.socket_pool.set_connected(handle);
+            self.socket_pool.set_remote(handle, remote);
+            Ok(opened)
         }
         .await;
         result
     }
 
-    async fn write(&mut self, handle: u8, buf: &[u8]) -> Result<usize, SocketError> {
-        let mut response = [0u8; 32];
-        let mut remaining = buf.len();
-        trace!("Write request with {} bytes", remaining);
-        self.send_string(command!(8, "P0={}", handle), &mut response)
+    /// Apply the TLS options to the selected socket, refusing to go ahead with a setting
+    /// the module did not accept rather than silently falling back to its defaults.
+    async fn configure_tls(
+        &mut self,
+        handle: SocketHandle,
+        tls: &TlsConfig<'_>,
+    ) -> Result<(), SocketError> {
+        self.tls_command(handle, command!(16, "P9=", tls.verify as usize))
+            .await?;
+        self.tls_command(handle, command!(16, "PE=0,", tls.ca_slot))
+            .await?;
+        self.tls_command(handle, command!(16, "PV=", tls.version as usize))
+            .await?;
+        if let Some(identity) = tls.client_identity {
+            self.tls_command(handle, command!(16, "PE=1,", identity.cert_slot))
+                .await?;
+            self.tls_command(handle, command!(16, "PE=2,", identity.key_slot))
+                .await?;
+        }
+        if let Some(name) = tls.server_name {
+            self.tls_command(handle, command!(264, "PS=", name)).await?;
+        }
+        self.tls_command(handle, command!(16, "PH=", tls.verify_hostname as usize))
             .await
-            .map_err(|_| SocketError::WriteError)?;
-        while remaining > 0 {
-            // info!("Writing buf with len {}", len);
-
-            let to_send = core::cmp::min(1200, remaining);
-            trace!("Writing {} bytes to adapter", to_send);
-
-            async {
-                let mut prefix = command!(16, "S3={}", to_send).into_bytes();
+    }
 
-                let (prefix, data) = if prefix.len() % 2 == 0 {
-                    (&prefix[..], &buf[..to_send])
-                } else {
-                    prefix.push(buf[0]).unwrap();
-                    (&prefix[..], &buf[1..to_send])
-                };
+    /// Send one TLS setting for the selected socket, failing unless the module confirms it.
+    async fn tls_command<const N: usize>(
+        &mut self,
+        handle: SocketHandle,
+        command: Vec<u8, N>,
+    ) -> Result<(), SocketError> {
+        let response = self.send_command(command).await.map_err(|e| {
+            trace!("[{}] TLS 1", handle);
+            match e {
+                Error::Unsupported(_) => SocketError::Unsupported,
+                _ => SocketError::ConnectError,
+            }
+        })?;
+        if parser::is_ok(response) {
+            Ok(())
+        } else {
+            warn!("[{}] Module rejected TLS setting", handle);
+            Err(SocketError::Unsupported)
+        }
+    }
 
-                self.wait_ready()
-                    .await
-                    .map_err(|_| SocketError::WriteError)?;
+    /// Start the module's `protocol` server on `port` for the given socket.
+    ///
+    /// A UDP server takes datagrams right away, while a TCP server only connects once a
+    /// client is attached, see [`DriverState::client`].
+    async fn bind(
+        &mut self,
+        handle: SocketHandle,
+        protocol: Protocol,
+        port: u16,
+    ) -> Result<(), SocketError> {
+        self.send_command(command!(8, "P0=", handle))
+            .await
+            .map_err(|_| SocketError::OpenError)?;
+        self.send_command(command!(8, "P1=", protocol as usize))
+            .await
+            .map_err(|_| SocketError::OpenError)?;
+        self.send_command(command!(16, "P2=", port))
+            .await
+            .map_err(|_| SocketError::OpenError)?;
+        let response = self
+            .send_command(command!(8, "P5=1"))
+            .await
+            .map_err(|_| SocketError::OpenError)?;
 
-                {
-                    let _cs = Cs::new(&mut self.cs).map_err(|_| SocketError::WriteError)?;
-
-                    trace!("Writing prefix of {} bytes", prefix.len());
-                    for chunk in prefix.chunks(2) {
-                        let mut xfer: [u8; 2] = [0; 2];
-                        xfer[1] = chunk[0];
-                        if chunk.len() == 2 {
-                            xfer[0] = chunk[1]
-                        } else {
-                            xfer[0] = 0x0A
-                        }
+        if parser::is_ok(response) {
+            self.socket_pool.set_listening(handle);
+            if protocol == Protocol::Udp {
+                self.socket_pool.set_connected(handle);
+            }
+            Ok(())
+        } else {
+            debug!("[{}] Unable to bind port {}", handle, port);
+            Err(SocketError::OpenError)
+        }
+    }
 
-                        let a = xfer[0];
-                        let b = xfer[1];
+    /// Address of the client attached to the TCP server of the given socket, if any.
+    async fn client(&mut self, handle: SocketHandle) -> Result<Option<SocketAddr>, SocketError> {
+        self.send_command(command!(8, "P0=", handle))
+            .await
+            .map_err(|_| SocketError::ReadError)?;
+        let response = self
+            .send_command(command!(4, "P?"))
+            .await
+            .map_err(|_| SocketError::ReadError)?;
+        match parser::transport_status(response) {
+            Some(status) => Ok(status.client_connected.then_some(status.remote)),
+            None => {
+                debug!("[{}] Unable to parse transport status", handle);
+                Err(SocketError::ReadError)
+            }
+        }
+    }
 
-                        Self::spi_transfer(&mut self.spi, &mut xfer, &[a, b])
-                            .await
-                            .map_err(|_| SocketError::WriteError)?;
-                    }
+    /// Stop and start again the TCP server of the given socket, dropping the client
+    /// attached to it. The module keeps the protocol and port set by [`DriverState::bind`].
+    async fn restart_server(&mut self, handle: SocketHandle) -> Result<(), SocketError> {
+        self.send_command(command!(8, "P0=", handle))
+            .await
+            .map_err(|_| SocketError::CloseError)?;
+        let response = self
+            .send_command(command!(8, "P5=0"))
+            .await
+            .map_err(|_| SocketError::CloseError)?;
+        if !parser::is_ok(response) {
+            debug!("[{}] Unable to stop server", handle);
+            return Err(SocketError::CloseError);
+        }
+        self.socket_pool.set_open(handle);
+        let response = self
+            .send_command(command!(8, "P5=1"))
+            .await
+            .map_err(|_| SocketError::OpenError)?;
+        if parser::is_ok(response) {
+            Ok(())
+        } else {
+            debug!("[{}] Unable to restart server", handle);
+            Err(SocketError::OpenError)
+        }
+    }
 
-                    trace!("Writing data of {} bytes", data.len());
-                    for chunk in data.chunks(2) {
-                        let mut xfer: [u8; 2] = [0; 2];
-                        xfer[1] = chunk[0];
-                        if chunk.len() == 2 {
-                            xfer[0] = chunk[1]
-                        } else {
-                            xfer[0] = 0x0A
-                        }
+    /// Point the host address of a bound UDP socket at a multicast group, or back to
+    /// any host when leaving.
+    async fn set_multicast(
+        &mut self,
+        handle: SocketHandle,
+        group: Option<Ipv4Addr>,
+    ) -> Result<(), SocketError> {
+        let host = IpAddr::V4(group.unwrap_or(Ipv4Addr::new(0, 0, 0, 0)));
+        self.send_command(command!(8, "P0=", handle))
+            .await
+            .map_err(|_| SocketError::Unsupported)?;
+        let response = self
+            .send_command(command!(32, "P3=", host))
+            .await
+            .map_err(|_| SocketError::Unsupported)?;
+        if parser::is_ok(response) {
+            Ok(())
+        } else {
+            debug!("[{}] Module rejected multicast group", handle);
+            Err(SocketError::Unsupported)
+        }
+    }
 
-                        let a = xfer[0];
-                        let b = xfer[1];
+    /// Write as much of `buf` as the module currently accepts.
+    ///
+    /// The module reports how many bytes it took for each `S3` chunk. A short
+    /// count, `-1` or an error telling of a full buffer means its transmit buffer
+    /// is full, in which case the bytes accepted so far are returned and the caller
+    /// is expected to back off before retrying. `Ok(0)` therefore signals
+    /// backpressure, not EOF. Since the module also answers `-1` for a connection
+    /// that is gone, the caller checks on the socket before waiting.
+    ///
+    /// `progress` is called with the number of bytes accepted for each chunk. An empty
+    /// `buf` returns `Ok(0)` right away, without talking to the module.
+    async fn write(
+        &mut self,
+        handle: SocketHandle,
+        buf: &[u8],
+        progress: &mut dyn FnMut(usize),
+    ) -> Result<usize, SocketError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if !self.socket_pool.is_connected(handle) {
+            return Err(SocketError::SocketClosed);
+        }
+        let mut pos = 0;
+        trace!("Write request with {} bytes", buf.len());
+        self.send_command(command!(8, "P0=", handle))
+            .await
+            .map_err(|_| SocketError::WriteError)?;
+        while pos < buf.len() {
+            // info!("Writing buf with len {}", len);
 
-                        Self::spi_transfer(&mut self.spi, &mut xfer, &[a, b])
-                            .await
-                            .map_err(|_| SocketError::WriteError)?;
-                    }
-                }
+            let to_send = core::cmp::min(self.chunk_size.get(), buf.len() - pos);
+            let chunk = &buf[pos..pos + to_send];
+            trace!("Writing {} bytes to adapter", to_send);
 
-                let response = self
-                    .receive(&mut response)
+            let (accepted, rejected) = async {
+                let prefix = command!(16, "S3=", to_send);
+                self.trace(">", &prefix);
+                self.trace_payload(">", to_send);
+                self.history.command(&prefix);
+                self.transport
+                    .write(&prefix, chunk)
                     .await
                     .map_err(|_| SocketError::WriteError)?;
 
-                if let Ok((_, WriteResponse::Ok(len))) = parser::write_response(response) {
-                    remaining -= to_send;
-                    Ok(len)
-                } else {
-                    trace!("Error reading response");
-                    trace!("response:  {:?}", core::str::from_utf8(&response).unwrap());
-                    Err(SocketError::WriteError)
-                }
+                let response = self.receive().await.map_err(|_| SocketError::WriteError)?;
+
+                Ok((
+                    proto::write_result(response, to_send),
+                    parser::error_message(response).is_some(),
+                ))
             }
             .await?;
+            if rejected && self.chunk_size.rejected(to_send) {
+                continue;
+            }
+            let accepted = accepted?;
+            if accepted > 0 {
+                self.chunk_size.accepted(to_send);
+            }
+
+            pos += accepted;
+            if accepted > 0 {
+                progress(accepted);
+            }
+            if accepted < to_send {
+                trace!(
+                    "[{}] Module accepted {} of {} bytes, buffer full",
+                    handle,
+                    accepted,
+                    to_send
+                );
+                break;
+            }
+        }
+        if pos > 0 {
+            self.socket_pool.touch(handle);
         }
-        Ok(buf.len())
+        Ok(pos)
     }
 
-    async fn read(&mut self, handle: u8, buf: &mut [u8]) -> Result<usize, SocketError> {
-        let mut pos = 0;
-        //let buf_len = buf.len();
-        loop {
-            let result = async {
-                let mut response = [0u8; 1470];
-
-                self.send_string(command!(8, "P0={}", handle), &mut response)
-                    .await
-                    .map_err(|_| {
-                        debug!("[{}] READ 1", handle);
-                        SocketError::ReadError
-                    })?;
+    /// Read at most `len` bytes in a single `R0` transaction, returning the number of
+    /// bytes the module delivered.
+    ///
+    /// `len` may exceed `buf.len()` by one byte, which then lands in the status trailer:
+    /// a returned length larger than `buf.len()` means the data did not fit.
+    async fn read_chunk(
+        &mut self,
+        handle: SocketHandle,
+        buf: &mut [u8],
+        len: usize,
+    ) -> Result<usize, SocketError> {
+        debug_assert!(len <= buf.len() + 1);
 
-                let maxlen = buf.len() - pos;
-                let len = core::cmp::min(response.len() - 10, maxlen);
+        self.send_command(command!(8, "P0=", handle))
+            .await
+            .map_err(|_| {
+                debug!("[{}] READ 1", handle);
+                SocketError::ReadError
+            })?;
 
-                self.send_string(command!(16, "R1={}", len), &mut response)
-                    .await
-                    .map_err(|_| {
-                        debug!("[{}] READ 2", handle);
-                        SocketError::ReadError
-                    })?;
+        self.send_command(command!(16, "R1=", len))
+            .await
+            .map_err(|_| {
+                debug!("[{}] READ 2", handle);
+                SocketError::ReadError
+            })?;
 
-                /*
-                self.send_string(&command!(8, "R2=10000"), &mut response)
-                    .await
-                    .map_err(|_| SocketError::ReadError)?;
-                */
+        /*
+        self.send_command(&command!(8, "R2=10000"))
+            .await
+            .map_err(|_| SocketError::ReadError)?;
+        */
+
+        self.send_command(command!(8, "R3=1")).await.map_err(|_| {
+            debug!("[{}] READ 3", handle);
+            SocketError::ReadError
+        })?;
+
+        self.transmit(&command!(4, "R0")[..]).await.map_err(|_| {
+            debug!("[{}] READ 4", handle);
+            SocketError::ReadError
+        })?;
+
+        trace!("Receiving {} bytes, buffer size is {}", len, buf.len());
+
+        // The payload is received straight into the caller's buffer, with the
+        // leading CRLF and the status trailer going to the scratch buffers.
+        let mut head = [0u8; 2];
+        let mut tail = [0u8; 16];
+        let body_cap = core::cmp::min(len, buf.len());
+        let body = &mut buf[..body_cap];
+        let received = self
+            .receive_into(&mut ResponseBuf::new(&mut head, body, &mut tail))
+            .await
+            .map_err(|_| {
+                debug!("[{}] READ 8", handle);
+                SocketError::ReadError
+            })?;
 
-                self.send_string(command!(8, "R3=1"), &mut response)
-                    .await
-                    .map_err(|_| {
-                        debug!("[{}] READ 3", handle);
-                        SocketError::ReadError
-                    })?;
-
-                self.wait_ready().await.map_err(|_| {
-                    debug!("[{}] READ 4", handle);
-                    SocketError::ReadError
-                })?;
+        trace!("Response is {} bytes", received);
+        self.trace_payload("<", received);
 
-                {
-                    let _cs = Cs::new(&mut self.cs).map_err(|_| {
-                        debug!("[{}] READ 5", handle);
-                        SocketError::ReadError
-                    })?;
-
-                    let mut xfer = [b'0', b'R'];
-                    Self::spi_transfer(&mut self.spi, &mut xfer, &[b'0', b'R'])
-                        .await
-                        .map_err(|_| {
-                            debug!("[{}] READ 6", handle);
-                            SocketError::ReadError
-                        })?;
-
-                    xfer = [b'\n', b'\r'];
-                    Self::spi_transfer(&mut self.spi, &mut xfer, &[b'\n', b'\r'])
-                        .await
-                        .map_err(|_| {
-                            debug!("[{}] READ 7", handle);
-                            SocketError::ReadError
-                        })?;
-                }
+        let body_len = core::cmp::min(received.saturating_sub(head.len()), body_cap);
+        let tail_len = received.saturating_sub(head.len() + body_cap);
+        let body = &buf[..body_len];
+        let tail = &tail[..tail_len];
 
-                trace!(
-                    "Receiving {} bytes, total buffer size is {}, pos is {}",
-                    len,
-                    buf.len(),
-                    pos
-                );
-                let response = self.receive(&mut response).await.map_err(|_| {
-                    debug!("[{}] READ 8", handle);
-                    SocketError::ReadError
-                })?;
+        proto::read_result(handle, received, &head, body, tail)
+    }
 
-                trace!("Response is {} bytes", response.len());
-                //trace!("{:02x}", response);
-
-                match parser::parse_response(&response) {
-                    Ok((_, ReadResponse::Ok(data))) => {
-                        if pos + data.len() > buf.len() {
-                            trace!(
-                                "Buf len is {}, pos is {}, Len is {}, data len is {}",
-                                buf.len(),
-                                pos,
-                                len,
-                                data.len()
-                            );
-                            if let Ok(s) = core::str::from_utf8(&data) {
-                                trace!("response parsed:  {:?}", s);
-                            }
-                            trace!("response raw data: {:?}", response);
-                            Err(SocketError::ReadError)
-                        } else {
-                            for (i, b) in data.iter().enumerate() {
-                                buf[pos + i] = *b;
-                            }
-                            trace!("Read {} bytes", data.len());
-                            Ok(data.len())
-                        }
-                    }
-                    Ok((_, ReadResponse::Err)) => {
-                        trace!("[{}] READ 9 ReadResponse::Err", handle);
-                        //      warn!("response raw data: {:02x}", response);
-                        Err(SocketError::ReadError)
-                    }
-                    _ => {
-                        warn!("[{}] READ 9 parse error", handle);
-                        if let Ok(s) = core::str::from_utf8(&response[..]) {
-                            trace!("response parsed:  {:?}", s);
-                        }
-                        trace!("response raw data: {:?}", response);
-                        Err(SocketError::ReadError)
-                    }
+    /// Read what the module has received, up to `buf.len()` bytes. An empty `buf`
+    /// returns `Ok(0)` right away, without talking to the module.
+    async fn read(&mut self, handle: SocketHandle, buf: &mut [u8]) -> Result<usize, SocketError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if !self.socket_pool.is_connected(handle) {
+            return Err(SocketError::SocketClosed);
+        }
+        let mut pos = 0;
+        loop {
+            let len = core::cmp::min(READ_CHUNK_SIZE, buf.len() - pos);
+            let result = match self.read_chunk(handle, &mut buf[pos..], len).await {
+                Ok(data_len) if data_len > len => {
+                    trace!(
+                        "Buf len is {}, pos is {}, Len is {}, data len is {}",
+                        buf.len(),
+                        pos,
+                        len,
+                        data_len
+                    );
+                    Err(SocketError::ReadError)
                 }
-            }
-            .await;
+                result => result,
+            };
 
             match result {
                 Ok(len) => {
                     pos += len;
+                    if len > 0 {
+                        self.socket_pool.touch(handle);
+                    }
                     if len == 0 || pos == buf.len() {
                         return Ok(pos);
                     }
@@ -660,121 +1635,568 @@ where
         }
     }
 
-    async fn close(&mut self, handle: u8) -> Result<(), SocketError> {
+    /// Receive a single datagram, along with the address it was sent from.
+    ///
+    /// The module hands out at most one datagram per read, so one byte more than fits in
+    /// `buf` is requested to tell a datagram that exactly fills it from one that was cut
+    /// short.
+    async fn recv_from(
+        &mut self,
+        handle: SocketHandle,
+        buf: &mut [u8],
+    ) -> Result<Option<(usize, SocketAddr)>, SocketError> {
+        if !self.socket_pool.is_connected(handle) {
+            return Err(SocketError::SocketClosed);
+        }
+        let len = core::cmp::min(READ_CHUNK_SIZE, buf.len() + 1);
+        let data_len = self.read_chunk(handle, buf, len).await?;
+        if data_len == 0 {
+            return Ok(None);
+        }
+        self.socket_pool.touch(handle);
+        if data_len > buf.len() {
+            warn!(
+                "[{}] Dropping datagram larger than {} bytes",
+                handle,
+                buf.len()
+            );
+            return Err(SocketError::DatagramTooLarge);
+        }
+        let response = self
+            .send_command(command!(4, "P?"))
+            .await
+            .map_err(|_| SocketError::ReadError)?;
+        match parser::transport_status(response) {
+            Some(status) => Ok(Some((data_len, status.remote))),
+            None => {
+                debug!("[{}] Unable to parse transport status", handle);
+                Err(SocketError::ReadError)
+            }
+        }
+    }
+
+    async fn close(&mut self, handle: SocketHandle) -> Result<(), SocketError> {
         trace!("Closing connection for {}", handle);
+        if self.socket_pool.state(handle) == SocketState::Closed {
+            // Already closed, e.g. by the idle reaper, and possibly reused since.
+            return Ok(());
+        }
         self.socket_pool.close(handle);
-        let mut response = [0u8; 32];
 
-        self.send_string(command!(8, "P0={}", handle), &mut response)
+        self.send_command(command!(8, "P0=", handle))
             .await
             .map_err(|_| {
                 debug!("[{}] CLOSE 1", handle);
                 SocketError::CloseError
             })?;
 
-        let response = self
-            .send_string(command!(8, "P6=0"), &mut response)
-            .await
-            .map_err(|_| {
-                debug!("[{}] CLOSE 2", handle);
-                SocketError::CloseError
-            })?;
+        let stop = if self.socket_pool.is_listening(handle) {
+            command!(8, "P5=0")
+        } else {
+            command!(8, "P6=0")
+        };
+        let response = self.send_command(stop).await.map_err(|_| {
+            debug!("[{}] CLOSE 2", handle);
+            SocketError::CloseError
+        })?;
 
-        match parser::close_response(&response) {
-            Ok((_, CloseResponse::Ok)) => {
-                debug!("[{}] Connection closed", handle);
-                self.socket_pool.close(handle);
-                Ok(())
-            }
-            Ok((_, _)) => {
-                debug!("[{}] Error1 closing connection", handle);
-                /*info!(
-                    "[{}] close response:  {:?}",
-                    handle,
-                    core::str::from_utf8(&response).unwrap()
-                );*/
-                self.socket_pool.close(handle);
-                Err(SocketError::CloseError)
-            }
-            Err(_) => {
-                debug!("[{}] Error2 closing connection", handle);
-                //info!("[{}] close response: {:x}", handle, response,);
-                if let Ok(s) = core::str::from_utf8(&response) {
-                    debug!("response parsed:  {:?}", s);
-                }
-                self.socket_pool.close(handle);
-                Err(SocketError::CloseError)
-            }
-        }
+        let result = proto::close_result(handle, response);
+        // The socket is gone whatever the module answered.
+        self.socket_pool.close(handle);
+        result
     }
 }
 
 /// eS-WiFi driver.
-pub struct EsWifi<SPI, CS, RESET, WAKEUP, READY>
+///
+/// `SOCKETS` is the number of concurrent sockets supported by the module firmware, which
+/// is checked when the driver starts. It must be at least 1, which is checked at compile
+/// time.
+///
+/// `M` is the raw mutex guarding the driver state and its channels. The default
+/// [`NoopRawMutex`] keeps the driver and its handles on one executor; use
+/// `CriticalSectionRawMutex` to share the driver and its [`Control`] handles across
+/// executors, provided the transport and pins are `Send`.
+pub struct EsWifi<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize = 4, M = NoopRawMutex>
 where
-    SPI: SpiBus<u8>,
-    CS: OutputPin,
+    TRANSPORT: Transport,
     RESET: OutputPin,
     WAKEUP: OutputPin,
-    READY: InputPin + Wait,
+    M: RawMutex,
 {
-    adapter: LocalMutex<DriverState<SPI, CS, RESET, WAKEUP, READY>>,
-    control: Channel<DriverMutex, Control, 1>,
+    adapter: Mutex<M, Adapter<TRANSPORT, RESET, WAKEUP, SOCKETS>>,
+    control: Channel<M, Message, 1>,
+    events: Channel<M, Event, EVENT_QUEUE_SIZE>,
+    connect_config: ConnectConfig,
+    idle_timeout: Option<Duration>,
+    dhcp_retry: Option<Duration>,
+    address_check: Option<Duration>,
+    link_monitor: Option<LinkMonitorConfig>,
+    probe: Option<ProbeConfig>,
+    roaming: Option<RoamingConfig>,
+    proxy: Option<ProxyConfig>,
 }
 
-impl<SPI, CS, RESET, WAKEUP, READY> EsWifi<SPI, CS, RESET, WAKEUP, READY>
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
 where
-    SPI: SpiBus<u8>,
-    CS: OutputPin,
+    TRANSPORT: Transport,
     RESET: OutputPin,
     WAKEUP: OutputPin,
-    READY: InputPin + Wait,
+    M: RawMutex,
 {
-    /// Create a new instance of the driver.
-    pub fn new(spi: SPI, cs: CS, reset: RESET, wakeup: WAKEUP, ready: READY) -> Self {
-        let state = DriverState::new(spi, cs, reset, wakeup, ready);
+    /// Create a new instance of the driver talking to the module over `transport`.
+    ///
+    /// `reset` and `wakeup` are `None` on boards where those lines are not wired to the
+    /// host, see [`NoPin`].
+    pub fn with_transport(
+        transport: TRANSPORT,
+        reset: Option<RESET>,
+        wakeup: Option<WAKEUP>,
+    ) -> Self {
         Self {
-            adapter: LocalMutex::new(state, true),
+            adapter: Mutex::new(Adapter::new(transport, reset, wakeup)),
             control: Channel::new(),
+            events: Channel::new(),
+            connect_config: ConnectConfig::default(),
+            idle_timeout: None,
+            dhcp_retry: None,
+            address_check: None,
+            link_monitor: None,
+            probe: None,
+            roaming: None,
+            proxy: None,
+        }
+    }
+
+    /// Ask the run loop to restart the module with its software reset command and then
+    /// rejoin the network, closing all connections.
+    ///
+    /// Unlike the hardware reset performed when the driver starts, this works on boards
+    /// whose RESET line is not wired to the host.
+    pub async fn soft_reset(&self) {
+        self.control.send(Message::SoftReset).await;
+    }
+
+    /// Set the retry behavior used when establishing connections through
+    /// [`TcpConnect`](embedded_nal_async::TcpConnect).
+    pub fn set_connect_config(&mut self, config: ConnectConfig) {
+        self.connect_config = config;
+    }
+
+    /// When the network is joined but DHCP fails, keep waiting up to `timeout` for an
+    /// address instead of reporting [`JoinError::DhcpTimeout`] right away. The module stays
+    /// associated meanwhile, so this is quicker than joining again. Disabled by default.
+    pub fn set_dhcp_retry(&mut self, timeout: Option<Duration>) {
+        self.dhcp_retry = timeout;
+    }
+
+    /// Close connections without any traffic for longer than `timeout`.
+    ///
+    /// With only four sockets available on the module, this keeps connections leaked by
+    /// clients that never drop them from starving everyone else. Reaped sockets report
+    /// [`SocketError::SocketClosed`] on further use. Disabled by default.
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.idle_timeout = timeout;
+    }
+
+    /// Wait for the next event reported by the run loop or a socket.
+    ///
+    /// Events are queued while nobody is waiting for them; when the queue is full, new
+    /// events are dropped.
+    pub async fn next_event(&self) -> Event {
+        self.events.receive().await
+    }
+
+    fn notify(&self, event: Event) {
+        debug!("Event: {:?}", event);
+        if self.events.try_send(event).is_err() {
+            warn!("Event queue full, dropping {:?}", event);
         }
     }
 
-    async fn new_socket(&self) -> Result<u8, SocketError> {
+    async fn close_idle(&self, timeout: Duration) {
+        let mut adapter = self.adapter.lock().await;
+        for handle in adapter.socket_pool.idle(timeout) {
+            debug!("[{}] Closing idle connection", handle);
+            self.socket_closed(&adapter, handle, CloseReason::Idle);
+            if let Err(e) = adapter.close(handle).await {
+                warn!("Error closing idle connection {}: {:?}", handle, e);
+            }
+        }
+    }
+
+    /// Address assigned to the module by the network it joined, if any.
+    pub async fn ip_address(&self) -> Option<IpAddr> {
+        self.adapter.lock().await.join_info.map(|info| info.ip)
+    }
+
+    /// Details of the network joined, or `None` while not joined.
+    pub async fn join_info(&self) -> Option<JoinInfo> {
+        self.adapter.lock().await.join_info
+    }
+
+    /// Announce the module as `<hostname>.local` and answer mDNS queries for it.
+    ///
+    /// Runs until an error occurs and should be spawned alongside [`EsWifi::run`] once
+    /// the network has been joined. Uses two of the module's sockets while running.
+    pub async fn run_mdns(&self, hostname: &str) -> Result<(), SocketError> {
+        use embedded_io_async::Write;
+
+        if !mdns::is_valid_hostname(hostname) {
+            return Err(SocketError::InvalidAddress);
+        }
+        let ip = match self.ip_address().await {
+            Some(IpAddr::V4(ip)) => ip,
+            _ => return Err(SocketError::SocketClosed),
+        };
+
+        let mut announce = [0u8; mdns::MAX_HOSTNAME + 34];
+        let len = mdns::announcement(hostname, ip, &mut announce);
+        let announce = &announce[..len];
+
+        let mut listener = self.udp_bind(mdns::PORT).await?;
+        listener.join_multicast(mdns::GROUP).await?;
+        let mut sender = self
+            .udp_connect(SocketAddr::new(IpAddr::V4(mdns::GROUP), mdns::PORT))
+            .await?;
+
+        // Probing is skipped, but the announcement is repeated as RFC 6762 recommends.
+        sender.write(announce).await?;
+        Timer::after(Duration::from_secs(1)).await;
+        sender.write(announce).await?;
+
+        let mut query = [0u8; 512];
+        loop {
+            let len = match listener.recv_from(&mut query).await {
+                Ok((len, _)) => len,
+                Err(SocketError::DatagramTooLarge) => continue,
+                Err(e) => return Err(e),
+            };
+            if mdns::is_query_for(&query[..len], hostname) {
+                trace!("Answering mDNS query for {}.local", hostname);
+                sender.write(announce).await?;
+            }
+        }
+    }
+
+    /// Connect to `remote` with a single attempt, returning the first failure as-is.
+    pub async fn connect_once(
+        &self,
+        remote: SocketAddr,
+    ) -> Result<EsWifiSocket<'_, TRANSPORT, RESET, WAKEUP, SOCKETS, M>, SocketError> {
+        let config = ConnectConfig {
+            max_attempts: Some(1),
+            ..self.connect_config
+        };
+        self.open(remote, Protocol::Tcp, config, None).await
+    }
+
+    /// Connect to `remote` over TLS, with the handshake and encryption handled by the
+    /// module.
+    ///
+    /// The certificates and keys referenced by `tls` must already be stored in the module.
+    pub async fn connect_tls(
+        &self,
+        remote: SocketAddr,
+        tls: TlsConfig<'_>,
+    ) -> Result<EsWifiSocket<'_, TRANSPORT, RESET, WAKEUP, SOCKETS, M>, SocketError> {
+        match tls.server_name {
+            Some(name) if name.is_empty() || name.len() > MAX_SERVER_NAME => {
+                return Err(SocketError::InvalidAddress)
+            }
+            None if tls.verify_hostname => {
+                warn!("Hostname verification needs a server name");
+                return Err(SocketError::InvalidAddress);
+            }
+            _ => {}
+        }
+        self.open(remote, Protocol::Tls, self.connect_config, Some(&tls))
+            .await
+    }
+
+    /// Open a UDP socket exchanging datagrams with `remote`.
+    pub async fn udp_connect(
+        &self,
+        remote: SocketAddr,
+    ) -> Result<EsWifiSocket<'_, TRANSPORT, RESET, WAKEUP, SOCKETS, M>, SocketError> {
+        self.open(remote, Protocol::Udp, self.connect_config, None)
+            .await
+    }
+
+    /// Open a UDP socket broadcasting datagrams to every host on the local network
+    /// listening on `port`.
+    pub async fn udp_broadcast(
+        &self,
+        port: u16,
+    ) -> Result<EsWifiSocket<'_, TRANSPORT, RESET, WAKEUP, SOCKETS, M>, SocketError> {
+        let remote = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(255, 255, 255, 255)), port);
+        self.udp_connect(remote).await
+    }
+
+    /// Open a UDP socket receiving datagrams sent to the local `port`.
+    pub async fn udp_bind(
+        &self,
+        port: u16,
+    ) -> Result<EsWifiSocket<'_, TRANSPORT, RESET, WAKEUP, SOCKETS, M>, SocketError> {
+        let handle = self.new_socket().await?;
+        let socket = self.socket(handle, Protocol::Udp);
+        let mut adapter = self.adapter.lock().await;
+        adapter.bind(handle, Protocol::Udp, port).await?;
+        Ok(socket)
+    }
+
+    /// Open a TCP socket listening on the local `port`, taking clients with
+    /// [`EsWifiSocket::accept`].
+    ///
+    /// The module's server has one client attached at a time on each socket; listen on
+    /// several sockets to serve clients side by side.
+    pub async fn tcp_listen(
+        &self,
+        port: u16,
+    ) -> Result<EsWifiSocket<'_, TRANSPORT, RESET, WAKEUP, SOCKETS, M>, SocketError> {
+        let handle = self.new_socket().await?;
+        let socket = self.socket(handle, Protocol::Tcp);
+        let mut adapter = self.adapter.lock().await;
+        adapter.bind(handle, Protocol::Tcp, port).await?;
+        Ok(socket)
+    }
+
+    async fn open(
+        &self,
+        remote: SocketAddr,
+        protocol: Protocol,
+        config: ConnectConfig,
+        tls: Option<&TlsConfig<'_>>,
+    ) -> Result<EsWifiSocket<'_, TRANSPORT, RESET, WAKEUP, SOCKETS, M>, SocketError> {
+        let handle = self.new_socket().await?;
+        let mut socket = self.socket(handle, protocol);
+        socket.connect(remote, config, tls, None).await?;
+        Ok(socket)
+    }
+
+    fn socket(
+        &self,
+        handle: SocketHandle,
+        protocol: Protocol,
+    ) -> EsWifiSocket<'_, TRANSPORT, RESET, WAKEUP, SOCKETS, M> {
+        EsWifiSocket {
+            handle,
+            protocol,
+            adapter: self,
+            control: self.control.sender().into(),
+            write_timeout: DEFAULT_WRITE_TIMEOUT,
+            stats: SocketStats::default(),
+            timings: ConnectTimings::default(),
+            rate_limit: None,
+            progress: None,
+            nodelay: false,
+            closed: false,
+        }
+    }
+
+    async fn new_socket(&self) -> Result<SocketHandle, SocketError> {
         let mut adapter = self.adapter.lock().await;
         let handle = adapter.socket().await?;
+        self.notify(Event::SocketOpened { handle });
         Ok(handle)
     }
 
-    async fn reset(
+    /// Report a socket as closed for `reason`, unless it already was.
+    fn socket_closed(
+        &self,
+        adapter: &DriverState<TRANSPORT, SOCKETS>,
+        handle: SocketHandle,
+        reason: CloseReason,
+    ) {
+        if adapter.socket_pool.report_close(handle) {
+            self.notify(Event::SocketClosed {
+                handle,
+                remote: adapter.socket_pool.remote(handle),
+                reason,
+            });
+        }
+    }
+
+    async fn reset<P: CredentialsProvider>(
+        &self,
+        mode: &mut Mode<'_, P>,
+    ) -> Result<(), Error<TRANSPORT::Error>> {
+        {
+            let mut adapter = self.adapter.lock().await;
+            let (state, mut pins) = adapter.split();
+            state.start(&mut pins).await?;
+        }
+        self.bring_up(mode).await
+    }
+
+    /// Join the network described by `credentials`, waiting for DHCP as configured with
+    /// [`set_dhcp_retry`](Self::set_dhcp_retry).
+    pub(crate) async fn join_network(
         &self,
-        ssid: &str,
-        psk: &str,
-    ) -> Result<(), Error<SPI::Error, CS::Error, RESET::Error, READY::Error>> {
+        credentials: &Credentials,
+    ) -> Result<JoinInfo, JoinError> {
         let mut adapter = self.adapter.lock().await;
-        adapter.start().await?;
         debug!("Joining WiFi network...");
-        adapter
-            .join_wep(ssid, psk)
-            .await
-            .map_err(|e| Error::Join(e))?;
-        debug!("WiFi network joined");
+        let joined = adapter
+            .join_wep(credentials.ssid.as_str(), credentials.password.as_str())
+            .await;
+        let joined = match (joined, self.dhcp_retry) {
+            (Err(JoinError::DhcpTimeout), Some(timeout)) => {
+                debug!("No address obtained, waiting for DHCP...");
+                adapter.await_address(timeout).await
+            }
+            (joined, _) => joined,
+        };
+        if joined.is_ok() {
+            debug!("WiFi network joined");
+        }
+        joined
+    }
+
+    /// Join the network or start the access point on a module that has just booted.
+    async fn bring_up<P: CredentialsProvider>(
+        &self,
+        mode: &mut Mode<'_, P>,
+    ) -> Result<(), Error<TRANSPORT::Error>> {
+        {
+            // The module dropped its connections when it restarted.
+            let adapter = self.adapter.lock().await;
+            for handle in adapter.socket_pool.force_close() {
+                self.socket_closed(&adapter, handle, CloseReason::Reset);
+            }
+        }
+        match mode {
+            Mode::Station(provider) => {
+                let credentials = provider
+                    .credentials()
+                    .await
+                    .ok_or(Error::Join(JoinError::CredentialsUnavailable))?;
+                self.join_network(&credentials).await.map_err(Error::Join)?;
+            }
+            Mode::AccessPoint(config) => {
+                let mut adapter = self.adapter.lock().await;
+                debug!("Starting soft access point...");
+                adapter.start_ap(config).await.map_err(Error::AccessPoint)?;
+                debug!("Soft access point started");
+            }
+        }
         Ok(())
     }
 
     /// Run driver stack
-    pub async fn run(
+    pub async fn run(&self, ssid: &str, psk: &str) -> Result<(), Error<TRANSPORT::Error>> {
+        let credentials = Credentials::new(ssid, psk).map_err(Error::Join)?;
+        self.run_with(credentials).await
+    }
+
+    /// Run driver stack, fetching credentials from `provider` whenever the network is
+    /// (re)joined instead of holding on to them.
+    pub async fn run_with<P: CredentialsProvider>(
+        &self,
+        provider: P,
+    ) -> Result<(), Error<TRANSPORT::Error>> {
+        self.serve(Mode::Station(provider)).await
+    }
+
+    /// Run driver stack with the module hosting its own network, as described by `config`.
+    pub async fn run_ap(&self, config: ApConfig<'_>) -> Result<(), Error<TRANSPORT::Error>> {
+        self.serve(Mode::<Credentials>::AccessPoint(config)).await
+    }
+
+    async fn serve<P: CredentialsProvider>(
         &self,
-        ssid: &str,
-        psk: &str,
-    ) -> Result<(), Error<SPI::Error, CS::Error, RESET::Error, READY::Error>> {
-        self.reset(ssid, psk).await?;
+        mut mode: Mode<'_, P>,
+    ) -> Result<(), Error<TRANSPORT::Error>> {
+        self.reset(&mut mode).await?;
+        let mut next_idle_check = Instant::now() + IDLE_CHECK_INTERVAL;
+        let mut next_link_check = Instant::now();
+        let mut next_address_check = Instant::now();
+        let mut next_probe = Instant::now();
+        let mut next_roaming_check = Instant::now() + ROAMING_SETTLE_TIME;
+        let mut next_heartbeat = None;
         loop {
-            match self.control.recv().await {
-                Control::Close(id) => {
+            let deadline = [
+                next_heartbeat,
+                self.idle_timeout.map(|_| next_idle_check),
+                self.link_monitor.map(|_| next_link_check),
+                self.address_check.map(|_| next_address_check),
+                self.probe.map(|_| next_probe),
+                self.roaming.map(|_| next_roaming_check),
+            ]
+            .into_iter()
+            .flatten()
+            .min();
+            let message = match deadline {
+                Some(deadline) => {
+                    let wait = deadline.saturating_duration_since(Instant::now());
+                    match with_timeout(wait, self.control.receive()).await {
+                        Ok(message) => message,
+                        Err(_) => {
+                            let now = Instant::now();
+                            if matches!(next_heartbeat, Some(due) if now >= due) {
+                                next_heartbeat = self.send_heartbeats().await;
+                            }
+                            if let Some(timeout) = self.idle_timeout {
+                                if now >= next_idle_check {
+                                    self.close_idle(timeout).await;
+                                    next_idle_check = now + IDLE_CHECK_INTERVAL;
+                                }
+                            }
+                            if let Some(config) = &self.link_monitor {
+                                if now >= next_link_check {
+                                    self.check_link(config).await;
+                                    next_link_check = now + config.interval;
+                                }
+                            }
+                            if let Some(interval) = self.address_check {
+                                if now >= next_address_check {
+                                    self.check_address().await;
+                                    next_address_check = now + interval;
+                                }
+                            }
+                            if let Some(config) = &self.probe {
+                                if now >= next_probe {
+                                    // A hosted network has no gateway to probe, and rejoining
+                                    // would undo a switch to it.
+                                    if !self.hosting().await
+                                        && self.check_probe(config).await
+                                        && config.rejoin
+                                    {
+                                        warn!("Link is down, rejoining");
+                                        self.reset(&mut mode).await?;
+                                    }
+                                    next_probe = Instant::now() + config.interval;
+                                }
+                            }
+                            if let (Some(config), Mode::Station(provider)) =
+                                (&self.roaming, &mut mode)
+                            {
+                                if now >= next_roaming_check {
+                                    let roamed = if self.hosting().await {
+                                        Ok(())
+                                    } else {
+                                        self.check_roaming(config, provider).await
+                                    };
+                                    if let Err(e) = roamed {
+                                        warn!("Roaming failed, rejoining: {:?}", e);
+                                        self.reset(&mut mode).await?;
+                                    }
+                                    next_roaming_check = Instant::now() + config.interval;
+                                }
+                            }
+                            continue;
+                        }
+                    }
+                }
+                None => self.control.receive().await,
+            };
+            match message {
+                Message::Close(id) => {
+                    self.socket_closed(&*self.adapter.lock().await, id, CloseReason::Local);
                     let mut retries = 3;
                     while retries > 0 {
                         let mut adapter = self.adapter.lock().await;
-                        match with_timeout(Duration::from_secs(10), adapter.close(id)).await {
+                        match with_timeout(CLOSE_TIMEOUT, adapter.close(id)).await {
                             Ok(r) => {
                                 if let Err(e) = r {
                                     warn!("Error closing connection {}: {:?}", id, e);
@@ -791,9 +2213,17 @@ where
                             }
                         }
                     }
-                    // Resetting adapter to get it out of the bad state.
+                    // Recovering adapter to get it out of the bad state.
                     if retries == 0 {
-                        self.reset(ssid, psk).await?;
+                        self.recover(&mut mode).await?;
+                    }
+                }
+                Message::Heartbeat => next_heartbeat = self.next_heartbeat().await,
+                Message::SoftReset => {
+                    let restarted = self.adapter.lock().await.soft_reset().await;
+                    if !restarted || self.bring_up(&mut mode).await.is_err() {
+                        warn!("Soft reset failed, recovering");
+                        self.recover(&mut mode).await?;
                     }
                 }
             }
@@ -801,143 +2231,772 @@ where
     }
 }
 
+impl<SPI, CS, RESET, WAKEUP, READY, const SOCKETS: usize, M>
+    EsWifi<SpiTransport<SPI, CS, READY>, RESET, WAKEUP, SOCKETS, M>
+where
+    SPI: SpiBus<u8>,
+    CS: OutputPin,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    READY: InputPin + Wait,
+    M: RawMutex,
+{
+    /// Create a new instance of the driver.
+    pub fn new(spi: SPI, cs: CS, reset: RESET, wakeup: WAKEUP, ready: READY) -> Self {
+        Self::with_transport(SpiTransport::new(spi, cs, ready), Some(reset), Some(wakeup))
+    }
+}
+
+impl<SPI, CS, WAKEUP, READY, const SOCKETS: usize, M>
+    EsWifi<SpiTransport<SPI, CS, READY>, NoPin, WAKEUP, SOCKETS, M>
+where
+    SPI: SpiBus<u8>,
+    CS: OutputPin,
+    WAKEUP: OutputPin,
+    READY: InputPin + Wait,
+    M: RawMutex,
+{
+    /// Create a new instance of the driver for a board whose RESET line is not wired to
+    /// the host. The module is restarted with its software reset command instead.
+    pub fn new_without_reset(spi: SPI, cs: CS, wakeup: WAKEUP, ready: READY) -> Self {
+        Self::with_transport(SpiTransport::new(spi, cs, ready), None, Some(wakeup))
+    }
+}
+
+impl<SPI, CS, RESET, READY, const SOCKETS: usize, M>
+    EsWifi<SpiTransport<SPI, CS, READY>, RESET, NoPin, SOCKETS, M>
+where
+    SPI: SpiBus<u8>,
+    CS: OutputPin,
+    RESET: OutputPin,
+    READY: InputPin + Wait,
+    M: RawMutex,
+{
+    /// Create a new instance of the driver for a board whose WAKEUP line is strapped
+    /// rather than wired to the host.
+    pub fn new_without_wakeup(spi: SPI, cs: CS, reset: RESET, ready: READY) -> Self {
+        Self::with_transport(SpiTransport::new(spi, cs, ready), Some(reset), None)
+    }
+}
+
+impl<SPI, CS, READY, const SOCKETS: usize, M>
+    EsWifi<SpiTransport<SPI, CS, READY>, NoPin, NoPin, SOCKETS, M>
+where
+    SPI: SpiBus<u8>,
+    CS: OutputPin,
+    READY: InputPin + Wait,
+    M: RawMutex,
+{
+    /// Create a new instance of the driver for a board where neither the RESET nor the
+    /// WAKEUP line is wired to the host.
+    pub fn new_without_control_pins(spi: SPI, cs: CS, ready: READY) -> Self {
+        Self::with_transport(SpiTransport::new(spi, cs, ready), None, None)
+    }
+}
+
+/// Placeholder for a pin of the module that is not connected to the host.
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NoPin;
+
+impl embedded_hal::digital::ErrorType for NoPin {
+    type Error = core::convert::Infallible;
+}
+
+impl OutputPin for NoPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
 /// Socket representing a single connection.
-pub struct EsWifiSocket<'a, SPI, CS, RESET, WAKEUP, READY>
+///
+/// The driver does not buffer outgoing data: each `write` is handed to the module right
+/// away, in `S3` chunks whose size is probed with [`EsWifi::set_write_probe`] or else
+/// 1200 bytes, and the module sends each chunk as soon as it has taken it. The firmware
+/// has no option to coalesce small writes, but a write may return having handed over
+/// only part of the data while the module's buffer is full; see
+/// [`set_nodelay`](Self::set_nodelay) for pushing every write out whole.
+pub struct EsWifiSocket<'a, TRANSPORT, RESET, WAKEUP, const SOCKETS: usize = 4, M = NoopRawMutex>
 where
-    SPI: SpiBus<u8> + 'a,
-    CS: OutputPin + 'a,
+    TRANSPORT: Transport + 'a,
     RESET: OutputPin + 'a,
     WAKEUP: OutputPin + 'a,
-    READY: InputPin + Wait + 'a,
+    M: RawMutex,
 {
-    handle: u8,
-    adapter: &'a EsWifi<SPI, CS, RESET, WAKEUP, READY>,
-    control: DynamicSender<'a, Control>,
-    connect_timeout: Duration,
+    handle: SocketHandle,
+    protocol: Protocol,
+    adapter: &'a EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>,
+    control: DynamicSender<'a, Message>,
+    write_timeout: Duration,
+    stats: SocketStats,
+    timings: ConnectTimings,
+    rate_limit: Option<TokenBucket>,
+    progress: Option<&'a dyn Fn(usize)>,
+    nodelay: bool,
+    /// Whether the socket was closed with [`close`](Self::close), so dropping it has
+    /// nothing left to do.
+    closed: bool,
 }
 
-impl<SPI, CS, RESET, WAKEUP, READY> embedded_nal_async::TcpConnect
-    for EsWifi<SPI, CS, RESET, WAKEUP, READY>
+impl<'a, TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> Debug
+    for EsWifiSocket<'a, TRANSPORT, RESET, WAKEUP, SOCKETS, M>
 where
-    SPI: SpiBus<u8>,
-    CS: OutputPin,
+    TRANSPORT: Transport + 'a,
+    RESET: OutputPin + 'a,
+    WAKEUP: OutputPin + 'a,
+    M: RawMutex,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("EsWifiSocket")
+            .field("handle", &self.handle)
+            .field("protocol", &self.protocol)
+            .finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<'a, TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> defmt::Format
+    for EsWifiSocket<'a, TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport + 'a,
+    RESET: OutputPin + 'a,
+    WAKEUP: OutputPin + 'a,
+    M: RawMutex,
+{
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "EsWifiSocket {{ handle: {}, protocol: {} }}",
+            self.handle,
+            self.protocol
+        )
+    }
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> embedded_nal_async::TcpConnect
+    for EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
     RESET: OutputPin,
     WAKEUP: OutputPin,
-    READY: InputPin + Wait,
+    M: RawMutex,
 {
     type Error = SocketError;
-    type Connection<'m> = EsWifiSocket<'m, SPI, CS, RESET, WAKEUP, READY> where Self: 'm;
-
-    async fn connect<'m>(&'m self, remote: SocketAddr) -> Result<Self::Connection<'m>, Self::Error>
-    where
-        Self: 'm,
-    {
-        let handle = self.new_socket().await?;
-        let mut socket = EsWifiSocket {
-            handle,
-            adapter: self,
-            control: self.control.sender().into(),
-            connect_timeout: Duration::from_secs(60),
+    type Connection<'m> = EsWifiSocket<'m, TRANSPORT, RESET, WAKEUP, SOCKETS, M> where Self: 'm;
+
+    async fn connect<'m>(
+        &'m self,
+        remote: SocketAddr,
+    ) -> Result<Self::Connection<'m>, Self::Error> {
+        let Some(proxy) = &self.proxy else {
+            return self
+                .open(remote, Protocol::Tcp, self.connect_config, None)
+                .await;
         };
-        socket.connect(remote).await?;
+        let mut socket = self
+            .open(proxy.address, Protocol::Tcp, self.connect_config, None)
+            .await?;
+        socket
+            .proxy_connect(
+                &proxy::target(remote),
+                proxy.credentials.as_ref(),
+                self.connect_config.timeout,
+            )
+            .await?;
         Ok(socket)
     }
 }
 
-impl<'a, SPI, CS, RESET, WAKEUP, READY> EsWifiSocket<'a, SPI, CS, RESET, WAKEUP, READY>
+impl<'a, TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M>
+    EsWifiSocket<'a, TRANSPORT, RESET, WAKEUP, SOCKETS, M>
 where
-    SPI: SpiBus<u8> + 'a,
-    CS: OutputPin + 'a,
+    TRANSPORT: Transport + 'a,
     RESET: OutputPin + 'a,
     WAKEUP: OutputPin + 'a,
-    READY: InputPin + Wait + 'a,
+    M: RawMutex,
 {
-    async fn connect(&mut self, remote: SocketAddr) -> Result<(), SocketError> {
-        let timeout = Instant::now() + self.connect_timeout;
-        while Instant::now() < timeout {
+    /// Handle of the module socket backing this connection.
+    pub fn handle(&self) -> SocketHandle {
+        self.handle
+    }
+
+    /// Give up on writes the module has not accepted within `timeout`, reporting
+    /// [`SocketError::TimedOut`]. Defaults to 10 seconds.
+    ///
+    /// While waiting, the driver is released between attempts so other sockets keep
+    /// working when the peer of this one stalls. Data already handed to the module is not
+    /// cut short, as that would leave its response unread.
+    pub fn set_write_timeout(&mut self, timeout: Duration) {
+        self.write_timeout = timeout;
+    }
+
+    /// Limit the rate data is written to this socket, on top of any limit set for all
+    /// sockets with [`EsWifi::set_rate_limit`]. Unlimited by default.
+    pub fn set_rate_limit(&mut self, limit: Option<RateLimit>) {
+        self.rate_limit = limit.map(|limit| TokenBucket::new(limit, Instant::now()));
+    }
+
+    /// Call `progress` with the number of bytes the module accepted, for each chunk
+    /// written, so large uploads can report how far along they are. Chunks are as large
+    /// as the firmware takes, see [`EsWifi::set_write_probe`].
+    pub fn set_progress(&mut self, progress: Option<&'a dyn Fn(usize)>) {
+        self.progress = progress;
+    }
+
+    /// Hand each write to the module whole before returning, for latency-sensitive
+    /// traffic. Off by default.
+    ///
+    /// The module sends whatever it takes right away, but while its buffer is full a write
+    /// otherwise returns with only part of the data taken, and the rest waits for the next
+    /// call. With `nodelay`, the write keeps going until all of it is taken, each
+    /// attempt bound by the write timeout, see [`set_write_timeout`](Self::set_write_timeout).
+    pub fn set_nodelay(&mut self, nodelay: bool) {
+        self.nodelay = nodelay;
+    }
+
+    /// Bytes transferred, retries and errors since the socket was opened.
+    pub fn stats(&self) -> SocketStats {
+        self.stats
+    }
+
+    /// Time taken to establish the connection, all zero for sockets that were bound
+    /// rather than connected.
+    pub fn timings(&self) -> ConnectTimings {
+        self.timings
+    }
+
+    /// Close the socket, waiting up to 10 seconds for the module to confirm.
+    ///
+    /// Dropping a socket only asks the run loop to close it some time later, so the
+    /// module may still be closing it when its handle is handed out again. Once this
+    /// returns `Ok`, the socket is closed and its handle free. Should closing fail or
+    /// take too long, the run loop takes over as if the socket had been dropped.
+    pub async fn close(mut self) -> Result<(), SocketError> {
+        let span = Span::begin("close", self.handle, 0);
+        let closed = {
             let mut adapter = self.adapter.adapter.lock().await;
+            self.adapter
+                .socket_closed(&adapter, self.handle, CloseReason::Local);
+            with_timeout(CLOSE_TIMEOUT, adapter.close(self.handle)).await
+        };
+        let closed = match closed {
+            Ok(Ok(())) => {
+                self.closed = true;
+                Ok(())
+            }
+            Ok(Err(e)) => Err(e),
+            Err(_) => {
+                warn!("[{}] Timed out closing connection", self.handle);
+                Err(SocketError::TimedOut)
+            }
+        };
+        span.end(&closed);
+        closed
+    }
 
-            if adapter.is_connected(self.handle)? {
-                adapter.close(self.handle).await?;
+    /// Whether the driver still considers this socket connected.
+    ///
+    /// This only consults the driver's own bookkeeping and does not talk to the module.
+    pub async fn is_open(&self) -> bool {
+        let adapter = self.adapter.adapter.lock().await;
+        adapter.socket_pool.is_connected(self.handle)
+    }
+
+    /// Wait for a client to connect to a socket opened with [`EsWifi::tcp_listen`],
+    /// returning its address. Reads and writes then go to that client.
+    ///
+    /// Returns right away while a client is still attached, so once done with it, call
+    /// [`disconnect`](Self::disconnect) before waiting for the next one. A client that
+    /// stays connected otherwise keeps every other client out.
+    pub async fn accept(&mut self) -> Result<SocketAddr, SocketError> {
+        if self.protocol != Protocol::Tcp {
+            return Err(SocketError::Unsupported);
+        }
+        loop {
+            {
+                let mut adapter = self.adapter.adapter.lock().await;
+                if !adapter.socket_pool.is_listening(self.handle) {
+                    return Err(SocketError::Unsupported);
+                }
+                if let Some(remote) = adapter.client(self.handle).await? {
+                    adapter.socket_pool.set_connected(self.handle);
+                    adapter.socket_pool.set_remote(self.handle, remote);
+                    self.adapter.notify(Event::SocketConnected {
+                        handle: self.handle,
+                        remote,
+                    });
+                    return Ok(remote);
+                }
             }
+            Timer::after(ACCEPT_POLL_INTERVAL).await;
+        }
+    }
 
-            match with_timeout(self.connect_timeout, adapter.connect(self.handle, remote)).await {
-                Ok(Err(_e)) => {
-                    Timer::after(Duration::from_millis(100)).await;
+    /// Drop the client attached to a socket opened with [`EsWifi::tcp_listen`], restarting
+    /// the module's server so that [`accept`](Self::accept) waits for a new client.
+    pub async fn disconnect(&mut self) -> Result<(), SocketError> {
+        if self.protocol != Protocol::Tcp {
+            return Err(SocketError::Unsupported);
+        }
+        let mut adapter = self.adapter.adapter.lock().await;
+        if !adapter.socket_pool.is_listening(self.handle) {
+            return Err(SocketError::Unsupported);
+        }
+        adapter.restart_server(self.handle).await
+    }
+
+    /// Receive datagrams sent to the multicast `group` on a socket opened with
+    /// [`EsWifi::udp_bind`].
+    pub async fn join_multicast(&self, group: Ipv4Addr) -> Result<(), SocketError> {
+        if self.protocol != Protocol::Udp {
+            return Err(SocketError::Unsupported);
+        }
+        if !group.is_multicast() {
+            return Err(SocketError::InvalidAddress);
+        }
+        let mut adapter = self.adapter.adapter.lock().await;
+        adapter.set_multicast(self.handle, Some(group)).await
+    }
+
+    /// Stop receiving datagrams sent to the multicast `group`.
+    pub async fn leave_multicast(&self, group: Ipv4Addr) -> Result<(), SocketError> {
+        if self.protocol != Protocol::Udp {
+            return Err(SocketError::Unsupported);
+        }
+        if !group.is_multicast() {
+            return Err(SocketError::InvalidAddress);
+        }
+        let mut adapter = self.adapter.adapter.lock().await;
+        adapter.set_multicast(self.handle, None).await
+    }
+
+    /// Wait for a single datagram and copy it into `buf`, returning its length and the
+    /// address it was sent from.
+    ///
+    /// Unlike `read`, datagrams are never merged or split. A datagram larger than `buf`
+    /// is dropped and reported as [`SocketError::DatagramTooLarge`].
+    pub async fn recv_from(&mut self, buf: &mut [u8]) -> Result<(usize, SocketAddr), SocketError> {
+        if self.protocol != Protocol::Udp {
+            return Err(SocketError::Unsupported);
+        }
+        let span = Span::begin("recv_from", self.handle, buf.len());
+        let received = self.poll_recv_from(buf).await;
+        span.end(&received);
+        received
+    }
+
+    async fn poll_recv_from(&mut self, buf: &mut [u8]) -> Result<(usize, SocketAddr), SocketError> {
+        loop {
+            let received = {
+                let mut adapter = self.adapter.adapter.lock().await;
+                adapter.recv_from(self.handle, buf).await
+            };
+            match self.record(received)? {
+                Some((len, remote)) => {
+                    self.stats.bytes_received += len as u64;
+                    return Ok((len, remote));
                 }
-                Ok(r) => return r,
-                Err(_) => return Err(SocketError::ConnectError),
+                None => Timer::after(RECV_POLL_INTERVAL).await,
+            }
+        }
+    }
+
+    /// Read like `read`, also returning when the data was received, for measuring latency
+    /// or protocols sensitive to timing.
+    pub async fn read_with_meta(&mut self, buf: &mut [u8]) -> Result<(usize, RxMeta), SocketError> {
+        let span = Span::begin("read", self.handle, buf.len());
+        let read = {
+            let mut adapter = self.adapter.adapter.lock().await;
+            adapter.read(self.handle, buf).await
+        };
+        let received_at = Instant::now();
+        span.end(&read);
+        let len = self.record(read)?;
+        self.stats.bytes_received += len as u64;
+        Ok((len, RxMeta { received_at }))
+    }
+
+    /// Count `result` in the socket's error statistics.
+    fn record<T>(&mut self, result: Result<T, SocketError>) -> Result<T, SocketError> {
+        if result.is_err() {
+            self.stats.errors += 1;
+        }
+        result
+    }
+
+    /// Connection state of this socket, confirmed with the module when the driver
+    /// believes it to be connected.
+    pub async fn state(&self) -> Result<ConnectionState, SocketError> {
+        let mut adapter = self.adapter.adapter.lock().await;
+        let state = adapter.state(self.handle).await;
+        if let Ok(ConnectionState::Disconnected) = state {
+            self.adapter
+                .socket_closed(&adapter, self.handle, CloseReason::Peer);
+        }
+        state
+    }
+
+    /// Connect to `remote`, retrying as `config` says. With an `attempt_timeout`, each
+    /// attempt the module has not answered in time is given up on.
+    async fn connect(
+        &mut self,
+        remote: SocketAddr,
+        config: ConnectConfig,
+        tls: Option<&TlsConfig<'_>>,
+        attempt_timeout: Option<Duration>,
+    ) -> Result<(), SocketError> {
+        let span = Span::begin("connect", self.handle, 0);
+        let start = Instant::now();
+        let connected = self.try_connect(remote, config, tls, attempt_timeout).await;
+        span.end(&connected);
+        let opened = self.record(connected)?;
+        self.timings.connect = start.elapsed();
+        self.timings.handshake = tls.map(|_| opened);
+        self.adapter.notify(Event::SocketConnected {
+            handle: self.handle,
+            remote,
+        });
+        Ok(())
+    }
+
+    async fn try_connect(
+        &mut self,
+        remote: SocketAddr,
+        config: ConnectConfig,
+        tls: Option<&TlsConfig<'_>>,
+        attempt_timeout: Option<Duration>,
+    ) -> Result<Duration, SocketError> {
+        let timeout = Instant::now() + config.timeout;
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let result = {
+                let mut adapter = self.adapter.adapter.lock().await;
+
+                if adapter.is_connected(self.handle)? {
+                    adapter.close(self.handle).await?;
+                }
+
+                // Cancelling an attempt midway would leave its response unread, so the
+                // deadline is only checked between attempts, unless each attempt has its
+                // own timeout.
+                adapter
+                    .connect(self.handle, remote, self.protocol, tls, attempt_timeout)
+                    .await
+            };
+
+            match result {
+                Err(e) if !e.is_transient() => return Err(e),
+                Err(e) => {
+                    let exhausted = matches!(config.max_attempts, Some(max) if attempt >= max);
+                    if exhausted || Instant::now() + config.retry_interval >= timeout {
+                        debug!(
+                            "[{}] Giving up after {} connect attempts",
+                            self.handle, attempt
+                        );
+                        return Err(e);
+                    }
+                    self.stats.retries += 1;
+                    Timer::after(config.retry_interval).await;
+                }
+                Ok(opened) => return Ok(opened),
             }
         }
-        Err(SocketError::ConnectError)
     }
 }
 
-impl<'a, SPI, CS, RESET, WAKEUP, READY> embedded_io::Io
-    for EsWifiSocket<'a, SPI, CS, RESET, WAKEUP, READY>
+impl<'a, TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> embedded_io::ErrorType
+    for EsWifiSocket<'a, TRANSPORT, RESET, WAKEUP, SOCKETS, M>
 where
-    SPI: SpiBus<u8> + 'a,
-    CS: OutputPin + 'a,
+    TRANSPORT: Transport + 'a,
     RESET: OutputPin + 'a,
     WAKEUP: OutputPin + 'a,
-    READY: InputPin + Wait + 'a,
+    M: RawMutex,
 {
     type Error = SocketError;
 }
 
 impl embedded_io::Error for SocketError {
     fn kind(&self) -> embedded_io::ErrorKind {
-        embedded_io::ErrorKind::Other
+        match self {
+            SocketError::ConnectionRefused => embedded_io::ErrorKind::ConnectionRefused,
+            SocketError::ConnectTimeout | SocketError::TimedOut => embedded_io::ErrorKind::TimedOut,
+            SocketError::SocketClosed => embedded_io::ErrorKind::NotConnected,
+            SocketError::InvalidAddress => embedded_io::ErrorKind::InvalidInput,
+            SocketError::Unsupported => embedded_io::ErrorKind::Unsupported,
+            SocketError::ProxyRejected => embedded_io::ErrorKind::ConnectionRefused,
+            SocketError::ProxyAuthenticationRequired => embedded_io::ErrorKind::PermissionDenied,
+            _ => embedded_io::ErrorKind::Other,
+        }
     }
 }
 
-impl<'a, SPI, CS, RESET, WAKEUP, READY> embedded_io::asynch::Write
-    for EsWifiSocket<'a, SPI, CS, RESET, WAKEUP, READY>
+impl<'a, TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> embedded_io_async::Write
+    for EsWifiSocket<'a, TRANSPORT, RESET, WAKEUP, SOCKETS, M>
 where
-    SPI: SpiBus<u8> + 'a,
-    CS: OutputPin + 'a,
+    TRANSPORT: Transport + 'a,
     RESET: OutputPin + 'a,
     WAKEUP: OutputPin + 'a,
-    READY: InputPin + Wait + 'a,
+    M: RawMutex,
 {
     async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
-        let mut adapter = self.adapter.adapter.lock().await;
-        adapter.write(self.handle, buf).await
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let span = Span::begin("write", self.handle, buf.len());
+        let written = if self.nodelay {
+            self.write_whole(buf).await
+        } else {
+            self.write_chunk(buf).await
+        };
+        span.end(&written);
+        written
     }
 
+    /// Nothing to do, as writes are never held back by the driver.
     async fn flush(&mut self) -> Result<(), Self::Error> {
         Ok(())
     }
 }
 
-impl<'a, SPI, CS, RESET, WAKEUP, READY> embedded_io::asynch::Read
-    for EsWifiSocket<'a, SPI, CS, RESET, WAKEUP, READY>
+impl<'a, TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M>
+    EsWifiSocket<'a, TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport + 'a,
+    RESET: OutputPin + 'a,
+    WAKEUP: OutputPin + 'a,
+    M: RawMutex,
+{
+    /// Write all of `buf`, returning short only when an error stops it after some of it
+    /// was taken.
+    async fn write_whole(&mut self, buf: &[u8]) -> Result<usize, SocketError> {
+        let mut pos = 0;
+        while pos < buf.len() {
+            match self.write_chunk(&buf[pos..]).await {
+                Ok(len) => pos += len,
+                Err(_) if pos > 0 => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(pos)
+    }
+
+    /// Write as much of `buf` as the module and the rate limits allow, waiting for the
+    /// module while its buffer is full.
+    async fn write_chunk(&mut self, buf: &[u8]) -> Result<usize, SocketError> {
+        let mut deadline = Instant::now() + self.write_timeout;
+        // Waiting on the rate limits pushes the deadline back, by at most the timeout
+        // again, so sockets starving each other of the shared limit still time out.
+        let latest = deadline + self.write_timeout;
+        loop {
+            let written = {
+                let mut adapter = self.adapter.adapter.lock().await;
+                let adapter = &mut *adapter;
+                let now = Instant::now();
+                let allowed = adapter
+                    .rate_limit
+                    .iter_mut()
+                    .chain(self.rate_limit.iter_mut())
+                    .try_fold(buf.len(), |len, bucket| {
+                        bucket.available(now).map(|available| available.min(len))
+                    });
+                match allowed {
+                    Ok(len) => {
+                        let mut progress = |accepted| {
+                            if let Some(progress) = self.progress {
+                                progress(accepted)
+                            }
+                        };
+                        // Cancelling the write midway would leave its response unread, so
+                        // the deadline is only checked between attempts.
+                        let written = adapter.write(self.handle, &buf[..len], &mut progress).await;
+                        if let Ok(len) = written {
+                            adapter
+                                .rate_limit
+                                .iter_mut()
+                                .chain(self.rate_limit.iter_mut())
+                                .for_each(|bucket| bucket.consume(len));
+                        }
+                        Ok(written)
+                    }
+                    Err(wait) => Err(wait),
+                }
+            };
+            let written = match written {
+                Ok(written) => written,
+                Err(wait) => {
+                    if Instant::now() + wait > latest {
+                        break;
+                    }
+                    deadline = (deadline + wait).min(latest);
+                    Timer::after(wait).await;
+                    continue;
+                }
+            };
+            match written {
+                Ok(0) if self.protocol != Protocol::Udp => {
+                    // The module answers the same for a connection that is gone, which
+                    // waiting would not bring back.
+                    if !matches!(self.state().await, Ok(ConnectionState::Connected)) {
+                        warn!("[{}] Connection lost while writing", self.handle);
+                        return self.record(Err(SocketError::WriteError));
+                    }
+                }
+                Ok(0) => {}
+                Ok(len) => {
+                    self.stats.bytes_sent += len as u64;
+                    return Ok(len);
+                }
+                Err(e) => return self.record(Err(e)),
+            }
+            // Module buffer is full: release the adapter so other sockets can make
+            // progress while it drains, then try again.
+            if Instant::now() + WRITE_BACKOFF >= deadline {
+                break;
+            }
+            self.stats.retries += 1;
+            Timer::after(WRITE_BACKOFF).await;
+        }
+        warn!(
+            "[{}] Timed out waiting for module to accept data",
+            self.handle
+        );
+        self.record(Err(SocketError::TimedOut))
+    }
+}
+
+impl<'a, TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> embedded_io_async::Read
+    for EsWifiSocket<'a, TRANSPORT, RESET, WAKEUP, SOCKETS, M>
 where
-    SPI: SpiBus<u8> + 'a,
-    CS: OutputPin + 'a,
+    TRANSPORT: Transport + 'a,
     RESET: OutputPin + 'a,
     WAKEUP: OutputPin + 'a,
-    READY: InputPin + Wait + 'a,
+    M: RawMutex,
 {
     async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-        let mut adapter = self.adapter.adapter.lock().await;
-        adapter.read(self.handle, buf).await
+        self.read_with_meta(buf).await.map(|(len, _)| len)
     }
 }
 
-impl<'a, SPI, CS, RESET, WAKEUP, READY> Drop for EsWifiSocket<'a, SPI, CS, RESET, WAKEUP, READY>
+impl<'a, TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> Drop
+    for EsWifiSocket<'a, TRANSPORT, RESET, WAKEUP, SOCKETS, M>
 where
-    SPI: SpiBus<u8> + 'a,
-    CS: OutputPin + 'a,
+    TRANSPORT: Transport + 'a,
     RESET: OutputPin + 'a,
     WAKEUP: OutputPin + 'a,
-    READY: InputPin + Wait + 'a,
+    M: RawMutex,
 {
     fn drop(&mut self) {
-        let _ = self.control.try_send(Control::Close(self.handle));
+        if !self.closed {
+            let _ = self.control.try_send(Message::Close(self.handle));
+        }
     }
 }
 
-enum Control {
-    Close(u8),
+enum Message {
+    Close(SocketHandle),
+    SoftReset,
+    /// A socket's heartbeat changed.
+    Heartbeat,
+}
+
+/// Events reported by the run loop and sockets, see [`EsWifi::next_event`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// Signal strength dropped to the link monitor's low threshold
+    SignalLow {
+        /// Signal strength, in dBm
+        rssi: i8,
+    },
+    /// Signal strength recovered after having been reported low
+    SignalRecovered {
+        /// Signal strength, in dBm
+        rssi: i8,
+    },
+    /// The keep-alive probe target stopped answering
+    LinkDown,
+    /// The module's address changed, such as when a DHCP lease was renewed with another
+    /// address
+    IpChanged {
+        /// Address before the change
+        old: IpAddr,
+        /// Address after the change
+        new: IpAddr,
+    },
+    /// Reassociated with a stronger access point of the same network
+    Roamed {
+        /// MAC address of the new access point
+        bssid: [u8; 6],
+        /// Signal strength of the new access point, in dBm
+        rssi: i8,
+    },
+    /// The module stopped responding and was brought back
+    Recovered {
+        /// Least disruptive measure that brought the module back
+        level: RecoveryLevel,
+    },
+    /// A socket was opened
+    SocketOpened {
+        /// Handle of the socket
+        handle: SocketHandle,
+    },
+    /// A socket connected to its remote host
+    SocketConnected {
+        /// Handle of the socket
+        handle: SocketHandle,
+        /// Address of the remote host
+        remote: SocketAddr,
+    },
+    /// A socket was closed. Reported once per socket, for the first reason that applied.
+    SocketClosed {
+        /// Handle of the socket
+        handle: SocketHandle,
+        /// Address of the remote host, if the socket had connected
+        remote: Option<SocketAddr>,
+        /// Why the socket was closed
+        reason: CloseReason,
+    },
+    /// The module switched between joining a network and hosting one, see
+    /// [`Control::switch_to_ap`] and [`Control::switch_to_station`]
+    ModeChanged {
+        /// Mode switched to
+        mode: WifiMode,
+    },
+}
+
+/// Why a socket was closed, see [`Event::SocketClosed`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CloseReason {
+    /// The socket was dropped by the application
+    Local,
+    /// The driver closed the socket after it was idle for too long
+    Idle,
+    /// The remote host closed the connection, as found when checking the socket's state
+    Peer,
+    /// The module was restarted, dropping all its connections
+    Reset,
+    /// The network was left through [`Control::leave`]
+    Left,
+    /// The socket was parked before the host went to sleep, see
+    /// [`EsWifi::prepare_for_host_sleep`]
+    Parked,
+    /// The module switched between joining a network and hosting one, see
+    /// [`Control::switch_to_ap`] and [`Control::switch_to_station`]
+    ModeChanged,
+    /// The module moved to a stronger access point of the network, see
+    /// [`EsWifi::set_roaming`]
+    Roamed,
+}
+
+/// How the module is brought up after a reset.
+enum Mode<'a, P> {
+    Station(P),
+    AccessPoint(ApConfig<'a>),
 }
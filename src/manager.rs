@@ -0,0 +1,111 @@
+//! Radio-neutral interface for managing the WiFi connection, so firmware supporting
+//! several radio modules can be written once against it.
+
+use crate::{Control, Event, JoinError, ScanError, ScanResult, Transport};
+use core::fmt::Debug;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embedded_hal::digital::OutputPin;
+
+/// Changes in the state of the link reported by a [`WifiManager`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum LinkEvent {
+    /// The network stopped carrying traffic
+    Down,
+    /// Signal strength dropped below the usable level
+    SignalLow {
+        /// Signal strength, in dBm
+        rssi: i8,
+    },
+    /// Signal strength recovered after having been reported low
+    SignalRecovered {
+        /// Signal strength, in dBm
+        rssi: i8,
+    },
+    /// Moved to another access point of the same network
+    Roamed {
+        /// MAC address of the new access point
+        bssid: [u8; 6],
+    },
+}
+
+/// Joining, leaving and scanning for networks, as offered by any WiFi radio driver.
+///
+/// Methods take no generic parameters, so the trait stays usable behind a boxing adapter
+/// where dynamic dispatch is needed.
+#[allow(async_fn_in_trait)]
+pub trait WifiManager {
+    /// Error joining or scanning
+    type Error: Debug;
+
+    /// Join the network `ssid` with passphrase `psk`, returning once an address has been
+    /// obtained.
+    async fn join(&mut self, ssid: &str, psk: &str) -> Result<(), Self::Error>;
+
+    /// Disconnect from the network joined.
+    async fn leave(&mut self);
+
+    /// Scan for access points in range, calling `on_result` for each one found.
+    async fn scan(&mut self, on_result: &mut dyn FnMut(ScanResult)) -> Result<(), Self::Error>;
+
+    /// Wait for the next change in the state of the link.
+    async fn next_link_event(&mut self) -> LinkEvent;
+}
+
+/// Errors of the [`WifiManager`] implemented by [`Control`].
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WifiManagerError {
+    /// Joining the network failed
+    Join(JoinError),
+    /// Scanning failed
+    Scan(ScanError),
+}
+
+/// Link events are taken from the driver's event queue, see
+/// [`EsWifi::next_event`](crate::EsWifi::next_event). Other events are discarded, so
+/// events should be read through one or the other, not both.
+impl<'a, TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> WifiManager
+    for Control<'a, TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    type Error = WifiManagerError;
+
+    async fn join(&mut self, ssid: &str, psk: &str) -> Result<(), WifiManagerError> {
+        Control::join(self, ssid, psk)
+            .await
+            .map(|_| ())
+            .map_err(WifiManagerError::Join)
+    }
+
+    async fn leave(&mut self) {
+        Control::leave(self).await
+    }
+
+    async fn scan(
+        &mut self,
+        on_result: &mut dyn FnMut(ScanResult),
+    ) -> Result<(), WifiManagerError> {
+        Control::scan(self, on_result)
+            .await
+            .map_err(WifiManagerError::Scan)
+    }
+
+    async fn next_link_event(&mut self) -> LinkEvent {
+        loop {
+            let event = match self.driver.next_event().await {
+                Event::LinkDown => LinkEvent::Down,
+                Event::SignalLow { rssi } => LinkEvent::SignalLow { rssi },
+                Event::SignalRecovered { rssi } => LinkEvent::SignalRecovered { rssi },
+                Event::Roamed { bssid, .. } => LinkEvent::Roamed { bssid },
+                _ => continue,
+            };
+            return event;
+        }
+    }
+}
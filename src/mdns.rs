@@ -0,0 +1,171 @@
+//! Minimal mDNS responder, answering `A` queries for a single `<hostname>.local` name.
+//!
+//! The eS-WiFi firmware has no mDNS support of its own, so packets are built and matched
+//! here and exchanged over the driver's UDP sockets.
+
+use core::net::Ipv4Addr;
+
+/// mDNS multicast group.
+pub(crate) const GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+
+/// mDNS port.
+pub(crate) const PORT: u16 = 5353;
+
+/// Time-to-live advertised for the host record, in seconds.
+const TTL: u32 = 120;
+
+const TYPE_A: u16 = 1;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+const CACHE_FLUSH: u16 = 0x8000;
+const UNICAST_RESPONSE: u16 = 0x8000;
+
+/// Longest hostname label allowed by DNS.
+pub(crate) const MAX_HOSTNAME: usize = 63;
+
+/// Whether `hostname` can be announced as a single label under `.local`.
+pub(crate) fn is_valid_hostname(hostname: &str) -> bool {
+    !hostname.is_empty()
+        && hostname.len() <= MAX_HOSTNAME
+        && hostname
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'-')
+}
+
+/// Write an unsolicited response announcing `hostname.local` at `ip` into `out`,
+/// returning its length.
+///
+/// `out` must hold at least `MAX_HOSTNAME + 34` bytes.
+pub(crate) fn announcement(hostname: &str, ip: Ipv4Addr, out: &mut [u8]) -> usize {
+    let mut pos = 0;
+    let mut put = |bytes: &[u8]| {
+        out[pos..pos + bytes.len()].copy_from_slice(bytes);
+        pos += bytes.len();
+    };
+
+    // id, flags (authoritative response), 0 questions, 1 answer, 0 authority, 0 additional
+    put(&[0, 0, 0x84, 0, 0, 0, 0, 1, 0, 0, 0, 0]);
+    put(&[hostname.len() as u8]);
+    put(hostname.as_bytes());
+    put(&[5]);
+    put(b"local");
+    put(&[0]);
+    put(&TYPE_A.to_be_bytes());
+    put(&(CLASS_IN | CACHE_FLUSH).to_be_bytes());
+    put(&TTL.to_be_bytes());
+    put(&4u16.to_be_bytes());
+    put(&ip.octets());
+    pos
+}
+
+/// Whether the mDNS `packet` is a query asking for the address of `hostname.local`.
+pub(crate) fn is_query_for(packet: &[u8], hostname: &str) -> bool {
+    if packet.len() < 12 {
+        return false;
+    }
+    let flags = u16::from_be_bytes([packet[2], packet[3]]);
+    if flags & 0x8000 != 0 {
+        // A response, not a query
+        return false;
+    }
+    let questions = u16::from_be_bytes([packet[4], packet[5]]);
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        let mut labels = 0;
+        let mut matches = true;
+        loop {
+            let len = match packet.get(pos) {
+                Some(len) => *len as usize,
+                None => return false,
+            };
+            if len & 0xC0 != 0 {
+                // Compressed names only point back at earlier questions, which have
+                // already been checked.
+                matches = false;
+                pos += 2;
+                break;
+            }
+            pos += 1;
+            if len == 0 {
+                break;
+            }
+            let label = match packet.get(pos..pos + len) {
+                Some(label) => label,
+                None => return false,
+            };
+            let expected: &[u8] = match labels {
+                0 => hostname.as_bytes(),
+                1 => b"local",
+                _ => &[],
+            };
+            matches &= label.eq_ignore_ascii_case(expected);
+            labels += 1;
+            pos += len;
+        }
+        let (qtype, qclass) = match packet.get(pos..pos + 4) {
+            Some(q) => (
+                u16::from_be_bytes([q[0], q[1]]),
+                u16::from_be_bytes([q[2], q[3]]) & !UNICAST_RESPONSE,
+            ),
+            None => return false,
+        };
+        pos += 4;
+
+        if matches && labels == 2 && (qtype == TYPE_A || qtype == TYPE_ANY) && qclass == CLASS_IN {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(name: &[&str], qtype: u16) -> ([u8; 64], usize) {
+        let mut packet = [0u8; 64];
+        packet[5] = 1;
+        let mut pos = 12;
+        for label in name {
+            packet[pos] = label.len() as u8;
+            packet[pos + 1..pos + 1 + label.len()].copy_from_slice(label.as_bytes());
+            pos += 1 + label.len();
+        }
+        packet[pos] = 0;
+        pos += 1;
+        packet[pos..pos + 2].copy_from_slice(&qtype.to_be_bytes());
+        packet[pos + 2..pos + 4].copy_from_slice(&(CLASS_IN | UNICAST_RESPONSE).to_be_bytes());
+        (packet, pos + 4)
+    }
+
+    #[test]
+    fn test_query_matching() {
+        let (packet, len) = query(&["Device", "local"], TYPE_A);
+        assert!(is_query_for(&packet[..len], "device"));
+        assert!(!is_query_for(&packet[..len], "other"));
+
+        let (packet, len) = query(&["device", "local"], 28);
+        assert!(!is_query_for(&packet[..len], "device"));
+
+        let (packet, len) = query(&["device", "example", "local"], TYPE_ANY);
+        assert!(!is_query_for(&packet[..len], "device"));
+        assert!(!is_query_for(&packet[..len - 1], "device"));
+    }
+
+    #[test]
+    fn test_announcement() {
+        let mut out = [0u8; MAX_HOSTNAME + 34];
+        let len = announcement("device", Ipv4Addr::new(192, 168, 1, 20), &mut out);
+        assert_eq!(len, 12 + 1 + 6 + 1 + 5 + 1 + 10 + 4);
+        assert_eq!(&out[12..26], b"\x06device\x05local\x00");
+        assert_eq!(&out[len - 4..len], &[192, 168, 1, 20]);
+    }
+
+    #[test]
+    fn test_hostname_validation() {
+        assert!(is_valid_hostname("es-wifi-1"));
+        assert!(!is_valid_hostname(""));
+        assert!(!is_valid_hostname("my.device"));
+    }
+}
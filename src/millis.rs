@@ -0,0 +1,36 @@
+//! Durations in serialized configuration, as whole milliseconds.
+
+use embassy_time::Duration;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub(crate) fn serialize<S: Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    duration.as_millis().serialize(serializer)
+}
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Duration, D::Error> {
+    u64::deserialize(deserializer).map(Duration::from_millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ProbeConfig;
+    use embassy_time::Duration;
+
+    #[test]
+    fn test_duration_as_millis() {
+        let config: ProbeConfig = serde_json::from_str(
+            r#"{"target":"192.168.1.1","interval":1500,"max_failures":2,"rejoin":false}"#,
+        )
+        .unwrap();
+        assert_eq!(config.interval, Duration::from_millis(1500));
+        assert_eq!(config.max_failures, 2);
+
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(json.contains(r#""interval":1500"#));
+    }
+}
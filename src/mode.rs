@@ -0,0 +1,113 @@
+//! Switching the module between joining a network and hosting its own at runtime, such as
+//! for a "hold the button to re-provision" flow on a device already in service.
+
+use crate::{
+    parser, ApConfig, ApError, CloseReason, Control, Credentials, DriverState, EsWifi, Event,
+    JoinError, JoinInfo, Transport,
+};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embedded_hal::digital::OutputPin;
+
+/// Role of the module on the network, see [`Control::mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WifiMode {
+    /// Joined to a network as a station
+    Station,
+    /// Hosting its own network as a soft access point
+    AccessPoint,
+}
+
+impl<TRANSPORT, const SOCKETS: usize> DriverState<TRANSPORT, SOCKETS>
+where
+    TRANSPORT: Transport,
+{
+    /// Leave the network joined or stop the access point, whichever the module is in.
+    async fn leave_mode(&mut self) {
+        let command = match self.mode {
+            Some(WifiMode::Station) => command!(4, "CD"),
+            Some(WifiMode::AccessPoint) => command!(4, "AE"),
+            None => return,
+        };
+        match self.send_command(command).await {
+            Ok(response) if parser::is_ok(response) => {}
+            _ => warn!("Module did not confirm leaving its mode"),
+        }
+        self.join_info = None;
+        self.ap_address = None;
+        self.mode = None;
+        self.web_config = None;
+        self.dns_cache.flush();
+    }
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Leave the current mode, closing every socket for `reason`.
+    pub(crate) async fn leave_mode(&self, reason: CloseReason) {
+        let mut adapter = self.adapter.lock().await;
+        adapter.leave_mode().await;
+        for handle in adapter.socket_pool.force_close() {
+            self.socket_closed(&adapter, handle, reason);
+        }
+    }
+
+    /// Whether the module is hosting its own network, in which case there is no network
+    /// joined to probe or roam in.
+    pub(crate) async fn hosting(&self) -> bool {
+        self.adapter.lock().await.mode == Some(WifiMode::AccessPoint)
+    }
+}
+
+impl<'a, TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M>
+    Control<'a, TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Whether the module is joined to a network or hosting one, `None` if neither.
+    pub async fn mode(&self) -> Option<WifiMode> {
+        self.driver.adapter.lock().await.mode
+    }
+
+    /// Leave the network joined, or stop the access point, and host the network described
+    /// by `config` instead.
+    ///
+    /// Every socket is closed and reported with [`CloseReason::ModeChanged`], and
+    /// [`Event::ModeChanged`] is reported once the access point is up. Like a network
+    /// joined through this handle, the switch lasts until the run loop has to recover the
+    /// module, which brings it back up in the mode the run loop was started in.
+    pub async fn switch_to_ap(&self, config: &ApConfig<'_>) -> Result<(), ApError> {
+        config.validate()?;
+        self.driver.leave_mode(CloseReason::ModeChanged).await;
+        debug!("Starting soft access point...");
+        self.driver.adapter.lock().await.start_ap(config).await?;
+        debug!("Soft access point started");
+        self.driver.notify(Event::ModeChanged {
+            mode: WifiMode::AccessPoint,
+        });
+        Ok(())
+    }
+
+    /// Stop the access point, or leave the network joined, and join the network `ssid`
+    /// with passphrase `psk` instead.
+    ///
+    /// Every socket is closed and reported with [`CloseReason::ModeChanged`], and
+    /// [`Event::ModeChanged`] is reported once the network is joined.
+    pub async fn switch_to_station(&self, ssid: &str, psk: &str) -> Result<JoinInfo, JoinError> {
+        let credentials = Credentials::new(ssid, psk)?;
+        self.driver.leave_mode(CloseReason::ModeChanged).await;
+        let info = self.driver.join_network(&credentials).await?;
+        self.driver.notify(Event::ModeChanged {
+            mode: WifiMode::Station,
+        });
+        Ok(info)
+    }
+}
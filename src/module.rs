@@ -0,0 +1,313 @@
+//! Features of the module itself, beyond networking.
+
+use crate::{parser, CommandId, DriverState, Error, EsWifi, Transport, Wipe};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embedded_hal::digital::{OutputPin, PinState};
+use heapless::{FnvIndexMap, String, Vec};
+
+/// Snapshot of the module's settings, keyed by setting name.
+pub type Settings = FnvIndexMap<&'static str, String<64>, 32>;
+
+/// Names of the fields shown by `I?`.
+const INFO_FIELDS: &[Option<&str>] = &[
+    Some("product_id"),
+    Some("firmware_version"),
+    Some("api_version"),
+    Some("stack_version"),
+    Some("rtos_version"),
+    Some("cpu_clock"),
+    Some("product_name"),
+];
+
+/// Names of the fields shown by `C?`. The passphrase is left out of snapshots.
+const NETWORK_FIELDS: &[Option<&str>] = &[
+    Some("ssid"),
+    None,
+    Some("security"),
+    Some("dhcp"),
+    Some("ip_version"),
+    Some("ip"),
+    Some("netmask"),
+    Some("gateway"),
+    Some("dns1"),
+    Some("dns2"),
+    Some("join_retries"),
+    Some("auto_connect"),
+    Some("authentication"),
+    Some("country"),
+    Some("status"),
+];
+
+/// Errors for module level requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ModuleError {
+    /// The module rejected the request, or its response could not be parsed
+    Rejected,
+    /// Communication with the module failed
+    Transport,
+    /// Module firmware does not know the command
+    Unsupported(CommandId),
+}
+
+impl<TRANSPORT> From<Error<TRANSPORT>> for ModuleError {
+    fn from(e: Error<TRANSPORT>) -> Self {
+        match e {
+            Error::Unsupported(command) => ModuleError::Unsupported(command),
+            _ => ModuleError::Transport,
+        }
+    }
+}
+
+/// Outcome of [`EsWifi::health_check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HealthReport {
+    /// The module answered with its prompt over SPI.
+    pub spi: bool,
+    /// The firmware answered a request for its version.
+    pub firmware: bool,
+    /// The module is associated with a network, if checked.
+    pub associated: Option<bool>,
+    /// The gateway answered a ping, if checked.
+    pub gateway_reachable: Option<bool>,
+}
+
+impl HealthReport {
+    /// Whether every check that was run passed.
+    pub fn is_healthy(&self) -> bool {
+        self.spi
+            && self.firmware
+            && self.associated != Some(false)
+            && self.gateway_reachable != Some(false)
+    }
+}
+
+/// Antenna used by the module, as numbered by its `ZA` command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Antenna {
+    /// On-module chip antenna
+    Internal = 0,
+    /// External antenna connector
+    External = 1,
+    /// Switch between both antennas for the best signal
+    Diversity = 2,
+}
+
+impl<TRANSPORT, const SOCKETS: usize> DriverState<TRANSPORT, SOCKETS>
+where
+    TRANSPORT: Transport,
+{
+    /// Send a command expecting a plain `OK`.
+    pub(crate) async fn module_command<const N: usize>(
+        &mut self,
+        command: Vec<u8, N>,
+    ) -> Result<(), ModuleError> {
+        let response = self
+            .send_command(command)
+            .await
+            .map_err(ModuleError::from)?;
+        if parser::is_ok(response) {
+            Ok(())
+        } else {
+            Err(ModuleError::Rejected)
+        }
+    }
+
+    async fn set_module_gpio(&mut self, pin: u8, level: PinState) -> Result<(), ModuleError> {
+        let level: u8 = match level {
+            PinState::Low => 0,
+            PinState::High => 1,
+        };
+        self.module_command(command!(16, "G2=", pin, ",", level))
+            .await
+    }
+
+    async fn read_module_gpio(&mut self, pin: u8) -> Result<PinState, ModuleError> {
+        let response = self
+            .send_command(command!(16, "G3=", pin))
+            .await
+            .map_err(ModuleError::from)?;
+        match parser::value(response) {
+            Some(b"0") => Ok(PinState::Low),
+            Some(b"1") => Ok(PinState::High),
+            _ => Err(ModuleError::Rejected),
+        }
+    }
+
+    async fn persist_config(&mut self) -> Result<(), ModuleError> {
+        self.module_command(command!(4, "Z0")).await
+    }
+
+    /// Compare the network settings stored in flash, shown by `Z?`, with the ones in use,
+    /// shown by `C?`.
+    ///
+    /// Only the fields both show are compared, as `C?` also shows the state of the
+    /// connection and the addresses leased over DHCP.
+    async fn is_config_persisted(&mut self) -> Result<bool, ModuleError> {
+        let current: Wipe<Vec<u8, 256>> = self
+            .query_secret(command!(4, "C?"), |response| {
+                Ok::<_, ModuleError>(Wipe(
+                    Vec::from_slice(response).map_err(|_| ModuleError::Rejected)?,
+                ))
+            })
+            .await
+            .map_err(ModuleError::from)??;
+        let current = parser::network_config(&current.0).ok_or(ModuleError::Rejected)?;
+        self.query_secret(command!(4, "Z?"), |stored| {
+            let stored = parser::network_config(stored).ok_or(ModuleError::Rejected)?;
+            Ok(stored == current)
+        })
+        .await
+        .map_err(ModuleError::from)?
+    }
+
+    async fn dump_settings(&mut self) -> Result<Settings, ModuleError> {
+        let mut settings = Settings::new();
+        self.collect_settings(command!(4, "I?"), INFO_FIELDS, &mut settings)
+            .await?;
+        self.collect_settings(command!(4, "C?"), NETWORK_FIELDS, &mut settings)
+            .await?;
+        Ok(settings)
+    }
+
+    /// Add the comma separated fields shown by a command to `settings`, under `names`.
+    async fn collect_settings<const N: usize>(
+        &mut self,
+        command: Vec<u8, N>,
+        names: &[Option<&'static str>],
+        settings: &mut Settings,
+    ) -> Result<(), ModuleError> {
+        // Network settings include the passphrase.
+        self.query_secret(command, |response| {
+            let fields = parser::value(response).ok_or(ModuleError::Rejected)?;
+            for (name, field) in names.iter().zip(fields.split(|c| *c == b',')) {
+                if let Some(name) = name {
+                    let mut value = String::new();
+                    for c in field {
+                        if value.push(*c as char).is_err() {
+                            break;
+                        }
+                    }
+                    settings
+                        .insert(name, value)
+                        .map_err(|_| ModuleError::Rejected)?;
+                }
+            }
+            Ok(())
+        })
+        .await
+        .map_err(ModuleError::from)?
+    }
+
+    async fn health_check(&mut self, network: bool) -> HealthReport {
+        // A bare carriage return is an empty command, which the module answers with just
+        // its prompt, so it shows the bus works without involving the firmware.
+        let spi = matches!(
+            self.send_command(command!(2, "")).await,
+            Ok(response) if response.ends_with(b"> ")
+        );
+        let firmware = matches!(
+            self.send_command(command!(4, "I?")).await,
+            Ok(response) if parser::is_ok(response)
+        );
+        let mut report = HealthReport {
+            spi,
+            firmware,
+            associated: None,
+            gateway_reachable: None,
+        };
+        if network {
+            let associated = self.join_info.is_some() && self.rssi().await.is_some();
+            report.associated = Some(associated);
+            report.gateway_reachable = Some(match self.gateway().await {
+                Some(gateway) if associated => self.ping(gateway).await,
+                _ => false,
+            });
+        }
+        report
+    }
+
+    async fn set_antenna(&mut self, antenna: Antenna) -> Result<(), ModuleError> {
+        self.module_command(command!(8, "ZA=", antenna as usize))
+            .await
+    }
+
+    async fn antenna(&mut self) -> Result<Antenna, ModuleError> {
+        let response = self
+            .send_command(command!(4, "ZA?"))
+            .await
+            .map_err(ModuleError::from)?;
+        match parser::value(response) {
+            Some(b"0") => Ok(Antenna::Internal),
+            Some(b"1") => Ok(Antenna::External),
+            Some(b"2") => Ok(Antenna::Diversity),
+            _ => Err(ModuleError::Rejected),
+        }
+    }
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Drive one of the module's spare GPIOs, such as an LED wired to the module.
+    pub async fn set_module_gpio(&self, pin: u8, level: PinState) -> Result<(), ModuleError> {
+        self.adapter.lock().await.set_module_gpio(pin, level).await
+    }
+
+    /// Read the level of one of the module's spare GPIOs.
+    pub async fn read_module_gpio(&self, pin: u8) -> Result<PinState, ModuleError> {
+        self.adapter.lock().await.read_module_gpio(pin).await
+    }
+
+    /// Select the antenna used by the module, for boards with an external antenna
+    /// connector.
+    pub async fn set_antenna(&self, antenna: Antenna) -> Result<(), ModuleError> {
+        self.adapter.lock().await.set_antenna(antenna).await
+    }
+
+    /// Antenna the module is currently using.
+    pub async fn antenna(&self) -> Result<Antenna, ModuleError> {
+        self.adapter.lock().await.antenna().await
+    }
+
+    /// Store the settings currently applied to the module, such as network credentials,
+    /// security and addressing, in its flash, so they need not be sent again on every
+    /// boot.
+    pub async fn persist_config(&self) -> Result<(), ModuleError> {
+        self.adapter.lock().await.persist_config().await
+    }
+
+    /// Whether the network settings stored in the module's flash match the ones in use,
+    /// leaving out addresses leased over DHCP.
+    pub async fn is_config_persisted(&self) -> Result<bool, ModuleError> {
+        self.adapter.lock().await.is_config_persisted().await
+    }
+
+    /// Read the module's settings and firmware information, for support bundles.
+    ///
+    /// The network passphrase is never included.
+    pub async fn dump_settings(&self) -> Result<Settings, ModuleError> {
+        self.adapter.lock().await.dump_settings().await
+    }
+
+    /// Check the module is working, for manufacturing tests and diagnostics.
+    ///
+    /// Communication over SPI and the firmware are always checked. With `network` set,
+    /// so are the association and whether the gateway answers a ping.
+    pub async fn health_check(&self, network: bool) -> HealthReport {
+        self.adapter.lock().await.health_check(network).await
+    }
+
+    /// Message of the last command the module answered with `ERROR`, such as
+    /// `[TCP  RC] Connection refused`, for attaching to error reports. Empty when the
+    /// module gave no reason, and cut short after 64 bytes.
+    pub async fn last_module_error(&self) -> Option<String<64>> {
+        self.adapter.lock().await.last_error.clone()
+    }
+}
@@ -0,0 +1,447 @@
+//! Periodic checks of the WiFi link, run by the driver's run loop.
+
+use crate::{
+    parser, CloseReason, CredentialsProvider, DriverState, EsWifi, Event, JoinError, JoinInfo,
+    Security, Transport, WifiMode,
+};
+use core::net::{IpAddr, Ipv4Addr};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_time::Duration;
+use embedded_hal::digital::OutputPin;
+
+/// Link quality monitoring, reporting [`Event::SignalLow`] and
+/// [`Event::SignalRecovered`] as the signal crosses the thresholds.
+///
+/// Recovery uses its own, higher threshold so a signal hovering around the low
+/// threshold does not produce a stream of events.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LinkMonitorConfig {
+    /// How often the signal strength is sampled.
+    #[cfg_attr(feature = "serde", serde(with = "crate::millis"))]
+    pub interval: Duration,
+    /// Signal strength, in dBm, at or below which the signal is reported low.
+    pub low_threshold: i8,
+    /// Signal strength, in dBm, at or above which a low signal is reported recovered.
+    pub recovered_threshold: i8,
+}
+
+impl Default for LinkMonitorConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10),
+            low_threshold: -80,
+            recovered_threshold: -70,
+        }
+    }
+}
+
+/// Details of the current association, for diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AssociationInfo {
+    /// WiFi channel.
+    pub channel: u8,
+    /// PHY rate, in kbit/s.
+    pub phy_rate_kbps: u32,
+    /// Security used by the network.
+    pub security: Security,
+}
+
+/// Keep-alive probing, pinging a host to detect links that are associated but no longer
+/// pass traffic, such as when the access point has lost its uplink.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProbeConfig {
+    /// Host to ping, or `None` for the gateway of the joined network.
+    pub target: Option<Ipv4Addr>,
+    /// How often the host is pinged.
+    #[cfg_attr(feature = "serde", serde(with = "crate::millis"))]
+    pub interval: Duration,
+    /// Consecutive failed pings after which [`Event::LinkDown`] is reported.
+    pub max_failures: u8,
+    /// Whether to rejoin the network once the link is reported down.
+    pub rejoin: bool,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self {
+            target: None,
+            interval: Duration::from_secs(30),
+            max_failures: 3,
+            rejoin: true,
+        }
+    }
+}
+
+/// Roaming between access points of the same network.
+///
+/// The network is scanned periodically, and when an access point is heard stronger than
+/// the current association by at least `margin` dB the module reassociates with it. Open
+/// sockets are closed when that happens.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RoamingConfig {
+    /// How often to look for a stronger access point.
+    #[cfg_attr(feature = "serde", serde(with = "crate::millis"))]
+    pub interval: Duration,
+    /// Improvement in signal strength, in dB, needed to switch access points.
+    pub margin: u8,
+}
+
+impl Default for RoamingConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            margin: 10,
+        }
+    }
+}
+
+impl<TRANSPORT, const SOCKETS: usize> DriverState<TRANSPORT, SOCKETS>
+where
+    TRANSPORT: Transport,
+{
+    /// Signal strength of the current association, in dBm.
+    pub(crate) async fn rssi(&mut self) -> Option<i8> {
+        let response = self.send_command(command!(4, "CR")).await.ok()?;
+        parser::rssi(response)
+    }
+
+    pub(crate) async fn association(&mut self) -> Option<AssociationInfo> {
+        let response = self.send_command(command!(4, "CI")).await.ok()?;
+        let info = parser::connection_info(response)?;
+        Some(AssociationInfo {
+            channel: info.channel,
+            phy_rate_kbps: info.rate_kbps,
+            security: Security::parse(info.security),
+        })
+    }
+
+    /// Gateway of the joined network.
+    pub(crate) async fn gateway(&mut self) -> Option<Ipv4Addr> {
+        self.query_secret(command!(4, "C?"), parser::network_gateway)
+            .await
+            .ok()?
+    }
+
+    /// Join the access point with the given BSSID, rather than whichever one the module
+    /// prefers.
+    async fn join_bssid(
+        &mut self,
+        ssid: &str,
+        psk: &str,
+        bssid: [u8; 6],
+    ) -> Result<JoinInfo, JoinError> {
+        self.send_command(command!(32, "C5=", bssid))
+            .await
+            .map_err(|_| JoinError::Unknown)?;
+        let joined = self.join_wep(ssid, psk).await.map(|info| JoinInfo {
+            bssid: Some(bssid),
+            ..info
+        });
+        if let Ok(info) = joined {
+            self.join_info.replace(info);
+            self.mode = Some(WifiMode::Station);
+        }
+        // Unpin again, so later joins are free to pick any access point. The join itself
+        // went through either way, so a failure here is not worth undoing it for.
+        match self.send_command(command!(8, "C5=")).await {
+            Ok(response) if parser::is_ok(response) => {}
+            _ => warn!("Module did not confirm unpinning the access point"),
+        }
+        joined
+    }
+
+    /// Ping `target` once, returning whether it answered.
+    pub(crate) async fn ping(&mut self, target: Ipv4Addr) -> bool {
+        let target = IpAddr::V4(target);
+        let setup = [command!(32, "T1=", target), command!(32, "T2=1")];
+        for command in setup {
+            match self.send_command(command).await {
+                Ok(response) if parser::is_ok(response) => {}
+                _ => return false,
+            }
+        }
+        match self.send_command(command!(4, "T0")).await {
+            Ok(response) => {
+                parser::is_ok(response) && !parser::contains_ignore_case(response, b"timeout")
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Channel, PHY rate and security of the current association, or `None` when the
+    /// module is not associated or could not report them.
+    pub async fn association(&self) -> Option<AssociationInfo> {
+        self.adapter.lock().await.association().await
+    }
+
+    /// Sample the signal strength periodically while [`EsWifi::run`] is running and report
+    /// changes in link quality as events. Disabled by default.
+    pub fn set_link_monitor(&mut self, config: Option<LinkMonitorConfig>) {
+        self.link_monitor = config;
+    }
+
+    pub(crate) async fn check_link(&self, config: &LinkMonitorConfig) {
+        let mut adapter = self.adapter.lock().await;
+        let rssi = match adapter.rssi().await {
+            Some(rssi) => rssi,
+            None => {
+                debug!("Unable to read signal strength");
+                return;
+            }
+        };
+        trace!("Signal strength is {} dBm", rssi);
+        if !adapter.signal_low && rssi <= config.low_threshold {
+            adapter.signal_low = true;
+            self.notify(Event::SignalLow { rssi });
+        } else if adapter.signal_low && rssi >= config.recovered_threshold {
+            adapter.signal_low = false;
+            self.notify(Event::SignalRecovered { rssi });
+        }
+    }
+
+    /// Check the module's address every `interval` while [`EsWifi::run`] is running,
+    /// reporting [`Event::IpChanged`] when it changed, such as when a DHCP lease was
+    /// renewed with another address. Disabled by default.
+    pub fn set_address_check(&mut self, interval: Option<Duration>) {
+        self.address_check = interval;
+    }
+
+    pub(crate) async fn check_address(&self) {
+        let mut adapter = self.adapter.lock().await;
+        let Some(old) = adapter.join_info.map(|info| info.ip) else {
+            return;
+        };
+        let new = match adapter
+            .query_secret(command!(4, "C?"), parser::network_address)
+            .await
+        {
+            Ok(Some(ip)) => IpAddr::V4(ip),
+            _ => {
+                debug!("Unable to read address");
+                return;
+            }
+        };
+        // An unspecified address only means a renewal is under way.
+        if new == old || new.is_unspecified() {
+            return;
+        }
+        info!("Address changed from {} to {}", old, new);
+        if let Some(info) = adapter.join_info.as_mut() {
+            info.ip = new;
+        }
+        self.notify(Event::IpChanged { old, new });
+    }
+
+    /// Ping a host periodically while [`EsWifi::run`] is running, reporting
+    /// [`Event::LinkDown`] when it stops answering. Disabled by default.
+    pub fn set_probe(&mut self, config: Option<ProbeConfig>) {
+        self.probe = config;
+    }
+
+    /// Look for a stronger access point of the network and move to it when
+    /// [`EsWifi::run`] is running, closing every connection as [`CloseReason::Roamed`].
+    /// Disabled by default.
+    pub fn set_roaming(&mut self, config: Option<RoamingConfig>) {
+        self.roaming = config;
+    }
+
+    /// Reassociate with a stronger access point of the network `provider` has
+    /// credentials for, if there is one, closing every connection as
+    /// [`CloseReason::Roamed`].
+    pub(crate) async fn check_roaming<P: CredentialsProvider>(
+        &self,
+        config: &RoamingConfig,
+        provider: &mut P,
+    ) -> Result<(), JoinError> {
+        let credentials = provider
+            .credentials()
+            .await
+            .ok_or(JoinError::CredentialsUnavailable)?;
+        let (ssid, psk) = (credentials.ssid.as_str(), credentials.password.as_str());
+        let mut adapter = self.adapter.lock().await;
+        let current = match adapter.rssi().await {
+            Some(rssi) => rssi,
+            None => return Ok(()),
+        };
+        let candidate = match adapter.scan_for(ssid).await {
+            Ok(Some(candidate)) => candidate,
+            _ => return Ok(()),
+        };
+        // Reassociating with the access point already joined would only drop the
+        // connections.
+        let joined = adapter.join_info.and_then(|info| info.bssid);
+        if joined == Some(candidate.bssid) {
+            return Ok(());
+        }
+        if (candidate.rssi as i16) < current as i16 + config.margin as i16 {
+            return Ok(());
+        }
+
+        info!(
+            "Roaming to access point at {} dBm, from {} dBm",
+            candidate.rssi, current
+        );
+        for handle in adapter.socket_pool.connected() {
+            self.socket_closed(&adapter, handle, CloseReason::Roamed);
+            if let Err(e) = adapter.close(handle).await {
+                warn!("Error closing connection {}: {:?}", handle, e);
+            }
+        }
+        adapter.join_bssid(ssid, psk, candidate.bssid).await?;
+        self.notify(Event::Roamed {
+            bssid: candidate.bssid,
+            rssi: candidate.rssi,
+        });
+        Ok(())
+    }
+
+    /// Run one keep-alive probe, returning whether the link should be considered down.
+    pub(crate) async fn check_probe(&self, config: &ProbeConfig) -> bool {
+        let mut adapter = self.adapter.lock().await;
+        let target = match config.target {
+            Some(target) => Some(target),
+            None => adapter.gateway().await,
+        };
+        let alive = match target {
+            Some(target) => adapter.ping(target).await,
+            None => {
+                debug!("No gateway to probe");
+                false
+            }
+        };
+        if alive {
+            adapter.probe_failures = 0;
+            return false;
+        }
+        adapter.probe_failures = adapter.probe_failures.saturating_add(1);
+        debug!("Keep-alive probe failed {} times", adapter.probe_failures);
+        if adapter.probe_failures == config.max_failures {
+            self.notify(Event::LinkDown);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Credentials, NoPin, SocketAddr};
+    use core::cell::Cell;
+    use core::convert::Infallible;
+    use embassy_futures::block_on;
+    use embedded_nal_async::TcpConnect;
+
+    const STRONGER: [u8; 6] = [0xC4, 0x12, 0xF5, 0x00, 0x11, 0x22];
+
+    /// Module joined at -70 dBm, with a stronger access point of the network in range
+    /// and firmware that does not know the command unpinning the access point.
+    struct Module<'a> {
+        pins: &'a Cell<usize>,
+        response: &'static [u8],
+    }
+
+    impl Transport for Module<'_> {
+        type Error = Infallible;
+
+        async fn write(&mut self, command: &[u8], _data: &[u8]) -> Result<(), Infallible> {
+            self.response = match command {
+                b"CR\r" => b"\r\n-70\r\nOK\r\n> ",
+                b"F0\r" => {
+                    b"\r\n#001,\"drogue\",C4:12:F5:00:11:22,-40,72.0,Infrastructure,\
+                      WPA2 AES,2.4GHz,6\r\nOK\r\n> "
+                }
+                b"C0\r" => b"\r\n[JOIN   ] drogue,192.168.1.174,0,0\r\nOK\r\n> ",
+                b"C5=\r" => b"\r\nUnknown command\r\nERROR\r\n> ",
+                _ if command.starts_with(b"C5=") => {
+                    self.pins.set(self.pins.get() + 1);
+                    b"\r\nOK\r\n> "
+                }
+                _ => b"\r\nOK\r\n> ",
+            };
+            Ok(())
+        }
+
+        async fn read(
+            &mut self,
+            _room: bool,
+            mut push: impl FnMut(u8) -> bool,
+        ) -> Result<(), Infallible> {
+            for &byte in core::mem::take(&mut self.response) {
+                if !push(byte) {
+                    break;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_roaming() {
+        let pins = Cell::new(0);
+        let driver: EsWifi<_, NoPin, NoPin> = EsWifi::with_transport(
+            Module {
+                pins: &pins,
+                response: &[],
+            },
+            None,
+            None,
+        );
+        let config = RoamingConfig::default();
+        let mut credentials = Credentials::new("drogue", "secret").unwrap();
+        let remote: SocketAddr = "192.0.2.1:80".parse().unwrap();
+        block_on(async {
+            let socket = driver.connect(remote).await.unwrap();
+            let handle = socket.handle();
+
+            // Unpinning failing afterwards does not fail the move itself.
+            driver
+                .check_roaming(&config, &mut credentials)
+                .await
+                .unwrap();
+            assert_eq!(pins.get(), 1);
+            let mut event = driver.next_event().await;
+            while !matches!(event, Event::SocketClosed { .. }) {
+                event = driver.next_event().await;
+            }
+            assert_eq!(
+                event,
+                Event::SocketClosed {
+                    handle,
+                    remote: Some(remote),
+                    reason: CloseReason::Roamed,
+                }
+            );
+            assert_eq!(
+                driver.next_event().await,
+                Event::Roamed {
+                    bssid: STRONGER,
+                    rssi: -40,
+                }
+            );
+
+            // Still the strongest, but already joined.
+            driver
+                .check_roaming(&config, &mut credentials)
+                .await
+                .unwrap();
+            assert_eq!(pins.get(), 1);
+        });
+    }
+}
@@ -0,0 +1,320 @@
+//! Reuse of TCP connections across requests, for HTTP clients doing keep-alive.
+
+use crate::{
+    ConnectionState, EsWifi, EsWifiSocket, RateLimit, SocketError, SocketStats, Transport,
+};
+use core::cell::{Cell, RefCell};
+use core::net::SocketAddr;
+use embassy_sync::blocking_mutex::raw::{NoopRawMutex, RawMutex};
+use embassy_time::{Duration, Instant};
+use embedded_hal::digital::OutputPin;
+use embedded_io_async::{Read, Write};
+use embedded_nal_async::TcpConnect;
+use heapless::Vec;
+
+/// Connection parked in the pool, waiting to be reused.
+struct Idle<'d, TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M>
+where
+    TRANSPORT: Transport + 'd,
+    RESET: OutputPin + 'd,
+    WAKEUP: OutputPin + 'd,
+    M: RawMutex,
+{
+    remote: SocketAddr,
+    socket: EsWifiSocket<'d, TRANSPORT, RESET, WAKEUP, SOCKETS, M>,
+    since: Instant,
+}
+
+/// [`TcpConnect`] implementation keeping up to `N` connections open, handing out an idle
+/// connection to the same remote instead of opening a new one.
+///
+/// Connections are returned to the pool when dropped, unless they failed or were closed
+/// by the remote. Pooled connections unused for longer than the idle timeout are closed,
+/// and the least recently used one makes room when all `N` are taken. `N` is capped at
+/// the number of sockets of the module.
+pub struct TcpClientPool<
+    'd,
+    TRANSPORT,
+    RESET,
+    WAKEUP,
+    const N: usize,
+    const SOCKETS: usize = 4,
+    M = NoopRawMutex,
+> where
+    TRANSPORT: Transport + 'd,
+    RESET: OutputPin + 'd,
+    WAKEUP: OutputPin + 'd,
+    M: RawMutex,
+{
+    driver: &'d EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>,
+    idle: RefCell<Vec<Idle<'d, TRANSPORT, RESET, WAKEUP, SOCKETS, M>, N>>,
+    in_use: Cell<usize>,
+    idle_timeout: Duration,
+}
+
+impl<'d, TRANSPORT, RESET, WAKEUP, const N: usize, const SOCKETS: usize, M>
+    TcpClientPool<'d, TRANSPORT, RESET, WAKEUP, N, SOCKETS, M>
+where
+    TRANSPORT: Transport + 'd,
+    RESET: OutputPin + 'd,
+    WAKEUP: OutputPin + 'd,
+    M: RawMutex,
+{
+    /// Pool connections opened through `driver`, closing them once idle for longer than
+    /// `idle_timeout`.
+    pub fn new(
+        driver: &'d EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>,
+        idle_timeout: Duration,
+    ) -> Self {
+        Self {
+            driver,
+            idle: RefCell::new(Vec::new()),
+            in_use: Cell::new(0),
+            idle_timeout,
+        }
+    }
+
+    /// Number of connections waiting in the pool to be reused.
+    pub fn idle_connections(&self) -> usize {
+        self.idle.borrow().len()
+    }
+
+    /// Close every connection waiting in the pool.
+    pub fn clear(&self) {
+        self.idle.borrow_mut().clear();
+    }
+
+    fn limit() -> usize {
+        core::cmp::min(N, SOCKETS)
+    }
+
+    /// Take the most recently parked connection to `remote`, if any.
+    fn take(
+        &self,
+        remote: SocketAddr,
+    ) -> Option<EsWifiSocket<'d, TRANSPORT, RESET, WAKEUP, SOCKETS, M>> {
+        let mut idle = self.idle.borrow_mut();
+        let index = idle.iter().rposition(|idle| idle.remote == remote)?;
+        Some(idle.swap_remove(index).socket)
+    }
+
+    /// Close connections idle for longer than the idle timeout.
+    fn expire(&self) {
+        let now = Instant::now();
+        self.idle
+            .borrow_mut()
+            .retain(|idle| now.saturating_duration_since(idle.since) < self.idle_timeout);
+    }
+
+    /// Close the least recently used connections until a socket is left for a new one.
+    fn make_room(&self) {
+        let mut idle = self.idle.borrow_mut();
+        while !idle.is_empty() && idle.len() + self.in_use.get() >= Self::limit() {
+            let oldest = idle
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, idle)| idle.since)
+                .map(|(index, _)| index)
+                .unwrap();
+            idle.swap_remove(oldest);
+        }
+    }
+
+    fn park(
+        &self,
+        remote: SocketAddr,
+        socket: EsWifiSocket<'d, TRANSPORT, RESET, WAKEUP, SOCKETS, M>,
+    ) {
+        let idle = Idle {
+            remote,
+            socket,
+            since: Instant::now(),
+        };
+        // Dropping the connection instead closes it.
+        let _ = self.idle.borrow_mut().push(idle);
+    }
+}
+
+impl<'d, TRANSPORT, RESET, WAKEUP, const N: usize, const SOCKETS: usize, M> TcpConnect
+    for TcpClientPool<'d, TRANSPORT, RESET, WAKEUP, N, SOCKETS, M>
+where
+    TRANSPORT: Transport + 'd,
+    RESET: OutputPin + 'd,
+    WAKEUP: OutputPin + 'd,
+    M: RawMutex,
+{
+    type Error = SocketError;
+    type Connection<'m>
+        = PooledConnection<'m, 'd, TRANSPORT, RESET, WAKEUP, N, SOCKETS, M>
+    where
+        Self: 'm;
+
+    async fn connect<'m>(
+        &'m self,
+        remote: SocketAddr,
+    ) -> Result<Self::Connection<'m>, Self::Error> {
+        self.expire();
+        while let Some(socket) = self.take(remote) {
+            // The remote may have closed the connection while it sat in the pool.
+            if let Ok(ConnectionState::Connected) = socket.state().await {
+                trace!("[{}] Reusing pooled connection", socket.handle());
+                return Ok(PooledConnection::new(self, remote, socket));
+            }
+        }
+        self.make_room();
+        if self.in_use.get() >= Self::limit() {
+            return Err(SocketError::OpenError);
+        }
+        let socket = self.driver.connect(remote).await?;
+        Ok(PooledConnection::new(self, remote, socket))
+    }
+}
+
+/// Connection handed out by [`TcpClientPool`], returned to it when dropped.
+pub struct PooledConnection<
+    'm,
+    'd,
+    TRANSPORT,
+    RESET,
+    WAKEUP,
+    const N: usize,
+    const SOCKETS: usize,
+    M,
+> where
+    TRANSPORT: Transport + 'd,
+    RESET: OutputPin + 'd,
+    WAKEUP: OutputPin + 'd,
+    M: RawMutex,
+{
+    pool: &'m TcpClientPool<'d, TRANSPORT, RESET, WAKEUP, N, SOCKETS, M>,
+    remote: SocketAddr,
+    socket: Option<EsWifiSocket<'d, TRANSPORT, RESET, WAKEUP, SOCKETS, M>>,
+    reusable: bool,
+}
+
+impl<'m, 'd, TRANSPORT, RESET, WAKEUP, const N: usize, const SOCKETS: usize, M>
+    PooledConnection<'m, 'd, TRANSPORT, RESET, WAKEUP, N, SOCKETS, M>
+where
+    TRANSPORT: Transport + 'd,
+    RESET: OutputPin + 'd,
+    WAKEUP: OutputPin + 'd,
+    M: RawMutex,
+{
+    fn new(
+        pool: &'m TcpClientPool<'d, TRANSPORT, RESET, WAKEUP, N, SOCKETS, M>,
+        remote: SocketAddr,
+        socket: EsWifiSocket<'d, TRANSPORT, RESET, WAKEUP, SOCKETS, M>,
+    ) -> Self {
+        pool.in_use.set(pool.in_use.get() + 1);
+        Self {
+            pool,
+            remote,
+            socket: Some(socket),
+            reusable: true,
+        }
+    }
+
+    /// Close the connection when dropped rather than returning it to the pool.
+    pub fn discard(&mut self) {
+        self.reusable = false;
+    }
+
+    /// See [`EsWifiSocket::set_write_timeout`].
+    pub fn set_write_timeout(&mut self, timeout: Duration) {
+        self.socket().set_write_timeout(timeout);
+    }
+
+    /// See [`EsWifiSocket::set_rate_limit`].
+    pub fn set_rate_limit(&mut self, limit: Option<RateLimit>) {
+        self.socket().set_rate_limit(limit);
+    }
+
+    /// See [`EsWifiSocket::set_progress`].
+    pub fn set_progress(&mut self, progress: Option<&'d dyn Fn(usize)>) {
+        self.socket().set_progress(progress);
+    }
+
+    /// See [`EsWifiSocket::set_nodelay`].
+    pub fn set_nodelay(&mut self, nodelay: bool) {
+        self.socket().set_nodelay(nodelay);
+    }
+
+    /// See [`EsWifiSocket::stats`]. Counts accumulate over every checkout of the
+    /// underlying connection.
+    pub fn stats(&self) -> SocketStats {
+        // Only taken out when dropped.
+        self.socket.as_ref().unwrap().stats()
+    }
+
+    fn socket(&mut self) -> &mut EsWifiSocket<'d, TRANSPORT, RESET, WAKEUP, SOCKETS, M> {
+        // Only taken out when dropped.
+        self.socket.as_mut().unwrap()
+    }
+}
+
+impl<'m, 'd, TRANSPORT, RESET, WAKEUP, const N: usize, const SOCKETS: usize, M>
+    embedded_io::ErrorType for PooledConnection<'m, 'd, TRANSPORT, RESET, WAKEUP, N, SOCKETS, M>
+where
+    TRANSPORT: Transport + 'd,
+    RESET: OutputPin + 'd,
+    WAKEUP: OutputPin + 'd,
+    M: RawMutex,
+{
+    type Error = SocketError;
+}
+
+impl<'m, 'd, TRANSPORT, RESET, WAKEUP, const N: usize, const SOCKETS: usize, M> Read
+    for PooledConnection<'m, 'd, TRANSPORT, RESET, WAKEUP, N, SOCKETS, M>
+where
+    TRANSPORT: Transport + 'd,
+    RESET: OutputPin + 'd,
+    WAKEUP: OutputPin + 'd,
+    M: RawMutex,
+{
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let result = self.socket().read(buf).await;
+        if result.is_err() {
+            self.reusable = false;
+        }
+        result
+    }
+}
+
+impl<'m, 'd, TRANSPORT, RESET, WAKEUP, const N: usize, const SOCKETS: usize, M> Write
+    for PooledConnection<'m, 'd, TRANSPORT, RESET, WAKEUP, N, SOCKETS, M>
+where
+    TRANSPORT: Transport + 'd,
+    RESET: OutputPin + 'd,
+    WAKEUP: OutputPin + 'd,
+    M: RawMutex,
+{
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let result = self.socket().write(buf).await;
+        if result.is_err() {
+            self.reusable = false;
+        }
+        result
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.socket().flush().await
+    }
+}
+
+impl<'m, 'd, TRANSPORT, RESET, WAKEUP, const N: usize, const SOCKETS: usize, M> Drop
+    for PooledConnection<'m, 'd, TRANSPORT, RESET, WAKEUP, N, SOCKETS, M>
+where
+    TRANSPORT: Transport + 'd,
+    RESET: OutputPin + 'd,
+    WAKEUP: OutputPin + 'd,
+    M: RawMutex,
+{
+    fn drop(&mut self) {
+        self.pool.in_use.set(self.pool.in_use.get() - 1);
+        if let Some(socket) = self.socket.take() {
+            if self.reusable {
+                self.pool.park(self.remote, socket);
+            }
+        }
+    }
+}
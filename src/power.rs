@@ -0,0 +1,99 @@
+//! Power save while associated, trading latency for power on battery devices.
+//!
+//! In power save the module sleeps between beacons of the access point, which buffers
+//! traffic for it meanwhile. The listen interval tells the access point how many beacon
+//! intervals the module may sleep through, and skipping DTIM beacons lets it sleep
+//! through broadcast traffic as well. Longer sleeps save power, at the cost of data
+//! waiting longer to reach the module.
+
+use crate::{parser, DriverState, EsWifi, ModuleError, Transport};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embedded_hal::digital::OutputPin;
+use heapless::Vec;
+
+/// Power save settings, see [`EsWifi::set_power_save`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PowerSaveConfig {
+    /// Beacon intervals the module may sleep through, as announced to the access point
+    /// when joining
+    pub listen_interval: u8,
+    /// DTIM beacons skipped between wakeups, 0 to wake for every one
+    pub dtim_skip: u8,
+}
+
+impl Default for PowerSaveConfig {
+    fn default() -> Self {
+        Self {
+            listen_interval: 1,
+            dtim_skip: 0,
+        }
+    }
+}
+
+impl<TRANSPORT, const SOCKETS: usize> DriverState<TRANSPORT, SOCKETS>
+where
+    TRANSPORT: Transport,
+{
+    async fn set_power_save(&mut self, config: Option<PowerSaveConfig>) -> Result<(), ModuleError> {
+        match config {
+            Some(config) => {
+                self.module_command(command!(8, "ZL=", config.listen_interval))
+                    .await?;
+                self.module_command(command!(8, "ZD=", config.dtim_skip))
+                    .await?;
+                self.module_command(command!(8, "ZP=1")).await
+            }
+            None => self.module_command(command!(8, "ZP=0")).await,
+        }
+    }
+
+    async fn power_save(&mut self) -> Result<Option<PowerSaveConfig>, ModuleError> {
+        if self.query_number(command!(4, "ZP?")).await? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(PowerSaveConfig {
+            listen_interval: self.query_number(command!(4, "ZL?")).await?,
+            dtim_skip: self.query_number(command!(4, "ZD?")).await?,
+        }))
+    }
+
+    /// Send a command showing a single number.
+    async fn query_number<const N: usize>(
+        &mut self,
+        command: Vec<u8, N>,
+    ) -> Result<u8, ModuleError> {
+        let response = self
+            .send_command(command)
+            .await
+            .map_err(ModuleError::from)?;
+        parser::value(response)
+            .and_then(parser::atoi_usize)
+            .and_then(|value| u8::try_from(value).ok())
+            .ok_or(ModuleError::Rejected)
+    }
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Let the module sleep between beacons while staying associated, or keep it awake
+    /// with `None`, which is the default.
+    ///
+    /// The listen interval is announced to the access point when joining, so it takes
+    /// effect from the next join. Firmware without power save support reports
+    /// [`ModuleError::Unsupported`].
+    pub async fn set_power_save(&self, config: Option<PowerSaveConfig>) -> Result<(), ModuleError> {
+        self.adapter.lock().await.set_power_save(config).await
+    }
+
+    /// Power save settings the module is using, or `None` while it stays awake.
+    pub async fn power_save(&self) -> Result<Option<PowerSaveConfig>, ModuleError> {
+        self.adapter.lock().await.power_save().await
+    }
+}
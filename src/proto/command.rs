@@ -0,0 +1,144 @@
+//! Encoding of module commands without going through `core::fmt`.
+
+use crate::SocketHandle;
+use core::net::{IpAddr, Ipv4Addr};
+use heapless::Vec;
+
+/// Build a `\r` terminated command frame of at most `$size` bytes from its parts.
+macro_rules! command {
+    ($size:tt, $($arg:expr),*) => ({
+        let mut c = heapless::Vec::<u8, $size>::new();
+        $( $crate::proto::command::Encode::encode(&$arg, &mut c); )*
+        c.push(b'\r').unwrap();
+        c
+    })
+}
+
+/// A value that can be appended to a command frame.
+pub(crate) trait Encode {
+    fn encode<const N: usize>(&self, out: &mut Vec<u8, N>);
+}
+
+impl Encode for &str {
+    fn encode<const N: usize>(&self, out: &mut Vec<u8, N>) {
+        out.extend_from_slice(self.as_bytes()).unwrap();
+    }
+}
+
+impl Encode for u8 {
+    fn encode<const N: usize>(&self, out: &mut Vec<u8, N>) {
+        (*self as usize).encode(out);
+    }
+}
+
+impl Encode for SocketHandle {
+    fn encode<const N: usize>(&self, out: &mut Vec<u8, N>) {
+        self.index().encode(out);
+    }
+}
+
+impl Encode for u16 {
+    fn encode<const N: usize>(&self, out: &mut Vec<u8, N>) {
+        (*self as usize).encode(out);
+    }
+}
+
+impl Encode for usize {
+    fn encode<const N: usize>(&self, out: &mut Vec<u8, N>) {
+        let mut digits = [0; 20];
+        out.extend_from_slice(encode_decimal(*self, &mut digits))
+            .unwrap();
+    }
+}
+
+impl Encode for Ipv4Addr {
+    fn encode<const N: usize>(&self, out: &mut Vec<u8, N>) {
+        for (i, octet) in self.octets().iter().enumerate() {
+            if i > 0 {
+                out.push(b'.').unwrap();
+            }
+            octet.encode(out);
+        }
+    }
+}
+
+impl Encode for IpAddr {
+    fn encode<const N: usize>(&self, out: &mut Vec<u8, N>) {
+        match self {
+            IpAddr::V4(ip) => ip.encode(out),
+            // The module only speaks IPv4, callers reject IPv6 before getting here.
+            IpAddr::V6(_) => {}
+        }
+    }
+}
+
+/// MAC addresses, as colon separated upper case hex.
+impl Encode for [u8; 6] {
+    fn encode<const N: usize>(&self, out: &mut Vec<u8, N>) {
+        const HEX: &[u8; 16] = b"0123456789ABCDEF";
+        for (i, octet) in self.iter().enumerate() {
+            if i > 0 {
+                out.push(b':').unwrap();
+            }
+            out.push(HEX[(octet >> 4) as usize]).unwrap();
+            out.push(HEX[(octet & 0xF) as usize]).unwrap();
+        }
+    }
+}
+
+/// Bytes as upper case hex, two digits each without separators.
+pub(crate) struct Hex<'a>(pub(crate) &'a [u8]);
+
+impl Encode for Hex<'_> {
+    fn encode<const N: usize>(&self, out: &mut Vec<u8, N>) {
+        const HEX: &[u8; 16] = b"0123456789ABCDEF";
+        for octet in self.0 {
+            out.push(HEX[(octet >> 4) as usize]).unwrap();
+            out.push(HEX[(octet & 0xF) as usize]).unwrap();
+        }
+    }
+}
+
+/// Write `value` as decimal ASCII at the end of `buf` and return the digits.
+pub(crate) fn encode_decimal(mut value: usize, buf: &mut [u8; 20]) -> &[u8] {
+    let mut pos = buf.len();
+    loop {
+        pos -= 1;
+        buf[pos] = b'0' + (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            break;
+        }
+    }
+    &buf[pos..]
+}
+
+#[cfg(test)]
+mod tests {
+    use core::fmt::Write;
+    use core::net::{IpAddr, Ipv4Addr};
+    use heapless::String;
+
+    #[test]
+    fn test_encode_decimal() {
+        let mut buf = [0; 20];
+        assert_eq!(super::encode_decimal(0, &mut buf), b"0");
+        assert_eq!(super::encode_decimal(1460, &mut buf), b"1460");
+        let mut max: String<20> = String::new();
+        write!(max, "{}", usize::MAX).unwrap();
+        assert_eq!(super::encode_decimal(usize::MAX, &mut buf), max.as_bytes());
+    }
+
+    #[test]
+    fn test_command_frames() {
+        let handle: u8 = 3;
+        assert_eq!(&command!(8, "P0=", handle)[..], b"P0=3\r");
+        assert_eq!(&command!(8, "R3=1")[..], b"R3=1\r");
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+        assert_eq!(&command!(32, "P3=", ip)[..], b"P3=192.168.1.20\r");
+        let mac: [u8; 6] = [0xC4, 0x12, 0xF5, 0x00, 0x11, 0x2A];
+        assert_eq!(&command!(32, "AK=", mac)[..], b"AK=C4:12:F5:00:11:2A\r");
+        let pattern = super::Hex(&[0x08, 0x06, 0xAB]);
+        assert_eq!(&command!(16, "ZW=0,12,", pattern)[..], b"ZW=0,12,0806AB\r");
+    }
+}
@@ -0,0 +1,345 @@
+//! Framing of commands and responses on the wire, without the bus itself.
+//!
+//! Over SPI the module exchanges 16 bit words with swapped bytes, pads odd lengths and
+//! fills the time it has nothing to say with NAKs. Over UART bytes go as they are. On
+//! both, a response ends with the prompt.
+
+use crate::MAX_NAK_WORDS;
+
+/// Byte the module sends while it has no data ready, and pads odd responses with.
+pub(crate) const NAK: u8 = 0x15;
+
+/// Prompt the module prints when it is ready for the next command.
+const PROMPT: &[u8] = b"\r\n> ";
+
+/// Word clocked out while reading, and byte padding odd commands.
+pub(crate) const FILL: u8 = 0x0A;
+
+/// Frame `command` followed by `data` as the 16 bit words sent over SPI.
+///
+/// Each word carries two consecutive bytes, the first one in its low byte, which goes out
+/// second. Words may straddle the command and the data, and an odd total is padded with
+/// a line feed, which the module skips after a command and ignores after a payload whose
+/// length it was told.
+pub(crate) fn spi_words<'a>(
+    command: &'a [u8],
+    data: &'a [u8],
+) -> impl Iterator<Item = [u8; 2]> + 'a {
+    let mut bytes = command.iter().chain(data.iter()).copied();
+    core::iter::from_fn(move || {
+        let first = bytes.next()?;
+        Some([bytes.next().unwrap_or(FILL), first])
+    })
+}
+
+/// Whether the prompt shows up anywhere in `bytes`, which may also hold the end of an
+/// earlier response or padding around it.
+pub(crate) fn contains_prompt(bytes: &[u8]) -> bool {
+    bytes.windows(PROMPT.len()).any(|window| window == PROMPT)
+}
+
+/// Bytes of a response on their way to the driver, tracking whether they end with the
+/// prompt so far.
+pub(crate) struct Response<P> {
+    push: P,
+    room: bool,
+    matched: usize,
+}
+
+impl<P: FnMut(u8) -> bool> Response<P> {
+    pub(crate) fn new(room: bool, push: P) -> Self {
+        Self {
+            push,
+            room,
+            matched: 0,
+        }
+    }
+
+    /// Hand `byte` on while there is room, dropping it otherwise.
+    pub(crate) fn push(&mut self, byte: u8) {
+        if self.room {
+            self.room = (self.push)(byte);
+        }
+        self.matched = match byte {
+            _ if byte == PROMPT[self.matched % PROMPT.len()] => self.matched % PROMPT.len() + 1,
+            _ if byte == PROMPT[0] => 1,
+            _ => 0,
+        };
+    }
+
+    /// Whether the bytes so far end with the prompt.
+    pub(crate) fn complete(&self) -> bool {
+        self.matched == PROMPT.len()
+    }
+}
+
+/// Filter leaving out the echo of the command the module prints ahead of its response
+/// while verbose messages are on.
+///
+/// Responses proper start with a line ending, so anything before the first carriage
+/// return is the echo, which ends with the command's own carriage return.
+pub(crate) struct Echo {
+    state: EchoState,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EchoState {
+    Start,
+    Echo,
+    Response,
+}
+
+impl Echo {
+    pub(crate) fn new(verbose: bool) -> Self {
+        Self {
+            state: if verbose {
+                EchoState::Start
+            } else {
+                EchoState::Response
+            },
+        }
+    }
+
+    /// Whether `byte` belongs to the response rather than the echo.
+    pub(crate) fn keep(&mut self, byte: u8) -> bool {
+        match self.state {
+            EchoState::Response => true,
+            EchoState::Start if byte == b'\r' => {
+                self.state = EchoState::Response;
+                true
+            }
+            _ => {
+                self.state = if byte == b'\r' {
+                    EchoState::Response
+                } else {
+                    EchoState::Echo
+                };
+                false
+            }
+        }
+    }
+}
+
+/// What a word received over SPI turned out to be, see [`SpiResponse::word`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) enum Word {
+    /// Part of the response
+    Data,
+    /// Two NAKs, which are either idle filler or payload, as told later
+    Nak,
+    /// More NAKs in a row than any response could hold
+    NakStorm,
+}
+
+/// Decoder for a response received over SPI, one word at a time.
+///
+/// The module pads a response of odd length with a NAK, and answers with NAKs while it
+/// has no data ready. Since every response ends with the prompt, NAKs are only taken for
+/// padding once the prompt is in; before that they are payload, which may well contain
+/// 0x15. A payload may also contain the prompt itself, so a NAK following it, and words
+/// of NAKs anywhere, are held back until more data shows they were payload after all.
+pub(crate) struct SpiResponse<P> {
+    response: Response<P>,
+    naks: usize,
+    padding: bool,
+}
+
+impl<P: FnMut(u8) -> bool> SpiResponse<P> {
+    pub(crate) fn new(room: bool, push: P) -> Self {
+        Self {
+            response: Response::new(room, push),
+            naks: 0,
+            padding: false,
+        }
+    }
+
+    /// Take the next word clocked in.
+    pub(crate) fn word(&mut self, word: [u8; 2]) -> Word {
+        if word == [NAK, NAK] {
+            self.naks += 1;
+            return if self.naks > MAX_NAK_WORDS {
+                Word::NakStorm
+            } else {
+                Word::Nak
+            };
+        }
+        self.flush_naks();
+        self.response.push(word[1]);
+        if word[0] == NAK && self.response.complete() {
+            self.padding = true;
+        } else {
+            self.response.push(word[0]);
+        }
+        Word::Data
+    }
+
+    /// Hand on the NAKs held back at the end of a response cut off before the prompt.
+    pub(crate) fn finish(mut self) {
+        if !self.response.complete() {
+            self.flush_naks();
+        }
+    }
+
+    fn flush_naks(&mut self) {
+        if core::mem::take(&mut self.padding) {
+            self.response.push(NAK);
+        }
+        for _ in 0..core::mem::take(&mut self.naks) * 2 {
+            self.response.push(NAK);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heapless::Vec;
+
+    /// Undo the framing of [`spi_words`], keeping the padding.
+    fn unframe(command: &[u8], data: &[u8]) -> Vec<u8, 64> {
+        let mut bytes = Vec::new();
+        for [high, low] in spi_words(command, data) {
+            bytes.extend_from_slice(&[low, high]).unwrap();
+        }
+        bytes
+    }
+
+    /// Decode `words`, returning the bytes handed on and the last word's outcome.
+    fn decode(words: &[[u8; 2]]) -> (Vec<u8, 64>, Word) {
+        let mut received = Vec::new();
+        let mut decoder = SpiResponse::new(true, |byte| received.push(byte).is_ok());
+        let mut last = Word::Data;
+        for &word in words {
+            last = decoder.word(word);
+        }
+        decoder.finish();
+        (received, last)
+    }
+
+    #[test]
+    fn test_spi_words() {
+        let payload = b"0123456";
+        for command in [&b"S3=4\r"[..], &b"S3=10\r"[..]] {
+            for len in 0..=payload.len() {
+                let data = &payload[..len];
+                let total = command.len() + data.len();
+                let bytes = unframe(command, data);
+                assert_eq!(bytes.len(), total + total % 2);
+                assert_eq!(&bytes[..command.len()], command);
+                assert_eq!(&bytes[command.len()..total], data);
+                if total % 2 == 1 {
+                    assert_eq!(bytes[total], b'\n');
+                }
+            }
+        }
+        assert_eq!(spi_words(b"", b"").count(), 0);
+    }
+
+    #[test]
+    fn test_prompt() {
+        let mut response = Response::new(true, |_| true);
+        for &byte in b"\r\nOK\r\n>" {
+            response.push(byte);
+            assert!(!response.complete());
+        }
+        response.push(b' ');
+        assert!(response.complete());
+        response.push(b'x');
+        assert!(!response.complete());
+    }
+
+    #[test]
+    fn test_contains_prompt() {
+        assert!(contains_prompt(b"\r\n> "));
+        assert!(contains_prompt(b"\x15\x15\r\n> "));
+        assert!(contains_prompt(b"OK\r\n> \x15"));
+        assert!(!contains_prompt(b"\r\n>"));
+        assert!(!contains_prompt(b"\n> \r"));
+    }
+
+    #[test]
+    fn test_response_without_room() {
+        let mut pushed = 0;
+        let mut response = Response::new(false, |_| {
+            pushed += 1;
+            true
+        });
+        for &byte in b"\r\nOK\r\n> " {
+            response.push(byte);
+        }
+        assert!(response.complete());
+        assert_eq!(pushed, 0);
+    }
+
+    #[test]
+    fn test_padding_nak_dropped() {
+        // Seven bytes, so the module pads the prompt with a NAK.
+        let (received, _) = decode(&[*b"\n\r", *b"\rx", *b">\n", [NAK, b' ']]);
+        assert_eq!(&received[..], b"\r\nx\r\n> ");
+    }
+
+    #[test]
+    fn test_payload_naks_kept() {
+        let (received, last) = decode(&[*b"\n\r", [NAK, NAK], *b"\n\r", *b" >"]);
+        assert_eq!(last, Word::Data);
+        assert_eq!(&received[..], b"\r\n\x15\x15\r\n> ");
+        // Cut off before the prompt, NAKs held back are still payload.
+        let (received, last) = decode(&[*b"\n\r", [NAK, NAK]]);
+        assert_eq!(last, Word::Nak);
+        assert_eq!(&received[..], b"\r\n\x15\x15");
+    }
+
+    #[test]
+    fn test_payload_prompt_naks_kept() {
+        // A payload holding the prompt followed by a NAK, which is not padding as more of
+        // the response follows.
+        let prompt = [*b"\rx", *b">\n", [NAK, b' ']];
+        let ok = [*b"\n\r", *b"KO", *b"\n\r", *b" >"];
+        let mut words = Vec::<[u8; 2], 16>::from_slice(&prompt).unwrap();
+        words.extend_from_slice(&ok).unwrap();
+        let (received, _) = decode(&words);
+        assert_eq!(&received[..], b"x\r\n> \x15\r\nOK\r\n> ");
+        // The same with a word of NAKs in between.
+        let mut words = Vec::<[u8; 2], 16>::from_slice(&prompt).unwrap();
+        words.push([NAK, NAK]).unwrap();
+        words.extend_from_slice(&ok).unwrap();
+        let (received, _) = decode(&words);
+        assert_eq!(&received[..], b"x\r\n> \x15\x15\x15\r\nOK\r\n> ");
+        // Nothing follows, so the NAK was padding after all.
+        let (received, _) = decode(&prompt);
+        assert_eq!(&received[..], b"x\r\n> ");
+    }
+
+    #[test]
+    fn test_idle_naks_dropped() {
+        let (received, _) = decode(&[*b"\n\r", *b" >", [NAK, NAK], [NAK, NAK]]);
+        assert_eq!(&received[..], b"\r\n> ");
+    }
+
+    fn strip_echo(verbose: bool, bytes: &[u8]) -> Vec<u8, 64> {
+        let mut echo = Echo::new(verbose);
+        bytes
+            .iter()
+            .copied()
+            .filter(|&byte| echo.keep(byte))
+            .collect()
+    }
+
+    #[test]
+    fn test_echo() {
+        let response = b"\r\n-52\r\nOK\r\n> ";
+        assert_eq!(&strip_echo(true, b"CR\r\r\n-52\r\nOK\r\n> ")[..], response);
+        assert_eq!(&strip_echo(true, response)[..], response);
+        assert_eq!(&strip_echo(false, b"CR\r")[..], b"CR\r");
+    }
+
+    #[test]
+    fn test_nak_storm() {
+        let mut decoder = SpiResponse::new(true, |_| true);
+        for _ in 0..MAX_NAK_WORDS {
+            assert_eq!(decoder.word([NAK, NAK]), Word::Nak);
+        }
+        assert_eq!(decoder.word([NAK, NAK]), Word::NakStorm);
+    }
+}
@@ -0,0 +1,16 @@
+//! The module's AT command protocol, free of any I/O.
+//!
+//! Commands are built, framed for the bus, and their responses decoded and interpreted
+//! here from plain byte slices, so all of it can be tested on the host. The transports
+//! only move bytes, and the driver sequences the commands.
+
+#[macro_use]
+pub(crate) mod command;
+pub(crate) mod framing;
+pub mod parser;
+mod response;
+
+pub(crate) use response::{
+    check_remote, close_result, connect_result, error_message, join_result, read_result,
+    well_formed, write_result, LineSplitter,
+};
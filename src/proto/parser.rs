@@ -0,0 +1,1091 @@
+//! Parsers for the module's AT command responses.
+//!
+//! With the `parser` feature, the parsers turning whole responses into typed values are
+//! public, for building on raw AT commands. They are not covered by semver guarantees,
+//! so hidden from the documentation. The pieces they are built from stay private.
+// nom 6 macros expand with trailing semicolons.
+#![allow(semicolon_in_expressions_from_non_local_macros)]
+//use drogue_nom_utils::parse_usize;
+#[cfg(not(feature = "tiny"))]
+use nom::{
+    alt, char, character::streaming::digit1, complete, do_parse, named, tag, take_until, IResult,
+};
+
+use core::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+#[cfg(any(test, feature = "tiny"))]
+mod minimal;
+//use crate::util::nom::{parse_u8, parse_usize};
+
+#[cfg(not(feature = "tiny"))]
+named!(
+    pub(crate) ok,
+    tag!("OK\r\n")
+);
+
+#[cfg(not(feature = "tiny"))]
+named!(
+    pub(crate) error,
+    tag!("ERROR\r\n")
+);
+
+#[cfg(not(feature = "tiny"))]
+named!(
+    pub(crate) prompt,
+    tag!("> ")
+);
+
+#[cfg(feature = "tiny")]
+pub use minimal::{close_response, connect_response, join_response, write_response};
+
+/// Outcome of joining a network with `C0`.
+#[derive(Debug)]
+pub enum JoinResponse {
+    /// Joined, with the address obtained
+    Ok(IpAddr),
+    /// The module could not join the network
+    JoinError(JoinFailure),
+}
+
+/// Reason for a failed join, as reported by the module.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum JoinFailure {
+    /// The passphrase was rejected
+    WrongPassword,
+    /// No access point of the network is in range
+    NetworkNotFound,
+    /// Associated, but no address was obtained over DHCP
+    DhcpFailed,
+    /// The network did not answer in time
+    Timeout,
+    /// Any other reason
+    Other,
+}
+
+/// Classify the text the module prints ahead of `ERROR` for a failed join.
+pub(crate) fn join_failure(message: &[u8]) -> JoinFailure {
+    let contains = |needle: &[u8]| contains_ignore_case(message, needle);
+    if contains(b"auth") || contains(b"password") || contains(b"passphrase") {
+        JoinFailure::WrongPassword
+    } else if contains(b"not found") || contains(b"no ap") || contains(b"no network") {
+        JoinFailure::NetworkNotFound
+    } else if contains(b"dhcp") {
+        JoinFailure::DhcpFailed
+    } else if contains(b"timeout") || contains(b"timed out") {
+        JoinFailure::Timeout
+    } else {
+        JoinFailure::Other
+    }
+}
+
+/// Whether `needle` occurs in `haystack`, ignoring ASCII case. An empty needle occurs
+/// everywhere.
+pub(crate) fn contains_ignore_case(haystack: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty()
+        || haystack
+            .windows(needle.len())
+            .any(|window| window.eq_ignore_ascii_case(needle))
+}
+
+#[cfg(not(feature = "tiny"))]
+#[rustfmt::skip]
+named!(
+    ip_addr<Ipv4Addr>,
+    do_parse!(
+        a: parse_u8 >>
+        char!('.') >>
+        b: parse_u8 >>
+        char!('.') >>
+        c: parse_u8 >>
+        char!('.') >>
+        d: parse_u8 >>
+        (
+            Ipv4Addr::new(a, b, c, d)
+        )
+    )
+);
+
+// [JOIN   ] drogue,192.168.1.174,0,0
+#[cfg(not(feature = "tiny"))]
+#[rustfmt::skip]
+named!(
+    pub(crate) join<JoinResponse>,
+    do_parse!(
+        tag!("[JOIN   ] ") >>
+        _ssid: take_until!(",") >>
+        char!(',') >>
+        //ip: take_until!(",") >>
+        ip: ip_addr >>
+        char!(',') >>
+        tag!("0,0") >>
+        tag!("\r\n") >>
+        ok >>
+        (
+            //log::info!("ip --> {:?}", ip );
+            JoinResponse::Ok(IpAddr::V4(ip))
+        )
+    )
+);
+
+// [JOIN   ] drogue
+// [JOIN   ] Failed
+#[cfg(not(feature = "tiny"))]
+named!(
+    pub(crate) join_error<JoinResponse>,
+    do_parse!(
+        message: take_until!( "ERROR" ) >>
+        error >>
+        (
+            JoinResponse::JoinError(join_failure(message))
+        )
+    )
+);
+
+#[cfg(not(feature = "tiny"))]
+named!(
+    /// Parse the response to `C0`, up to and including the prompt.
+    #[cfg(not(feature = "tiny"))],
+    pub join_response<JoinResponse>,
+    do_parse!(
+        tag!("\r\n") >>
+        response:
+        alt!(
+              complete!(join)
+            | complete!(join_error)
+        ) >>
+        prompt >>
+        (
+            response
+        )
+
+    )
+);
+
+/// Outcome of starting a client connection with `P6=1`.
+#[derive(Debug)]
+pub enum ConnectResponse {
+    /// Connected, or started for a connectionless UDP client
+    Ok,
+    /// The module could not connect
+    Error(ConnectFailure),
+}
+
+/// Reason for a failed connection, as reported by the module.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConnectFailure {
+    /// The remote host refused or reset the connection
+    Refused,
+    /// No route to the remote host
+    Unreachable,
+    /// The remote host did not answer in time
+    TimedOut,
+    /// Any other reason
+    Other,
+}
+
+/// Classify the text the module prints ahead of `ERROR` for a failed connection.
+///
+/// The wording differs between firmware versions, so only the distinguishing
+/// keywords are matched, ignoring case.
+pub(crate) fn connect_failure(message: &[u8]) -> ConnectFailure {
+    let contains = |needle: &[u8]| contains_ignore_case(message, needle);
+    if contains(b"refused") || contains(b"reset by peer") {
+        ConnectFailure::Refused
+    } else if contains(b"unreachable") || contains(b"no route") {
+        ConnectFailure::Unreachable
+    } else if contains(b"timeout") || contains(b"timed out") {
+        ConnectFailure::TimedOut
+    } else {
+        ConnectFailure::Other
+    }
+}
+
+// [TCP  RC] Connecting to 192.168.1.2
+// [UDP  RC] Connecting to 192.168.1.2
+#[cfg(not(feature = "tiny"))]
+named!(
+    pub(crate) connected<ConnectResponse>,
+    do_parse!(
+        tag!("\r\n") >>
+        tag!("[") >>
+        take_until!("] Connecting to ") >>
+        tag!("] Connecting to ") >>
+        take_until!( "\r\n") >>
+        tag!("\r\n") >>
+        ok >>
+        prompt >>
+        (
+            ConnectResponse::Ok
+        )
+    )
+);
+
+// Connectionless UDP clients may start without printing anything
+#[cfg(not(feature = "tiny"))]
+named!(
+    pub(crate) started<ConnectResponse>,
+    do_parse!(
+        tag!("\r\n") >>
+        ok >>
+        prompt >>
+        (
+            ConnectResponse::Ok
+        )
+    )
+);
+
+#[cfg(not(feature = "tiny"))]
+named!(
+    pub(crate) connection_failure<ConnectResponse>,
+    do_parse!(
+        message: take_until!( "ERROR" ) >>
+        error >>
+        (
+            ConnectResponse::Error(connect_failure(message))
+        )
+    )
+);
+
+#[cfg(not(feature = "tiny"))]
+named!(
+    /// Parse the response to `P6=1`, up to and including the prompt.
+    #[cfg(not(feature = "tiny"))],
+    pub connect_response<ConnectResponse>,
+    alt!(
+        complete!(connected)
+        | complete!(started)
+        | complete!(connection_failure)
+    )
+);
+
+/// Outcome of closing a connection with `P6=0`.
+#[derive(Debug)]
+pub enum CloseResponse {
+    /// Closed
+    Ok,
+    /// The module reported an error
+    Error,
+}
+
+#[cfg(not(feature = "tiny"))]
+named!(
+    pub(crate) closed<CloseResponse>,
+    do_parse!(
+        tag!("\r\n") >>
+        tag!("\r\n") >>
+        ok >>
+        prompt >>
+        (
+            CloseResponse::Ok
+        )
+    )
+);
+
+#[cfg(not(feature = "tiny"))]
+named!(
+    pub(crate) close_error<CloseResponse>,
+    do_parse!(
+        tag!("\r\n") >>
+        take_until!( "ERROR" ) >>
+        error >>
+        prompt >>
+        (
+            CloseResponse::Error
+        )
+    )
+);
+
+#[cfg(not(feature = "tiny"))]
+named!(
+    /// Parse the response to `P6=0`, up to and including the prompt.
+    #[cfg(not(feature = "tiny"))],
+    pub close_response<CloseResponse>,
+    alt!(
+          complete!(closed)
+        | complete!(close_error)
+    )
+);
+
+/// Outcome of sending data with `S3`.
+#[derive(Debug)]
+pub enum WriteResponse {
+    /// Number of bytes the module took
+    Ok(usize),
+    /// The module took nothing, such as while its buffer is full
+    Error,
+}
+
+#[cfg(not(feature = "tiny"))]
+named!(
+    pub(crate) write_ok<WriteResponse>,
+    do_parse!(
+        tag!("\r\n") >>
+        len: parse_usize >>
+        tag!("\r\n") >>
+        ok >>
+        prompt >>
+        (
+            WriteResponse::Ok(len)
+        )
+    )
+);
+
+#[cfg(not(feature = "tiny"))]
+named!(
+    pub(crate) write_error<WriteResponse>,
+    do_parse!(
+        tag!("\r\n") >>
+        tag!("-1") >>
+        tag!("\r\n") >>
+        ok >>
+        prompt >>
+        (
+            WriteResponse::Error
+        )
+    )
+);
+
+#[cfg(not(feature = "tiny"))]
+named!(
+    /// Parse the response to `S3`, up to and including the prompt.
+    #[cfg(not(feature = "tiny"))],
+    pub write_response<WriteResponse>,
+    alt!(
+          complete!(write_ok)
+        | complete!(write_error)
+    )
+);
+
+/// Outcome of a read whose payload was received directly into the caller's buffer.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) enum ReadStatus {
+    /// Payload of the given length, located at the start of the received bytes.
+    Ok(usize),
+    Err,
+}
+
+/// Classify a read response, without its leading CRLF, received as `body` followed by `tail`.
+pub(crate) fn read_status(body: &[u8], tail: &[u8]) -> Option<ReadStatus> {
+    const OK: &[u8] = b"\r\nOK\r\n> ";
+    const ERROR: &[u8] = b"-1\r\nERROR\r\n> ";
+
+    let total = body.len() + tail.len();
+    let ends_with = |suffix: &[u8]| {
+        total >= suffix.len()
+            && suffix.iter().enumerate().all(|(i, b)| {
+                let index = total - suffix.len() + i;
+                let c = if index < body.len() {
+                    body[index]
+                } else {
+                    tail[index - body.len()]
+                };
+                c == *b
+            })
+    };
+
+    if ends_with(OK) {
+        Some(ReadStatus::Ok(total - OK.len()))
+    } else if ends_with(ERROR) {
+        Some(ReadStatus::Err)
+    } else {
+        None
+    }
+}
+
+/// Whether a command response ends in `OK` followed by the prompt.
+pub fn is_ok(input: &[u8]) -> bool {
+    input.ends_with(b"OK\r\n> ")
+}
+
+/// Whether the module answered that it does not know the command. Firmware versions word
+/// it as `Unknown command` or `Command not found`, on a line of its own ahead of `ERROR`
+/// or following `ERROR: `.
+pub fn unknown_command(input: &[u8]) -> bool {
+    !is_ok(input)
+        && input
+            .split(|c| *c == b'\r' || *c == b'\n')
+            .map(|line| line.strip_prefix(b"ERROR: ").unwrap_or(line))
+            .any(|line| {
+                line.eq_ignore_ascii_case(b"Unknown command")
+                    || line.eq_ignore_ascii_case(b"Command not found")
+            })
+}
+
+/// Message of a response ending in `ERROR`: its last non-empty line before `ERROR`, which
+/// is empty when the module gave no reason.
+pub fn error_message(input: &[u8]) -> Option<&[u8]> {
+    let body = input.strip_suffix(b"ERROR\r\n> ")?;
+    Some(
+        body.split(|c| *c == b'\r' || *c == b'\n')
+            .rfind(|line| !line.is_empty())
+            .unwrap_or(&[]),
+    )
+}
+
+/// Transport settings of the selected socket, as shown by `P?`.
+#[derive(Debug)]
+pub struct TransportStatus {
+    /// Remote host and port
+    pub remote: SocketAddr,
+    /// Whether a client connection is active
+    pub client_connected: bool,
+}
+
+/// Parse the transport settings shown by `P?`.
+//
+// \r\n0,192.168.1.174,0,192.168.1.2,8088,0,0,0,1\r\nOK\r\n>
+//
+// Fields are protocol, local ip, local port, remote ip, remote port, server active,
+// backlog, accepted connections and client active.
+pub fn transport_status(input: &[u8]) -> Option<TransportStatus> {
+    const OK: &[u8] = b"\r\nOK\r\n> ";
+    let line = input.strip_prefix(b"\r\n")?.strip_suffix(OK)?;
+    let mut fields = line.split(|c| *c == b',').skip(3);
+    let (ip, rest) = ipv4(fields.next()?)?;
+    if !rest.is_empty() {
+        return None;
+    }
+    let port = u16::try_from(atoi_usize(fields.next()?)?).ok()?;
+    let client = fields.nth(3)?;
+    Some(TransportStatus {
+        remote: SocketAddr::new(IpAddr::V4(ip), port),
+        client_connected: atoi_usize(client)? != 0,
+    })
+}
+
+/// Parse a dotted IPv4 address at the start of `input`, returning it and the rest.
+pub(crate) fn ipv4(input: &[u8]) -> Option<(Ipv4Addr, &[u8])> {
+    let mut octets = [0u8; 4];
+    let mut rest = input;
+    for (i, octet) in octets.iter_mut().enumerate() {
+        if i > 0 {
+            rest = rest.strip_prefix(b".")?;
+        }
+        let digits = rest.iter().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 || digits > 3 {
+            return None;
+        }
+        let value = atoi_usize(&rest[..digits])?;
+        *octet = u8::try_from(value).ok()?;
+        rest = &rest[digits..];
+    }
+    Some((Ipv4Addr::from(octets), rest))
+}
+
+/// Parse a colon separated MAC address, such as `C4:12:F5:00:11:22`.
+pub(crate) fn mac_address(input: &[u8]) -> Option<[u8; 6]> {
+    let mut mac = [0u8; 6];
+    let mut parts = input.split(|c| *c == b':');
+    for octet in mac.iter_mut() {
+        let part = parts.next()?;
+        if part.len() != 2 {
+            return None;
+        }
+        let hex = core::str::from_utf8(part).ok()?;
+        *octet = u8::from_str_radix(hex, 16).ok()?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(mac)
+}
+
+/// Parse a signed decimal, such as an RSSI reading.
+pub(crate) fn atoi_isize(digits: &[u8]) -> Option<isize> {
+    match digits.strip_prefix(b"-") {
+        Some(digits) => Some(-(atoi_usize(digits)? as isize)),
+        None => Some(atoi_usize(digits)? as isize),
+    }
+}
+
+// \r\nC4:12:F5:00:11:22,-52\r\n28:6C:07:AA:BB:CC,-71\r\nOK\r\n>
+//
+// One line per station associated with the soft access point: MAC address and RSSI.
+pub(crate) fn ap_stations(input: &[u8]) -> Option<impl Iterator<Item = ([u8; 6], i8)> + '_> {
+    let body = input.strip_prefix(b"\r\n")?.strip_suffix(b"OK\r\n> ")?;
+    Some(body.split(|c| *c == b'\n').filter_map(|line| {
+        let line = line.strip_suffix(b"\r")?;
+        let comma = line.iter().position(|c| *c == b',')?;
+        let mac = mac_address(&line[..comma])?;
+        let rssi = i8::try_from(atoi_isize(&line[comma + 1..])?).ok()?;
+        Some((mac, rssi))
+    }))
+}
+
+// \r\ndrogue,secret,4,1,0,192.168.1.174,255.255.255.0,192.168.1.1,...\r\nOK\r\n>
+//
+// Network settings shown by `C?`, starting with the SSID and passphrase.
+pub(crate) fn network_credentials(input: &[u8]) -> Option<(&[u8], &[u8])> {
+    let line = input.strip_prefix(b"\r\n")?.strip_suffix(b"\r\nOK\r\n> ")?;
+    let mut fields = line.split(|c| *c == b',');
+    Some((fields.next()?, fields.next()?))
+}
+
+/// Network settings kept in flash, as found at the start of both `C?` and `Z?`.
+///
+/// Addresses are only part of the settings with DHCP off; with DHCP on, `C?` shows the
+/// leased ones and `Z?` whatever was last entered.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NetworkConfig<'a> {
+    /// Network name
+    pub ssid: &'a [u8],
+    /// Passphrase, in the clear
+    pub passphrase: &'a [u8],
+    /// Security type, as numbered by `C3`
+    pub security: &'a [u8],
+    /// Whether the address is obtained over DHCP
+    pub dhcp: bool,
+    /// Address, network mask and gateway, `None` with DHCP on.
+    pub addresses: Option<[Ipv4Addr; 3]>,
+}
+
+/// Parse the network settings shown by `C?` or `Z?`.
+//
+// \r\ndrogue,secret,4,0,0,192.168.1.174,255.255.255.0,192.168.1.1,...\r\nOK\r\n>
+//
+// Fields after the gateway differ between the two, `C?` going on with the connection
+// status, so they are left out.
+pub fn network_config(input: &[u8]) -> Option<NetworkConfig<'_>> {
+    let line = input.strip_prefix(b"\r\n")?.strip_suffix(b"\r\nOK\r\n> ")?;
+    let mut fields = line.split(|c| *c == b',');
+    let (ssid, passphrase, security) = (fields.next()?, fields.next()?, fields.next()?);
+    let dhcp = match fields.next()? {
+        b"0" => false,
+        b"1" => true,
+        _ => return None,
+    };
+    // IP version
+    fields.next()?;
+    let mut addresses = [Ipv4Addr::UNSPECIFIED; 3];
+    for address in addresses.iter_mut() {
+        let (ip, rest) = ipv4(fields.next()?)?;
+        if !rest.is_empty() {
+            return None;
+        }
+        *address = ip;
+    }
+    Some(NetworkConfig {
+        ssid,
+        passphrase,
+        security,
+        dhcp,
+        addresses: (!dhcp).then_some(addresses),
+    })
+}
+
+/// Address of the module from the network settings shown by `C?`, unspecified while
+/// DHCP has not completed.
+pub(crate) fn network_address(input: &[u8]) -> Option<Ipv4Addr> {
+    network_setting(input, 5)
+}
+
+/// Network mask from the network settings shown by `C?`.
+pub(crate) fn network_netmask(input: &[u8]) -> Option<Ipv4Addr> {
+    network_setting(input, 6)
+}
+
+/// Gateway address from the network settings shown by `C?`.
+pub(crate) fn network_gateway(input: &[u8]) -> Option<Ipv4Addr> {
+    network_setting(input, 7)
+}
+
+/// Primary DNS server from the network settings shown by `C?`.
+pub(crate) fn network_dns(input: &[u8]) -> Option<Ipv4Addr> {
+    network_setting(input, 8)
+}
+
+fn network_setting(input: &[u8], index: usize) -> Option<Ipv4Addr> {
+    let line = input.strip_prefix(b"\r\n")?.strip_suffix(b"\r\nOK\r\n> ")?;
+    let (ip, rest) = ipv4(line.split(|c| *c == b',').nth(index)?)?;
+    if rest.is_empty() {
+        Some(ip)
+    } else {
+        None
+    }
+}
+
+/// Access point found by a scan, borrowing from the `F0` response.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScanEntry<'a> {
+    /// Network name
+    pub ssid: &'a [u8],
+    /// MAC address of the access point
+    pub bssid: [u8; 6],
+    /// Signal strength, in dBm
+    pub rssi: i8,
+    /// Security mode, such as `WPA2 AES`
+    pub security: &'a [u8],
+    /// WiFi channel
+    pub channel: u8,
+}
+
+/// Parse one line of the `F0` scan results.
+//
+// #001,"drogue",C4:12:F5:00:11:22,-52,72.0,Infrastructure,WPA2 AES,2.4GHz,6
+//
+// The SSID is quoted and may itself contain commas, so the fields after it are found
+// from the closing quote.
+pub fn scan_entry(line: &[u8]) -> Option<ScanEntry<'_>> {
+    let line = line.strip_prefix(b"#")?;
+    let open = line.iter().position(|c| *c == b'"')?;
+    let close = open + 1 + line[open + 1..].windows(2).rposition(|w| w == b"\",")?;
+    let ssid = &line[open + 1..close];
+    let mut fields = line[close + 2..].split(|c| *c == b',');
+    let bssid = mac_address(fields.next()?)?;
+    let rssi = i8::try_from(atoi_isize(fields.next()?)?).ok()?;
+    let security = fields.nth(2)?;
+    let channel = u8::try_from(atoi_usize(fields.nth(1)?)?).ok()?;
+    Some(ScanEntry {
+        ssid,
+        bssid,
+        rssi,
+        security,
+        channel,
+    })
+}
+
+/// The single line of a successful response, such as `-52` in `\r\n-52\r\nOK\r\n> `.
+pub fn value(input: &[u8]) -> Option<&[u8]> {
+    input.strip_prefix(b"\r\n")?.strip_suffix(b"\r\nOK\r\n> ")
+}
+
+/// Address returned by a `D0` host lookup, such as `\r\n93.184.216.34\r\nOK\r\n> `.
+pub fn host_address(input: &[u8]) -> Option<Ipv4Addr> {
+    match ipv4(value(input)?)? {
+        (ip, b"") => Some(ip),
+        _ => None,
+    }
+}
+
+/// Signal strength shown by `CR`, in dBm.
+//
+// \r\n-52\r\nOK\r\n>
+pub fn rssi(input: &[u8]) -> Option<i8> {
+    i8::try_from(atoi_isize(value(input)?)?).ok()
+}
+
+/// Parse a decimal with up to three fractional digits, such as `72.2`, in thousandths.
+pub(crate) fn atoi_milli(digits: &[u8]) -> Option<u32> {
+    let (whole, fraction) = match digits.iter().position(|c| *c == b'.') {
+        Some(dot) => (&digits[..dot], &digits[dot + 1..]),
+        None => (digits, &b""[..]),
+    };
+    if whole.is_empty() || fraction.len() > 3 {
+        return None;
+    }
+    let mut value = u32::try_from(atoi_usize(whole)?).ok()?.checked_mul(1000)?;
+    let mut scale = 100;
+    for digit in fraction {
+        value += ascii_to_digit(*digit)? as u32 * scale;
+        scale /= 10;
+    }
+    Some(value)
+}
+
+/// Association details, borrowing from the `CI` response.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnectionInfo<'a> {
+    /// WiFi channel
+    pub channel: u8,
+    /// PHY rate, in kbit/s
+    pub rate_kbps: u32,
+    /// Security mode, such as `WPA2 AES`
+    pub security: &'a [u8],
+}
+
+/// Parse the association details shown by `CI`.
+//
+// \r\n6,72.2,WPA2 AES\r\nOK\r\n>
+//
+// Fields are channel, PHY rate in Mbit/s and security mode.
+pub fn connection_info(input: &[u8]) -> Option<ConnectionInfo<'_>> {
+    let line = input.strip_prefix(b"\r\n")?.strip_suffix(b"\r\nOK\r\n> ")?;
+    let mut fields = line.splitn(3, |c| *c == b',');
+    Some(ConnectionInfo {
+        channel: u8::try_from(atoi_usize(fields.next()?)?).ok()?,
+        rate_kbps: atoi_milli(fields.next()?)?,
+        security: fields.next()?,
+    })
+}
+
+#[cfg(not(feature = "tiny"))]
+pub(crate) fn parse_u8(input: &[u8]) -> IResult<&[u8], u8> {
+    let (input, digits) = digit1(input)?;
+    IResult::Ok((input, atoi_u8(digits).unwrap()))
+}
+
+#[cfg(not(feature = "tiny"))]
+pub(crate) fn parse_usize(input: &[u8]) -> IResult<&[u8], usize> {
+    let (input, digits) = digit1(input)?;
+    let num = atoi_usize(digits).unwrap();
+    IResult::Ok((input, num))
+}
+
+pub(crate) fn ascii_to_digit(character: u8) -> Option<u8> {
+    match character {
+        b'0' => Some(0),
+        b'1' => Some(1),
+        b'2' => Some(2),
+        b'3' => Some(3),
+        b'4' => Some(4),
+        b'5' => Some(5),
+        b'6' => Some(6),
+        b'7' => Some(7),
+        b'8' => Some(8),
+        b'9' => Some(9),
+        _ => None,
+    }
+}
+
+#[cfg(not(feature = "tiny"))]
+pub(crate) fn atoi_u8(digits: &[u8]) -> Option<u8> {
+    let mut num: u8 = 0;
+    let len = digits.len();
+    for (i, digit) in digits.iter().enumerate() {
+        let digit = ascii_to_digit(*digit)?;
+        let mut exp = 1;
+        for _ in 0..(len - i - 1) {
+            exp *= 10;
+        }
+        num += exp * digit;
+    }
+    Some(num)
+}
+
+pub(crate) fn atoi_usize(digits: &[u8]) -> Option<usize> {
+    let mut num: usize = 0;
+    let len = digits.len();
+    for (i, digit) in digits.iter().enumerate() {
+        let digit = ascii_to_digit(*digit)? as usize;
+        let mut exp = 1;
+        for _ in 0..(len - i - 1) {
+            exp *= 10;
+        }
+        num += exp * digit;
+    }
+    Some(num)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_response_parser_eagerness() {
+        let payload = &[
+            0x01, 0x02, 0x0d, 0x0a, 0x4f, 0x4b, 0x0d, 0x0a, 0x3e, 0x20, 0x03, 0x04,
+        ];
+        let input = &[
+            0x0d, 0x0a, 0x01, 0x02, 0x0d, 0x0a, 0x4f, 0x4b, 0x0d, 0x0a, 0x3e, 0x20, 0x03, 0x04,
+            0x0d, 0x0a, 0x4f, 0x4b, 0x0d, 0x0a, 0x3e, 0x20,
+        ];
+        let result = super::read_status(&input[2..], &[]);
+        if let Some(super::ReadStatus::Ok(len)) = result {
+            assert_eq!(&input[2..2 + len], payload);
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn test_response_parser_expected_error() {
+        let input = &[
+            0x0d, 0x0a, 0x2d, 0x31, 0x0d, 0x0a, 0x45, 0x52, 0x52, 0x4f, 0x52, 0x0d, 0x0a, 0x3e,
+            0x20,
+        ];
+        let result = super::read_status(&input[2..], &[]);
+        assert_eq!(result, Some(super::ReadStatus::Err));
+    }
+
+    #[test]
+    fn test_response_parser_unexpected_error() {
+        let input = &[
+            0x0d, 0x0a, 0x2d, 0x31, 0x0d, 0x0a, 0x52, 0x0d, 0x0a, 0x3e, 0x20,
+        ];
+        let result = super::read_status(&input[2..], &[]);
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_connect_response_protocols() {
+        use super::ConnectResponse;
+
+        let input = b"\r\n[TCP  RC] Connecting to 192.168.1.2\r\nOK\r\n> ";
+        assert!(matches!(
+            super::connect_response(input),
+            Ok((_, ConnectResponse::Ok))
+        ));
+        let input = b"\r\n[UDP  RC] Connecting to 192.168.1.2\r\nOK\r\n> ";
+        assert!(matches!(
+            super::connect_response(input),
+            Ok((_, ConnectResponse::Ok))
+        ));
+        let input = b"\r\nOK\r\n> ";
+        assert!(matches!(
+            super::connect_response(input),
+            Ok((_, ConnectResponse::Ok))
+        ));
+    }
+
+    #[test]
+    fn test_connect_failure_classification() {
+        use super::{ConnectFailure, ConnectResponse};
+
+        let input = b"\r\n[TCP  RC] Connecting to 192.168.1.2\r\n[TCP  RC] Connection refused\r\nERROR\r\n> ";
+        let result = super::connect_response(input);
+        assert!(matches!(
+            result,
+            Ok((_, ConnectResponse::Error(ConnectFailure::Refused)))
+        ));
+
+        assert_eq!(
+            super::connect_failure(b"Host Unreachable\r\n"),
+            ConnectFailure::Unreachable
+        );
+        assert_eq!(
+            super::connect_failure(b"[TCP  RC] Connection Timeout\r\n"),
+            ConnectFailure::TimedOut
+        );
+        assert_eq!(super::connect_failure(b"\r\n"), ConnectFailure::Other);
+    }
+
+    #[test]
+    fn test_contains_ignore_case() {
+        assert!(super::contains_ignore_case(
+            b"Connection Refused",
+            b"refused"
+        ));
+        assert!(!super::contains_ignore_case(b"refuse", b"refused"));
+        assert!(super::contains_ignore_case(b"anything", b""));
+        assert!(super::contains_ignore_case(b"", b""));
+    }
+
+    #[test]
+    fn test_join_failure_classification() {
+        use super::{JoinFailure, JoinResponse};
+
+        let input = b"\r\n[JOIN   ] drogue\r\n[JOIN   ] AP not found\r\nERROR\r\n> ";
+        let result = super::join_response(input);
+        assert!(matches!(
+            result,
+            Ok((_, JoinResponse::JoinError(JoinFailure::NetworkNotFound)))
+        ));
+
+        assert_eq!(
+            super::join_failure(b"[JOIN   ] Authentication failed\r\n"),
+            JoinFailure::WrongPassword
+        );
+        assert_eq!(
+            super::join_failure(b"[JOIN   ] DHCP failed\r\n"),
+            JoinFailure::DhcpFailed
+        );
+        assert_eq!(
+            super::join_failure(b"[JOIN   ] Failed\r\n"),
+            JoinFailure::Other
+        );
+    }
+
+    #[test]
+    fn test_transport_status() {
+        let status =
+            super::transport_status(b"\r\n0,192.168.1.174,0,192.168.1.2,8088,0,0,0,1\r\nOK\r\n> ")
+                .unwrap();
+        assert!(status.client_connected);
+        assert_eq!(
+            status.remote,
+            super::SocketAddr::new(
+                super::IpAddr::V4(super::Ipv4Addr::new(192, 168, 1, 2)),
+                8088
+            )
+        );
+        let status =
+            super::transport_status(b"\r\n0,192.168.1.174,0,192.168.1.2,8088,0,0,0,0\r\nOK\r\n> ");
+        assert!(!status.unwrap().client_connected);
+        assert!(super::transport_status(b"\r\nERROR\r\n> ").is_none());
+    }
+
+    #[test]
+    fn test_error_message() {
+        let input = b"\r\n[JOIN   ] drogue\r\n[JOIN   ] AP not found\r\nERROR\r\n> ";
+        assert_eq!(
+            super::error_message(input),
+            Some(&b"[JOIN   ] AP not found"[..])
+        );
+        assert_eq!(super::error_message(b"\r\nERROR\r\n> "), Some(&b""[..]));
+    }
+
+    #[test]
+    fn test_unknown_command() {
+        assert!(super::unknown_command(
+            b"\r\nUnknown command\r\nERROR\r\n> "
+        ));
+        assert!(super::unknown_command(
+            b"\r\nERROR: Command not found\r\n> "
+        ));
+        assert!(!super::unknown_command(
+            b"\r\nConnection refused\r\nERROR\r\n> "
+        ));
+        assert!(!super::unknown_command(b"\r\nOK\r\n> "));
+        assert_eq!(super::error_message(b"\r\nOK\r\n> "), None);
+    }
+
+    #[test]
+    fn test_ap_stations() {
+        let response = b"\r\nC4:12:F5:00:11:22,-52\r\n28:6c:07:aa:bb:cc,-71\r\nOK\r\n> ";
+        let mut stations = super::ap_stations(response).unwrap();
+        assert_eq!(
+            stations.next(),
+            Some(([0xC4, 0x12, 0xF5, 0x00, 0x11, 0x22], -52))
+        );
+        assert_eq!(
+            stations.next(),
+            Some(([0x28, 0x6C, 0x07, 0xAA, 0xBB, 0xCC], -71))
+        );
+        assert_eq!(stations.next(), None);
+
+        assert_eq!(super::ap_stations(b"\r\nOK\r\n> ").unwrap().count(), 0);
+        assert!(super::ap_stations(b"\r\nERROR\r\n> ").is_none());
+        assert!(super::mac_address(b"C4:12:F5:00:11").is_none());
+    }
+
+    #[test]
+    fn test_network_config() {
+        let current = b"\r\nhome,hunter22,4,1,0,192.168.1.174,255.255.255.0,192.168.1.1,\
+                        192.168.1.1,0.0.0.0,5,1,0,GB,1\r\nOK\r\n> ";
+        let stored = b"\r\nhome,hunter22,4,1,0,0.0.0.0,0.0.0.0,0.0.0.0,0.0.0.0,0.0.0.0,5,1,0,\
+                       GB\r\nOK\r\n> ";
+        let config = super::network_config(current).unwrap();
+        assert_eq!(config.ssid, b"home");
+        assert_eq!(config.passphrase, b"hunter22");
+        assert!(config.dhcp);
+        assert_eq!(config.addresses, None);
+        // The leased address and connection status are not part of the settings.
+        assert_eq!(super::network_config(stored), Some(config));
+
+        let fixed = b"\r\nhome,hunter22,4,0,0,10.0.0.2,255.0.0.0,10.0.0.1\r\nOK\r\n> ";
+        let config = super::network_config(fixed).unwrap();
+        assert_eq!(
+            config.addresses,
+            Some([
+                super::Ipv4Addr::new(10, 0, 0, 2),
+                super::Ipv4Addr::new(255, 0, 0, 0),
+                super::Ipv4Addr::new(10, 0, 0, 1),
+            ])
+        );
+        assert_ne!(super::network_config(stored), Some(config));
+        assert!(super::network_config(b"\r\nhome,hunter22,4\r\nOK\r\n> ").is_none());
+    }
+
+    #[test]
+    fn test_network_credentials() {
+        let response =
+            b"\r\nhome,hunter22,4,1,0,192.168.1.174,255.255.255.0,192.168.1.1\r\nOK\r\n> ";
+        assert_eq!(
+            super::network_credentials(response),
+            Some((&b"home"[..], &b"hunter22"[..]))
+        );
+        assert_eq!(
+            super::network_credentials(b"\r\n,,0,1,0\r\nOK\r\n> "),
+            Some((&b""[..], &b""[..]))
+        );
+        assert!(super::network_credentials(b"\r\nERROR\r\n> ").is_none());
+        assert_eq!(
+            super::network_gateway(response),
+            Some(super::Ipv4Addr::new(192, 168, 1, 1))
+        );
+        assert_eq!(
+            super::network_address(response),
+            Some(super::Ipv4Addr::new(192, 168, 1, 174))
+        );
+        assert_eq!(
+            super::network_netmask(response),
+            Some(super::Ipv4Addr::new(255, 255, 255, 0))
+        );
+        assert_eq!(super::network_dns(response), None);
+        assert_eq!(
+            super::network_dns(
+                b"\r\nhome,hunter22,4,1,0,192.168.1.174,255.255.255.0,192.168.1.1,1.1.1.1,8.8.8.8\r\nOK\r\n> "
+            ),
+            Some(super::Ipv4Addr::new(1, 1, 1, 1))
+        );
+    }
+
+    #[test]
+    fn test_scan_entry() {
+        assert_eq!(
+            super::scan_entry(
+                b"#001,\"drogue\",C4:12:F5:00:11:22,-52,72.0,Infrastructure,WPA2 AES,2.4GHz,6"
+            ),
+            Some(super::ScanEntry {
+                ssid: b"drogue",
+                bssid: [0xC4, 0x12, 0xF5, 0x00, 0x11, 0x22],
+                rssi: -52,
+                security: b"WPA2 AES",
+                channel: 6,
+            })
+        );
+        let entry = super::scan_entry(
+            b"#002,\"a,b\",28:6C:07:AA:BB:CC,-80,72.0,Infrastructure,Open,2.4GHz,11",
+        )
+        .unwrap();
+        assert_eq!(entry.ssid, b"a,b");
+        assert_eq!(entry.channel, 11);
+        assert!(super::scan_entry(b"OK").is_none());
+    }
+
+    #[test]
+    fn test_host_address() {
+        assert_eq!(
+            super::host_address(b"\r\n93.184.216.34\r\nOK\r\n> "),
+            Some(super::Ipv4Addr::new(93, 184, 216, 34))
+        );
+        assert_eq!(super::host_address(b"\r\n-1\r\nOK\r\n> "), None);
+        assert_eq!(super::host_address(b"\r\nERROR\r\n> "), None);
+    }
+
+    #[test]
+    fn test_rssi() {
+        assert_eq!(super::rssi(b"\r\n-52\r\nOK\r\n> "), Some(-52));
+        assert_eq!(super::rssi(b"\r\n-200\r\nOK\r\n> "), None);
+        assert_eq!(super::rssi(b"\r\nERROR\r\n> "), None);
+    }
+
+    #[test]
+    fn test_connection_info() {
+        assert_eq!(
+            super::connection_info(b"\r\n6,72.2,WPA2 AES\r\nOK\r\n> "),
+            Some(super::ConnectionInfo {
+                channel: 6,
+                rate_kbps: 72_200,
+                security: b"WPA2 AES",
+            })
+        );
+        assert_eq!(super::atoi_milli(b"5.5"), Some(5_500));
+        assert_eq!(super::atoi_milli(b"54"), Some(54_000));
+        assert_eq!(super::atoi_milli(b"1.0005"), None);
+        assert!(super::connection_info(b"\r\nERROR\r\n> ").is_none());
+    }
+
+    #[test]
+    fn test_read_status_split_trailer() {
+        let body = b"hello\r\nO";
+        let tail = b"K\r\n> ";
+        assert_eq!(
+            super::read_status(body, tail),
+            Some(super::ReadStatus::Ok(5))
+        );
+        assert_eq!(
+            super::read_status(b"hi\r\nOK\r\n> ", &[]),
+            Some(super::ReadStatus::Ok(2))
+        );
+        assert_eq!(
+            super::read_status(b"-1\r\nERR", b"OR\r\n> "),
+            Some(super::ReadStatus::Err)
+        );
+        assert_eq!(super::read_status(b"hello", &[]), None);
+    }
+}
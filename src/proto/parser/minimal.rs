@@ -0,0 +1,192 @@
+//! Hand-written versions of the response parsers, used by the `tiny` feature to keep
+//! nom's combinators out of the binary.
+
+use super::{
+    atoi_usize, connect_failure, ipv4, join_failure, CloseResponse, ConnectResponse, IpAddr,
+    JoinResponse, WriteResponse,
+};
+
+const OK: &[u8] = b"OK\r\n> ";
+const ERROR: &[u8] = b"ERROR\r\n";
+
+/// Response that does not parse.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Invalid;
+
+/// Value parsed with the input left after it, matched alike to what the nom parsers
+/// return.
+pub type Parsed<'a, T> = Result<(&'a [u8], T), Invalid>;
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Parse the response to `C0`, up to and including the prompt.
+//
+// \r\n[JOIN   ] drogue,192.168.1.174,0,0\r\nOK\r\n>
+pub fn join_response(input: &[u8]) -> Parsed<'_, JoinResponse> {
+    let body = match input.strip_prefix(b"\r\n") {
+        Some(body) => body,
+        None => return Err(Invalid),
+    };
+    if let Some(rest) = body.strip_prefix(b"[JOIN   ] ") {
+        let joined = find(rest, b",")
+            .and_then(|comma| ipv4(&rest[comma + 1..]))
+            .and_then(|(ip, rest)| {
+                let remaining = rest.strip_prefix(b",0,0\r\n")?.strip_prefix(OK)?;
+                Some((remaining, ip))
+            });
+        if let Some((remaining, ip)) = joined {
+            return Ok((remaining, JoinResponse::Ok(IpAddr::V4(ip))));
+        }
+    }
+    match find(body, ERROR) {
+        Some(pos) => match body[pos + ERROR.len()..].strip_prefix(b"> ") {
+            Some(remaining) => Ok((
+                remaining,
+                JoinResponse::JoinError(join_failure(&body[..pos])),
+            )),
+            None => Err(Invalid),
+        },
+        None => Err(Invalid),
+    }
+}
+
+/// Parse the response to `P6=1`, up to and including the prompt.
+//
+// \r\n[TCP  RC] Connecting to 192.168.1.2\r\nOK\r\n>
+// \r\nOK\r\n>
+pub fn connect_response(input: &[u8]) -> Parsed<'_, ConnectResponse> {
+    if let Some(rest) = input.strip_prefix(b"\r\n") {
+        if let Some(remaining) = rest.strip_prefix(OK) {
+            return Ok((remaining, ConnectResponse::Ok));
+        }
+        if let Some(start) = find(rest, b"] Connecting to ").filter(|_| rest.starts_with(b"[")) {
+            let rest = &rest[start..];
+            if let Some(eol) = find(rest, b"\r\n") {
+                if let Some(remaining) = rest[eol + 2..].strip_prefix(OK) {
+                    return Ok((remaining, ConnectResponse::Ok));
+                }
+            }
+        }
+    }
+    match find(input, ERROR) {
+        Some(pos) => Ok((
+            &input[pos + ERROR.len()..],
+            ConnectResponse::Error(connect_failure(&input[..pos])),
+        )),
+        None => Err(Invalid),
+    }
+}
+
+/// Parse the response to `P6=0`, up to and including the prompt.
+//
+// \r\n\r\nOK\r\n>
+pub fn close_response(input: &[u8]) -> Parsed<'_, CloseResponse> {
+    if let Some(remaining) = input
+        .strip_prefix(b"\r\n\r\n")
+        .and_then(|rest| rest.strip_prefix(OK))
+    {
+        return Ok((remaining, CloseResponse::Ok));
+    }
+    let body = match input.strip_prefix(b"\r\n") {
+        Some(body) => body,
+        None => return Err(Invalid),
+    };
+    match find(body, ERROR) {
+        Some(pos) => match body[pos + ERROR.len()..].strip_prefix(b"> ") {
+            Some(remaining) => Ok((remaining, CloseResponse::Error)),
+            None => Err(Invalid),
+        },
+        None => Err(Invalid),
+    }
+}
+
+/// Parse the response to `S3`, up to and including the prompt.
+//
+// \r\n1200\r\nOK\r\n>
+pub fn write_response(input: &[u8]) -> Parsed<'_, WriteResponse> {
+    let body = match input.strip_prefix(b"\r\n") {
+        Some(body) => body,
+        None => return Err(Invalid),
+    };
+    let (response, rest) = if let Some(rest) = body.strip_prefix(b"-1") {
+        (WriteResponse::Error, rest)
+    } else {
+        let digits = body.iter().take_while(|c| c.is_ascii_digit()).count();
+        match atoi_usize(&body[..digits]) {
+            Some(len) if digits > 0 => (WriteResponse::Ok(len), &body[digits..]),
+            _ => return Err(Invalid),
+        }
+    };
+    match rest
+        .strip_prefix(b"\r\n")
+        .and_then(|rest| rest.strip_prefix(OK))
+    {
+        Some(remaining) => Ok((remaining, response)),
+        None => Err(Invalid),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::net::Ipv4Addr;
+
+    #[test]
+    fn test_join() {
+        let input = b"\r\n[JOIN   ] drogue,192.168.1.174,0,0\r\nOK\r\n> ";
+        let (_, response) = join_response(input).unwrap();
+        assert!(matches!(
+            response,
+            JoinResponse::Ok(IpAddr::V4(ip)) if ip == Ipv4Addr::new(192, 168, 1, 174)
+        ));
+
+        let input = b"\r\n[JOIN   ] drogue\r\n[JOIN   ] Failed\r\nERROR\r\n> ";
+        let (_, response) = join_response(input).unwrap();
+        assert!(matches!(response, JoinResponse::JoinError(_)));
+    }
+
+    #[test]
+    fn test_connect_and_close() {
+        let input = b"\r\n[TCP  RC] Connecting to 192.168.1.2\r\nOK\r\n> ";
+        assert!(matches!(
+            connect_response(input),
+            Ok((_, ConnectResponse::Ok))
+        ));
+        let input = b"\r\n[UDP  RC] Connecting to 192.168.1.2\r\nOK\r\n> ";
+        assert!(matches!(
+            connect_response(input),
+            Ok((_, ConnectResponse::Ok))
+        ));
+        let input = b"\r\n[TCP  RC] Connecting to 192.168.1.2\r\nERROR\r\n> ";
+        assert!(matches!(
+            connect_response(input),
+            Ok((_, ConnectResponse::Error(_)))
+        ));
+
+        assert!(matches!(
+            close_response(b"\r\n\r\nOK\r\n> "),
+            Ok((_, CloseResponse::Ok))
+        ));
+        assert!(matches!(
+            close_response(b"\r\nNot connected\r\nERROR\r\n> "),
+            Ok((_, CloseResponse::Error))
+        ));
+    }
+
+    #[test]
+    fn test_write() {
+        assert!(matches!(
+            write_response(b"\r\n1200\r\nOK\r\n> "),
+            Ok((_, WriteResponse::Ok(1200)))
+        ));
+        assert!(matches!(
+            write_response(b"\r\n-1\r\nOK\r\n> "),
+            Ok((_, WriteResponse::Error))
+        ));
+        assert!(write_response(b"\r\nERROR\r\n> ").is_err());
+    }
+}
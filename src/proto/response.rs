@@ -0,0 +1,321 @@
+//! Interpretation of module responses, kept free of the transport and pin types.
+//!
+//! The driver state is generic over the transport, so code living in its methods is
+//! compiled again for every transport a firmware uses; the RESET and WAKEUP pins are
+//! already erased behind trait objects. The decisions taken on a response only depend on
+//! its bytes, so they live here and are compiled once. The transport itself stays a type
+//! parameter: [`Transport`](crate::Transport) hands out futures, which cannot be
+//! type-erased without an allocator.
+
+use crate::parser::{
+    self, CloseResponse, ConnectFailure, ConnectResponse, JoinFailure, JoinResponse, ReadStatus,
+    WriteResponse,
+};
+use crate::{IpAddr, JoinError, Protocol, SocketAddr, SocketError, SocketHandle};
+use heapless::String;
+
+/// Check that `remote` is an address the module can connect `protocol` sockets to.
+pub(crate) fn check_remote(
+    handle: SocketHandle,
+    remote: SocketAddr,
+    protocol: Protocol,
+) -> Result<(), SocketError> {
+    let ip = match remote.ip() {
+        IpAddr::V4(ip) => ip,
+        IpAddr::V6(_) => {
+            warn!("[{}] IPv6 is not supported by the adapter", handle);
+            return Err(SocketError::InvalidAddress);
+        }
+    };
+    if protocol != Protocol::Udp && (ip.is_broadcast() || ip.is_multicast()) {
+        warn!("[{}] TCP connections need a unicast address", handle);
+        return Err(SocketError::InvalidAddress);
+    }
+    if protocol != Protocol::Udp && (ip.is_unspecified() || remote.port() == 0) {
+        warn!(
+            "[{}] TCP connections need a remote address and port",
+            handle
+        );
+        return Err(SocketError::InvalidAddress);
+    }
+    Ok(())
+}
+
+/// Outcome of a join, as the address obtained or the reason it failed.
+pub(crate) fn join_result(response: &[u8]) -> Result<IpAddr, JoinError> {
+    match parser::join_response(response) {
+        Ok((_, response)) => match response {
+            // Some firmware reports success before DHCP has completed.
+            JoinResponse::Ok(IpAddr::V4(ip)) if ip.is_unspecified() => {
+                debug!("Joined without an address");
+                Err(JoinError::DhcpTimeout)
+            }
+            JoinResponse::Ok(ip) => Ok(ip),
+            JoinResponse::JoinError(failure) => {
+                debug!("Join failed: {:?}", failure);
+                Err(match failure {
+                    JoinFailure::WrongPassword => JoinError::WrongPassword,
+                    JoinFailure::NetworkNotFound => JoinError::NetworkNotFound,
+                    JoinFailure::DhcpFailed => JoinError::DhcpTimeout,
+                    JoinFailure::Timeout => JoinError::AssociationTimeout,
+                    JoinFailure::Other => JoinError::UnableToAssociate,
+                })
+            }
+        },
+        Err(_) => {
+            trace!("{:?}", &response);
+            Err(JoinError::UnableToAssociate)
+        }
+    }
+}
+
+/// Outcome of starting a client connection.
+pub(crate) fn connect_result(handle: SocketHandle, response: &[u8]) -> Result<(), SocketError> {
+    match parser::connect_response(response) {
+        Ok((_, ConnectResponse::Ok)) => Ok(()),
+        Ok((_, ConnectResponse::Error(failure))) => {
+            trace!("[{}] CONNECT 6 {:?}", handle, failure);
+            Err(match failure {
+                ConnectFailure::Refused => SocketError::ConnectionRefused,
+                ConnectFailure::Unreachable => SocketError::HostUnreachable,
+                ConnectFailure::TimedOut => SocketError::ConnectTimeout,
+                ConnectFailure::Other => SocketError::ConnectError,
+            })
+        }
+        Err(_) => {
+            trace!("[{}] CONNECT 7", handle);
+            Err(SocketError::ConnectError)
+        }
+    }
+}
+
+/// Number of the `sent` bytes of an `S3` chunk the module accepted, 0 meaning its
+/// transmit buffer is full.
+///
+/// The module also answers `-1` for a connection that is gone, which the caller tells
+/// apart by checking on the socket. An error message fails the write, unless it reports
+/// the buffer being full.
+pub(crate) fn write_result(response: &[u8], sent: usize) -> Result<usize, SocketError> {
+    match parser::write_response(response) {
+        Ok((_, WriteResponse::Ok(len))) => Ok(core::cmp::min(len, sent)),
+        Ok((_, WriteResponse::Error)) => Ok(0),
+        Err(_) => match parser::error_message(response) {
+            Some(message) if buffer_full(message) => Ok(0),
+            _ => {
+                trace!("Error reading response");
+                #[cfg(not(feature = "tiny"))]
+                if let Ok(s) = core::str::from_utf8(response) {
+                    trace!("response:  {:?}", s);
+                }
+                Err(SocketError::WriteError)
+            }
+        },
+    }
+}
+
+/// Whether the error `message` of a write tells of the transmit buffer being full.
+fn buffer_full(message: &[u8]) -> bool {
+    parser::contains_ignore_case(message, b"full") || parser::contains_ignore_case(message, b"busy")
+}
+
+/// Number of payload bytes delivered by an `R0` response of `received` bytes, split
+/// into its leading line ending `head`, the payload `body` and the status `tail`.
+pub(crate) fn read_result(
+    handle: SocketHandle,
+    received: usize,
+    head: &[u8],
+    body: &[u8],
+    tail: &[u8],
+) -> Result<usize, SocketError> {
+    if received < head.len() || head != b"\r\n" {
+        warn!("[{}] READ 9 parse error", handle);
+        trace!("response header: {:?}", head);
+        return Err(SocketError::ReadError);
+    }
+
+    match parser::read_status(body, tail) {
+        Some(ReadStatus::Ok(data_len)) => {
+            trace!("Read {} bytes", data_len);
+            Ok(data_len)
+        }
+        Some(ReadStatus::Err) => {
+            trace!("[{}] READ 9 ReadResponse::Err", handle);
+            Err(SocketError::ReadError)
+        }
+        None => {
+            warn!("[{}] READ 9 parse error", handle);
+            #[cfg(not(feature = "tiny"))]
+            if let Ok(s) = core::str::from_utf8(body) {
+                trace!("response parsed:  {:?}", s);
+            }
+            trace!("response raw tail: {:?}", tail);
+            Err(SocketError::ReadError)
+        }
+    }
+}
+
+/// Outcome of closing a connection or stopping a server.
+pub(crate) fn close_result(handle: SocketHandle, response: &[u8]) -> Result<(), SocketError> {
+    match parser::close_response(response) {
+        Ok((_, CloseResponse::Ok)) => {
+            debug!("[{}] Connection closed", handle);
+            Ok(())
+        }
+        Ok((_, _)) => {
+            debug!("[{}] Error1 closing connection", handle);
+            Err(SocketError::CloseError)
+        }
+        Err(_) => {
+            debug!("[{}] Error2 closing connection", handle);
+            #[cfg(not(feature = "tiny"))]
+            if let Ok(s) = core::str::from_utf8(response) {
+                debug!("response parsed:  {:?}", s);
+            }
+            Err(SocketError::CloseError)
+        }
+    }
+}
+
+/// Whether `response` ends with the status line every complete response carries. A
+/// response without one, such as a lone prompt, means the driver lost step with the
+/// module.
+pub(crate) fn well_formed(response: &[u8]) -> bool {
+    parser::is_ok(response)
+        || parser::error_message(response).is_some()
+        || parser::unknown_command(response)
+}
+
+/// Error message carried by `response`, if any, to be kept as the module's last error.
+pub(crate) fn error_message(response: &[u8]) -> Option<String<64>> {
+    let message = parser::error_message(response)?;
+    let mut error = String::new();
+    for c in message {
+        if error.push(*c as char).is_err() {
+            break;
+        }
+    }
+    Some(error)
+}
+
+/// Splits a response into lines as it arrives, for responses too long to buffer whole.
+pub(crate) struct LineSplitter<'a> {
+    line: &'a mut [u8],
+    len: usize,
+    /// Whether the current line is longer than the buffer, and is to be skipped.
+    overflow: bool,
+}
+
+impl<'a> LineSplitter<'a> {
+    pub(crate) fn new(line: &'a mut [u8]) -> Self {
+        Self {
+            line,
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    /// Take the next byte of the response, returning the line it completes without its
+    /// line ending, if any.
+    pub(crate) fn push(&mut self, byte: u8) -> Option<&[u8]> {
+        if byte == b'\n' {
+            let len = core::mem::take(&mut self.len);
+            if core::mem::take(&mut self.overflow) {
+                return None;
+            }
+            let end = if len > 0 && self.line[len - 1] == b'\r' {
+                len - 1
+            } else {
+                len
+            };
+            return Some(&self.line[..end]);
+        }
+        if self.len < self.line.len() {
+            self.line[self.len] = byte;
+            self.len += 1;
+        } else {
+            self.overflow = true;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_result() {
+        assert!(matches!(
+            join_result(b"\r\n[JOIN   ] drogue,192.168.1.174,0,0\r\nOK\r\n> "),
+            Ok(ip) if ip == IpAddr::from([192, 168, 1, 174])
+        ));
+        assert!(matches!(
+            join_result(b"\r\n[JOIN   ] drogue,0.0.0.0,0,0\r\nOK\r\n> "),
+            Err(JoinError::DhcpTimeout)
+        ));
+        assert!(matches!(
+            join_result(b"\r\n[JOIN   ] drogue\r\n[JOIN   ] AP not found\r\nERROR\r\n> "),
+            Err(JoinError::NetworkNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_check_remote() {
+        let pool = crate::socket_pool::SocketPool::<1>::new();
+        let handle = pool.open_reserved(pool.reserve().unwrap()).unwrap();
+        let remote = |addr: &str| addr.parse::<SocketAddr>().unwrap();
+        assert!(check_remote(handle, remote("192.0.2.1:80"), Protocol::Tcp).is_ok());
+        assert!(check_remote(handle, remote("224.0.0.251:5353"), Protocol::Udp).is_ok());
+        for addr in ["224.0.0.251:5353", "0.0.0.0:80", "192.0.2.1:0", "[::1]:80"] {
+            assert!(check_remote(handle, remote(addr), Protocol::Tcp).is_err());
+        }
+    }
+
+    #[test]
+    fn test_write_result() {
+        assert!(matches!(
+            write_result(b"\r\n1200\r\nOK\r\n> ", 1200),
+            Ok(1200)
+        ));
+        assert!(matches!(
+            write_result(b"\r\n300\r\nOK\r\n> ", 1200),
+            Ok(300)
+        ));
+        assert!(matches!(write_result(b"\r\n-1\r\nOK\r\n> ", 1200), Ok(0)));
+        assert!(matches!(
+            write_result(b"\r\nTX buffer full\r\nERROR\r\n> ", 1200),
+            Ok(0)
+        ));
+        assert!(matches!(
+            write_result(b"\r\nSocket not connected\r\nERROR\r\n> ", 1200),
+            Err(SocketError::WriteError)
+        ));
+        assert!(matches!(
+            write_result(b"\r\n> ", 1200),
+            Err(SocketError::WriteError)
+        ));
+    }
+
+    #[test]
+    fn test_well_formed() {
+        assert!(well_formed(b"\r\n-52\r\nOK\r\n> "));
+        assert!(well_formed(b"\r\nConnection refused\r\nERROR\r\n> "));
+        assert!(!well_formed(b"\r\n> "));
+        assert!(!well_formed(b"\r\n[TCP  RX] Socket 0\r\n> "));
+    }
+
+    #[test]
+    fn test_line_splitter() {
+        let mut buf = [0; 4];
+        let mut lines = LineSplitter::new(&mut buf);
+        let mut found = heapless::Vec::<String<4>, 4>::new();
+        for &byte in b"\r\nab\r\ntoo long\r\ncd\n" {
+            if let Some(line) = lines.push(byte) {
+                found
+                    .push(String::from(core::str::from_utf8(line).unwrap()))
+                    .unwrap();
+            }
+        }
+        assert_eq!(found, ["", "ab", "cd"]);
+    }
+}
@@ -0,0 +1,243 @@
+//! HTTP server taking the credentials of the network to join from a form, for provisioning
+//! in soft access point mode.
+//!
+//! Every `GET` is answered with the form, whatever the path, so the captive portal checks
+//! of phones land on it too, see [`EsWifi::run_captive_dns`]. The form is posted back
+//! URL-encoded, and the credentials in it are handed to the caller, which can join with
+//! them through [`Control::switch_to_station`](crate::Control::switch_to_station) or keep
+//! them for [`EsWifi::run_with`].
+
+use crate::{Credentials, EsWifi, SocketError, Transport, Wipe};
+use core::fmt::Write as _;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal::digital::OutputPin;
+use heapless::String;
+
+/// Largest request taken, headers and form included.
+const MAX_REQUEST: usize = 1024;
+
+/// Time allowed for a client to send its whole request once connected.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Delay between reads while waiting for the rest of a request.
+const READ_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+const FORM: &str = "<!DOCTYPE html><html><head>\
+<meta name=\"viewport\" content=\"width=device-width\"><title>WiFi setup</title></head>\
+<body><form method=\"post\" action=\"/\">\
+<p><label>Network <input name=\"ssid\" maxlength=\"32\" required></label></p>\
+<p><label>Password <input name=\"password\" type=\"password\" maxlength=\"63\"></label></p>\
+<p><button>Join</button></p></form></body></html>";
+
+const SAVED: &str = "<!DOCTYPE html><html><body><p>Joining the network.</p></body></html>";
+
+/// Request received by the provisioning server.
+#[derive(Debug, PartialEq, Eq)]
+enum Request<'a> {
+    /// Any `GET`, answered with the form
+    Form,
+    /// The form posted back, with its URL-encoded body
+    Submit(&'a [u8]),
+    /// Anything else, or a request that cannot be parsed
+    Invalid,
+}
+
+/// Parse the request at the start of `input`, or `None` while it is incomplete.
+fn request(input: &[u8]) -> Option<Request<'_>> {
+    let end = input.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let (head, body) = (&input[..end], &input[end + 4..]);
+    let mut lines = head
+        .split(|c| *c == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line));
+    let mut start = lines.next().unwrap_or(&[]).split(|c| *c == b' ');
+    match (start.next(), start.next()) {
+        (Some(b"GET"), Some(_)) => Some(Request::Form),
+        (Some(b"POST"), Some(b"/")) => {
+            let length = lines.find_map(|line| {
+                let (name, value) = split_once(line, b':')?;
+                if !name.eq_ignore_ascii_case(b"content-length") {
+                    return None;
+                }
+                core::str::from_utf8(value)
+                    .ok()?
+                    .trim()
+                    .parse::<usize>()
+                    .ok()
+            });
+            match length {
+                Some(length) if body.len() >= length => Some(Request::Submit(&body[..length])),
+                Some(_) => None,
+                None => Some(Request::Invalid),
+            }
+        }
+        _ => Some(Request::Invalid),
+    }
+}
+
+fn split_once(input: &[u8], separator: u8) -> Option<(&[u8], &[u8])> {
+    let at = input.iter().position(|c| *c == separator)?;
+    Some((&input[..at], &input[at + 1..]))
+}
+
+/// Value of the field `name` in the URL-encoded `form`, decoded into `out`.
+fn field<'b>(form: &[u8], name: &[u8], out: &'b mut [u8]) -> Option<&'b str> {
+    let value = form
+        .split(|c| *c == b'&')
+        .filter_map(|pair| split_once(pair, b'='))
+        .find(|(key, _)| *key == name)?
+        .1;
+    let mut len = 0;
+    let mut bytes = value.iter();
+    while let Some(&c) = bytes.next() {
+        let decoded = match c {
+            b'+' => b' ',
+            b'%' => {
+                let hex = [*bytes.next()?, *bytes.next()?];
+                u8::from_str_radix(core::str::from_utf8(&hex).ok()?, 16).ok()?
+            }
+            c => c,
+        };
+        *out.get_mut(len)? = decoded;
+        len += 1;
+    }
+    core::str::from_utf8(&out[..len]).ok()
+}
+
+/// Credentials posted in the URL-encoded `form`, or `None` if they are missing or do not
+/// fit the module.
+fn credentials(form: &[u8]) -> Option<Credentials> {
+    let mut ssid = [0u8; 32];
+    let mut password = Wipe([0u8; 64]);
+    let ssid = field(form, b"ssid", &mut ssid).filter(|ssid| !ssid.is_empty())?;
+    let password = field(form, b"password", &mut password.0).unwrap_or("");
+    Credentials::new(ssid, password).ok()
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Serve a form asking for the network to join on `port`, usually 80, until it is
+    /// posted back with credentials that fit the module, and return them.
+    ///
+    /// Should be spawned alongside [`EsWifi::run_ap`] once the access point is up. Uses
+    /// one of the module's sockets while running, serving one request per client and one
+    /// client at a time. A form with a missing or overlong SSID or password is answered
+    /// with an error and served again.
+    pub async fn run_provisioning_server(&self, port: u16) -> Result<Credentials, SocketError> {
+        let mut server = self.tcp_listen(port).await?;
+        let mut buf = Wipe([0u8; MAX_REQUEST]);
+        loop {
+            let client = server.accept().await?;
+            debug!("Provisioning client connected from {}", client);
+            let deadline = Instant::now() + REQUEST_TIMEOUT;
+            let mut len = 0;
+            let request = loop {
+                if let Some(request) = request(&buf.0[..len]) {
+                    break Some(request);
+                }
+                if len == buf.0.len() {
+                    break Some(Request::Invalid);
+                }
+                match embedded_io_async::Read::read(&mut server, &mut buf.0[len..]).await? {
+                    0 if Instant::now() >= deadline => break None,
+                    0 => Timer::after(READ_POLL_INTERVAL).await,
+                    read => len += read,
+                }
+            };
+            match request {
+                None => debug!("Provisioning client sent no complete request"),
+                Some(Request::Form) => respond(&mut server, "200 OK", FORM).await?,
+                Some(Request::Submit(form)) => match credentials(form) {
+                    Some(credentials) => {
+                        info!("Provisioned with network {}", credentials.ssid.as_str());
+                        respond(&mut server, "200 OK", SAVED).await?;
+                        return Ok(credentials);
+                    }
+                    None => respond(&mut server, "400 Bad Request", FORM).await?,
+                },
+                Some(Request::Invalid) => respond(&mut server, "400 Bad Request", "").await?,
+            }
+            // Drop the client even if it keeps the connection open, so the next one gets in.
+            server.disconnect().await?;
+        }
+    }
+}
+
+/// Write a complete response with `status` and the HTML `body`, asking the client to
+/// close the connection afterwards.
+async fn respond<W: embedded_io_async::Write<Error = SocketError>>(
+    client: &mut W,
+    status: &str,
+    body: &str,
+) -> Result<(), SocketError> {
+    let mut head: String<128> = String::new();
+    // Fits: the longest status is 15 bytes and the length at most 20 digits.
+    let _ = write!(
+        head,
+        "HTTP/1.1 {}\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        body.len()
+    );
+    client.write_all(head.as_bytes()).await?;
+    client.write_all(body.as_bytes()).await?;
+    client.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request() {
+        assert_eq!(request(b"GET / HTTP/1.1\r\nHost: 192.168.10.1"), None);
+        assert_eq!(
+            request(b"GET /generate_204 HTTP/1.1\r\nHost: x\r\n\r\n"),
+            Some(Request::Form)
+        );
+
+        let post = b"POST / HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\n\
+content-length: 27\r\n\r\nssid=drogue&password=secret";
+        assert_eq!(request(&post[..post.len() - 1]), None);
+        assert_eq!(
+            request(post),
+            Some(Request::Submit(b"ssid=drogue&password=secret"))
+        );
+
+        assert_eq!(
+            request(b"POST /other HTTP/1.1\r\n\r\n"),
+            Some(Request::Invalid)
+        );
+        assert_eq!(request(b"POST / HTTP/1.1\r\n\r\n"), Some(Request::Invalid));
+        assert_eq!(
+            request(b"DELETE / HTTP/1.1\r\n\r\n"),
+            Some(Request::Invalid)
+        );
+    }
+
+    #[test]
+    fn test_credentials() {
+        let provisioned = credentials(b"ssid=My+Home%21&password=p%26ss%3Dword").unwrap();
+        assert_eq!(provisioned.ssid.as_str(), "My Home!");
+        assert_eq!(provisioned.password.as_str(), "p&ss=word");
+
+        let open = credentials(b"password=&ssid=cafe").unwrap();
+        assert_eq!(open.ssid.as_str(), "cafe");
+        assert_eq!(open.password.as_str(), "");
+
+        // Missing or empty SSID, broken escapes, and values too long for the module
+        assert!(credentials(b"password=secret").is_none());
+        assert!(credentials(b"ssid=&password=secret").is_none());
+        assert!(credentials(b"ssid=home%2").is_none());
+        assert!(credentials(b"ssid=home%zz").is_none());
+        let long = [b'x'; 33];
+        let mut form = [0u8; 38];
+        form[..5].copy_from_slice(b"ssid=");
+        form[5..].copy_from_slice(&long);
+        assert!(credentials(&form).is_none());
+    }
+}
@@ -0,0 +1,131 @@
+//! Token bucket rate limiting of data written to the module, to cap its airtime.
+
+use crate::{EsWifi, Transport};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_time::{Duration, Instant};
+use embedded_hal::digital::OutputPin;
+
+/// Sustained rate and burst size of a write rate limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RateLimit {
+    /// Bytes allowed per second, on average. Zero is taken as 1.
+    pub bytes_per_second: u32,
+    /// Bytes that may be written at once after a quiet period. Zero is taken as 1, as no
+    /// write could ever go through otherwise.
+    pub burst: u32,
+}
+
+/// Bytes that may be written under a [`RateLimit`].
+///
+/// Writes may take more than is available, putting the bucket in debt until it refills,
+/// so sockets sharing a bucket never have to reserve bytes up front.
+pub(crate) struct TokenBucket {
+    limit: RateLimit,
+    tokens: i64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// Create a full bucket.
+    pub(crate) fn new(limit: RateLimit, now: Instant) -> Self {
+        let limit = RateLimit {
+            bytes_per_second: limit.bytes_per_second.max(1),
+            burst: limit.burst.max(1),
+        };
+        Self {
+            limit,
+            tokens: limit.burst as i64,
+            updated: now,
+        }
+    }
+
+    /// Bytes that may be written at `now`, or how long to wait before any can be.
+    pub(crate) fn available(&mut self, now: Instant) -> Result<usize, Duration> {
+        let rate = self.limit.bytes_per_second as u64;
+        let elapsed = now.saturating_duration_since(self.updated).as_micros();
+        let refill = elapsed * rate / 1_000_000;
+        if refill > 0 {
+            // Only advance by the time accounted for, so fractions of a byte are not lost.
+            self.updated += Duration::from_micros(refill * 1_000_000 / rate);
+            self.tokens += refill as i64;
+        }
+        if self.tokens >= self.limit.burst as i64 {
+            self.tokens = self.limit.burst as i64;
+            self.updated = now;
+        }
+        if self.tokens > 0 {
+            Ok(self.tokens as usize)
+        } else {
+            let missing = (1 - self.tokens) as u64;
+            Err(Duration::from_micros((missing * 1_000_000).div_ceil(rate)))
+        }
+    }
+
+    /// Account for `len` bytes written.
+    pub(crate) fn consume(&mut self, len: usize) {
+        self.tokens -= len as i64;
+    }
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Limit the rate data is written across all sockets, on top of any per-socket
+    /// limit set with [`EsWifiSocket::set_rate_limit`](crate::EsWifiSocket::set_rate_limit).
+    /// Unlimited by default.
+    pub async fn set_rate_limit(&self, limit: Option<RateLimit>) {
+        self.adapter.lock().await.rate_limit =
+            limit.map(|limit| TokenBucket::new(limit, Instant::now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: RateLimit = RateLimit {
+        bytes_per_second: 1000,
+        burst: 100,
+    };
+
+    #[test]
+    fn bucket_starts_full() {
+        let mut bucket = TokenBucket::new(LIMIT, Instant::from_secs(1));
+        assert_eq!(bucket.available(Instant::from_secs(1)), Ok(100));
+        assert_eq!(bucket.available(Instant::from_secs(10)), Ok(100));
+    }
+
+    #[test]
+    fn bucket_refills_debt() {
+        let start = Instant::from_secs(1);
+        let mut bucket = TokenBucket::new(LIMIT, start);
+        bucket.consume(150);
+        assert_eq!(bucket.available(start), Err(Duration::from_millis(51)));
+        let later = start + Duration::from_millis(60);
+        assert_eq!(bucket.available(later), Ok(10));
+        let later = later + Duration::from_micros(500);
+        assert_eq!(bucket.available(later), Ok(10));
+        let later = later + Duration::from_micros(500);
+        assert_eq!(bucket.available(later), Ok(11));
+    }
+
+    #[test]
+    fn bucket_takes_zero_as_one() {
+        let start = Instant::from_secs(1);
+        let limit = RateLimit {
+            bytes_per_second: 0,
+            burst: 0,
+        };
+        let mut bucket = TokenBucket::new(limit, start);
+        assert_eq!(bucket.available(start), Ok(1));
+        bucket.consume(1);
+        assert_eq!(bucket.available(start), Err(Duration::from_secs(1)));
+        assert_eq!(bucket.available(start + Duration::from_secs(1)), Ok(1));
+    }
+}
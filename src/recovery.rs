@@ -0,0 +1,242 @@
+//! Recovery of a module that stopped responding, escalating from the least disruptive
+//! measure to a full restart.
+
+use crate::{
+    parser, CredentialsProvider, DriverState, Error, EsWifi, Event, Mode, Pins, Transport,
+};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_time::{with_timeout, Duration, Instant};
+use embedded_hal::digital::OutputPin;
+
+/// Time allowed for the module to respond after each recovery step.
+const STEP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time allowed for a boot prompt still pending to come in when resynchronizing.
+const DRAIN_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Measure that brought an unresponsive module back, from least to most disruptive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RecoveryLevel {
+    /// Software reset command, followed by rejoining the network
+    SoftReset,
+    /// Toggling the WAKEUP pin; the module kept its network state
+    Wakeup,
+    /// Pulsing the RESET pin, followed by rejoining the network
+    HardReset,
+    /// Full restart of the module, as when the driver is started
+    Reinit,
+}
+
+/// Number of recoveries completed at each level, see [`EsWifi::recovery_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RecoveryStats {
+    /// Recoveries by software reset
+    pub soft_resets: u32,
+    /// Recoveries by toggling the WAKEUP pin
+    pub wakeups: u32,
+    /// Recoveries by pulsing the RESET pin
+    pub hard_resets: u32,
+    /// Recoveries by full restart
+    pub reinits: u32,
+}
+
+/// Restarts of the module by the driver, see [`NetworkStats`](crate::NetworkStats).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ResetStats {
+    /// Number of times the module was reset, by its RESET pin or its software reset
+    /// command, including when the driver started
+    pub resets: u32,
+    /// When the module was last reset
+    pub last_reset: Option<Instant>,
+}
+
+impl ResetStats {
+    pub(crate) fn record(&mut self) {
+        self.resets = self.resets.saturating_add(1);
+        self.last_reset = Some(Instant::now());
+    }
+}
+
+impl RecoveryStats {
+    fn record(&mut self, level: RecoveryLevel) {
+        let count = match level {
+            RecoveryLevel::SoftReset => &mut self.soft_resets,
+            RecoveryLevel::Wakeup => &mut self.wakeups,
+            RecoveryLevel::HardReset => &mut self.hard_resets,
+            RecoveryLevel::Reinit => &mut self.reinits,
+        };
+        *count = count.saturating_add(1);
+    }
+}
+
+impl<TRANSPORT, const SOCKETS: usize> DriverState<TRANSPORT, SOCKETS>
+where
+    TRANSPORT: Transport,
+{
+    /// Whether the module has printed its prompt after booting.
+    async fn booted(&mut self) -> bool {
+        let mut received = [0; crate::INIT_RESPONSE_SIZE];
+        matches!(self.await_prompt(&mut received, &mut 0).await, Ok(true))
+    }
+
+    /// Whether the module answers commands.
+    pub(crate) async fn responsive(&mut self) -> bool {
+        matches!(
+            with_timeout(STEP_TIMEOUT, self.send_command(command!(4, "I?"))).await,
+            Ok(Ok(response)) if parser::is_ok(response)
+        )
+    }
+
+    /// Get back in step with the module, without restarting it: read any prompt it has
+    /// pending, check it answers an empty line with its prompt, and set its verbosity
+    /// again.
+    pub(crate) async fn resync(&mut self) -> Result<(), Error<TRANSPORT::Error>> {
+        self.malformed = 0;
+        // A module that restarted on its own has printed its boot prompt unasked.
+        with_timeout(DRAIN_TIMEOUT, self.handshake()).await.ok();
+        self.transmit(b"\r").await?;
+        let received = match with_timeout(STEP_TIMEOUT, self.handshake()).await {
+            Ok(received) => received?,
+            Err(_) => &[],
+        };
+        if !received.ends_with(b"\r\n> ") {
+            return Err(init_failed(received));
+        }
+        // Not through `send_command`, which may end up here.
+        self.transmit(&self.verbosity_command()[..]).await?;
+        let response = self.receive().await?;
+        if !parser::is_ok(response) {
+            return Err(init_failed(response));
+        }
+        info!("eS-WiFi adapter resynchronized");
+        Ok(())
+    }
+
+    /// Restart the module with its software reset command, without touching any pins.
+    pub(crate) async fn soft_reset(&mut self) -> bool {
+        if !self.reset_command().await {
+            return false;
+        }
+        self.clear_state();
+        self.booted().await && self.init().await.is_ok()
+    }
+
+    /// Try a single recovery step, returning whether the module answers afterwards.
+    async fn recovery_step(&mut self, pins: &mut Pins<'_>, level: RecoveryLevel) -> bool {
+        match level {
+            RecoveryLevel::SoftReset => self.soft_reset().await,
+            RecoveryLevel::Wakeup if pins.wakeup.is_none() => false,
+            RecoveryLevel::Wakeup => self.wakeup(pins).await.is_ok() && self.responsive().await,
+            // Without a RESET line this would only repeat the software reset.
+            RecoveryLevel::HardReset if pins.reset.is_none() => false,
+            RecoveryLevel::HardReset => {
+                if self.reset(pins).await.is_err() {
+                    return false;
+                }
+                self.clear_state();
+                self.booted().await && self.init().await.is_ok()
+            }
+            RecoveryLevel::Reinit => self.start(pins).await.is_ok(),
+        }
+    }
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Get back in step with a module that was power cycled independently of the
+    /// microcontroller, or otherwise left the driver's idea of its prompt and verbosity
+    /// behind, without restarting it or rejoining the network.
+    ///
+    /// The driver does this on its own once several responses in a row fail to parse.
+    /// State the module lost when it restarted, such as the network joined and open
+    /// connections, is not restored.
+    pub async fn resync(&self) -> Result<(), Error<TRANSPORT::Error>> {
+        self.adapter.lock().await.resync().await
+    }
+
+    /// Number of times the run loop had to recover the module, by the measure that
+    /// brought it back.
+    pub async fn recovery_stats(&self) -> RecoveryStats {
+        self.adapter.lock().await.recoveries
+    }
+
+    /// Bring an unresponsive module back, trying each [`RecoveryLevel`] in turn and
+    /// reporting the one that worked as [`Event::Recovered`].
+    pub(crate) async fn recover<P: CredentialsProvider>(
+        &self,
+        mode: &mut Mode<'_, P>,
+    ) -> Result<RecoveryLevel, Error<TRANSPORT::Error>> {
+        let levels = [
+            RecoveryLevel::SoftReset,
+            RecoveryLevel::Wakeup,
+            RecoveryLevel::HardReset,
+        ];
+        for level in levels {
+            debug!("Attempting recovery by {:?}", level);
+            let restarted = {
+                let mut adapter = self.adapter.lock().await;
+                let (state, mut pins) = adapter.split();
+                if !state.recovery_step(&mut pins, level).await {
+                    continue;
+                }
+                level != RecoveryLevel::Wakeup
+            };
+            if restarted && self.bring_up(mode).await.is_err() {
+                continue;
+            }
+            return Ok(self.recovered(level).await);
+        }
+
+        debug!("Attempting recovery by {:?}", RecoveryLevel::Reinit);
+        self.reset(mode).await?;
+        Ok(self.recovered(RecoveryLevel::Reinit).await)
+    }
+
+    async fn recovered(&self, level: RecoveryLevel) -> RecoveryLevel {
+        info!("eS-WiFi module recovered by {:?}", level);
+        self.adapter.lock().await.recoveries.record(level);
+        self.notify(Event::Recovered { level });
+        level
+    }
+}
+
+/// Report the module failing to resynchronize, keeping the start of what it sent.
+fn init_failed<T>(received: &[u8]) -> Error<T> {
+    let mut response = [0; crate::INIT_RESPONSE_SIZE];
+    let len = core::cmp::min(received.len(), response.len());
+    response[..len].copy_from_slice(&received[..len]);
+    Error::InitFailed {
+        received: response,
+        len,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_record() {
+        let mut stats = RecoveryStats::default();
+        stats.record(RecoveryLevel::Wakeup);
+        stats.record(RecoveryLevel::Wakeup);
+        stats.record(RecoveryLevel::Reinit);
+        assert_eq!(
+            stats,
+            RecoveryStats {
+                wakeups: 2,
+                reinits: 1,
+                ..Default::default()
+            }
+        );
+        assert!(RecoveryLevel::SoftReset < RecoveryLevel::Reinit);
+    }
+}
@@ -0,0 +1,77 @@
+//! Sockets set aside for traffic that must never wait for a free one.
+
+use crate::{EsWifi, EsWifiSocket, Event, Protocol, SocketError, Transport};
+use core::net::SocketAddr;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embedded_hal::digital::OutputPin;
+
+/// One of the module's sockets, set aside with [`EsWifi::reserve_socket`] so other
+/// connections never take it.
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SocketReservation {
+    index: u8,
+}
+
+impl SocketReservation {
+    /// Socket number used by the module.
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Set one of the module's sockets aside for a designated purpose, such as a cloud
+    /// command channel, so bulk transfers can never starve it.
+    ///
+    /// Other connections only see the remaining sockets from then on. Only a socket
+    /// that is not in use can be reserved, [`SocketError::OpenError`] is returned when
+    /// there is none.
+    pub async fn reserve_socket(&self) -> Result<SocketReservation, SocketError> {
+        let adapter = self.adapter.lock().await;
+        let index = adapter
+            .socket_pool
+            .reserve()
+            .ok_or(SocketError::OpenError)?;
+        debug!("Reserved socket {}", index);
+        Ok(SocketReservation { index })
+    }
+
+    /// Return a reserved socket to the pool shared by all connections. A connection
+    /// using it stays open until dropped.
+    pub async fn release_socket(&self, reservation: SocketReservation) {
+        self.adapter
+            .lock()
+            .await
+            .socket_pool
+            .release(reservation.index);
+    }
+
+    /// Connect to `remote` over TCP using the reserved socket. Fails with
+    /// [`SocketError::OpenError`] while an earlier connection on it is still open.
+    pub async fn connect_reserved(
+        &self,
+        reservation: &SocketReservation,
+        remote: SocketAddr,
+    ) -> Result<EsWifiSocket<'_, TRANSPORT, RESET, WAKEUP, SOCKETS, M>, SocketError> {
+        let handle = self
+            .adapter
+            .lock()
+            .await
+            .socket_pool
+            .open_reserved(reservation.index)
+            .map_err(|_| SocketError::OpenError)?;
+        self.notify(Event::SocketOpened { handle });
+        let mut socket = self.socket(handle, Protocol::Tcp);
+        socket
+            .connect(remote, self.connect_config, None, None)
+            .await?;
+        Ok(socket)
+    }
+}
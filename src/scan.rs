@@ -0,0 +1,332 @@
+//! Scanning for access points in range.
+
+use crate::{parser, DriverState, EsWifi, Transport};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embedded_hal::digital::OutputPin;
+use heapless::{String, Vec};
+
+/// Access point found by a scan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScanResult {
+    /// Network name.
+    pub ssid: String<32>,
+    /// MAC address of the access point.
+    pub bssid: [u8; 6],
+    /// Signal strength, in dBm.
+    pub rssi: i8,
+    /// Security used by the network.
+    pub security: Security,
+    /// WiFi channel.
+    pub channel: u8,
+}
+
+/// Security used by a network, as reported by the module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Security {
+    /// No security
+    Open,
+    /// WEP
+    Wep,
+    /// WPA personal
+    Wpa,
+    /// WPA2 personal
+    Wpa2,
+    /// WPA and WPA2 personal, mixed mode
+    WpaWpa2,
+    /// Reported by the module in a form the driver does not recognize
+    Unknown,
+}
+
+/// Which scan results to keep, and how, see [`EsWifi::scan_filtered`].
+///
+/// The default keeps every result.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScanFilter<'a> {
+    /// Weakest signal kept, in dBm, or any with `None`.
+    pub min_rssi: Option<i8>,
+    /// Security types kept, or any with `None`.
+    pub security: Option<&'a [Security]>,
+    /// Leave out networks that do not broadcast their name.
+    pub skip_hidden: bool,
+    /// Keep only the strongest access point of each network.
+    pub distinct_ssids: bool,
+}
+
+impl ScanFilter<'_> {
+    /// Whether `result` passes the filter.
+    pub fn matches(&self, result: &ScanResult) -> bool {
+        self.min_rssi.is_none_or(|min| result.rssi >= min)
+            && self
+                .security
+                .is_none_or(|security| security.contains(&result.security))
+            && !(self.skip_hidden && result.ssid.is_empty())
+    }
+
+    /// Add `result` to `results` if it passes the filter, keeping them sorted strongest
+    /// first. Once `results` is full, the weakest result is dropped.
+    pub fn insert<const N: usize>(&self, results: &mut Vec<ScanResult, N>, result: ScanResult) {
+        if !self.matches(&result) {
+            return;
+        }
+        if self.distinct_ssids {
+            if let Some(i) = results.iter().position(|r| r.ssid == result.ssid) {
+                if results[i].rssi >= result.rssi {
+                    return;
+                }
+                remove(results, i);
+            }
+        }
+        let pos = results
+            .iter()
+            .position(|r| r.rssi < result.rssi)
+            .unwrap_or(results.len());
+        if pos == N {
+            return;
+        }
+        if results.is_full() {
+            results.pop();
+        }
+        results.push(result).ok();
+        // Move the new result up into place.
+        results[pos..].rotate_right(1);
+    }
+}
+
+/// Remove the element at `index`, keeping the order of the rest.
+fn remove<T, const N: usize>(vec: &mut Vec<T, N>, index: usize) {
+    vec[index..].rotate_left(1);
+    vec.pop();
+}
+
+impl ScanResult {
+    fn from_entry(entry: parser::ScanEntry<'_>) -> Option<Self> {
+        let ssid = core::str::from_utf8(entry.ssid).ok()?;
+        let mut result = Self {
+            ssid: String::new(),
+            bssid: entry.bssid,
+            rssi: entry.rssi,
+            security: Security::parse(entry.security),
+            channel: entry.channel,
+        };
+        result.ssid.push_str(ssid).ok()?;
+        Some(result)
+    }
+}
+
+impl Security {
+    pub(crate) fn parse(security: &[u8]) -> Self {
+        if parser::contains_ignore_case(security, b"open") {
+            Security::Open
+        } else if parser::contains_ignore_case(security, b"wep") {
+            Security::Wep
+        } else if parser::contains_ignore_case(security, b"mixed") {
+            Security::WpaWpa2
+        } else if parser::contains_ignore_case(security, b"wpa2") {
+            Security::Wpa2
+        } else if parser::contains_ignore_case(security, b"wpa") {
+            Security::Wpa
+        } else {
+            Security::Unknown
+        }
+    }
+}
+
+/// Scan errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ScanError {
+    /// SSID is empty or longer than 32 bytes
+    InvalidSsid,
+    /// The module rejected the scan or returned results that could not be parsed
+    Rejected,
+    /// Communication with the module failed
+    Transport,
+}
+
+impl<TRANSPORT, const SOCKETS: usize> DriverState<TRANSPORT, SOCKETS>
+where
+    TRANSPORT: Transport,
+{
+    /// Scan for access points, handing each one to `on_result` as soon as its line of the
+    /// response has been parsed, so no buffer for the full result list is needed.
+    async fn scan(&mut self, mut on_result: impl FnMut(ScanResult)) -> Result<(), ScanError> {
+        self.transmit(&command!(4, "F0")[..])
+            .await
+            .map_err(|_| ScanError::Transport)?;
+
+        let mut ok = false;
+        self.receive_lines(|line| {
+            if line == b"OK" {
+                ok = true;
+            } else if let Some(result) = parser::scan_entry(line).and_then(ScanResult::from_entry) {
+                on_result(result);
+            }
+        })
+        .await
+        .map_err(|_| ScanError::Transport)?;
+
+        if ok {
+            Ok(())
+        } else {
+            Err(ScanError::Rejected)
+        }
+    }
+
+    /// Directed scan: the module only probes for `ssid`, which is much quicker than
+    /// sweeping every channel for every network.
+    pub(crate) async fn scan_for(&mut self, ssid: &str) -> Result<Option<ScanResult>, ScanError> {
+        if ssid.is_empty() || ssid.len() > 32 {
+            return Err(ScanError::InvalidSsid);
+        }
+        let filter = self
+            .send_command(command!(40, "F3=", ssid))
+            .await
+            .map_err(|_| ScanError::Transport)?;
+        if !parser::is_ok(filter) {
+            return Err(ScanError::Rejected);
+        }
+
+        let mut best: Option<ScanResult> = None;
+        let result = self
+            .scan(|found| {
+                let stronger = match &best {
+                    Some(best) => found.rssi > best.rssi,
+                    None => true,
+                };
+                if found.ssid == ssid && stronger {
+                    best.replace(found);
+                }
+            })
+            .await;
+
+        // Clear the filter again, so later scans see every network.
+        self.send_command(command!(4, "F3="))
+            .await
+            .map_err(|_| ScanError::Transport)?;
+        result.map(|_| best)
+    }
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Scan for access points in range, calling `on_result` for each one found.
+    ///
+    /// Results are delivered while the module's response is still being read, so memory
+    /// use does not grow with the number of networks around.
+    pub async fn scan(&self, on_result: impl FnMut(ScanResult)) -> Result<(), ScanError> {
+        self.adapter.lock().await.scan(on_result).await
+    }
+
+    /// Scan for access points in range, returning up to `N` that pass `filter`, strongest
+    /// first.
+    pub async fn scan_filtered<const N: usize>(
+        &self,
+        filter: &ScanFilter<'_>,
+    ) -> Result<Vec<ScanResult, N>, ScanError> {
+        let mut results = Vec::new();
+        self.scan(|result| filter.insert(&mut results, result))
+            .await?;
+        Ok(results)
+    }
+
+    /// Check whether the network named `ssid` is in range, returning its strongest
+    /// access point.
+    pub async fn scan_for(&self, ssid: &str) -> Result<Option<ScanResult>, ScanError> {
+        self.adapter.lock().await.scan_for(ssid).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ScanFilter, ScanResult, Security};
+    use heapless::{String, Vec};
+
+    fn result(ssid: &str, rssi: i8, security: Security) -> ScanResult {
+        ScanResult {
+            ssid: String::from(ssid),
+            bssid: [0, 0, 0, 0, 0, rssi as u8],
+            rssi,
+            security,
+            channel: 6,
+        }
+    }
+
+    fn collect<const N: usize>(filter: &ScanFilter<'_>) -> Vec<(&'static str, i8), N> {
+        let found = [
+            ("drogue", -70, Security::Wpa2),
+            ("cafe", -40, Security::Open),
+            ("drogue", -50, Security::Wpa2),
+            ("", -45, Security::Wpa2),
+            ("legacy", -60, Security::Wep),
+            ("drogue", -90, Security::Wpa2),
+        ];
+        let mut results = Vec::<ScanResult, N>::new();
+        for (ssid, rssi, security) in found {
+            filter.insert(&mut results, result(ssid, rssi, security));
+        }
+        results
+            .iter()
+            .map(|r| {
+                let ssid = found.iter().find(|f| f.0 == r.ssid).unwrap().0;
+                (ssid, r.rssi)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sorted_by_signal() {
+        assert_eq!(
+            collect::<8>(&ScanFilter::default()),
+            [
+                ("cafe", -40),
+                ("", -45),
+                ("drogue", -50),
+                ("legacy", -60),
+                ("drogue", -70),
+                ("drogue", -90)
+            ]
+        );
+        // Only the strongest results are kept once full.
+        assert_eq!(
+            collect::<2>(&ScanFilter::default()),
+            [("cafe", -40), ("", -45)]
+        );
+    }
+
+    #[test]
+    fn test_filtered() {
+        let filter = ScanFilter {
+            min_rssi: Some(-75),
+            security: Some(&[Security::Wpa2, Security::WpaWpa2]),
+            skip_hidden: true,
+            distinct_ssids: false,
+        };
+        assert_eq!(collect::<8>(&filter), [("drogue", -50), ("drogue", -70)]);
+        let filter = ScanFilter {
+            distinct_ssids: true,
+            ..ScanFilter::default()
+        };
+        assert_eq!(
+            collect::<8>(&filter),
+            [("cafe", -40), ("", -45), ("drogue", -50), ("legacy", -60)]
+        );
+    }
+
+    #[test]
+    fn test_security() {
+        assert_eq!(Security::parse(b"Open"), Security::Open);
+        assert_eq!(Security::parse(b"WPA2 AES"), Security::Wpa2);
+        assert_eq!(Security::parse(b"WPA WPA2 Mixed"), Security::WpaWpa2);
+        assert_eq!(Security::parse(b"WPA TKIP"), Security::Wpa);
+    }
+}
@@ -0,0 +1,81 @@
+//! Passphrases and keys handled by the driver.
+//!
+//! With the `zeroize` feature, buffers holding secrets are wiped once they are
+//! no longer needed. Without it, the types below are thin wrappers.
+
+use core::fmt;
+use heapless::String;
+
+/// Passphrase or key, wiped from memory when dropped with the `zeroize` feature.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret<const N: usize>(String<N>);
+
+impl<const N: usize> Secret<N> {
+    /// Copy `secret`, or `None` if it is longer than `N` bytes.
+    pub fn new(secret: &str) -> Option<Self> {
+        let mut value = String::new();
+        value.push_str(secret).ok()?;
+        Some(Self(value))
+    }
+
+    /// The secret itself.
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl<const N: usize> Drop for Secret<N> {
+    fn drop(&mut self) {
+        // Zero bytes are valid UTF-8, so the string stays well-formed.
+        #[cfg(feature = "zeroize")]
+        zeroize::Zeroize::zeroize(&mut unsafe { self.0.as_mut_vec() }[..]);
+    }
+}
+
+// The value is left out, so it does not end up in logs.
+impl<const N: usize> fmt::Debug for Secret<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<const N: usize> serde::Serialize for Secret<N> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, const N: usize> serde::Deserialize<'de> for Secret<N> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self)
+    }
+}
+
+/// Command or response buffer that may hold a secret, wiped when dropped with
+/// the `zeroize` feature.
+pub(crate) struct Wipe<T: AsMut<[u8]>>(pub(crate) T);
+
+impl<T: AsMut<[u8]>> Drop for Wipe<T> {
+    fn drop(&mut self) {
+        #[cfg(feature = "zeroize")]
+        zeroize::Zeroize::zeroize(self.0.as_mut());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+
+    #[test]
+    fn secret_debug_is_redacted() {
+        let secret = Secret::<8>::new("hunter2").unwrap();
+        assert_eq!(secret.as_str(), "hunter2");
+        let mut debug: String<16> = String::new();
+        write!(debug, "{:?}", secret).unwrap();
+        assert_eq!(debug.as_str(), "Secret(..)");
+        assert!(Secret::<4>::new("hunter2").is_none());
+    }
+}
@@ -0,0 +1,251 @@
+//! Typed views of the settings the module shows with `C?` and `P?`, for diagnostics and
+//! for asserting configuration in tests.
+
+use crate::{
+    parser, DriverState, EsWifi, ModuleError, Protocol, Security, SocketHandle, Transport,
+};
+use core::net::{IpAddr, Ipv4Addr, SocketAddr};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embedded_hal::digital::OutputPin;
+use heapless::String;
+
+/// Network settings in use, as shown by `C?`. The passphrase is left out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnectionSettings {
+    /// Name of the network
+    pub ssid: String<32>,
+    /// Security of the network
+    pub security: Security,
+    /// Whether the address is obtained over DHCP
+    pub dhcp: bool,
+    /// Address of the module, unspecified while DHCP has not completed
+    pub ip: Ipv4Addr,
+    /// Network mask
+    pub netmask: Ipv4Addr,
+    /// Default gateway
+    pub gateway: Ipv4Addr,
+    /// Primary DNS server, if shown
+    pub primary_dns: Option<Ipv4Addr>,
+    /// Secondary DNS server, if shown
+    pub secondary_dns: Option<Ipv4Addr>,
+    /// Number of attempts made when joining, if shown
+    pub join_retries: Option<u8>,
+    /// Whether the module joins the network on its own after booting, if shown
+    pub auto_connect: Option<bool>,
+    /// Whether the module is associated with the network, if shown
+    pub connected: Option<bool>,
+}
+
+/// Settings of a socket, as shown by `P?`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SocketSettings {
+    /// Transport protocol, or `None` for one the driver does not use
+    pub protocol: Option<Protocol>,
+    /// Local address and port
+    pub local: SocketAddr,
+    /// Remote address and port
+    pub remote: SocketAddr,
+    /// Whether the socket's server is listening
+    pub server_active: bool,
+    /// Connections the server lets wait to be accepted
+    pub backlog: u8,
+    /// Connections the server accepted
+    pub accepted: u8,
+    /// Whether the socket's client is connected
+    pub client_active: bool,
+}
+
+/// Split the single line shown by a command into its comma separated fields.
+fn fields(response: &[u8]) -> Option<impl Iterator<Item = &[u8]>> {
+    Some(parser::value(response)?.split(|c| *c == b','))
+}
+
+fn address(field: &[u8]) -> Option<Ipv4Addr> {
+    match parser::ipv4(field)? {
+        (ip, b"") => Some(ip),
+        _ => None,
+    }
+}
+
+fn flag(field: &[u8]) -> Option<bool> {
+    Some(parser::atoi_usize(field)? != 0)
+}
+
+fn number(field: &[u8]) -> Option<u8> {
+    u8::try_from(parser::atoi_usize(field)?).ok()
+}
+
+/// Security as numbered by the module's `C3` command.
+fn security(field: &[u8]) -> Option<Security> {
+    Some(match parser::atoi_usize(field)? {
+        0 => Security::Open,
+        1 => Security::Wep,
+        2 => Security::Wpa,
+        // WPA2 with AES only, or mixed with TKIP.
+        3 | 4 => Security::Wpa2,
+        _ => Security::Unknown,
+    })
+}
+
+// \r\ndrogue,secret,4,1,0,192.168.1.174,255.255.255.0,192.168.1.1,1.1.1.1,8.8.8.8,3,0,0,CA,1\r\nOK\r\n>
+//
+// Fields are SSID, passphrase, security, DHCP, IP version, address, network mask, gateway,
+// primary and secondary DNS, join retries, auto connect, authentication, country and
+// status. Firmware versions differ in how many of the fields after the gateway they show.
+fn connection_settings(response: &[u8]) -> Option<ConnectionSettings> {
+    let mut fields = fields(response)?;
+    let mut ssid = String::new();
+    ssid.push_str(core::str::from_utf8(fields.next()?).ok()?)
+        .ok()?;
+    let _passphrase = fields.next()?;
+    let security = security(fields.next()?)?;
+    let dhcp = flag(fields.next()?)?;
+    let _ip_version = fields.next()?;
+    let ip = address(fields.next()?)?;
+    let netmask = address(fields.next()?)?;
+    let gateway = address(fields.next()?)?;
+    let mut optional = fields.map(Some).chain(core::iter::repeat(None));
+    let mut next = || optional.next().flatten();
+    let primary_dns = next().and_then(address);
+    let secondary_dns = next().and_then(address);
+    let join_retries = next().and_then(number);
+    let auto_connect = next().and_then(flag);
+    let _authentication = next();
+    let _country = next();
+    let connected = next().and_then(flag);
+    Some(ConnectionSettings {
+        ssid,
+        security,
+        dhcp,
+        ip,
+        netmask,
+        gateway,
+        primary_dns,
+        secondary_dns,
+        join_retries,
+        auto_connect,
+        connected,
+    })
+}
+
+// \r\n0,192.168.1.174,8002,192.168.1.2,8088,0,0,0,1\r\nOK\r\n>
+//
+// Fields are protocol, local address and port, remote address and port, server active,
+// backlog, accepted connections and client active.
+fn socket_settings(response: &[u8]) -> Option<SocketSettings> {
+    let mut fields = fields(response)?;
+    let protocol = match parser::atoi_usize(fields.next()?)? {
+        0 => Some(Protocol::Tcp),
+        1 => Some(Protocol::Udp),
+        3 => Some(Protocol::Tls),
+        _ => None,
+    };
+    let mut endpoint = || {
+        let ip = address(fields.next()?)?;
+        let port = u16::try_from(parser::atoi_usize(fields.next()?)?).ok()?;
+        Some(SocketAddr::new(IpAddr::V4(ip), port))
+    };
+    let local = endpoint()?;
+    let remote = endpoint()?;
+    Some(SocketSettings {
+        protocol,
+        local,
+        remote,
+        server_active: flag(fields.next()?)?,
+        backlog: number(fields.next()?)?,
+        accepted: number(fields.next()?)?,
+        client_active: flag(fields.next()?)?,
+    })
+}
+
+impl<TRANSPORT, const SOCKETS: usize> DriverState<TRANSPORT, SOCKETS>
+where
+    TRANSPORT: Transport,
+{
+    async fn connection_settings(&mut self) -> Result<ConnectionSettings, ModuleError> {
+        // The settings include the passphrase.
+        self.query_secret(command!(4, "C?"), connection_settings)
+            .await
+            .map_err(ModuleError::from)?
+            .ok_or(ModuleError::Rejected)
+    }
+
+    async fn socket_settings(
+        &mut self,
+        handle: SocketHandle,
+    ) -> Result<SocketSettings, ModuleError> {
+        self.send_command(command!(8, "P0=", handle))
+            .await
+            .map_err(ModuleError::from)?;
+        let response = self
+            .send_command(command!(4, "P?"))
+            .await
+            .map_err(ModuleError::from)?;
+        socket_settings(response).ok_or(ModuleError::Rejected)
+    }
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Network settings the module is using, without the passphrase.
+    pub async fn connection_settings(&self) -> Result<ConnectionSettings, ModuleError> {
+        self.adapter.lock().await.connection_settings().await
+    }
+
+    /// Settings of the module socket `handle`, as the module sees them.
+    pub async fn socket_settings(
+        &self,
+        handle: SocketHandle,
+    ) -> Result<SocketSettings, ModuleError> {
+        self.adapter.lock().await.socket_settings(handle).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_settings() {
+        let settings = connection_settings(
+            b"\r\ndrogue,secret,3,1,0,192.168.1.174,255.255.255.0,192.168.1.1,1.1.1.1,8.8.8.8,3,0,0,CA,1\r\nOK\r\n> ",
+        )
+        .unwrap();
+        assert_eq!(settings.ssid, "drogue");
+        assert_eq!(settings.security, Security::Wpa2);
+        assert!(settings.dhcp);
+        assert_eq!(settings.ip, Ipv4Addr::new(192, 168, 1, 174));
+        assert_eq!(settings.gateway, Ipv4Addr::new(192, 168, 1, 1));
+        assert_eq!(settings.secondary_dns, Some(Ipv4Addr::new(8, 8, 8, 8)));
+        assert_eq!(settings.join_retries, Some(3));
+        assert_eq!(settings.auto_connect, Some(false));
+        assert_eq!(settings.connected, Some(true));
+
+        let settings =
+            connection_settings(b"\r\nhome,,0,0,0,0.0.0.0,255.255.255.0,192.168.1.1\r\nOK\r\n> ")
+                .unwrap();
+        assert_eq!(settings.security, Security::Open);
+        assert_eq!(settings.primary_dns, None);
+        assert_eq!(settings.connected, None);
+        assert!(connection_settings(b"\r\nERROR\r\n> ").is_none());
+    }
+
+    #[test]
+    fn test_socket_settings() {
+        let settings =
+            socket_settings(b"\r\n3,192.168.1.174,8002,192.0.2.1,443,0,0,0,1\r\nOK\r\n> ").unwrap();
+        assert_eq!(settings.protocol, Some(Protocol::Tls));
+        assert_eq!(settings.local, "192.168.1.174:8002".parse().unwrap());
+        assert_eq!(settings.remote, "192.0.2.1:443".parse().unwrap());
+        assert!(!settings.server_active);
+        assert!(settings.client_active);
+        assert!(socket_settings(b"\r\n0,192.168.1.174,0\r\nOK\r\n> ").is_none());
+    }
+}
@@ -0,0 +1,92 @@
+//! Coordination with a host entering a low power mode that stops its clocks, such as STOP2
+//! on STM32, during which no exchange with the module may be under way.
+
+use crate::{Adapter, CloseReason, Error, EsWifi, Event, Transport};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::mutex::MutexGuard;
+use embedded_hal::digital::OutputPin;
+
+/// The driver held still while the host sleeps, see [`EsWifi::prepare_for_host_sleep`].
+///
+/// No other task talks to the module until this is handed to
+/// [`EsWifi::resume_from_host_sleep`], or dropped, which resumes without checking on the
+/// module.
+#[must_use = "the driver is held still until this is resumed or dropped"]
+pub struct HostSleep<'a, TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    adapter: MutexGuard<'a, M, Adapter<TRANSPORT, RESET, WAKEUP, SOCKETS>>,
+}
+
+/// State of the module found by [`EsWifi::resume_from_host_sleep`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HostResume {
+    /// The module did not answer and had to be resynchronized.
+    pub resynchronized: bool,
+    /// Whether the module is still associated with the network joined, `None` if none
+    /// was joined.
+    pub associated: Option<bool>,
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Wait for the exchange with the module under way, if any, and hold the driver still
+    /// until [`EsWifi::resume_from_host_sleep`], so the host can sleep without cutting a
+    /// transfer short.
+    ///
+    /// With `park_sockets`, every connected socket is closed first and reported with
+    /// [`CloseReason::Parked`], for peers that would time out the connections anyway.
+    pub async fn prepare_for_host_sleep(
+        &self,
+        park_sockets: bool,
+    ) -> HostSleep<'_, TRANSPORT, RESET, WAKEUP, SOCKETS, M> {
+        let mut adapter = self.adapter.lock().await;
+        if park_sockets {
+            for handle in adapter.socket_pool.connected() {
+                debug!("[{}] Parking connection for host sleep", handle);
+                self.socket_closed(&adapter, handle, CloseReason::Parked);
+                if let Err(e) = adapter.close(handle).await {
+                    warn!("Error parking connection {}: {:?}", handle, e);
+                }
+            }
+        }
+        HostSleep { adapter }
+    }
+
+    /// Let the driver go again after the host woke up, checking the module still answers,
+    /// resynchronizing with it if not, and that it is still associated.
+    ///
+    /// Losing the association while the host slept is reported as [`Event::LinkDown`].
+    pub async fn resume_from_host_sleep(
+        &self,
+        sleep: HostSleep<'_, TRANSPORT, RESET, WAKEUP, SOCKETS, M>,
+    ) -> Result<HostResume, Error<TRANSPORT::Error>> {
+        let mut adapter = sleep.adapter;
+        let resynchronized = !adapter.responsive().await;
+        if resynchronized {
+            warn!("eS-WiFi adapter does not answer after host sleep");
+            adapter.resync().await?;
+        }
+        let associated = match adapter.join_info {
+            Some(_) => Some(adapter.rssi().await.is_some()),
+            None => None,
+        };
+        if associated == Some(false) {
+            self.notify(Event::LinkDown);
+        }
+        Ok(HostResume {
+            resynchronized,
+            associated,
+        })
+    }
+}
@@ -1,56 +1,249 @@
 use core::cell::RefCell;
 use core::future::Future;
+use core::net::SocketAddr;
 use core::pin::Pin;
 use core::task::{Context, Poll, Waker};
 
-use heapless::spsc::Queue;
+use embassy_time::{Duration, Instant};
+use heapless::{spsc::Queue, Vec};
 
-#[derive(PartialEq)]
-enum SocketState {
+use crate::Heartbeat;
+
+/// Handle to one of the module's sockets.
+///
+/// Besides the module's socket number, a handle carries the generation of the pool slot
+/// it was opened from, so a stale handle kept around after its socket was closed and
+/// reopened can't affect the new owner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SocketHandle {
+    index: u8,
+    generation: u8,
+}
+
+impl SocketHandle {
+    /// Socket number used by the module.
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// Number of times the socket slot had been opened when this handle was issued.
+    pub fn generation(&self) -> u8 {
+        self.generation
+    }
+}
+
+impl core::fmt::Display for SocketHandle {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.index)
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Default)]
+pub(crate) enum SocketState {
     HalfClosed,
+    #[default]
     Closed,
     Open,
     Connected,
 }
 
-impl Default for SocketState {
-    fn default() -> Self {
-        Self::Closed
-    }
-}
-
-pub(crate) struct SocketPool {
-    sockets: RefCell<[SocketState; 4]>,
+pub(crate) struct SocketPool<const N: usize> {
+    sockets: RefCell<[SocketState; N]>,
+    generations: RefCell<[u8; N]>,
+    listening: RefCell<[bool; N]>,
+    last_activity: RefCell<[Instant; N]>,
+    heartbeats: RefCell<[Option<Heartbeat>; N]>,
+    remotes: RefCell<[Option<SocketAddr>; N]>,
+    /// Whether the closing of each socket has been reported, so it is only reported once.
+    close_reported: RefCell<[bool; N]>,
+    /// Sockets set aside for [`SocketPool::open_reserved`], which `open` passes over.
+    reserved: RefCell<[bool; N]>,
     waiters: RefCell<Queue<Waker, 8>>,
 }
 
-impl SocketPool {
+impl<const N: usize> SocketPool<N> {
     pub(crate) fn new() -> Self {
         Self {
-            sockets: Default::default(),
+            sockets: RefCell::new([SocketState::Closed; N]),
+            generations: RefCell::new([0; N]),
+            listening: RefCell::new([false; N]),
+            last_activity: RefCell::new([Instant::from_ticks(0); N]),
+            heartbeats: RefCell::new([None; N]),
+            remotes: RefCell::new([None; N]),
+            close_reported: RefCell::new([false; N]),
+            reserved: RefCell::new([false; N]),
             waiters: RefCell::new(Queue::new()),
         }
     }
 
-    pub(crate) async fn open<'a>(&'a self) -> Result<u8, ()> {
+    pub(crate) async fn open(&self) -> Result<SocketHandle, ()> {
         OpenFuture::new(self).await
     }
 
-    pub(crate) fn set_connected<'a>(&'a self, socket: u8) {
+    fn is_current(&self, socket: SocketHandle) -> bool {
+        self.generations.borrow()[socket.index as usize] == socket.generation
+    }
+
+    pub(crate) fn set_connected(&self, socket: SocketHandle) {
+        if !self.is_current(socket) {
+            return;
+        }
         let mut sockets = self.sockets.borrow_mut();
-        let index = socket as usize;
+        let index = socket.index as usize;
         sockets[index] = SocketState::Connected;
+        self.touch(socket);
+    }
+
+    /// Mark a connected socket as open again, once the client of its server is dropped.
+    pub(crate) fn set_open(&self, socket: SocketHandle) {
+        if self.is_current(socket)
+            && self.sockets.borrow()[socket.index as usize] == SocketState::Connected
+        {
+            self.sockets.borrow_mut()[socket.index as usize] = SocketState::Open;
+        }
+    }
+
+    /// Mark a socket as running the module's server rather than its client.
+    pub(crate) fn set_listening(&self, socket: SocketHandle) {
+        if self.is_current(socket) {
+            self.listening.borrow_mut()[socket.index as usize] = true;
+        }
+    }
+
+    pub(crate) fn is_listening(&self, socket: SocketHandle) -> bool {
+        self.is_current(socket) && self.listening.borrow()[socket.index as usize]
+    }
+
+    pub(crate) fn set_remote(&self, socket: SocketHandle, remote: SocketAddr) {
+        if self.is_current(socket) {
+            self.remotes.borrow_mut()[socket.index as usize] = Some(remote);
+        }
+    }
+
+    /// Address a socket was connected to, if it was.
+    pub(crate) fn remote(&self, socket: SocketHandle) -> Option<SocketAddr> {
+        if !self.is_current(socket) {
+            return None;
+        }
+        self.remotes.borrow()[socket.index as usize]
+    }
+
+    /// Record that the closing of a socket is being reported, returning whether it had
+    /// not been already.
+    pub(crate) fn report_close(&self, socket: SocketHandle) -> bool {
+        self.is_current(socket)
+            && !core::mem::replace(
+                &mut self.close_reported.borrow_mut()[socket.index as usize],
+                true,
+            )
+    }
+
+    /// Close every open socket on the driver's side, after the module restarted and
+    /// dropped its connections, returning the sockets closed.
+    pub(crate) fn force_close(&self) -> Vec<SocketHandle, N> {
+        let mut closed = Vec::new();
+        for index in 0..N {
+            if matches!(
+                self.sockets.borrow()[index],
+                SocketState::Open | SocketState::Connected
+            ) {
+                let handle = SocketHandle {
+                    index: index as u8,
+                    generation: self.generations.borrow()[index],
+                };
+                self.close(handle);
+                closed.push(handle).ok();
+            }
+        }
+        closed
     }
 
-    pub(crate) fn is_connected<'a>(&'a self, socket: u8) -> bool {
+    /// Record traffic on a socket, postponing it being considered idle.
+    pub(crate) fn touch(&self, socket: SocketHandle) {
+        if self.is_current(socket) {
+            self.last_activity.borrow_mut()[socket.index as usize] = Instant::now();
+        }
+    }
+
+    pub(crate) fn set_heartbeat(&self, socket: SocketHandle, heartbeat: Option<Heartbeat>) {
+        if self.is_current(socket) {
+            self.heartbeats.borrow_mut()[socket.index as usize] = heartbeat;
+        }
+    }
+
+    /// Connected sockets with a heartbeat, along with when it is next due.
+    fn heartbeats(&self) -> impl Iterator<Item = (SocketHandle, Heartbeat, Instant)> + '_ {
+        (0..N).filter_map(move |index| {
+            let heartbeat = self.heartbeats.borrow()[index]?;
+            if self.sockets.borrow()[index] != SocketState::Connected {
+                return None;
+            }
+            let handle = SocketHandle {
+                index: index as u8,
+                generation: self.generations.borrow()[index],
+            };
+            let due = self.last_activity.borrow()[index] + heartbeat.interval;
+            Some((handle, heartbeat, due))
+        })
+    }
+
+    /// Connected sockets whose heartbeat is due at `now`.
+    pub(crate) fn due_heartbeats(&self, now: Instant) -> Vec<(SocketHandle, Heartbeat), N> {
+        self.heartbeats()
+            .filter(|(_, _, due)| *due <= now)
+            .map(|(handle, heartbeat, _)| (handle, heartbeat))
+            .collect()
+    }
+
+    /// When the next heartbeat is due, if any socket has one.
+    pub(crate) fn next_heartbeat(&self) -> Option<Instant> {
+        self.heartbeats().map(|(_, _, due)| due).min()
+    }
+
+    /// Connected sockets without any traffic for at least `timeout`.
+    pub(crate) fn idle(&self, timeout: Duration) -> Vec<SocketHandle, N> {
+        let now = Instant::now();
+        let sockets = self.sockets.borrow();
+        let generations = self.generations.borrow();
+        let last_activity = self.last_activity.borrow();
+        sockets
+            .iter()
+            .enumerate()
+            .filter(|(index, state)| {
+                **state == SocketState::Connected && now - last_activity[*index] >= timeout
+            })
+            .map(|(index, _)| SocketHandle {
+                index: index as u8,
+                generation: generations[index],
+            })
+            .collect()
+    }
+
+    /// All connected sockets.
+    pub(crate) fn connected(&self) -> Vec<SocketHandle, N> {
+        self.idle(Duration::from_ticks(0))
+    }
+
+    pub(crate) fn is_connected(&self, socket: SocketHandle) -> bool {
         let sockets = self.sockets.borrow();
-        let index = socket as usize;
-        SocketState::Connected == sockets[index]
+        let index = socket.index as usize;
+        self.is_current(socket) && SocketState::Connected == sockets[index]
     }
 
-    pub(crate) fn close<'a>(&'a self, socket: u8) {
+    pub(crate) fn state(&self, socket: SocketHandle) -> SocketState {
+        if !self.is_current(socket) {
+            return SocketState::Closed;
+        }
+        self.sockets.borrow()[socket.index as usize]
+    }
+
+    pub(crate) fn close(&self, socket: SocketHandle) {
+        if !self.is_current(socket) {
+            return;
+        }
         let mut sockets = self.sockets.borrow_mut();
-        let index = socket as usize;
+        let index = socket.index as usize;
         match sockets[index] {
             SocketState::HalfClosed => {
                 sockets[index] = SocketState::Closed;
@@ -64,17 +257,55 @@ impl SocketPool {
         }
     }
 
-    fn poll_open(&self, waker: &Waker, waiting: bool) -> Poll<Result<u8, ()>> {
-        let mut sockets = self.sockets.borrow_mut();
-        let available = sockets
-            .iter()
-            .enumerate()
-            .filter(|e| matches!(e, (_, SocketState::Closed)))
-            .next();
+    /// Set a closed, unreserved socket aside for opening only with
+    /// [`open_reserved`](Self::open_reserved), returning its index.
+    pub(crate) fn reserve(&self) -> Option<u8> {
+        let sockets = self.sockets.borrow();
+        let mut reserved = self.reserved.borrow_mut();
+        let index =
+            (0..N).find(|&index| sockets[index] == SocketState::Closed && !reserved[index])?;
+        reserved[index] = true;
+        Some(index as u8)
+    }
+
+    /// Return a reserved socket to the pool.
+    pub(crate) fn release(&self, index: u8) {
+        self.reserved.borrow_mut()[index as usize] = false;
+    }
+
+    /// Open the reserved socket `index`, if it is not in use.
+    pub(crate) fn open_reserved(&self, index: u8) -> Result<SocketHandle, ()> {
+        let index = index as usize;
+        if self.sockets.borrow()[index] != SocketState::Closed {
+            return Err(());
+        }
+        Ok(self.claim(index))
+    }
+
+    /// Open the closed socket `index`.
+    fn claim(&self, index: usize) -> SocketHandle {
+        self.sockets.borrow_mut()[index] = SocketState::Open;
+        self.listening.borrow_mut()[index] = false;
+        self.heartbeats.borrow_mut()[index] = None;
+        self.remotes.borrow_mut()[index] = None;
+        self.close_reported.borrow_mut()[index] = false;
+        let mut generations = self.generations.borrow_mut();
+        generations[index] = generations[index].wrapping_add(1);
+        SocketHandle {
+            index: index as u8,
+            generation: generations[index],
+        }
+    }
+
+    fn poll_open(&self, waker: &Waker, waiting: bool) -> Poll<Result<SocketHandle, ()>> {
+        let available = {
+            let sockets = self.sockets.borrow();
+            let reserved = self.reserved.borrow();
+            (0..N).find(|&index| sockets[index] == SocketState::Closed && !reserved[index])
+        };
 
-        if let Some((index, _)) = available {
-            sockets[index] = SocketState::Open;
-            Poll::Ready(Ok(index as u8))
+        if let Some(index) = available {
+            Poll::Ready(Ok(self.claim(index)))
         } else {
             if !waiting {
                 return match self.waiters.borrow_mut().enqueue(waker.clone()) {
@@ -87,21 +318,21 @@ impl SocketPool {
     }
 }
 
-pub(crate) struct OpenFuture<'a> {
-    pool: &'a SocketPool,
+pub(crate) struct OpenFuture<'a, const N: usize> {
+    pool: &'a SocketPool<N>,
     waiting: bool,
 }
 
-impl<'a> OpenFuture<'a> {
-    fn new(pool: &'a SocketPool) -> Self {
+impl<'a, const N: usize> OpenFuture<'a, N> {
+    fn new(pool: &'a SocketPool<N>) -> Self {
         Self {
             pool,
             waiting: false,
         }
     }
 }
-impl<'a> Future for OpenFuture<'a> {
-    type Output = Result<u8, ()>;
+impl<'a, const N: usize> Future for OpenFuture<'a, N> {
+    type Output = Result<SocketHandle, ()>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let result = self.pool.poll_open(cx.waker(), self.waiting);
@@ -0,0 +1,105 @@
+//! Begin and end events for socket operations, for laying out the activity of each
+//! connection on a timeline.
+//!
+//! With the `socket-spans` feature, each operation on a socket logs one line as it starts
+//! and another as it finishes, in a fixed format that scripts can pick out of the log:
+//!
+//! ```text
+//! span begin write handle=0 len=512
+//! span end write handle=0 len=512 us=8300 ok=512
+//! span end read handle=0 len=1024 us=120 err=SocketClosed
+//! ```
+//!
+//! `len` is the size of the buffer handed in, `us` the time the operation took, and the
+//! result either the number of bytes transferred or the error. Without the feature, spans
+//! compile to nothing.
+
+use crate::{SocketAddr, SocketError, SocketHandle};
+use embassy_time::Duration;
+#[cfg(feature = "socket-spans")]
+use embassy_time::Instant;
+
+/// Result of a socket operation, reduced to the number of bytes it transferred.
+#[cfg_attr(not(feature = "socket-spans"), allow(dead_code))]
+pub(crate) trait Transferred {
+    fn transferred(&self) -> usize;
+}
+
+impl Transferred for () {
+    fn transferred(&self) -> usize {
+        0
+    }
+}
+
+/// Time a connection took to open.
+impl Transferred for Duration {
+    fn transferred(&self) -> usize {
+        0
+    }
+}
+
+impl Transferred for usize {
+    fn transferred(&self) -> usize {
+        *self
+    }
+}
+
+impl Transferred for (usize, SocketAddr) {
+    fn transferred(&self) -> usize {
+        self.0
+    }
+}
+
+/// A socket operation in progress.
+pub(crate) struct Span {
+    #[cfg(feature = "socket-spans")]
+    op: &'static str,
+    #[cfg(feature = "socket-spans")]
+    handle: SocketHandle,
+    #[cfg(feature = "socket-spans")]
+    len: usize,
+    #[cfg(feature = "socket-spans")]
+    start: Instant,
+}
+
+impl Span {
+    /// Log the start of `op` on socket `handle`, with a buffer of `len` bytes.
+    #[cfg_attr(not(feature = "socket-spans"), allow(unused_variables))]
+    pub(crate) fn begin(op: &'static str, handle: SocketHandle, len: usize) -> Self {
+        #[cfg(feature = "socket-spans")]
+        {
+            info!("span begin {} handle={} len={}", op, handle, len);
+            Self {
+                op,
+                handle,
+                len,
+                start: Instant::now(),
+            }
+        }
+        #[cfg(not(feature = "socket-spans"))]
+        Self {}
+    }
+
+    /// Log the end of the operation with its `result`.
+    #[cfg_attr(not(feature = "socket-spans"), allow(unused_variables))]
+    pub(crate) fn end<T: Transferred>(self, result: &Result<T, SocketError>) {
+        #[cfg(feature = "socket-spans")]
+        {
+            let us = self.start.elapsed().as_micros();
+            match result {
+                Ok(done) => info!(
+                    "span end {} handle={} len={} us={} ok={}",
+                    self.op,
+                    self.handle,
+                    self.len,
+                    us,
+                    done.transferred()
+                ),
+                Err(e) => info!(
+                    "span end {} handle={} len={} us={} err={:?}",
+                    self.op, self.handle, self.len, us, e
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Protocol tracing switched on at runtime, to capture failing transactions in the field.
+
+use crate::{DriverState, EsWifi, Transport};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_time::{Duration, Instant};
+use embedded_hal::digital::OutputPin;
+
+impl<TRANSPORT, const SOCKETS: usize> DriverState<TRANSPORT, SOCKETS>
+where
+    TRANSPORT: Transport,
+{
+    /// Whether commands and responses are currently being traced.
+    pub(crate) fn tracing(&self) -> bool {
+        matches!(self.trace_until, Some(until) if Instant::now() < until)
+    }
+
+    /// Log `bytes` exchanged with the module while tracing, `direction` telling which way
+    /// they went.
+    pub(crate) fn trace(&self, direction: &str, bytes: &[u8]) {
+        if self.tracing() {
+            log_exchange(direction, bytes);
+        }
+    }
+
+    /// Log the length of socket data exchanged with the module while tracing.
+    pub(crate) fn trace_payload(&self, direction: &str, len: usize) {
+        if self.tracing() {
+            info!("{} ({} bytes of socket data)", direction, len);
+        }
+    }
+}
+
+/// Log `bytes` exchanged with the module, as text where they are.
+pub(crate) fn log_exchange(direction: &str, bytes: &[u8]) {
+    match core::str::from_utf8(bytes) {
+        Ok(text) => info!("{} {:?}", direction, text),
+        Err(_) => info!("{} {:?}", direction, bytes),
+    }
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Log every command sent to the module and every response it gives for the next
+    /// `window`. The trace is logged at info level, so it shows up on devices built
+    /// with the driver's debug and trace output compiled out.
+    ///
+    /// Commands and responses holding a passphrase are left out. Socket payloads are
+    /// logged by length only.
+    pub async fn trace_for(&self, window: Duration) {
+        info!("Tracing module protocol for {} ms", window.as_millis());
+        self.adapter.lock().await.trace_until = Some(Instant::now() + window);
+    }
+
+    /// Leave the module's verbose messages on (`MT=0`) rather than turning them off, so
+    /// the exchange is easier to follow on a logic analyzer. The driver works the same
+    /// either way, leaving out the echo of each command verbose messages add. Off by
+    /// default.
+    ///
+    /// Takes effect when the module is next started or resynchronized, see
+    /// [`resync`](Self::resync).
+    pub async fn set_verbose(&self, verbose: bool) {
+        self.adapter.lock().await.verbose = verbose;
+    }
+
+    /// Stop a trace started with [`trace_for`](Self::trace_for) before its window ends.
+    pub async fn stop_trace(&self) {
+        self.adapter.lock().await.trace_until = None;
+    }
+}
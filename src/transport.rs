@@ -0,0 +1,493 @@
+//! Links carrying the module's AT command protocol.
+//!
+//! The ISM43362 speaks the same commands over SPI and UART; only the framing differs.
+//! The driver is written against [`Transport`], with an implementation for each, and
+//! other links such as a bridge from a host PC or a scripted test double can be plugged
+//! in by implementing it.
+
+use crate::proto::framing::{spi_words, Response, SpiResponse, Word, FILL};
+use core::fmt::Debug;
+use embassy_time::{block_for, Duration};
+use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal_async::{digital::Wait, spi::*};
+use embedded_io_async::{Read, Write};
+
+/// Link to the module, framing commands and responses for the underlying bus.
+///
+/// Commands are handed over complete, including their trailing `\r`, and must reach the
+/// module unchanged. Responses end with the module's `\r\n> ` prompt, which is part of
+/// the response. Once `push` has run out of room the rest of the response must be
+/// discarded, so the next one starts in sync.
+#[allow(async_fn_in_trait)]
+pub trait Transport {
+    /// Error reported by the underlying bus
+    type Error: Debug;
+
+    /// Send `command`, followed by `data` for commands that carry a payload.
+    async fn write(&mut self, command: &[u8], data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Receive the response to the last command, handing each byte to `push` as it
+    /// arrives. `push` returns whether it has room for more, and `room` tells whether it
+    /// has any to begin with.
+    async fn read(&mut self, room: bool, push: impl FnMut(u8) -> bool) -> Result<(), Self::Error>;
+}
+
+/// SPI transport errors
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SpiError<SPI, CS, READY> {
+    /// SPI error
+    SPI(SPI),
+    /// Chip select error
+    CS(CS),
+    /// Ready pin error
+    READY(READY),
+    /// Module sent nothing but NAKs for longer than any response could be
+    NakStorm,
+}
+
+/// The module's SPI interface, with the data ready line telling when it has a response.
+pub struct SpiTransport<SPI, CS, READY> {
+    spi: SPI,
+    cs: CS,
+    ready: READY,
+}
+
+impl<SPI, CS, READY> SpiTransport<SPI, CS, READY>
+where
+    SPI: SpiBus<u8>,
+    CS: OutputPin,
+    READY: InputPin + Wait,
+{
+    /// Create a transport using the provided peripheral and pins.
+    pub fn new(spi: SPI, cs: CS, ready: READY) -> Self {
+        Self { spi, cs, ready }
+    }
+
+    async fn wait_ready(&mut self) -> Result<(), SpiError<SPI::Error, CS::Error, READY::Error>> {
+        while self.ready.is_low().map_err(SpiError::READY)? {
+            self.ready
+                .wait_for_any_edge()
+                .await
+                .map_err(SpiError::READY)?;
+        }
+        Ok(())
+    }
+}
+
+impl<SPI, CS, READY> Transport for SpiTransport<SPI, CS, READY>
+where
+    SPI: SpiBus<u8>,
+    CS: OutputPin,
+    READY: InputPin + Wait,
+{
+    type Error = SpiError<SPI::Error, CS::Error, READY::Error>;
+
+    async fn write(&mut self, command: &[u8], data: &[u8]) -> Result<(), Self::Error> {
+        self.wait_ready().await?;
+        let _cs = Cs::new(&mut self.cs).map_err(SpiError::CS)?;
+        for mut xfer in spi_words(command, data) {
+            self.spi
+                .transfer_in_place(&mut xfer)
+                .await
+                .map_err(SpiError::SPI)?;
+        }
+        Ok(())
+    }
+
+    async fn read(&mut self, room: bool, push: impl FnMut(u8) -> bool) -> Result<(), Self::Error> {
+        self.wait_ready().await?;
+        let _cs = Cs::new(&mut self.cs).map_err(SpiError::CS)?;
+
+        let mut response = SpiResponse::new(room, push);
+        // READY stays high until the whole response is out, so keep clocking it even once
+        // `push` is out of room, or the rest would end up in front of the next response.
+        while self.ready.is_high().map_err(SpiError::READY)? {
+            let mut xfer: [u8; 2] = [FILL, FILL];
+            self.spi
+                .transfer_in_place(&mut xfer)
+                .await
+                .map_err(SpiError::SPI)?;
+
+            match response.word(xfer) {
+                Word::Data => {}
+                Word::Nak => block_for(Duration::from_micros(1)),
+                Word::NakStorm => {
+                    warn!("eS-WiFi module kept sending NAKs, giving up on response");
+                    return Err(SpiError::NakStorm);
+                }
+            }
+        }
+        response.finish();
+        Ok(())
+    }
+}
+
+struct Cs<'a, CS: OutputPin + 'a> {
+    cs: &'a mut CS,
+}
+
+impl<'a, CS: OutputPin + 'a> Cs<'a, CS> {
+    fn new(cs: &'a mut CS) -> Result<Self, CS::Error> {
+        cs.set_low()?;
+        block_for(Duration::from_micros(1000));
+        Ok(Self { cs })
+    }
+}
+
+impl<'a, CS: OutputPin + 'a> Drop for Cs<'a, CS> {
+    fn drop(&mut self) {
+        let _ = self.cs.set_high();
+        block_for(Duration::from_micros(15));
+    }
+}
+
+/// The module's UART interface, where the prompt printed after each response tells
+/// where the response ends.
+///
+/// The module must be configured for the UART's baud rate, 115200 by default.
+pub struct UartTransport<UART> {
+    uart: UART,
+}
+
+impl<UART> UartTransport<UART>
+where
+    UART: Read + Write,
+{
+    /// Create a transport using the provided serial port.
+    pub fn new(uart: UART) -> Self {
+        Self { uart }
+    }
+}
+
+impl<UART> Transport for UartTransport<UART>
+where
+    UART: Read + Write,
+{
+    type Error = UART::Error;
+
+    async fn write(&mut self, command: &[u8], data: &[u8]) -> Result<(), Self::Error> {
+        self.uart.write_all(command).await?;
+        self.uart.write_all(data).await?;
+        self.uart.flush().await
+    }
+
+    async fn read(&mut self, room: bool, push: impl FnMut(u8) -> bool) -> Result<(), Self::Error> {
+        // The whole response is always read, so the next one starts in sync even when
+        // `push` ran out of room.
+        let mut response = Response::new(room, push);
+        let mut buf = [0; 32];
+        loop {
+            let len = self.uart.read(&mut buf).await?;
+            for &byte in &buf[..len] {
+                response.push(byte);
+                if response.complete() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SpiTransport, Transport, UartTransport};
+    use crate::proto::framing::NAK;
+    use crate::{DriverState, Error, NoPin, SCRATCH_SIZE};
+    use core::cell::Cell;
+    use core::convert::Infallible;
+    use embassy_futures::block_on;
+    use heapless::Vec;
+
+    /// Transport answering every command with the same canned response.
+    struct Script {
+        response: &'static [u8],
+        sent: Vec<u8, 64>,
+    }
+
+    impl Transport for Script {
+        type Error = Infallible;
+
+        async fn write(&mut self, command: &[u8], data: &[u8]) -> Result<(), Infallible> {
+            self.sent.extend_from_slice(command).unwrap();
+            self.sent.extend_from_slice(data).unwrap();
+            Ok(())
+        }
+
+        async fn read(
+            &mut self,
+            room: bool,
+            mut push: impl FnMut(u8) -> bool,
+        ) -> Result<(), Infallible> {
+            if room {
+                for &byte in self.response {
+                    if !push(byte) {
+                        break;
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// SPI bus clocking out a response as the module would, one word per transfer.
+    struct ScriptedSpi<'a> {
+        words: &'a [[u8; 2]],
+        sent: &'a Cell<usize>,
+    }
+
+    /// READY line of [`ScriptedSpi`], high while any of the response is left.
+    struct ScriptedReady<'a> {
+        words: usize,
+        sent: &'a Cell<usize>,
+    }
+
+    impl embedded_hal::spi::ErrorType for ScriptedSpi<'_> {
+        type Error = Infallible;
+    }
+
+    impl embedded_hal_async::spi::SpiBus for ScriptedSpi<'_> {
+        async fn read(&mut self, _words: &mut [u8]) -> Result<(), Infallible> {
+            unimplemented!()
+        }
+
+        async fn write(&mut self, _words: &[u8]) -> Result<(), Infallible> {
+            unimplemented!()
+        }
+
+        async fn transfer(&mut self, _read: &mut [u8], _write: &[u8]) -> Result<(), Infallible> {
+            unimplemented!()
+        }
+
+        async fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Infallible> {
+            words.copy_from_slice(&self.words[self.sent.get()]);
+            self.sent.set(self.sent.get() + 1);
+            Ok(())
+        }
+
+        async fn flush(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    impl embedded_hal::digital::ErrorType for ScriptedReady<'_> {
+        type Error = Infallible;
+    }
+
+    impl embedded_hal::digital::InputPin for ScriptedReady<'_> {
+        fn is_high(&mut self) -> Result<bool, Infallible> {
+            Ok(self.sent.get() < self.words)
+        }
+
+        fn is_low(&mut self) -> Result<bool, Infallible> {
+            Ok(self.sent.get() >= self.words)
+        }
+    }
+
+    impl embedded_hal_async::digital::Wait for ScriptedReady<'_> {
+        async fn wait_for_high(&mut self) -> Result<(), Infallible> {
+            unimplemented!()
+        }
+
+        async fn wait_for_low(&mut self) -> Result<(), Infallible> {
+            unimplemented!()
+        }
+
+        async fn wait_for_rising_edge(&mut self) -> Result<(), Infallible> {
+            unimplemented!()
+        }
+
+        async fn wait_for_falling_edge(&mut self) -> Result<(), Infallible> {
+            unimplemented!()
+        }
+
+        async fn wait_for_any_edge(&mut self) -> Result<(), Infallible> {
+            unimplemented!()
+        }
+    }
+
+    /// Serial port delivering `incoming` a few bytes per read, as bytes trickle in over
+    /// UART.
+    struct ScriptedUart {
+        incoming: &'static [u8],
+        chunk: usize,
+        written: Vec<u8, 64>,
+        flushed: bool,
+    }
+
+    impl ScriptedUart {
+        fn new(incoming: &'static [u8], chunk: usize) -> Self {
+            Self {
+                incoming,
+                chunk,
+                written: Vec::new(),
+                flushed: false,
+            }
+        }
+    }
+
+    impl embedded_io_async::ErrorType for ScriptedUart {
+        type Error = Infallible;
+    }
+
+    impl embedded_io_async::Read for ScriptedUart {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            let len = self.chunk.min(buf.len()).min(self.incoming.len());
+            assert!(len > 0, "read past the prompt");
+            buf[..len].copy_from_slice(&self.incoming[..len]);
+            self.incoming = &self.incoming[len..];
+            Ok(len)
+        }
+    }
+
+    impl embedded_io_async::Write for ScriptedUart {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            self.flushed = false;
+            self.written.extend_from_slice(buf).unwrap();
+            Ok(buf.len())
+        }
+
+        async fn flush(&mut self) -> Result<(), Infallible> {
+            self.flushed = true;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_uart_write() {
+        let mut transport = UartTransport::new(ScriptedUart::new(b"", 1));
+        block_on(transport.write(b"S3=4\r", b"ping")).unwrap();
+        assert_eq!(&transport.uart.written[..], b"S3=4\rping");
+        assert!(transport.uart.flushed);
+    }
+
+    #[test]
+    fn test_uart_prompt() {
+        // A line starting with `>` is not the prompt, and the next response is left
+        // alone.
+        let first = b"\r\n>5\r\nOK\r\n> ";
+        let second = b"\r\n-52\r\nOK\r\n> ";
+        let mut transport = UartTransport::new(ScriptedUart::new(
+            b"\r\n>5\r\nOK\r\n> \r\n-52\r\nOK\r\n> ",
+            1,
+        ));
+        for expected in [&first[..], &second[..]] {
+            let mut received = Vec::<u8, 32>::new();
+            block_on(transport.read(true, |byte| received.push(byte).is_ok())).unwrap();
+            assert_eq!(&received[..], expected);
+        }
+        assert!(transport.uart.incoming.is_empty());
+
+        // The prompt split across reads.
+        for chunk in 2..=first.len() {
+            let mut transport = UartTransport::new(ScriptedUart::new(first, chunk));
+            let mut received = Vec::<u8, 32>::new();
+            block_on(transport.read(true, |byte| received.push(byte).is_ok())).unwrap();
+            assert_eq!(&received[..], first);
+        }
+    }
+
+    #[test]
+    fn test_uart_no_room() {
+        // Without room, nothing is handed on but the whole response is still read.
+        let mut transport = UartTransport::new(ScriptedUart::new(b"\r\n-52\r\nOK\r\n> ", 4));
+        block_on(transport.read(false, |_| panic!("no room for the response"))).unwrap();
+        assert!(transport.uart.incoming.is_empty());
+
+        // Running out of room part way drops the rest of the response.
+        let mut transport = UartTransport::new(ScriptedUart::new(b"\r\n-52\r\nOK\r\n> ", 4));
+        let mut received = Vec::<u8, 4>::new();
+        block_on(transport.read(true, |byte| received.push(byte).is_ok())).unwrap();
+        assert_eq!(&received[..], b"\r\n-5");
+        assert!(transport.uart.incoming.is_empty());
+    }
+
+    /// Receive `response` over SPI, framed in words and padded with NAKs as the module
+    /// does, followed by `idle` words of NAKs.
+    fn receive_spi(response: &[u8], idle: usize) -> Vec<u8, 64> {
+        let mut words: Vec<[u8; 2], 64> = response
+            .chunks(2)
+            .map(|pair| [pair.get(1).copied().unwrap_or(NAK), pair[0]])
+            .collect();
+        for _ in 0..idle {
+            words.push([NAK, NAK]).unwrap();
+        }
+        let sent = Cell::new(0);
+        let spi = ScriptedSpi {
+            words: &words,
+            sent: &sent,
+        };
+        let ready = ScriptedReady {
+            words: words.len(),
+            sent: &sent,
+        };
+        let mut transport = SpiTransport::new(spi, NoPin, ready);
+        let mut received = Vec::new();
+        block_on(transport.read(true, |byte| received.push(byte).is_ok())).unwrap();
+        received
+    }
+
+    #[test]
+    fn test_spi_payload_naks() {
+        // Odd length, so the module pads the prompt with a NAK.
+        let response = b"\r\n\x15\x15\x15\r\nOK\r\n> ";
+        assert_eq!(&receive_spi(response, 0)[..], response);
+        assert_eq!(&receive_spi(response, 2)[..], response);
+        // Even length, with a word of NAKs in the payload.
+        let response = b"\r\n\x15\x15\r\nOK\r\n> ";
+        assert_eq!(&receive_spi(response, 0)[..], response);
+        assert_eq!(&receive_spi(response, 1)[..], response);
+        // Payload ending in NAKs, cut off before the prompt.
+        let response = b"\r\nAB\x15\x15";
+        assert_eq!(&receive_spi(response, 0)[..], response);
+        let response = b"\r\nA\x15";
+        assert_eq!(&receive_spi(response, 0)[..], response);
+    }
+
+    #[test]
+    fn test_scripted_transport() {
+        let script = Script {
+            response: b"\r\n-52\r\nOK\r\n> ",
+            sent: Vec::new(),
+        };
+        let mut state = DriverState::<_, 4>::new(script);
+        assert_eq!(block_on(state.rssi()), Some(-52));
+        assert_eq!(&state.transport.sent[..], b"CR\r");
+    }
+
+    #[test]
+    fn test_empty_read_and_write() {
+        let script = Script {
+            response: b"\r\nOK\r\n> ",
+            sent: Vec::new(),
+        };
+        let mut state = DriverState::<_, 4>::new(script);
+        let handle = block_on(state.socket()).unwrap();
+        state.socket_pool.set_connected(handle);
+        assert_eq!(
+            block_on(state.write(handle, &[], &mut |_| {})).ok(),
+            Some(0)
+        );
+        assert_eq!(block_on(state.read(handle, &mut [])).ok(), Some(0));
+        assert!(state.transport.sent.is_empty());
+    }
+
+    #[test]
+    fn test_response_too_large() {
+        if option_env!("ES_WIFI_SCRATCH_SIZE").is_none() {
+            assert_eq!(SCRATCH_SIZE, 1024);
+        }
+        static FITS: [u8; SCRATCH_SIZE] = [b'x'; SCRATCH_SIZE];
+        static TOO_LARGE: [u8; SCRATCH_SIZE + 1] = [b'x'; SCRATCH_SIZE + 1];
+        let script = Script {
+            response: &TOO_LARGE,
+            sent: Vec::new(),
+        };
+        let mut state = DriverState::<_, 4>::new(script);
+        let received = block_on(state.send_command(command!(4, "CR")));
+        assert!(matches!(received, Err(Error::BufferTooSmall)));
+        state.transport.response = &FITS;
+        let received = block_on(state.send_command(command!(4, "CR")));
+        assert_eq!(received.ok(), Some(&FITS[..]));
+    }
+}
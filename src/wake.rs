@@ -0,0 +1,101 @@
+//! Waking a sleeping host on packets of interest, so it can leave the network to the
+//! module while it sleeps.
+//!
+//! The module compares received packets against the wake patterns set, and signals the
+//! host when one matches. Firmware without wake pattern support reports
+//! [`ModuleError::Unsupported`].
+
+use crate::proto::command::Hex;
+use crate::{DriverState, EsWifi, ModuleError, Transport};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embedded_hal::digital::OutputPin;
+use embedded_hal_async::digital::Wait;
+
+/// Number of wake patterns the module holds.
+pub const WAKE_PATTERNS: u8 = 4;
+
+/// Longest wake pattern the module compares.
+const MAX_PATTERN_LEN: usize = 32;
+
+/// Bytes a received packet must carry for the module to wake the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WakePattern<'a> {
+    /// Position of the pattern in the packet, counted from the start of its Ethernet
+    /// header
+    pub offset: u16,
+    /// Bytes to match, at most 32
+    pub bytes: &'a [u8],
+}
+
+impl<TRANSPORT, const SOCKETS: usize> DriverState<TRANSPORT, SOCKETS>
+where
+    TRANSPORT: Transport,
+{
+    async fn set_wake_pattern(
+        &mut self,
+        slot: u8,
+        pattern: Option<&WakePattern<'_>>,
+    ) -> Result<(), ModuleError> {
+        if slot >= WAKE_PATTERNS {
+            return Err(ModuleError::Rejected);
+        }
+        match pattern {
+            Some(pattern) if pattern.bytes.is_empty() || pattern.bytes.len() > MAX_PATTERN_LEN => {
+                Err(ModuleError::Rejected)
+            }
+            Some(pattern) => {
+                self.module_command(command!(
+                    80,
+                    "ZW=",
+                    slot,
+                    ",",
+                    pattern.offset,
+                    ",",
+                    Hex(pattern.bytes)
+                ))
+                .await
+            }
+            None => self.module_command(command!(8, "ZW=", slot)).await,
+        }
+    }
+}
+
+impl<TRANSPORT, RESET, WAKEUP, const SOCKETS: usize, M> EsWifi<TRANSPORT, RESET, WAKEUP, SOCKETS, M>
+where
+    TRANSPORT: Transport,
+    RESET: OutputPin,
+    WAKEUP: OutputPin,
+    M: RawMutex,
+{
+    /// Set the wake pattern held in `slot`, below [`WAKE_PATTERNS`], or clear it with
+    /// `None`. A slot out of range or a pattern that is empty or too long is rejected.
+    pub async fn set_wake_pattern(
+        &self,
+        slot: u8,
+        pattern: Option<&WakePattern<'_>>,
+    ) -> Result<(), ModuleError> {
+        self.adapter
+            .lock()
+            .await
+            .set_wake_pattern(slot, pattern)
+            .await
+    }
+
+    /// Wait for the module to signal the host, on `pin` wired to its data ready line or
+    /// another output raised when a packet matches a wake pattern.
+    ///
+    /// Once `pin` goes high, the module is woken through the WAKEUP pin, if there is one,
+    /// so it answers commands right away.
+    pub async fn wait_for_wake<P: Wait>(&self, pin: &mut P) -> Result<(), P::Error> {
+        pin.wait_for_high().await?;
+        debug!("Woken by the eS-WiFi adapter");
+        let mut adapter = self.adapter.lock().await;
+        let (state, mut pins) = adapter.split();
+        if state.wakeup(&mut pins).await.is_err() {
+            // The module still answers, only not as promptly.
+            warn!("Failed to drive the WAKEUP pin of the eS-WiFi adapter");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+//! Address changes noticed by the run loop, such as after a DHCP renewal.
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::{FakeModule, JOINED};
+use core::cell::Cell;
+use embassy_futures::block_on;
+use embassy_futures::select::{select, Either};
+use embassy_time::Duration;
+use es_wifi_driver::{Event, IpAddr};
+
+#[test]
+fn address_change_reported() {
+    // The address changes once the network settings were shown twice.
+    let queries = Cell::new(0);
+    let mut driver = FakeModule::new(|command, _| match command {
+        b"ZR\r" => Some(b"\r\n> ".to_vec()),
+        b"C0\r" => Some(JOINED.to_vec()),
+        b"C?\r" => {
+            queries.set(queries.get() + 1);
+            let ip = if queries.get() > 2 {
+                "192.168.1.175"
+            } else {
+                "192.168.1.174"
+            };
+            Some(
+                format!("\r\ndrogue,secret,4,1,0,{ip},255.255.255.0,192.168.1.1\r\nOK\r\n> ")
+                    .into_bytes(),
+            )
+        }
+        _ => None,
+    })
+    .driver();
+    driver.set_address_check(Some(Duration::from_millis(10)));
+    let event = block_on(async {
+        match select(driver.run("drogue", "secret"), async {
+            loop {
+                if let event @ Event::IpChanged { .. } = driver.next_event().await {
+                    return event;
+                }
+            }
+        })
+        .await
+        {
+            Either::First(result) => panic!("run loop ended: {:?}", result.err()),
+            Either::Second(event) => event,
+        }
+    });
+    assert_eq!(
+        event,
+        Event::IpChanged {
+            old: IpAddr::from([192, 168, 1, 174]),
+            new: IpAddr::from([192, 168, 1, 175]),
+        }
+    );
+    assert_eq!(
+        block_on(driver.ip_address()),
+        Some(IpAddr::from([192, 168, 1, 175]))
+    );
+}
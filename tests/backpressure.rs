@@ -0,0 +1,119 @@
+//! Writes waiting for the module's transmit buffer to drain, failing once the connection
+//! is gone, and deadlines that never cut an exchange with the module short.
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::{FakeModule, RSSI};
+use core::cell::Cell;
+use embassy_futures::block_on;
+use embassy_time::{Duration, Instant};
+use embedded_io_async::Write;
+use embedded_nal_async::TcpConnect;
+use es_wifi_driver::{ConnectConfig, SocketAddr, SocketError};
+
+const CONNECTED: &[u8] = b"\r\n0,192.168.1.174,0,192.0.2.1,80,0,0,0,1\r\nOK\r\n> ";
+const DISCONNECTED: &[u8] = b"\r\n0,192.168.1.174,0,192.0.2.1,80,0,0,0,0\r\nOK\r\n> ";
+
+#[test]
+fn write_waits_for_buffer() {
+    // The module's buffer is full for the first two chunks written.
+    let full = Cell::new(2);
+    let driver = FakeModule::new(|command, data| match command {
+        b"P?\r" => Some(CONNECTED.to_vec()),
+        _ if command.starts_with(b"S3=") && full.get() > 0 => {
+            full.set(full.get() - 1);
+            Some(b"\r\n-1\r\nOK\r\n> ".to_vec())
+        }
+        _ if command.starts_with(b"S3=") => {
+            Some(format!("\r\n{}\r\nOK\r\n> ", data.len()).into_bytes())
+        }
+        _ => None,
+    })
+    .driver();
+    let remote: SocketAddr = "192.0.2.1:80".parse().unwrap();
+    block_on(async {
+        let mut socket = driver.connect(remote).await.unwrap();
+        assert_eq!(socket.write(b"hello").await.unwrap(), 5);
+        assert_eq!(socket.stats().retries, 2);
+    });
+}
+
+#[test]
+fn write_fails_once_disconnected() {
+    for refusal in [
+        &b"\r\n-1\r\nOK\r\n> "[..],
+        b"\r\nSocket not connected\r\nERROR\r\n> ",
+    ] {
+        let driver = FakeModule::new(|command, _| match command {
+            b"P?\r" => Some(DISCONNECTED.to_vec()),
+            _ if command.starts_with(b"S3=") => Some(refusal.to_vec()),
+            _ => None,
+        })
+        .driver();
+        let remote: SocketAddr = "192.0.2.1:80".parse().unwrap();
+        block_on(async {
+            let mut socket = driver.connect(remote).await.unwrap();
+            socket.set_write_timeout(Duration::from_secs(5));
+            let start = Instant::now();
+            assert!(matches!(
+                socket.write(b"hello").await,
+                Err(SocketError::WriteError)
+            ));
+            assert!(start.elapsed() < Duration::from_secs(1));
+        });
+    }
+}
+
+#[test]
+fn deadlines_leave_exchanges_whole() {
+    // Every exchange takes longer than the connect and write timeouts.
+    let mut driver = FakeModule::new(|command, data| match command {
+        b"CR\r" => Some(RSSI.to_vec()),
+        _ if command.starts_with(b"S3=") => {
+            Some(format!("\r\n{}\r\nOK\r\n> ", data.len()).into_bytes())
+        }
+        _ => None,
+    })
+    .with_latency(Duration::from_millis(20))
+    .driver();
+    driver.set_connect_config(ConnectConfig {
+        timeout: Duration::from_millis(10),
+        ..ConnectConfig::default()
+    });
+    let remote: SocketAddr = "192.0.2.1:80".parse().unwrap();
+    block_on(async {
+        let mut socket = driver.connect(remote).await.unwrap();
+        socket.set_write_timeout(Duration::from_millis(10));
+        assert_eq!(socket.write(b"hello").await.unwrap(), 5);
+        // The driver is still in step with the module.
+        assert_eq!(driver.control().rssi().await, Some(-52));
+    });
+}
+
+#[test]
+fn nodelay_writes_whole() {
+    // The module takes at most three bytes of each chunk.
+    for (nodelay, written) in [(false, 3), (true, 5)] {
+        let module = FakeModule::new(|command, data| match command {
+            _ if command.starts_with(b"S3=") => {
+                Some(format!("\r\n{}\r\nOK\r\n> ", data.len().min(3)).into_bytes())
+            }
+            _ => None,
+        });
+        let sent = module.sent();
+        let driver = module.driver();
+        let remote: SocketAddr = "192.0.2.1:80".parse().unwrap();
+        block_on(async {
+            let mut socket = driver.connect(remote).await.unwrap();
+            socket.set_nodelay(nodelay);
+            assert_eq!(socket.write(b"hello").await.unwrap(), written);
+        });
+        let chunks = sent
+            .borrow()
+            .iter()
+            .filter(|command| command.starts_with(b"S3="))
+            .count();
+        assert_eq!(chunks, if nodelay { 2 } else { 1 });
+    }
+}
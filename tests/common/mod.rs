@@ -0,0 +1,113 @@
+//! Scripted module shared by the integration tests.
+#![allow(dead_code)]
+
+use core::cell::RefCell;
+use core::convert::Infallible;
+use embassy_futures::yield_now;
+use embassy_time::{Duration, Timer};
+use es_wifi_driver::{EsWifi, NoPin, Transport};
+use std::rc::Rc;
+
+/// Response to a command that went through.
+pub const OK: &[u8] = b"\r\nOK\r\n> ";
+
+/// Response to joining the network `drogue`.
+pub const JOINED: &[u8] = b"\r\n[JOIN   ] drogue,192.168.1.174,0,0\r\nOK\r\n> ";
+
+/// Response to showing the signal strength.
+pub const RSSI: &[u8] = b"\r\n-52\r\nOK\r\n> ";
+
+/// Response to a scan finding the network `drogue`.
+pub const SCAN: &[u8] = b"\r\n#001,\"drogue\",C4:12:F5:00:11:22,-52,72.0,Infrastructure,\
+                          WPA2 AES,2.4GHz,6\r\nOK\r\n> ";
+
+/// Picks the response of a [`FakeModule`] to a command and its payload.
+pub trait Script: FnMut(&[u8], &[u8]) -> Option<Vec<u8>> {}
+
+impl<F: FnMut(&[u8], &[u8]) -> Option<Vec<u8>>> Script for F {}
+
+/// Driver talking to a [`FakeModule`], without RESET and WAKEUP pins.
+pub type Driver<F> = EsWifi<FakeModule<F>, NoPin, NoPin>;
+
+/// Commands written to a [`FakeModule`], each with its trailing carriage return.
+pub type Sent = Rc<RefCell<Vec<Vec<u8>>>>;
+
+/// Module answering each command with what its script returns for the command and its
+/// payload, or with [`OK`] when the script returns `None`.
+pub struct FakeModule<F> {
+    script: F,
+    sent: Sent,
+    latency: Duration,
+    response: Vec<u8>,
+}
+
+impl<F> FakeModule<F>
+where
+    F: FnMut(&[u8], &[u8]) -> Option<Vec<u8>>,
+{
+    pub fn new(script: F) -> Self {
+        Self {
+            script,
+            sent: Sent::default(),
+            latency: Duration::from_ticks(0),
+            response: Vec::new(),
+        }
+    }
+
+    /// Take `latency` to answer each command.
+    pub fn with_latency(self, latency: Duration) -> Self {
+        Self { latency, ..self }
+    }
+
+    /// Commands written to the module so far, kept up to date after it is handed to a
+    /// driver.
+    pub fn sent(&self) -> Sent {
+        self.sent.clone()
+    }
+
+    /// Driver talking to this module, without RESET and WAKEUP pins.
+    pub fn driver(self) -> Driver<F> {
+        EsWifi::with_transport(self, None, None)
+    }
+}
+
+/// Module answering every command with [`OK`].
+pub fn ok_module() -> FakeModule<impl Script> {
+    FakeModule::new(|_, _| None)
+}
+
+/// Whether `command`, without its carriage return, was written to the module.
+pub fn was_sent(sent: &Sent, command: &[u8]) -> bool {
+    sent.borrow()
+        .iter()
+        .any(|sent| sent.strip_suffix(b"\r") == Some(command))
+}
+
+impl<F: Script> Transport for FakeModule<F> {
+    type Error = Infallible;
+
+    async fn write(&mut self, command: &[u8], data: &[u8]) -> Result<(), Infallible> {
+        self.sent.borrow_mut().push(command.to_vec());
+        self.response = (self.script)(command, data).unwrap_or_else(|| OK.to_vec());
+        Ok(())
+    }
+
+    async fn read(
+        &mut self,
+        room: bool,
+        mut push: impl FnMut(u8) -> bool,
+    ) -> Result<(), Infallible> {
+        // Give other tasks a chance to run in the middle of each exchange, as they would
+        // while a real module answers.
+        yield_now().await;
+        Timer::after(self.latency).await;
+        if room {
+            for &byte in &core::mem::take(&mut self.response) {
+                if !push(byte) {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+}
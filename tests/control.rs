@@ -0,0 +1,291 @@
+//! Joining and leaving networks, and switching to hosting one, through the control handle,
+//! without the run loop.
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::{was_sent, FakeModule, Script, JOINED, RSSI};
+use core::cell::Cell;
+use core::net::Ipv4Addr;
+use embassy_futures::block_on;
+use embassy_futures::select::{select, Either};
+use embassy_time::{Duration, Instant, Timer};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embedded_nal_async::TcpConnect;
+use es_wifi_driver::{
+    ApConfig, ApError, CloseReason, Control, DhcpServerConfig, EsWifi, Event, IpAddr, NoPin,
+    ProbeConfig, SocketAddr, Transport, WifiMode,
+};
+
+/// Module with a network named `drogue` in range.
+fn module() -> FakeModule<impl Script> {
+    FakeModule::new(|command, _| match command {
+        b"C0\r" => Some(JOINED.to_vec()),
+        b"CR\r" => Some(RSSI.to_vec()),
+        b"ZU?\r" => Some(b"\r\n3600\r\nOK\r\n> ".to_vec()),
+        _ => None,
+    })
+}
+
+#[test]
+fn join_and_leave() {
+    let module = module();
+    let sent = module.sent();
+    let driver = module.driver();
+    let control = driver.control();
+    let remote: SocketAddr = "192.0.2.1:80".parse().unwrap();
+    block_on(async {
+        let info = control.join("drogue", "secret").await.unwrap();
+        assert_eq!(info.ip, IpAddr::from([192, 168, 1, 174]));
+        assert_eq!(control.rssi().await, Some(-52));
+
+        let socket = driver.connect(remote).await.unwrap();
+        let handle = socket.handle();
+        let stats = control.stats().await;
+        assert_eq!(stats.join_info, Some(info));
+        assert_eq!(stats.connected_sockets, 1);
+
+        // Copies of the handle act on the same driver.
+        let copy = control;
+        copy.leave().await;
+        assert!(was_sent(&sent, b"CD"));
+        let stats = control.stats().await;
+        assert_eq!(stats.join_info, None);
+        assert_eq!(stats.connected_sockets, 0);
+
+        // Skip the events reported while connecting.
+        let mut event = driver.next_event().await;
+        while !matches!(event, Event::SocketClosed { .. }) {
+            event = driver.next_event().await;
+        }
+        assert_eq!(
+            event,
+            Event::SocketClosed {
+                handle,
+                remote: Some(remote),
+                reason: CloseReason::Left,
+            }
+        );
+    });
+}
+
+#[test]
+fn switch_mode() {
+    let module = module();
+    let sent = module.sent();
+    let driver = module.driver();
+    let control = driver.control();
+    let remote: SocketAddr = "192.0.2.1:80".parse().unwrap();
+    block_on(async {
+        assert_eq!(control.mode().await, None);
+        control.join("drogue", "secret").await.unwrap();
+        assert_eq!(control.mode().await, Some(WifiMode::Station));
+        let socket = driver.connect(remote).await.unwrap();
+        let handle = socket.handle();
+
+        control
+            .switch_to_ap(&ApConfig::new("provision", ""))
+            .await
+            .unwrap();
+        assert!(was_sent(&sent, b"CD"));
+        assert_eq!(control.mode().await, Some(WifiMode::AccessPoint));
+        assert_eq!(control.stats().await.connected_sockets, 0);
+
+        let mut event = driver.next_event().await;
+        while !matches!(event, Event::SocketClosed { .. }) {
+            event = driver.next_event().await;
+        }
+        assert_eq!(
+            event,
+            Event::SocketClosed {
+                handle,
+                remote: Some(remote),
+                reason: CloseReason::ModeChanged,
+            }
+        );
+        assert_eq!(
+            driver.next_event().await,
+            Event::ModeChanged {
+                mode: WifiMode::AccessPoint
+            }
+        );
+
+        let info = control.switch_to_station("drogue", "secret").await.unwrap();
+        assert!(was_sent(&sent, b"AE"));
+        assert_eq!(control.mode().await, Some(WifiMode::Station));
+        assert_eq!(control.stats().await.join_info, Some(info));
+        assert_eq!(
+            driver.next_event().await,
+            Event::ModeChanged {
+                mode: WifiMode::Station
+            }
+        );
+    });
+}
+
+#[test]
+fn probe_skipped_while_hosting() {
+    let module = FakeModule::new(|command, _| match command {
+        b"ZR\r" => Some(b"\r\n> ".to_vec()),
+        b"C0\r" => Some(JOINED.to_vec()),
+        b"C?\r" => Some(
+            b"\r\ndrogue,secret,4,1,0,192.168.1.174,255.255.255.0,192.168.1.1\r\nOK\r\n> "
+                .to_vec(),
+        ),
+        _ => None,
+    });
+    let sent = module.sent();
+    let mut driver = module.driver();
+    driver.set_probe(Some(ProbeConfig {
+        interval: Duration::from_millis(10),
+        max_failures: 1,
+        ..ProbeConfig::default()
+    }));
+    let control = driver.control();
+    block_on(async {
+        match select(driver.run("drogue", "secret"), async {
+            // Wait for the probe to have pinged the gateway once.
+            while !was_sent(&sent, b"T0") {
+                Timer::after(Duration::from_millis(1)).await;
+            }
+            control
+                .switch_to_ap(&ApConfig::new("provision", ""))
+                .await
+                .unwrap();
+            let switched = sent.borrow().len();
+            Timer::after(Duration::from_millis(100)).await;
+            switched
+        })
+        .await
+        {
+            Either::First(result) => panic!("run loop ended: {:?}", result.err()),
+            Either::Second(switched) => {
+                // Nothing was probed, nor rejoined, once hosting.
+                assert_eq!(control.mode().await, Some(WifiMode::AccessPoint));
+                let sent = sent.borrow();
+                assert!(!sent[switched..]
+                    .iter()
+                    .any(|command| command.starts_with(b"T") || command.starts_with(b"C")));
+            }
+        }
+    });
+}
+
+#[test]
+fn uptime_and_resets() {
+    let module = module();
+    let sent = module.sent();
+    let driver = module.driver();
+    let control = driver.control();
+    let started = Instant::now();
+    block_on(async {
+        assert_eq!(
+            control.module_uptime().await.unwrap(),
+            Duration::from_secs(3600)
+        );
+        assert_eq!(control.stats().await.resets.resets, 0);
+
+        let joined = async {
+            while driver.ip_address().await.is_none() {
+                Timer::after(Duration::from_millis(1)).await;
+            }
+        };
+        if let Either::First(result) = select(driver.run("drogue", "secret"), joined).await {
+            panic!("run loop ended: {:?}", result.err());
+        }
+        let resets = control.stats().await.resets;
+        assert_eq!(resets.resets, 1);
+        assert!(resets.last_reset.unwrap() >= started);
+    });
+    assert!(was_sent(&sent, b"ZR"));
+}
+
+#[test]
+fn invalid_ap_config() {
+    let module = module();
+    let sent = module.sent();
+    let driver = module.driver();
+    let control = driver.control();
+    let ssid = core::str::from_utf8(&[b'x'; 40]).unwrap();
+    block_on(async {
+        control.join("drogue", "secret").await.unwrap();
+        let mut overlapping = ApConfig::new("provision", "");
+        overlapping.dhcp = Some(DhcpServerConfig {
+            address: Ipv4Addr::new(10, 42, 0, 1),
+            netmask: Ipv4Addr::new(255, 255, 255, 0),
+            lease_start: Ipv4Addr::new(10, 42, 0, 1),
+            lease_end: Ipv4Addr::new(10, 42, 0, 20),
+        });
+        for config in [
+            ApConfig::new(ssid, ""),
+            ApConfig::new("provision", "short"),
+            overlapping,
+        ] {
+            assert_eq!(
+                control.switch_to_ap(&config).await,
+                Err(ApError::InvalidConfig)
+            );
+        }
+        // The network joined was left alone, and nothing reached the module.
+        assert_eq!(control.mode().await, Some(WifiMode::Station));
+        assert!(!was_sent(&sent, b"CD"));
+        assert!(!sent.borrow().iter().any(|command| command.starts_with(b"A")));
+    });
+}
+
+#[test]
+fn web_config_credentials() {
+    // The module has `drogue` stored until the page submits `home`.
+    let submitted = Cell::new(false);
+    let module = FakeModule::new(|command, _| match command {
+        b"C?\r" if submitted.get() => Some(b"\r\nhome,p4ss,3,1,0,0.0.0.0\r\nOK\r\n> ".to_vec()),
+        b"C?\r" => Some(b"\r\ndrogue,secret,3,1,0,0.0.0.0\r\nOK\r\n> ".to_vec()),
+        _ => None,
+    });
+    let driver = module.driver();
+    let control = driver.control();
+    block_on(async {
+        assert_eq!(
+            driver.web_config_credentials().await,
+            Err(ApError::NotActive)
+        );
+        control
+            .switch_to_ap(&ApConfig::new("provision", ""))
+            .await
+            .unwrap();
+        // Nothing is reported before the page is enabled, nor for the network the module
+        // already had.
+        assert_eq!(driver.web_config_credentials().await, Ok(None));
+        driver.set_web_config(true).await.unwrap();
+        assert_eq!(driver.web_config_credentials().await, Ok(None));
+
+        submitted.set(true);
+        let credentials = driver.web_config_credentials().await.unwrap().unwrap();
+        assert_eq!(credentials.ssid.as_str(), "home");
+        assert_eq!(credentials.password.as_str(), "p4ss");
+    });
+}
+
+/// Transport that never answers, standing in for a bus peripheral that can move between
+/// executors.
+struct Silent;
+
+impl Transport for Silent {
+    type Error = ();
+
+    async fn write(&mut self, _: &[u8], _: &[u8]) -> Result<(), ()> {
+        Ok(())
+    }
+
+    async fn read(&mut self, _: bool, _: impl FnMut(u8) -> bool) -> Result<(), ()> {
+        Err(())
+    }
+}
+
+#[test]
+fn control_is_send() {
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+    assert_sync::<EsWifi<Silent, NoPin, NoPin, 4, CriticalSectionRawMutex>>();
+    assert_send::<Control<'static, Silent, NoPin, NoPin, 4, CriticalSectionRawMutex>>();
+}
@@ -0,0 +1,285 @@
+//! Falling back to other addresses of a service when some of them are down.
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::{Driver, FakeModule, Script, RSSI};
+use core::cell::RefCell;
+use embassy_futures::block_on;
+use embassy_time::{Duration, Instant, Timer};
+use es_wifi_driver::{
+    DnsCacheConfig, EsWifi, NoPin, SocketAddr, SocketError, TlsConfig, Transport,
+};
+
+/// Driver for a module refusing connections to the hosts in `down`, and resolving every
+/// name to `resolves_to`.
+fn driver<'a>(
+    received: &'a RefCell<Vec<String>>,
+    down: &'a [&'a str],
+    resolves_to: Option<&'a str>,
+) -> Driver<impl Script + 'a> {
+    module(received, down, resolves_to).driver()
+}
+
+fn module<'a>(
+    received: &'a RefCell<Vec<String>>,
+    down: &'a [&'a str],
+    resolves_to: Option<&'a str>,
+) -> FakeModule<impl Script + 'a> {
+    let mut host = String::new();
+    FakeModule::new(move |command, _| {
+        let command = String::from_utf8_lossy(command).trim_end().to_string();
+        let response = if let Some(ip) = command.strip_prefix("P3=") {
+            host = ip.to_string();
+            None
+        } else if command == "P6=1" && down.contains(&host.as_str()) {
+            Some(b"\r\nConnection refused\r\nERROR\r\n> ".to_vec())
+        } else if command == "CR" {
+            Some(RSSI.to_vec())
+        } else if command.starts_with("D0=") {
+            match resolves_to {
+                Some(ip) => Some(format!("\r\n{}\r\nOK\r\n> ", ip).into_bytes()),
+                None => Some(b"\r\n-1\r\nOK\r\n> ".to_vec()),
+            }
+        } else {
+            None
+        };
+        received.borrow_mut().push(command);
+        response
+    })
+}
+
+/// Time the module takes to answer connecting to an unreachable address.
+const HANG: Duration = Duration::from_millis(500);
+
+/// Module taking [`HANG`] to connect to the hosts in `hanging`, as it does waiting for an
+/// unreachable address.
+struct Hanging<'a, F> {
+    module: FakeModule<F>,
+    hanging: &'a [&'a str],
+    host: Vec<u8>,
+    answer_at: Option<Instant>,
+}
+
+impl<F: Script> Transport for Hanging<'_, F> {
+    type Error = core::convert::Infallible;
+
+    async fn write(&mut self, command: &[u8], data: &[u8]) -> Result<(), Self::Error> {
+        if let Some(host) = command.strip_prefix(b"P3=") {
+            self.host = host.strip_suffix(b"\r").unwrap_or(host).to_vec();
+        }
+        let host = String::from_utf8_lossy(&self.host);
+        if command == b"P6=1\r" && self.hanging.contains(&host.as_ref()) {
+            self.answer_at = Some(Instant::now() + HANG);
+        }
+        self.module.write(command, data).await
+    }
+
+    async fn read(&mut self, room: bool, push: impl FnMut(u8) -> bool) -> Result<(), Self::Error> {
+        if let Some(at) = self.answer_at {
+            Timer::at(at).await;
+            self.answer_at = None;
+        }
+        self.module.read(room, push).await
+    }
+}
+
+/// Driver for a module taking [`HANG`] to connect to the hosts in `hanging`, and
+/// resolving every name to `resolves_to`.
+fn hanging_driver<'a>(
+    received: &'a RefCell<Vec<String>>,
+    hanging: &'a [&'a str],
+    resolves_to: Option<&'a str>,
+) -> EsWifi<Hanging<'a, impl Script + 'a>, NoPin, NoPin> {
+    let module = Hanging {
+        module: module(received, &[], resolves_to),
+        hanging,
+        host: Vec::new(),
+        answer_at: None,
+    };
+    EsWifi::with_transport(module, None, None)
+}
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+fn addrs(addrs: &[&str]) -> Vec<SocketAddr> {
+    addrs.iter().map(|addr| addr.parse().unwrap()).collect()
+}
+
+fn attempts(received: &RefCell<Vec<String>>) -> Vec<String> {
+    received
+        .borrow()
+        .iter()
+        .filter_map(|command| command.strip_prefix("P3="))
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn connect_any_skips_addresses_that_are_down() {
+    let received = RefCell::new(Vec::new());
+    let driver = driver(&received, &["192.0.2.1", "192.0.2.2"], None);
+    let remotes = addrs(&["192.0.2.1:80", "192.0.2.2:80", "192.0.2.3:80"]);
+    block_on(driver.connect_any(&remotes, TIMEOUT)).unwrap();
+    assert_eq!(
+        attempts(&received),
+        ["192.0.2.1", "192.0.2.2", "192.0.2.3"]
+    );
+}
+
+#[test]
+fn connect_any_reports_last_failure() {
+    let received = RefCell::new(Vec::new());
+    let driver = driver(&received, &["192.0.2.1"], None);
+    let result = block_on(driver.connect_any(&addrs(&["192.0.2.1:80"]), TIMEOUT));
+    assert!(matches!(result, Err(SocketError::ConnectionRefused)));
+    let result = block_on(driver.connect_any(&[], TIMEOUT));
+    assert!(matches!(result, Err(SocketError::InvalidAddress)));
+}
+
+#[test]
+fn connect_any_gives_up_on_address_that_hangs() {
+    let received = RefCell::new(Vec::new());
+    let driver = hanging_driver(&received, &["192.0.2.1"], None);
+    let remotes = addrs(&["192.0.2.1:80", "192.0.2.2:80"]);
+    block_on(async {
+        let result = driver
+            .connect_any(&remotes[..1], Duration::from_millis(100))
+            .await;
+        assert!(matches!(result, Err(SocketError::ConnectTimeout)));
+        driver
+            .connect_any(&remotes, Duration::from_millis(100))
+            .await
+            .unwrap();
+        // The late answers were not taken for those of later commands.
+        assert_eq!(driver.control().rssi().await, Some(-52));
+    });
+    assert_eq!(attempts(&received), ["192.0.2.1", "192.0.2.1", "192.0.2.2"]);
+}
+
+#[test]
+fn connect_host_falls_back_without_retrying_resolved_address() {
+    let received = RefCell::new(Vec::new());
+    let driver = driver(&received, &["192.0.2.1"], Some("192.0.2.1"));
+    let fallback = addrs(&["192.0.2.1:443", "198.51.100.1:443"]);
+    block_on(driver.connect_host("example.com", 443, &fallback, TIMEOUT)).unwrap();
+    assert_eq!(attempts(&received), ["192.0.2.1", "198.51.100.1"]);
+}
+
+#[test]
+fn connect_host_falls_back_when_name_does_not_resolve() {
+    let received = RefCell::new(Vec::new());
+    let driver = driver(&received, &[], None);
+    let fallback = addrs(&["198.51.100.1:443"]);
+    block_on(driver.connect_host("example.com", 443, &fallback, TIMEOUT)).unwrap();
+    assert_eq!(attempts(&received), ["198.51.100.1"]);
+}
+
+#[test]
+fn connect_timings() {
+    let received = RefCell::new(Vec::new());
+    let driver = driver(&received, &[], Some("192.0.2.1"));
+    let socket = block_on(driver.connect_any(&addrs(&["192.0.2.1:80"]), TIMEOUT)).unwrap();
+    assert_eq!(socket.timings().dns, None);
+    assert_eq!(socket.timings().handshake, None);
+    drop(socket);
+    let socket = block_on(driver.connect_host("example.com", 443, &[], TIMEOUT)).unwrap();
+    assert!(socket.timings().dns.is_some());
+    let remote = "192.0.2.1:443".parse().unwrap();
+    let tls = TlsConfig {
+        server_name: Some("example.com"),
+        ..TlsConfig::default()
+    };
+    let socket = block_on(driver.connect_tls(remote, tls)).unwrap();
+    let timings = socket.timings();
+    assert!(timings.handshake.unwrap() <= timings.connect);
+}
+
+#[test]
+fn dns_cache() {
+    let received = RefCell::new(Vec::new());
+    let driver = driver(&received, &[], Some("192.0.2.1"));
+    let lookups = || {
+        received
+            .borrow()
+            .iter()
+            .filter(|command| command.starts_with("D0="))
+            .count()
+    };
+    block_on(async {
+        driver.set_dns_cache(Some(DnsCacheConfig::default())).await;
+        for _ in 0..2 {
+            driver
+                .connect_host("example.com", 443, &[], TIMEOUT)
+                .await
+                .unwrap();
+        }
+        assert_eq!(lookups(), 1);
+        driver.control().flush_dns_cache().await;
+        driver
+            .connect_host("example.com", 443, &[], TIMEOUT)
+            .await
+            .unwrap();
+        assert_eq!(lookups(), 2);
+    });
+}
+
+#[test]
+fn connect_host_tries_stale_cached_address_first() {
+    let received = RefCell::new(Vec::new());
+    let driver = driver(&received, &["192.0.2.1"], Some("192.0.2.1"));
+    let lookups = || {
+        received
+            .borrow()
+            .iter()
+            .filter(|command| command.starts_with("D0="))
+            .count()
+    };
+    // Every address is past its TTL as soon as it is kept.
+    let config = DnsCacheConfig {
+        ttl: Duration::from_ticks(0),
+        ..DnsCacheConfig::default()
+    };
+    block_on(async {
+        driver.set_dns_cache(Some(config)).await;
+        for _ in 0..2 {
+            let result = driver.connect_host("example.com", 443, &[], TIMEOUT).await;
+            assert!(matches!(result, Err(SocketError::ConnectionRefused)));
+        }
+    });
+    // The cached address failed, so the name was looked up again.
+    assert_eq!(lookups(), 2);
+    assert_eq!(attempts(&received), ["192.0.2.1", "192.0.2.1", "192.0.2.1"]);
+}
+
+#[test]
+fn connect_host_gives_up_on_stale_cached_address_that_hangs() {
+    let received = RefCell::new(Vec::new());
+    let driver = hanging_driver(&received, &["192.0.2.1"], Some("192.0.2.1"));
+    let lookups = || {
+        received
+            .borrow()
+            .iter()
+            .filter(|command| command.starts_with("D0="))
+            .count()
+    };
+    // Every address is past its TTL as soon as it is kept, and the head start is shorter
+    // than the module takes to connect.
+    let config = DnsCacheConfig {
+        ttl: Duration::from_ticks(0),
+        ..DnsCacheConfig::default()
+    };
+    assert!(config.head_start < HANG);
+    block_on(async {
+        driver.set_dns_cache(Some(config)).await;
+        for _ in 0..2 {
+            driver
+                .connect_host("example.com", 443, &[], TIMEOUT)
+                .await
+                .unwrap();
+        }
+    });
+    // The cached address ran out of head start, so the name was looked up again.
+    assert_eq!(lookups(), 2);
+    assert_eq!(attempts(&received), ["192.0.2.1", "192.0.2.1", "192.0.2.1"]);
+}
@@ -0,0 +1,67 @@
+//! Holding the driver still while the host sleeps, and checking on the module after.
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::{was_sent, FakeModule};
+use core::cell::Cell;
+use embassy_futures::block_on;
+use embedded_nal_async::TcpConnect;
+use es_wifi_driver::{CloseReason, Event, HostResume, SocketAddr};
+
+#[test]
+fn host_sleep() {
+    // The module stops answering commands while `lost` is set, until it is sent an
+    // empty line.
+    let lost = Cell::new(false);
+    let module = FakeModule::new(|command, _| match command {
+        b"\r" => {
+            lost.set(false);
+            Some(b"\r\n> ".to_vec())
+        }
+        _ if lost.get() => Some(Vec::new()),
+        b"P6=0\r" => Some(b"\r\n\r\nOK\r\n> ".to_vec()),
+        _ => None,
+    });
+    let sent = module.sent();
+    let driver = module.driver();
+    let remote: SocketAddr = "192.0.2.1:80".parse().unwrap();
+    block_on(async {
+        let socket = driver.connect(remote).await.unwrap();
+        let handle = socket.handle();
+        assert_eq!(driver.next_event().await, Event::SocketOpened { handle });
+        assert_eq!(
+            driver.next_event().await,
+            Event::SocketConnected { handle, remote }
+        );
+
+        let sleep = driver.prepare_for_host_sleep(true).await;
+        assert!(was_sent(&sent, b"P6=0"));
+        assert_eq!(
+            driver.next_event().await,
+            Event::SocketClosed {
+                handle,
+                remote: Some(remote),
+                reason: CloseReason::Parked,
+            }
+        );
+        assert_eq!(
+            driver.resume_from_host_sleep(sleep).await.unwrap(),
+            HostResume {
+                resynchronized: false,
+                associated: None,
+            }
+        );
+
+        // The module lost step with the driver while the host slept.
+        let sleep = driver.prepare_for_host_sleep(false).await;
+        lost.set(true);
+        assert_eq!(
+            driver.resume_from_host_sleep(sleep).await.unwrap(),
+            HostResume {
+                resynchronized: true,
+                associated: None,
+            }
+        );
+    });
+}
@@ -0,0 +1,57 @@
+//! Two drivers, each with its own module, used concurrently from one task.
+//!
+//! Needs `std` for the embassy-time driver and critical section the driver's timeouts run on.
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::{Driver, FakeModule, Script};
+use core::cell::RefCell;
+use embassy_futures::{block_on, join::join};
+use embedded_io_async::Write;
+use embedded_nal_async::TcpConnect;
+use es_wifi_driver::{EsWifi, NoPin, SocketAddr, Transport};
+
+/// Driver for a module answering every command with `OK`, and every payload as fully
+/// accepted, recording the commands and payloads in `received`.
+fn driver(
+    received: &RefCell<Vec<u8>>,
+) -> Driver<impl Script + '_> {
+    FakeModule::new(|command, data| {
+        let mut received = received.borrow_mut();
+        received.extend_from_slice(command);
+        received.extend_from_slice(data);
+        command
+            .starts_with(b"S3=")
+            .then(|| format!("\r\n{}\r\nOK\r\n> ", data.len()).into_bytes())
+    })
+    .driver()
+}
+
+async fn send<T: Transport>(driver: &EsWifi<T, NoPin, NoPin>, remote: &str, payload: &[u8]) {
+    let remote: SocketAddr = remote.parse().unwrap();
+    let mut socket = driver.connect(remote).await.unwrap();
+    for _ in 0..3 {
+        socket.write_all(payload).await.unwrap();
+    }
+}
+
+#[test]
+fn two_drivers_run_concurrently() {
+    let (first, second) = (RefCell::new(Vec::new()), RefCell::new(Vec::new()));
+    let first_driver = driver(&first);
+    let second_driver = driver(&second);
+
+    block_on(join(
+        send(&first_driver, "192.168.1.10:80", b"first"),
+        send(&second_driver, "192.168.2.10:80", b"second"),
+    ));
+
+    let first = String::from_utf8(first.take()).unwrap();
+    let second = String::from_utf8(second.take()).unwrap();
+    assert!(first.contains("P3=192.168.1.10\r"));
+    assert!(second.contains("P3=192.168.2.10\r"));
+    assert_eq!(first.matches("S3=5\rfirst").count(), 3);
+    assert_eq!(second.matches("S3=6\rsecond").count(), 3);
+    assert!(!first.contains("second") && !second.contains("first"));
+}
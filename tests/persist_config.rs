@@ -0,0 +1,32 @@
+//! Checking that the settings in use were stored in the module's flash.
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::FakeModule;
+use embassy_futures::block_on;
+
+const CURRENT: &[u8] = b"\r\ndrogue,secret,4,1,0,192.168.1.174,255.255.255.0,192.168.1.1,\
+                         192.168.1.1,0.0.0.0,5,1,0,GB,1\r\nOK\r\n> ";
+
+#[test]
+fn persisted_settings_compared() {
+    for (stored, persisted) in [
+        (
+            &b"\r\ndrogue,secret,4,1,0,0.0.0.0,0.0.0.0,0.0.0.0,0.0.0.0,0.0.0.0,5,1,0,GB\r\nOK\r\n> "[..],
+            true,
+        ),
+        (
+            b"\r\ndrogue,hunter22,4,1,0,0.0.0.0,0.0.0.0,0.0.0.0,0.0.0.0,0.0.0.0,5,1,0,GB\r\nOK\r\n> ",
+            false,
+        ),
+    ] {
+        let driver = FakeModule::new(|command, _| match command {
+            b"C?\r" => Some(CURRENT.to_vec()),
+            b"Z?\r" => Some(stored.to_vec()),
+            _ => None,
+        })
+        .driver();
+        assert_eq!(block_on(driver.is_config_persisted()), Ok(persisted));
+    }
+}
@@ -0,0 +1,43 @@
+//! Power save settings applied to the module and read back.
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::{FakeModule, OK};
+use embassy_futures::block_on;
+use es_wifi_driver::PowerSaveConfig;
+use std::collections::HashMap;
+
+#[test]
+fn power_save_applied() {
+    // The module keeps the values set with `Zx=` commands, and shows them with `Zx?`.
+    let mut settings = HashMap::new();
+    let driver = FakeModule::new(move |command, _| {
+        let command = command.strip_suffix(b"\r").unwrap();
+        match command {
+            [b'Z', _, b'?'] => {
+                let value = settings.get(&command[..2]).cloned().unwrap_or(b"0".to_vec());
+                Some([&b"\r\n"[..], &value, OK].concat())
+            }
+            [b'Z', _, b'=', value @ ..] => {
+                settings.insert(command[..2].to_vec(), value.to_vec());
+                None
+            }
+            _ => None,
+        }
+    })
+    .driver();
+    block_on(async {
+        assert_eq!(driver.power_save().await.unwrap(), None);
+
+        let config = PowerSaveConfig {
+            listen_interval: 10,
+            dtim_skip: 2,
+        };
+        driver.set_power_save(Some(config)).await.unwrap();
+        assert_eq!(driver.power_save().await.unwrap(), Some(config));
+
+        driver.set_power_save(None).await.unwrap();
+        assert_eq!(driver.power_save().await.unwrap(), None);
+    });
+}
@@ -0,0 +1,106 @@
+//! Credentials of the network to join taken from a form served over HTTP.
+#![cfg(all(feature = "std", feature = "http-provisioning"))]
+
+mod common;
+
+use common::{was_sent, FakeModule};
+use core::cell::{Cell, RefCell};
+use embassy_futures::block_on;
+use std::collections::VecDeque;
+
+const ATTACHED: &[u8] = b"\r\n0,192.168.10.1,80,192.168.10.2,50000,1,0,1,1\r\nOK\r\n> ";
+
+#[test]
+fn form_posted_back() {
+    // A browser asks for the form, then posts it back in two pieces, with nothing left
+    // to read in between.
+    let requests = RefCell::new(VecDeque::from([
+        &b"GET / HTTP/1.1\r\nHost: 192.168.10.1\r\n\r\n"[..],
+        b"",
+        b"POST / HTTP/1.1\r\nContent-Length: 30\r\n\r\nssid=My+Home",
+        b"",
+        b"&password=s3cr%26t",
+    ]));
+    let written = RefCell::new(Vec::new());
+    let module = FakeModule::new(|command, data| match command {
+        b"P?\r" => Some(ATTACHED.to_vec()),
+        b"R0\r" => {
+            let mut response = b"\r\n".to_vec();
+            response.extend_from_slice(requests.borrow_mut().pop_front().unwrap_or_default());
+            response.extend_from_slice(b"\r\nOK\r\n> ");
+            Some(response)
+        }
+        _ if command.starts_with(b"S3=") => {
+            written.borrow_mut().extend_from_slice(data);
+            Some(format!("\r\n{}\r\nOK\r\n> ", data.len()).into_bytes())
+        }
+        _ => None,
+    });
+    let sent = module.sent();
+    let driver = module.driver();
+    let credentials = block_on(driver.run_provisioning_server(80)).unwrap();
+    assert_eq!(credentials.ssid.as_str(), "My Home");
+    assert_eq!(credentials.password.as_str(), "s3cr&t");
+    assert!(was_sent(&sent, b"P2=80"));
+
+    let written = String::from_utf8(written.into_inner()).unwrap();
+    let mut responses = written.split("HTTP/1.1 ").skip(1);
+    let form = responses.next().unwrap();
+    assert!(form.starts_with("200 OK\r\n"));
+    assert!(form.contains("<form method=\"post\""));
+    assert!(responses.next().unwrap().starts_with("200 OK\r\n"));
+    assert!(responses.next().is_none());
+}
+
+#[test]
+fn client_kept_connected() {
+    // The first client gets the form and keeps the connection open without sending
+    // anything more, the second posts the form back. Each stop of the server drops the
+    // client attached.
+    let client = Cell::new(0);
+    let requests = RefCell::new([
+        VecDeque::from([&b"GET / HTTP/1.1\r\n\r\n"[..]]),
+        VecDeque::from([&b"POST / HTTP/1.1\r\nContent-Length: 11\r\n\r\nssid=drogue"[..]]),
+    ]);
+    let module = FakeModule::new(|command, data| match command {
+        b"P?\r" => Some(
+            format!(
+                "\r\n0,192.168.10.1,80,192.168.10.{},50000,1,0,1,1\r\nOK\r\n> ",
+                client.get() + 2
+            )
+            .into_bytes(),
+        ),
+        b"P5=0\r" => {
+            client.set(client.get() + 1);
+            None
+        }
+        b"R0\r" => {
+            let mut response = b"\r\n".to_vec();
+            let mut requests = requests.borrow_mut();
+            let request = requests.get_mut(client.get()).and_then(VecDeque::pop_front);
+            response.extend_from_slice(request.unwrap_or_default());
+            response.extend_from_slice(b"\r\nOK\r\n> ");
+            Some(response)
+        }
+        _ if command.starts_with(b"S3=") => {
+            Some(format!("\r\n{}\r\nOK\r\n> ", data.len()).into_bytes())
+        }
+        _ => None,
+    });
+    let sent = module.sent();
+    let driver = module.driver();
+    let credentials = block_on(driver.run_provisioning_server(80)).unwrap();
+    assert_eq!(credentials.ssid.as_str(), "drogue");
+    assert_eq!(client.get(), 1);
+
+    // The server is stopped and started again once the form is written.
+    let sent = sent.borrow();
+    let restart = sent
+        .iter()
+        .position(|command| command.as_slice() == b"P5=0\r")
+        .unwrap();
+    assert!(sent[..restart]
+        .iter()
+        .any(|command| command.starts_with(b"S3=")));
+    assert_eq!(sent[restart + 1], b"P5=1\r");
+}
@@ -0,0 +1,24 @@
+//! Captured exchanges with a module played back through the driver.
+#![cfg(all(feature = "std", feature = "capture"))]
+
+use embassy_futures::block_on;
+use embedded_io_async::Write;
+use embedded_nal_async::TcpConnect;
+use es_wifi_driver::{EsWifi, IpAddr, NoPin, Replay, SocketAddr};
+
+#[test]
+fn join_and_send() {
+    let capture = include_str!("captures/join_and_send.txt");
+    let driver: EsWifi<_, NoPin, NoPin> = EsWifi::with_transport(Replay::new(capture), None, None);
+    let remote: SocketAddr = "192.0.2.1:80".parse().unwrap();
+    block_on(async {
+        let info = driver.control().join("drogue", "secret").await.unwrap();
+        assert_eq!(info.ip, IpAddr::from([192, 168, 1, 174]));
+        assert_eq!(info.gateway, Some([192, 168, 1, 1].into()));
+        assert_eq!(info.rssi, Some(-52));
+        assert_eq!(info.channel, Some(6));
+
+        let mut socket = driver.connect(remote).await.unwrap();
+        assert_eq!(socket.write(b"GET / HTTP/1.0\r\n").await.unwrap(), 9);
+    });
+}
@@ -0,0 +1,34 @@
+//! Sockets reserved for one purpose are kept out of reach of other connections.
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::ok_module;
+use embassy_futures::{block_on, poll_once};
+use embedded_nal_async::TcpConnect;
+use es_wifi_driver::{EsWifi, NoPin, SocketAddr, SocketError};
+
+#[test]
+fn reserved_socket_is_kept_out_of_the_pool() {
+    let driver: EsWifi<_, NoPin, NoPin, 2> = EsWifi::with_transport(ok_module(), None, None);
+    let remote: SocketAddr = "192.0.2.1:80".parse().unwrap();
+    block_on(async {
+        let reservation = driver.reserve_socket().await.unwrap();
+        let bulk = driver.connect(remote).await.unwrap();
+        assert_ne!(bulk.handle().index(), reservation.index());
+
+        // The only socket left is reserved, so other connections have to wait.
+        assert!(poll_once(driver.connect(remote)).is_pending());
+        assert!(matches!(
+            driver.reserve_socket().await,
+            Err(SocketError::OpenError)
+        ));
+
+        let control = driver.connect_reserved(&reservation, remote).await.unwrap();
+        assert_eq!(control.handle().index(), reservation.index());
+        assert!(matches!(
+            driver.connect_reserved(&reservation, remote).await,
+            Err(SocketError::OpenError)
+        ));
+    });
+}
@@ -0,0 +1,50 @@
+//! Getting back in step with a module that restarted behind the driver's back.
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::{Driver, FakeModule, Script, Sent, RSSI};
+use embassy_futures::block_on;
+
+/// Driver for a module that restarted on its own, answering every command with a bare
+/// prompt until verbosity is turned off again.
+fn driver() -> (
+    Driver<impl Script>,
+    Sent,
+) {
+    let mut restarted = true;
+    let module = FakeModule::new(move |command, _| {
+        if command == b"MT=1\r" {
+            restarted = false;
+        }
+        match command {
+            b"\r" => Some(b"\r\n> ".to_vec()),
+            _ if restarted => Some(b"\r\n> ".to_vec()),
+            b"CR\r" => Some(RSSI.to_vec()),
+            _ => None,
+        }
+    });
+    let sent = module.sent();
+    (module.driver(), sent)
+}
+
+#[test]
+fn resync_after_malformed_responses() {
+    let (driver, sent) = driver();
+    let control = driver.control();
+    block_on(async {
+        for _ in 0..3 {
+            assert_eq!(control.rssi().await, None);
+        }
+        assert_eq!(control.rssi().await, Some(-52));
+    });
+    let sent = sent.borrow();
+    assert_eq!(sent[3..], [&b"\r"[..], b"MT=1\r", b"CR\r"]);
+}
+
+#[test]
+fn explicit_resync() {
+    let (driver, _) = driver();
+    block_on(driver.resync()).unwrap();
+    assert_eq!(block_on(driver.control().rssi()), Some(-52));
+}
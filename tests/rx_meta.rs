@@ -0,0 +1,28 @@
+//! Reception time reported along with data read.
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::FakeModule;
+use embassy_futures::block_on;
+use embassy_time::Instant;
+use embedded_nal_async::TcpConnect;
+use es_wifi_driver::SocketAddr;
+
+#[test]
+fn read_with_meta() {
+    // The module has `hello` waiting to be read.
+    let driver = FakeModule::new(|command, _| {
+        (command == b"R0\r").then(|| b"\r\nhello\r\nOK\r\n> ".to_vec())
+    })
+    .driver();
+    let remote: SocketAddr = "192.0.2.1:80".parse().unwrap();
+    block_on(async {
+        let mut socket = driver.connect(remote).await.unwrap();
+        let mut buf = [0; 5];
+        let before = Instant::now();
+        let (len, meta) = socket.read_with_meta(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], b"hello");
+        assert!(before <= meta.received_at && meta.received_at <= Instant::now());
+    });
+}
@@ -0,0 +1,82 @@
+//! Socket lifecycle events reported for connection auditing.
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::{Driver, FakeModule, Script, Sent};
+use embassy_futures::block_on;
+use embedded_nal_async::TcpConnect;
+use es_wifi_driver::{CloseReason, ConnectionState, Event, SocketAddr};
+
+/// Driver for a module whose peer has hung up, answering status queries accordingly.
+fn driver() -> (
+    Driver<impl Script>,
+    Sent,
+) {
+    let module = FakeModule::new(|command, _| match command {
+        b"P?\r" => Some(b"\r\n0,192.168.1.174,0,192.0.2.1,80,0,0,0,0\r\nOK\r\n> ".to_vec()),
+        b"P6=0\r" => Some(b"\r\n\r\nOK\r\n> ".to_vec()),
+        _ => None,
+    });
+    let sent = module.sent();
+    (module.driver(), sent)
+}
+
+#[test]
+fn socket_lifecycle_is_reported() {
+    let (driver, _) = driver();
+    let remote: SocketAddr = "192.0.2.1:80".parse().unwrap();
+    block_on(async {
+        let socket = driver.connect(remote).await.unwrap();
+        let handle = socket.handle();
+        assert_eq!(driver.next_event().await, Event::SocketOpened { handle });
+        assert_eq!(
+            driver.next_event().await,
+            Event::SocketConnected { handle, remote }
+        );
+
+        // Closing by the peer is reported once, however often it is seen.
+        for _ in 0..2 {
+            assert_eq!(
+                socket.state().await.unwrap(),
+                ConnectionState::Disconnected
+            );
+        }
+        assert_eq!(
+            driver.next_event().await,
+            Event::SocketClosed {
+                handle,
+                remote: Some(remote),
+                reason: CloseReason::Peer,
+            }
+        );
+        assert!(embassy_futures::poll_once(driver.next_event()).is_pending());
+    });
+}
+
+#[test]
+fn close_completes_inline() {
+    let (driver, sent) = driver();
+    let remote: SocketAddr = "192.0.2.1:80".parse().unwrap();
+    block_on(async {
+        let socket = driver.connect(remote).await.unwrap();
+        let handle = socket.handle();
+        socket.close().await.unwrap();
+        assert_eq!(sent.borrow().last().unwrap(), b"P6=0\r");
+
+        // The handle is free again right away.
+        let socket = driver.connect(remote).await.unwrap();
+        assert_eq!(socket.handle().index(), handle.index());
+
+        driver.next_event().await;
+        driver.next_event().await;
+        assert_eq!(
+            driver.next_event().await,
+            Event::SocketClosed {
+                handle,
+                remote: Some(remote),
+                reason: CloseReason::Local,
+            }
+        );
+    });
+}
@@ -0,0 +1,90 @@
+//! Starting a module whose boot prompt arrives mangled or not at all.
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::{FakeModule, Script, JOINED};
+use core::convert::Infallible;
+use embassy_futures::block_on;
+use embassy_futures::select::{select, Either};
+use embassy_time::{Duration, Timer};
+use embedded_hal::digital::{ErrorKind, ErrorType, OutputPin};
+use es_wifi_driver::{Error, EsWifi, NoPin};
+
+/// Module answering the software reset with `boot`, and the carriage returns asking for
+/// the prompt again with `prompt`.
+fn module(
+    boot: &'static [u8],
+    prompt: &'static [u8],
+) -> FakeModule<impl Script> {
+    FakeModule::new(move |command, _| match command {
+        b"ZR\r" => Some(boot.to_vec()),
+        b"\r" => Some(prompt.to_vec()),
+        b"C0\r" => Some(JOINED.to_vec()),
+        _ => None,
+    })
+}
+
+/// RESET line whose pin driver always fails.
+struct BrokenPin;
+
+impl ErrorType for BrokenPin {
+    type Error = ErrorKind;
+}
+
+impl OutputPin for BrokenPin {
+    fn set_low(&mut self) -> Result<(), ErrorKind> {
+        Err(ErrorKind::Other)
+    }
+
+    fn set_high(&mut self) -> Result<(), ErrorKind> {
+        Err(ErrorKind::Other)
+    }
+}
+
+/// Run the driver until it has joined the network, or until the run loop gives up.
+fn start(module: FakeModule<impl Script>) -> Result<(), Error<Infallible>> {
+    let driver = module.driver();
+    block_on(async {
+        match select(driver.run("drogue", "secret"), async {
+            while driver.ip_address().await.is_none() {
+                Timer::after(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        {
+            Either::First(result) => result,
+            Either::Second(()) => Ok(()),
+        }
+    })
+}
+
+#[test]
+fn prompt_among_leftovers() {
+    // NAK padding and the end of an earlier response ahead of the prompt.
+    assert!(start(module(b"\x15\x15K\r\n\r\n> \x15", b"")).is_ok());
+}
+
+#[test]
+fn prompt_asked_for_again() {
+    // Only part of the prompt arrived after the reset.
+    assert!(start(module(b"\r\n", b"\r\n> ")).is_ok());
+}
+
+#[test]
+fn no_prompt() {
+    match start(module(b"\x15garbage", b"\r\n")) {
+        Err(Error::InitFailed { received, len }) => assert_eq!(&received[..len], b"\r\n"),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn broken_reset_pin() {
+    let module = module(b"\r\n> ", b"\r\n> ");
+    let driver: EsWifi<_, _, NoPin> = EsWifi::with_transport(module, Some(BrokenPin), None);
+    match block_on(driver.run("drogue", "secret")) {
+        Err(Error::Pin(ErrorKind::Other)) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+}
@@ -0,0 +1,102 @@
+//! TCP servers run by the module, taking one client at a time.
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::{was_sent, FakeModule};
+use core::cell::Cell;
+use embassy_futures::block_on;
+use embedded_io_async::Read;
+use es_wifi_driver::{Event, SocketAddr, SocketError};
+
+const LISTENING: &[u8] = b"\r\n0,192.168.10.1,8080,0.0.0.0,0,1,0,0,0\r\nOK\r\n> ";
+const ATTACHED: &[u8] = b"\r\n0,192.168.10.1,8080,192.168.10.2,50000,1,0,1,1\r\nOK\r\n> ";
+
+#[test]
+fn accept_client() {
+    // A client attaches on the third look at the transport status.
+    let polls = Cell::new(0);
+    let module = FakeModule::new(|command, _| match command {
+        b"P?\r" => {
+            polls.set(polls.get() + 1);
+            Some(if polls.get() > 2 { ATTACHED } else { LISTENING }.to_vec())
+        }
+        b"R0\r" => Some(b"\r\nhello\r\nOK\r\n> ".to_vec()),
+        _ => None,
+    });
+    let sent = module.sent();
+    let driver = module.driver();
+    block_on(async {
+        let mut server = driver.tcp_listen(8080).await.unwrap();
+        assert!(was_sent(&sent, b"P1=0"));
+        assert!(was_sent(&sent, b"P2=8080"));
+        assert!(was_sent(&sent, b"P5=1"));
+        // Nothing to read before a client is attached.
+        assert!(!server.is_open().await);
+        assert!(matches!(
+            server.read(&mut [0; 5]).await,
+            Err(SocketError::SocketClosed)
+        ));
+
+        let client: SocketAddr = "192.168.10.2:50000".parse().unwrap();
+        assert_eq!(server.accept().await.unwrap(), client);
+        assert_eq!(polls.get(), 3);
+        assert!(server.is_open().await);
+        let mut buf = [0; 5];
+        assert_eq!(server.read(&mut buf).await.unwrap(), 5);
+        assert_eq!(&buf, b"hello");
+
+        let handle = server.handle();
+        let mut event = driver.next_event().await;
+        while !matches!(event, Event::SocketConnected { .. }) {
+            event = driver.next_event().await;
+        }
+        assert_eq!(
+            event,
+            Event::SocketConnected {
+                handle,
+                remote: client
+            }
+        );
+
+        // Only listening TCP sockets take clients.
+        let mut udp = driver.udp_bind(5000).await.unwrap();
+        assert!(matches!(udp.accept().await, Err(SocketError::Unsupported)));
+    });
+}
+
+#[test]
+fn disconnect_client() {
+    // The client stays attached until the server is stopped.
+    let attached = Cell::new(true);
+    let module = FakeModule::new(|command, _| match command {
+        b"P?\r" => Some(if attached.get() { ATTACHED } else { LISTENING }.to_vec()),
+        b"P5=0\r" => {
+            attached.set(false);
+            None
+        }
+        _ => None,
+    });
+    let sent = module.sent();
+    let driver = module.driver();
+    block_on(async {
+        let mut server = driver.tcp_listen(8080).await.unwrap();
+        server.accept().await.unwrap();
+        sent.borrow_mut().clear();
+
+        server.disconnect().await.unwrap();
+        assert!(!attached.get());
+        assert!(!server.is_open().await);
+        assert_eq!(
+            *sent.borrow(),
+            [b"P0=0\r".to_vec(), b"P5=0\r".to_vec(), b"P5=1\r".to_vec()]
+        );
+
+        // Clients only ever attach to TCP servers.
+        let mut udp = driver.udp_bind(5000).await.unwrap();
+        assert!(matches!(
+            udp.disconnect().await,
+            Err(SocketError::Unsupported)
+        ));
+    });
+}
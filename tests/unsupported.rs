@@ -0,0 +1,32 @@
+//! Commands an older firmware does not know.
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::{FakeModule, RSSI};
+use embassy_futures::block_on;
+use es_wifi_driver::{Antenna, ModuleError};
+
+#[test]
+fn unknown_command() {
+    // The module's firmware predates the antenna commands.
+    let driver = FakeModule::new(|command, _| match command {
+        b"ZA?\r" => Some(b"\r\nUnknown command\r\nERROR\r\n> ".to_vec()),
+        b"ZA=1\r" => Some(b"\r\nERROR: Command not found\r\n> ".to_vec()),
+        b"CR\r" => Some(RSSI.to_vec()),
+        _ => None,
+    })
+    .driver();
+    block_on(async {
+        match driver.antenna().await {
+            Err(ModuleError::Unsupported(command)) => assert_eq!(command.as_str(), "ZA"),
+            other => panic!("unexpected {:?}", other),
+        }
+        match driver.set_antenna(Antenna::External).await {
+            Err(ModuleError::Unsupported(command)) => assert_eq!(command.to_string(), "ZA"),
+            other => panic!("unexpected {:?}", other),
+        }
+        // The module is still usable afterwards.
+        assert_eq!(driver.control().rssi().await, Some(-52));
+    });
+}
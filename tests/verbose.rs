@@ -0,0 +1,37 @@
+//! Driving a module left with its verbose messages on.
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::{was_sent, FakeModule, OK, RSSI, SCAN};
+use embassy_futures::block_on;
+use es_wifi_driver::Security;
+
+#[test]
+fn verbose_responses() {
+    // The module echoes every command ahead of its response, as it does with verbose
+    // messages on.
+    let module = FakeModule::new(|command, _| {
+        let response = match command {
+            b"\r" => b"\r\n> ",
+            b"CR\r" => RSSI,
+            b"F0\r" => SCAN,
+            _ => OK,
+        };
+        Some([command, response].concat())
+    });
+    let sent = module.sent();
+    let driver = module.driver();
+    block_on(async {
+        driver.set_verbose(true).await;
+        driver.resync().await.unwrap();
+        let control = driver.control();
+        assert_eq!(control.rssi().await, Some(-52));
+        let mut found = Vec::new();
+        control.scan(|result| found.push(result)).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].ssid, "drogue");
+        assert_eq!(found[0].security, Security::Wpa2);
+    });
+    assert!(was_sent(&sent, b"MT=0"));
+}
@@ -0,0 +1,95 @@
+//! Wake patterns, and waking the module along with the host.
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::ok_module;
+use core::cell::Cell;
+use core::convert::Infallible;
+use embassy_futures::block_on;
+use embedded_hal::digital::{ErrorType, OutputPin};
+use embedded_hal_async::digital::Wait;
+use es_wifi_driver::{EsWifi, ModuleError, NoPin, WakePattern};
+
+/// WAKEUP line counting the pulses sent to the module.
+struct WakeupPin<'a> {
+    pulses: &'a Cell<usize>,
+}
+
+impl ErrorType for WakeupPin<'_> {
+    type Error = Infallible;
+}
+
+impl OutputPin for WakeupPin<'_> {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.pulses.set(self.pulses.get() + 1);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// Host wake input, raised by the module right away.
+struct WakeInput;
+
+impl ErrorType for WakeInput {
+    type Error = Infallible;
+}
+
+impl Wait for WakeInput {
+    async fn wait_for_high(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+#[test]
+fn wake_patterns() {
+    let module = ok_module();
+    let sent = module.sent();
+    let pulses = Cell::new(0);
+    let driver: EsWifi<_, NoPin, _> =
+        EsWifi::with_transport(module, None, Some(WakeupPin { pulses: &pulses }));
+    block_on(async {
+        // ARP requests.
+        let arp = WakePattern {
+            offset: 12,
+            bytes: &[0x08, 0x06],
+        };
+        driver.set_wake_pattern(1, Some(&arp)).await.unwrap();
+        driver.set_wake_pattern(1, None).await.unwrap();
+        assert_eq!(
+            driver.set_wake_pattern(4, Some(&arp)).await,
+            Err(ModuleError::Rejected)
+        );
+        let empty = WakePattern {
+            offset: 0,
+            bytes: &[],
+        };
+        assert_eq!(
+            driver.set_wake_pattern(0, Some(&empty)).await,
+            Err(ModuleError::Rejected)
+        );
+
+        driver.wait_for_wake(&mut WakeInput).await.unwrap();
+    });
+    assert_eq!(*sent.borrow(), [&b"ZW=1,12,0806\r"[..], b"ZW=1\r"]);
+    assert_eq!(pulses.get(), 1);
+}
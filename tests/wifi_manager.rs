@@ -0,0 +1,37 @@
+//! Firmware written against the radio-neutral `WifiManager` trait.
+#![cfg(all(feature = "std", feature = "wifi-manager"))]
+
+mod common;
+
+use common::{FakeModule, JOINED, SCAN};
+use embassy_futures::block_on;
+use es_wifi_driver::WifiManager;
+
+/// Join the strongest network named `ssid` in range, whatever the radio.
+async fn join_if_in_range<M: WifiManager>(manager: &mut M, ssid: &str) -> bool {
+    let mut found = false;
+    manager
+        .scan(&mut |result| found |= result.ssid == ssid)
+        .await
+        .unwrap();
+    found && manager.join(ssid, "secret").await.is_ok()
+}
+
+#[test]
+fn control_implements_wifi_manager() {
+    // The module has a network named `drogue` in range.
+    let driver = FakeModule::new(|command, _| match command {
+        b"C0\r" => Some(JOINED.to_vec()),
+        b"F0\r" => Some(SCAN.to_vec()),
+        _ => None,
+    })
+    .driver();
+    let mut control = driver.control();
+    block_on(async {
+        assert!(!join_if_in_range(&mut control, "elsewhere").await);
+        assert!(join_if_in_range(&mut control, "drogue").await);
+        assert!(driver.join_info().await.is_some());
+        WifiManager::leave(&mut control).await;
+        assert!(driver.join_info().await.is_none());
+    });
+}
@@ -0,0 +1,49 @@
+//! Finding the largest payload the firmware takes per write.
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::FakeModule;
+use core::cell::RefCell;
+use embassy_futures::block_on;
+use embedded_io_async::Write;
+use embedded_nal_async::TcpConnect;
+use es_wifi_driver::SocketAddr;
+
+#[test]
+fn probe_write_size() {
+    // The module takes up to 1024 bytes per `S3`. The sizes of the chunks written are
+    // recorded.
+    let chunks = RefCell::new(Vec::new());
+    let driver = FakeModule::new(|command, data| {
+        command.starts_with(b"S3=").then(|| {
+            chunks.borrow_mut().push(data.len());
+            if data.len() > 1024 {
+                b"\r\nData too long\r\nERROR\r\n> ".to_vec()
+            } else {
+                format!("\r\n{}\r\nOK\r\n> ", data.len()).into_bytes()
+            }
+        })
+    })
+    .driver();
+    let remote: SocketAddr = "192.0.2.1:80".parse().unwrap();
+    let data = [0x55; 3000];
+    block_on(async {
+        let mut socket = driver.connect(remote).await.unwrap();
+        // Without probing, the default size is more than this firmware takes.
+        assert!(socket.write(&data).await.is_err());
+        assert_eq!(*chunks.borrow(), [1200]);
+
+        chunks.borrow_mut().clear();
+        driver.set_write_probe(true).await;
+        assert_eq!(socket.write(&data).await.unwrap(), 3000);
+        assert_eq!(*chunks.borrow(), [1460, 1200, 1024, 1024, 952]);
+        assert_eq!(driver.write_chunk_size().await, 1024);
+
+        // The size found is kept.
+        driver.set_write_probe(true).await;
+        assert_eq!(driver.write_chunk_size().await, 1024);
+        driver.set_write_probe(false).await;
+        assert_eq!(driver.write_chunk_size().await, 1200);
+    });
+}